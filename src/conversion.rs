@@ -0,0 +1,958 @@
+//! Functions to convert between Protobuf messages and JSON. Messages are converted to the
+//! canonical Protobuf JSON form: fields are keyed by their JSON names, repeated fields are arrays,
+//! map fields are objects, enum values are their names, 64 bit integers and bytes fields (Base64
+//! encoded) are strings, and the well-known types have their special JSON forms (i.e. RFC 3339
+//! strings for timestamps). There is also a function to create an example message in this form
+//! from the descriptors, for documentation.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::BytesMut;
+use itertools::Itertools;
+use pact_plugin_driver::utils::to_proto_value;
+use prost::encoding::WireType;
+use prost::Message;
+use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+use prost_types::field_descriptor_proto::Type;
+use prost_types::value::Kind;
+use serde_json::{json, Map, Value};
+use tracing::{trace, warn};
+
+use crate::dynamic_message::DynamicMessage;
+use crate::message_decoder::{decode_message, ProtobufField, ProtobufFieldData};
+use crate::utils::{
+  enum_name,
+  find_enum_by_name,
+  find_enum_by_name_in_message,
+  find_message_type_by_name,
+  find_message_type_by_qualified_name,
+  find_nested_type,
+  float_to_json_string,
+  is_map_field,
+  is_repeated_field,
+  last_name,
+  well_known_json_type_to_json
+};
+
+/// Converts the fields of a Protobuf message into a JSON object in the canonical Protobuf JSON
+/// form. Fields that are not in the message descriptor are ignored.
+pub fn message_to_json(
+  fields: &[ProtobufField],
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<Value> {
+  message_to_json_with_names(fields, descriptor, descriptors, false)
+}
+
+/// Converts the fields of a Protobuf message into a JSON object in the canonical Protobuf JSON
+/// form, except that the fields are keyed by their names in the proto file instead of their JSON
+/// names. This is the form used for the interaction configuration.
+pub(crate) fn message_to_json_with_proto_names(
+  fields: &[ProtobufField],
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<Value> {
+  message_to_json_with_names(fields, descriptor, descriptors, true)
+}
+
+fn message_to_json_with_names(
+  fields: &[ProtobufField],
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet,
+  proto_names: bool
+) -> anyhow::Result<Value> {
+  let mut object = Map::new();
+
+  for field_descriptor in &descriptor.field {
+    let field_num = field_descriptor.number.unwrap_or_default() as u32;
+    let values = fields.iter().filter(|field| field.field_num == field_num).collect_vec();
+    if values.is_empty() {
+      continue;
+    }
+
+    let field_name = if proto_names {
+      field_descriptor.name.clone().unwrap_or_else(|| field_num.to_string())
+    } else {
+      json_name(field_descriptor)
+    };
+    let value = if is_map_field(descriptor, field_descriptor) {
+      let entry_descriptor = find_nested_type(descriptor, field_descriptor)
+        .ok_or_else(|| anyhow!("Did not find the map entry descriptor for field '{}'", field_name))?;
+      let entry_field = |number: i32| entry_descriptor.field.iter()
+        .find(|field| field.number == Some(number))
+        .ok_or_else(|| anyhow!("Did not find the map entry field {} for field '{}'", number, field_name));
+      let (key_descriptor, value_descriptor) = (entry_field(1)?, entry_field(2)?);
+      let mut map = Map::new();
+      for value in values {
+        if let ProtobufFieldData::Message(bytes, _) = &value.data {
+          let entry = decode_message(&mut BytesMut::from(bytes.as_slice()), &entry_descriptor, descriptors)?;
+          let key = entry.iter().find(|field| field.field_num == 1)
+            .map(|field| field_value_to_json(&field.data, key_descriptor, descriptors, proto_names))
+            .transpose()?
+            .map(|key| match key {
+              Value::String(s) => s,
+              key => key.to_string()
+            })
+            .unwrap_or_default();
+          let value = match entry.iter().find(|field| field.field_num == 2) {
+            Some(field) => field_value_to_json(&field.data, value_descriptor, descriptors, proto_names)?,
+            None => Value::Null
+          };
+          map.insert(key, value);
+        }
+      }
+      Value::Object(map)
+    } else if is_repeated_field(field_descriptor) {
+      Value::Array(values.iter()
+        .map(|value| field_value_to_json(&value.data, field_descriptor, descriptors, proto_names))
+        .collect::<anyhow::Result<Vec<_>>>()?)
+    } else {
+      // the last value wins for a singular field that has been received more than once
+      field_value_to_json(&values[values.len() - 1].data, field_descriptor, descriptors, proto_names)?
+    };
+    object.insert(field_name, value);
+  }
+
+  Ok(Value::Object(object))
+}
+
+/// The name of the field in Protobuf JSON. This is the JSON name set by protoc, otherwise it is
+/// the field name in lowerCamelCase.
+fn json_name(field_descriptor: &FieldDescriptorProto) -> String {
+  field_descriptor.json_name.clone()
+    .unwrap_or_else(|| to_lower_camel_case(field_descriptor.name.clone().unwrap_or_default().as_str()))
+}
+
+fn to_lower_camel_case(name: &str) -> String {
+  let mut result = String::with_capacity(name.len());
+  let mut upper = false;
+  for c in name.chars() {
+    if c == '_' {
+      upper = true;
+    } else if upper {
+      result.extend(c.to_uppercase());
+      upper = false;
+    } else {
+      result.push(c);
+    }
+  }
+  result
+}
+
+fn to_snake_case(name: &str) -> String {
+  let mut result = String::with_capacity(name.len() + 4);
+  for c in name.chars() {
+    if c.is_ascii_uppercase() {
+      result.push('_');
+      result.push(c.to_ascii_lowercase());
+    } else {
+      result.push(c);
+    }
+  }
+  result
+}
+
+fn field_value_to_json(
+  data: &ProtobufFieldData,
+  field_descriptor: &FieldDescriptorProto,
+  descriptors: &FileDescriptorSet,
+  proto_names: bool
+) -> anyhow::Result<Value> {
+  Ok(match data {
+    ProtobufFieldData::String(s) => Value::String(s.clone()),
+    ProtobufFieldData::Boolean(b) => Value::Bool(*b),
+    ProtobufFieldData::UInteger32(n) => json!(n),
+    ProtobufFieldData::Integer32(n) => json!(n),
+    ProtobufFieldData::UInteger64(n) => Value::String(n.to_string()),
+    ProtobufFieldData::Integer64(n) => Value::String(n.to_string()),
+    ProtobufFieldData::Float(n) => float_to_json(f32_to_f64(*n)),
+    ProtobufFieldData::Double(n) => float_to_json(*n),
+    ProtobufFieldData::Bytes(b) => Value::String(BASE64.encode(b)),
+    ProtobufFieldData::Enum(_, _) if field_descriptor.type_name.as_deref() == Some(".google.protobuf.NullValue") => Value::Null,
+    ProtobufFieldData::Enum(n, descriptor) => if descriptor.value.iter().any(|v| v.number == Some(*n)) {
+      Value::String(enum_name(*n, descriptor))
+    } else {
+      json!(n)
+    },
+    ProtobufFieldData::Message(b, descriptor) => {
+      let type_name = field_descriptor.type_name.clone().unwrap_or_default();
+      match well_known_type_to_json(type_name.as_str(), b, descriptors)? {
+        Some(json) => json,
+        None => {
+          let fields = decode_message(&mut BytesMut::from(b.as_slice()), descriptor, descriptors)?;
+          message_to_json_with_names(&fields, descriptor, descriptors, proto_names)?
+        }
+      }
+    }
+    ProtobufFieldData::Unknown(b) => Value::String(BASE64.encode(b))
+  })
+}
+
+/// Infinite and NaN values can not be represented as JSON numbers, so use the Protobuf JSON strings
+fn float_to_json(n: f64) -> Value {
+  if n.is_finite() {
+    json!(n)
+  } else {
+    Value::String(float_to_json_string(n))
+  }
+}
+
+// Use the shortest representation of the f32 value, otherwise 1.1 would become 1.100000023841858
+fn f32_to_f64(n: f32) -> f64 {
+  n.to_string().parse().unwrap_or(n as f64)
+}
+
+/// Converts the encoded value of the well-known types that have a special form in Protobuf JSON.
+/// Returns `None` for any other message type.
+fn well_known_type_to_json(type_name: &str, data: &[u8], descriptors: &FileDescriptorSet) -> anyhow::Result<Option<Value>> {
+  Ok(Some(match type_name {
+    ".google.protobuf.Timestamp" => Value::String(prost_types::Timestamp::decode(data)?.to_string()),
+    ".google.protobuf.Duration" => Value::String(prost_types::Duration::decode(data)?.to_string()),
+    ".google.protobuf.FieldMask" => Value::String(prost_types::FieldMask::decode(data)?.paths.iter()
+      .map(|path| to_lower_camel_case(path))
+      .join(",")),
+    ".google.protobuf.Struct" | ".google.protobuf.Value" | ".google.protobuf.ListValue" =>
+      well_known_json_type_to_json(type_name, data)?,
+    ".google.protobuf.DoubleValue" => float_to_json(f64::decode(data)?),
+    ".google.protobuf.FloatValue" => float_to_json(f32_to_f64(f32::decode(data)?)),
+    ".google.protobuf.Int64Value" => Value::String(i64::decode(data)?.to_string()),
+    ".google.protobuf.UInt64Value" => Value::String(u64::decode(data)?.to_string()),
+    ".google.protobuf.Int32Value" => json!(i32::decode(data)?),
+    ".google.protobuf.UInt32Value" => json!(u32::decode(data)?),
+    ".google.protobuf.BoolValue" => Value::Bool(bool::decode(data)?),
+    ".google.protobuf.StringValue" => Value::String(String::decode(data)?),
+    ".google.protobuf.BytesValue" => Value::String(BASE64.encode(Vec::<u8>::decode(data)?)),
+    ".google.protobuf.Any" => any_to_json(data, descriptors)?,
+    _ => return Ok(None)
+  }))
+}
+
+/// `google.protobuf.Any` values are the JSON form of the embedded message with an additional
+/// `@type` attribute. Well-known types with a special JSON form are set as the `value` attribute.
+fn any_to_json(data: &[u8], descriptors: &FileDescriptorSet) -> anyhow::Result<Value> {
+  let any = prost_types::Any::decode(data)?;
+  let type_name = format!(".{}", any.type_url.rsplit('/').next().unwrap_or_default());
+  let mut object = Map::new();
+  object.insert("@type".to_string(), Value::String(any.type_url.clone()));
+  if let Some(json) = well_known_type_to_json(type_name.as_str(), &any.value, descriptors)? {
+    object.insert("value".to_string(), json);
+  } else {
+    let (descriptor, _) = find_message_type_by_qualified_name(type_name.as_str(), descriptors)
+      .map_err(|err| anyhow!("Could not convert the google.protobuf.Any value with type '{}' to JSON - {}", any.type_url, err))?;
+    let fields = decode_message(&mut BytesMut::from(any.value.as_slice()), &descriptor, descriptors)?;
+    if let Value::Object(fields) = message_to_json(&fields, &descriptor, descriptors)? {
+      object.extend(fields);
+    }
+  }
+  Ok(Value::Object(object))
+}
+
+/// Converts a JSON object into the fields of a Protobuf message. The JSON must be in the form
+/// returned by `message_to_json`, and it is an error for the JSON to have an attribute that is
+/// not a field of the message.
+pub fn json_to_message(
+  json: &Value,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<Vec<ProtobufField>> {
+  let message_name = descriptor.name.clone().unwrap_or_default();
+  let object = json.as_object()
+    .ok_or_else(|| anyhow!("Expected a JSON object for message '{}', but got {}", message_name, json))?;
+  let mut fields = vec![];
+
+  for (name, value) in object {
+    let field_descriptor = descriptor.field.iter()
+      .find(|field| field.name.as_ref() == Some(name) || json_name(field) == *name)
+      .ok_or_else(|| anyhow!("Message '{}' does not have a field '{}'", message_name, name))?;
+    trace!(%name, "Converting JSON value {} to field data", value);
+
+    if is_map_field(descriptor, field_descriptor) {
+      let entry_descriptor = find_nested_type(descriptor, field_descriptor)
+        .ok_or_else(|| anyhow!("Did not find the map entry descriptor for field '{}'", name))?;
+      let entries = value.as_object()
+        .ok_or_else(|| anyhow!("Expected a JSON object for map field '{}', but got {}", name, value))?;
+      for (key, value) in entries {
+        let entry_json = json!({ "key": key_from_string(key, &entry_descriptor)?, "value": value });
+        let entry = json_to_message(&entry_json, &entry_descriptor, descriptors)?;
+        fields.push(message_field(field_descriptor, ProtobufFieldData::Message(encode(&entry, descriptors)?, entry_descriptor.clone())));
+      }
+    } else if is_repeated_field(field_descriptor) {
+      let values = value.as_array()
+        .ok_or_else(|| anyhow!("Expected a JSON array for repeated field '{}', but got {}", name, value))?;
+      for value in values {
+        fields.push(message_field(field_descriptor, json_to_field_value(value, field_descriptor, descriptor, descriptors)?));
+      }
+    } else if !value.is_null() || accepts_null(field_descriptor) {
+      fields.push(message_field(field_descriptor, json_to_field_value(value, field_descriptor, descriptor, descriptors)?));
+    }
+  }
+
+  Ok(fields.into_iter().sorted_by(|a, b| Ord::cmp(&a.field_num, &b.field_num)).collect())
+}
+
+/// Only `google.protobuf.Value` and `google.protobuf.NullValue` fields can be set to a JSON null,
+/// for any other field it means the field is not set
+fn accepts_null(field_descriptor: &FieldDescriptorProto) -> bool {
+  matches!(field_descriptor.type_name.as_deref(), Some(".google.protobuf.Value") | Some(".google.protobuf.NullValue"))
+}
+
+/// JSON object keys are always strings, so the key of a map entry needs to be converted into the
+/// JSON type of the key field
+fn key_from_string(key: &str, entry_descriptor: &DescriptorProto) -> anyhow::Result<Value> {
+  let key_type = entry_descriptor.field.iter()
+    .find(|field| field.number == Some(1))
+    .map(|field| field.r#type())
+    .unwrap_or(Type::String);
+  match key_type {
+    Type::String => Ok(Value::String(key.to_string())),
+    Type::Bool => Ok(Value::Bool(key.parse()?)),
+    _ => serde_json::from_str(key).map_err(|err| anyhow!("'{}' is not a valid map key - {}", key, err))
+  }
+}
+
+fn message_field(field_descriptor: &FieldDescriptorProto, data: ProtobufFieldData) -> ProtobufField {
+  let wire_type = match field_descriptor.r#type() {
+    Type::Double | Type::Fixed64 | Type::Sfixed64 => WireType::SixtyFourBit,
+    Type::Float | Type::Fixed32 | Type::Sfixed32 => WireType::ThirtyTwoBit,
+    Type::String | Type::Bytes | Type::Message | Type::Group => WireType::LengthDelimited,
+    _ => WireType::Varint
+  };
+  ProtobufField {
+    field_num: field_descriptor.number.unwrap_or_default() as u32,
+    field_name: field_descriptor.name.clone().unwrap_or_default(),
+    wire_type,
+    data
+  }
+}
+
+fn encode(fields: &[ProtobufField], descriptors: &FileDescriptorSet) -> anyhow::Result<Vec<u8>> {
+  let mut buffer = BytesMut::new();
+  DynamicMessage::new(fields, descriptors).write_to(&mut buffer)?;
+  Ok(buffer.to_vec())
+}
+
+fn json_to_field_value(
+  value: &Value,
+  field_descriptor: &FieldDescriptorProto,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<ProtobufFieldData> {
+  let name = field_descriptor.name.clone().unwrap_or_default();
+  let invalid = || anyhow!("{} is not a valid value for field '{}' of type {:?}", value, name, field_descriptor.r#type());
+  match field_descriptor.r#type() {
+    Type::String => value.as_str().map(|s| ProtobufFieldData::String(s.to_string())).ok_or_else(invalid),
+    Type::Bool => value.as_bool().map(ProtobufFieldData::Boolean).ok_or_else(invalid),
+    Type::Int32 | Type::Sint32 | Type::Sfixed32 => json_to_i64(value)
+      .and_then(|n| i32::try_from(n).ok())
+      .map(ProtobufFieldData::Integer32)
+      .ok_or_else(invalid),
+    Type::Uint32 | Type::Fixed32 => json_to_u64(value)
+      .and_then(|n| u32::try_from(n).ok())
+      .map(ProtobufFieldData::UInteger32)
+      .ok_or_else(invalid),
+    Type::Int64 | Type::Sint64 | Type::Sfixed64 => json_to_i64(value).map(ProtobufFieldData::Integer64).ok_or_else(invalid),
+    Type::Uint64 | Type::Fixed64 => json_to_u64(value).map(ProtobufFieldData::UInteger64).ok_or_else(invalid),
+    Type::Float => json_to_f64(value).map(|n| ProtobufFieldData::Float(n as f32)).ok_or_else(invalid),
+    Type::Double => json_to_f64(value).map(ProtobufFieldData::Double).ok_or_else(invalid),
+    Type::Bytes => match value.as_str() {
+      Some(s) => Ok(ProtobufFieldData::Bytes(BASE64.decode(s)?)),
+      None => Err(invalid())
+    },
+    Type::Enum => {
+      let enum_descriptor = enum_descriptor(field_descriptor, descriptor, descriptors)?;
+      match value {
+        Value::Null if accepts_null(field_descriptor) => Ok(ProtobufFieldData::Enum(0, enum_descriptor.clone())),
+        Value::String(s) => enum_descriptor.value.iter()
+          .find(|v| v.name.as_ref() == Some(s))
+          .and_then(|v| v.number)
+          .map(|n| ProtobufFieldData::Enum(n, enum_descriptor.clone()))
+          .ok_or_else(invalid),
+        _ => json_to_i64(value)
+          .and_then(|n| i32::try_from(n).ok())
+          .map(|n| ProtobufFieldData::Enum(n, enum_descriptor.clone()))
+          .ok_or_else(invalid)
+      }
+    }
+    Type::Message => {
+      let message_descriptor = message_descriptor(field_descriptor, descriptor, descriptors)?;
+      let type_name = field_descriptor.type_name.clone().unwrap_or_default();
+      let bytes = match json_to_well_known_type(type_name.as_str(), value, descriptors)? {
+        Some(bytes) => bytes,
+        None => encode(&json_to_message(value, &message_descriptor, descriptors)?, descriptors)?
+      };
+      Ok(ProtobufFieldData::Message(bytes, message_descriptor))
+    }
+    t => {
+      warn!("Fields of type {:?} can not be converted from JSON", t);
+      Err(invalid())
+    }
+  }
+}
+
+/// Encodes the JSON form of the well-known types that have a special form in Protobuf JSON.
+/// Returns `None` for any other message type.
+fn json_to_well_known_type(type_name: &str, value: &Value, descriptors: &FileDescriptorSet) -> anyhow::Result<Option<Vec<u8>>> {
+  let invalid = || anyhow!("{} is not a valid value for a {} field", value, type_name);
+  Ok(Some(match type_name {
+    ".google.protobuf.Timestamp" => value.as_str()
+      .and_then(|s| prost_types::Timestamp::from_str(s).ok())
+      .ok_or_else(invalid)?
+      .encode_to_vec(),
+    ".google.protobuf.Duration" => value.as_str()
+      .and_then(|s| prost_types::Duration::from_str(s).ok())
+      .ok_or_else(invalid)?
+      .encode_to_vec(),
+    ".google.protobuf.FieldMask" => prost_types::FieldMask {
+      paths: value.as_str().ok_or_else(invalid)?
+        .split(',')
+        .filter(|path| !path.is_empty())
+        .map(to_snake_case)
+        .collect()
+    }.encode_to_vec(),
+    ".google.protobuf.Value" => to_proto_value(value).encode_to_vec(),
+    ".google.protobuf.Struct" => match to_proto_value(value).kind {
+      Some(Kind::StructValue(s)) => s.encode_to_vec(),
+      _ => return Err(invalid())
+    },
+    ".google.protobuf.ListValue" => match to_proto_value(value).kind {
+      Some(Kind::ListValue(l)) => l.encode_to_vec(),
+      _ => return Err(invalid())
+    },
+    ".google.protobuf.DoubleValue" => json_to_f64(value).ok_or_else(invalid)?.encode_to_vec(),
+    ".google.protobuf.FloatValue" => json_to_f64(value).map(|n| n as f32).ok_or_else(invalid)?.encode_to_vec(),
+    ".google.protobuf.Int64Value" => json_to_i64(value).ok_or_else(invalid)?.encode_to_vec(),
+    ".google.protobuf.UInt64Value" => json_to_u64(value).ok_or_else(invalid)?.encode_to_vec(),
+    ".google.protobuf.Int32Value" => json_to_i64(value).and_then(|n| i32::try_from(n).ok()).ok_or_else(invalid)?.encode_to_vec(),
+    ".google.protobuf.UInt32Value" => json_to_u64(value).and_then(|n| u32::try_from(n).ok()).ok_or_else(invalid)?.encode_to_vec(),
+    ".google.protobuf.BoolValue" => value.as_bool().ok_or_else(invalid)?.encode_to_vec(),
+    ".google.protobuf.StringValue" => value.as_str().map(|s| s.to_string()).ok_or_else(invalid)?.encode_to_vec(),
+    ".google.protobuf.BytesValue" => BASE64.decode(value.as_str().ok_or_else(invalid)?)?.encode_to_vec(),
+    ".google.protobuf.Any" => json_to_any(value, descriptors)?.encode_to_vec(),
+    _ => return Ok(None)
+  }))
+}
+
+fn json_to_any(value: &Value, descriptors: &FileDescriptorSet) -> anyhow::Result<prost_types::Any> {
+  let object = value.as_object()
+    .ok_or_else(|| anyhow!("Expected a JSON object for a google.protobuf.Any field, but got {}", value))?;
+  let type_url = object.get("@type").and_then(|t| t.as_str())
+    .ok_or_else(|| anyhow!("A google.protobuf.Any value must have a '@type' attribute, but got {}", value))?;
+  let type_name = format!(".{}", type_url.rsplit('/').next().unwrap_or_default());
+  let embedded = object.get("value").cloned().unwrap_or(Value::Null);
+  let value = match json_to_well_known_type(type_name.as_str(), &embedded, descriptors)? {
+    Some(bytes) => bytes,
+    None => {
+      let (descriptor, _) = find_message_type_by_qualified_name(type_name.as_str(), descriptors)?;
+      let fields = Value::Object(object.iter()
+        .filter(|(key, _)| key.as_str() != "@type")
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect());
+      encode(&json_to_message(&fields, &descriptor, descriptors)?, descriptors)?
+    }
+  };
+  Ok(prost_types::Any { type_url: type_url.to_string(), value })
+}
+
+fn message_descriptor(
+  field_descriptor: &FieldDescriptorProto,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<DescriptorProto> {
+  let type_name = field_descriptor.type_name.clone().unwrap_or_default();
+  descriptor.nested_type.iter()
+    .find(|nested| nested.name.as_deref() == Some(last_name(type_name.as_str())))
+    .cloned()
+    .or_else(|| find_message_type_by_name(last_name(type_name.as_str()), descriptors).map(|(m, _)| m).ok())
+    .ok_or_else(|| anyhow!("Did not find the message type {} for field '{}'", type_name,
+      field_descriptor.name.clone().unwrap_or_default()))
+}
+
+fn enum_descriptor(
+  field_descriptor: &FieldDescriptorProto,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<EnumDescriptorProto> {
+  let type_name = field_descriptor.type_name.clone().unwrap_or_default();
+  find_enum_by_name_in_message(&descriptor.enum_type, type_name.as_str())
+    .or_else(|| find_enum_by_name(descriptors, type_name.as_str()))
+    .ok_or_else(|| anyhow!("Did not find the enum {} in the Protobuf descriptors", type_name))
+}
+
+/// Creates an example of the message type as JSON (in the form returned by `message_to_json`), with
+/// every field set to a sample value for the type of the field. Only the first field of each oneOf
+/// is set, and fields for a message type that contains itself are left out after the first level.
+pub fn example_for_message(message_type: &str, descriptors: &FileDescriptorSet) -> anyhow::Result<Value> {
+  let (descriptor, _) = find_message_type_by_name(last_name(message_type), descriptors)?;
+  example_message(&descriptor, descriptors, &mut vec![])
+}
+
+fn example_message(
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet,
+  message_stack: &mut Vec<String>
+) -> anyhow::Result<Value> {
+  message_stack.push(descriptor.name.clone().unwrap_or_default());
+  let mut object = Map::new();
+  let mut one_ofs = HashSet::new();
+
+  for field_descriptor in &descriptor.field {
+    if let Some(index) = field_descriptor.oneof_index {
+      if !field_descriptor.proto3_optional.unwrap_or(false) && !one_ofs.insert(index) {
+        continue;
+      }
+    }
+
+    let field_name = json_name(field_descriptor);
+    let value = if is_map_field(descriptor, field_descriptor) {
+      let entry_descriptor = find_nested_type(descriptor, field_descriptor)
+        .ok_or_else(|| anyhow!("Did not find the map entry descriptor for field '{}'", field_name))?;
+      let entry_field = |number: i32| entry_descriptor.field.iter().find(|field| field.number == Some(number));
+      let key = match entry_field(1) {
+        Some(field) => example_field_value(field, &entry_descriptor, descriptors, message_stack)?,
+        None => None
+      };
+      let value = match entry_field(2) {
+        Some(field) => example_field_value(field, &entry_descriptor, descriptors, message_stack)?,
+        None => None
+      };
+      match (key, value) {
+        (Some(key), Some(value)) => {
+          let key = match key {
+            Value::String(key) => key,
+            key => key.to_string()
+          };
+          Some(Value::Object(Map::from_iter([(key, value)])))
+        }
+        _ => None
+      }
+    } else if is_repeated_field(field_descriptor) {
+      example_field_value(field_descriptor, descriptor, descriptors, message_stack)?
+        .map(|value| Value::Array(vec![value]))
+    } else {
+      example_field_value(field_descriptor, descriptor, descriptors, message_stack)?
+    };
+
+    match value {
+      Some(value) => {
+        object.insert(field_name, value);
+      }
+      None => trace!(%field_name, "Not adding an example value for the field")
+    }
+  }
+
+  message_stack.pop();
+  Ok(Value::Object(object))
+}
+
+fn example_field_value(
+  field_descriptor: &FieldDescriptorProto,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet,
+  message_stack: &mut Vec<String>
+) -> anyhow::Result<Option<Value>> {
+  let name = field_descriptor.name.clone().unwrap_or_default();
+  Ok(match field_descriptor.r#type() {
+    Type::String => Some(Value::String(name)),
+    Type::Bool => Some(Value::Bool(true)),
+    Type::Int32 | Type::Sint32 | Type::Sfixed32 => Some(json!(-42)),
+    Type::Int64 | Type::Sint64 | Type::Sfixed64 => Some(json!("-42")),
+    Type::Uint32 | Type::Fixed32 => Some(json!(42)),
+    Type::Uint64 | Type::Fixed64 => Some(json!("42")),
+    Type::Float | Type::Double => Some(json!(1.5)),
+    Type::Bytes => Some(Value::String(BASE64.encode(name))),
+    Type::Enum => {
+      let enum_descriptor = enum_descriptor(field_descriptor, descriptor, descriptors)?;
+      // use the first value that is not the default value, as that is not sent on the wire
+      enum_descriptor.value.iter()
+        .find(|value| value.number != Some(0))
+        .or_else(|| enum_descriptor.value.first())
+        .map(|value| Value::String(value.name.clone().unwrap_or_default()))
+    }
+    Type::Message => {
+      if let Some(example) = well_known_type_example(field_descriptor.type_name.as_deref().unwrap_or_default()) {
+        return Ok(example);
+      }
+      let message_descriptor = message_descriptor(field_descriptor, descriptor, descriptors)?;
+      if message_stack.contains(&message_descriptor.name.clone().unwrap_or_default()) {
+        None
+      } else {
+        Some(example_message(&message_descriptor, descriptors, message_stack)?)
+      }
+    }
+    t => {
+      warn!("Fields of type {:?} are not supported in examples", t);
+      None
+    }
+  })
+}
+
+/// Examples of the well-known types that have a special form in Protobuf JSON. `google.protobuf.Any`
+/// fields are left out, as there is no message type to embed.
+fn well_known_type_example(type_name: &str) -> Option<Option<Value>> {
+  match type_name {
+    ".google.protobuf.Timestamp" => Some(Some(json!("2023-01-01T00:00:00Z"))),
+    ".google.protobuf.Duration" => Some(Some(json!("1.5s"))),
+    ".google.protobuf.FieldMask" => Some(Some(json!("name"))),
+    ".google.protobuf.Struct" => Some(Some(json!({ "key": "value" }))),
+    ".google.protobuf.Value" => Some(Some(json!("value"))),
+    ".google.protobuf.ListValue" => Some(Some(json!([ "value" ]))),
+    ".google.protobuf.DoubleValue" | ".google.protobuf.FloatValue" => Some(Some(json!(1.5))),
+    ".google.protobuf.Int64Value" => Some(Some(json!("-42"))),
+    ".google.protobuf.UInt64Value" => Some(Some(json!("42"))),
+    ".google.protobuf.Int32Value" => Some(Some(json!(-42))),
+    ".google.protobuf.UInt32Value" => Some(Some(json!(42))),
+    ".google.protobuf.BoolValue" => Some(Some(json!(true))),
+    ".google.protobuf.StringValue" => Some(Some(json!("value"))),
+    ".google.protobuf.BytesValue" => Some(Some(json!(BASE64.encode("value")))),
+    ".google.protobuf.Any" => Some(None),
+    _ => None
+  }
+}
+
+// Protobuf JSON allows 64 bit integers to be strings, as they can not all be represented as JSON numbers
+fn json_to_i64(value: &Value) -> Option<i64> {
+  match value {
+    Value::Number(n) => n.as_i64(),
+    Value::String(s) => s.parse().ok(),
+    _ => None
+  }
+}
+
+fn json_to_u64(value: &Value) -> Option<u64> {
+  match value {
+    Value::Number(n) => n.as_u64(),
+    Value::String(s) => s.parse().ok(),
+    _ => None
+  }
+}
+
+fn json_to_f64(value: &Value) -> Option<f64> {
+  match value {
+    Value::Number(n) => n.as_f64(),
+    Value::String(s) => s.parse().ok(),
+    _ => None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use bytes::BytesMut;
+  use expectest::prelude::*;
+  use prost_types::{
+    DescriptorProto,
+    EnumDescriptorProto,
+    EnumValueDescriptorProto,
+    FieldDescriptorProto,
+    FileDescriptorProto,
+    FileDescriptorSet,
+    MessageOptions,
+    OneofDescriptorProto
+  };
+  use prost_types::field_descriptor_proto::{Label, Type};
+  use serde_json::json;
+
+  use crate::conversion::{example_for_message, json_to_message, message_to_json};
+  use crate::dynamic_message::DynamicMessage;
+  use crate::message_decoder::decode_message;
+
+  fn field(name: &str, number: i32, field_type: Type, label: Label, type_name: Option<&str>) -> FieldDescriptorProto {
+    FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(label as i32),
+      r#type: Some(field_type as i32),
+      type_name: type_name.map(|name| name.to_string()),
+      .. FieldDescriptorProto::default()
+    }
+  }
+
+  fn descriptors() -> FileDescriptorSet {
+    FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("orders.proto".to_string()),
+          package: Some("orders".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Order".to_string()),
+              field: vec![
+                field("id", 1, Type::String, Label::Optional, None),
+                field("customer", 2, Type::Message, Label::Optional, Some(".orders.Customer")),
+                field("items", 3, Type::Message, Label::Repeated, Some(".orders.Item")),
+                field("counts", 4, Type::Message, Label::Repeated, Some(".orders.Order.CountsEntry")),
+                field("status", 5, Type::Enum, Label::Optional, Some(".orders.Order.Status")),
+                field("signature", 6, Type::Bytes, Label::Optional, None)
+              ],
+              nested_type: vec![
+                DescriptorProto {
+                  name: Some("CountsEntry".to_string()),
+                  field: vec![
+                    field("key", 1, Type::String, Label::Optional, None),
+                    field("value", 2, Type::Int32, Label::Optional, None)
+                  ],
+                  options: Some(MessageOptions { map_entry: Some(true), .. MessageOptions::default() }),
+                  .. DescriptorProto::default()
+                }
+              ],
+              enum_type: vec![
+                EnumDescriptorProto {
+                  name: Some("Status".to_string()),
+                  value: vec![
+                    EnumValueDescriptorProto { name: Some("PENDING".to_string()), number: Some(0), .. EnumValueDescriptorProto::default() },
+                    EnumValueDescriptorProto { name: Some("SHIPPED".to_string()), number: Some(1), .. EnumValueDescriptorProto::default() }
+                  ],
+                  .. EnumDescriptorProto::default()
+                }
+              ],
+              .. DescriptorProto::default()
+            },
+            DescriptorProto {
+              name: Some("Customer".to_string()),
+              field: vec![
+                field("name", 1, Type::String, Label::Optional, None),
+                field("address", 2, Type::Message, Label::Optional, Some(".orders.Address"))
+              ],
+              .. DescriptorProto::default()
+            },
+            DescriptorProto {
+              name: Some("Address".to_string()),
+              field: vec![
+                field("city", 1, Type::String, Label::Optional, None),
+                field("postcode", 2, Type::Int64, Label::Optional, None)
+              ],
+              .. DescriptorProto::default()
+            },
+            DescriptorProto {
+              name: Some("Item".to_string()),
+              field: vec![
+                field("sku", 1, Type::String, Label::Optional, None),
+                field("quantity", 2, Type::Int32, Label::Optional, None),
+                field("price", 3, Type::Double, Label::Optional, None)
+              ],
+              .. DescriptorProto::default()
+            }
+          ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    }
+  }
+
+  #[test_log::test]
+  fn round_trip_a_message_with_nested_messages() {
+    let descriptors = descriptors();
+    let order = descriptors.file[0].message_type[0].clone();
+    let json = json!({
+      "id": "1234",
+      "customer": {
+        "name": "Fred",
+        "address": { "city": "Paris", "postcode": "75001" }
+      },
+      "items": [
+        { "sku": "A-1", "quantity": 2, "price": 10.5 },
+        { "sku": "B-2", "quantity": 1 }
+      ],
+      "counts": { "boxes": 3 },
+      "status": "SHIPPED",
+      "signature": "AQID"
+    });
+
+    let fields = json_to_message(&json, &order, &descriptors).unwrap();
+    expect!(message_to_json(&fields, &order, &descriptors).unwrap()).to(be_equal_to(json.clone()));
+
+    // encode the message, and then check the decoded message converts to the same JSON
+    let mut buffer = BytesMut::new();
+    DynamicMessage::new(&fields, &descriptors).write_to(&mut buffer).unwrap();
+    let decoded = decode_message(&mut buffer, &order, &descriptors).unwrap();
+    expect!(message_to_json(&decoded, &order, &descriptors).unwrap()).to(be_equal_to(json));
+    expect!(json_to_message(&message_to_json(&decoded, &order, &descriptors).unwrap(), &order, &descriptors).unwrap())
+      .to(be_equal_to(decoded));
+  }
+
+  #[test_log::test]
+  fn message_to_json_returns_the_canonical_protobuf_json() {
+    let well_known_types = FileDescriptorProto {
+      name: Some("google/protobuf/wkt.proto".to_string()),
+      package: Some("google.protobuf".to_string()),
+      message_type: vec![
+        DescriptorProto {
+          name: Some("Timestamp".to_string()),
+          field: vec![
+            field("seconds", 1, Type::Int64, Label::Optional, None),
+            field("nanos", 2, Type::Int32, Label::Optional, None)
+          ],
+          .. DescriptorProto::default()
+        },
+        DescriptorProto {
+          name: Some("Duration".to_string()),
+          field: vec![
+            field("seconds", 1, Type::Int64, Label::Optional, None),
+            field("nanos", 2, Type::Int32, Label::Optional, None)
+          ],
+          .. DescriptorProto::default()
+        },
+        DescriptorProto {
+          name: Some("Int64Value".to_string()),
+          field: vec![ field("value", 1, Type::Int64, Label::Optional, None) ],
+          .. DescriptorProto::default()
+        },
+        DescriptorProto {
+          name: Some("FieldMask".to_string()),
+          field: vec![ field("paths", 1, Type::String, Label::Repeated, None) ],
+          .. DescriptorProto::default()
+        },
+        DescriptorProto {
+          name: Some("Any".to_string()),
+          field: vec![
+            field("type_url", 1, Type::String, Label::Optional, None),
+            field("value", 2, Type::Bytes, Label::Optional, None)
+          ],
+          .. DescriptorProto::default()
+        }
+      ],
+      .. FileDescriptorProto::default()
+    };
+    let mut descriptors = descriptors();
+    descriptors.file.push(well_known_types);
+    let event = DescriptorProto {
+      name: Some("Event".to_string()),
+      field: vec![
+        FieldDescriptorProto { json_name: Some("eventId".to_string()), .. field("event_id", 1, Type::Uint64, Label::Optional, None) },
+        field("created_at", 2, Type::Message, Label::Optional, Some(".google.protobuf.Timestamp")),
+        field("time_taken", 3, Type::Message, Label::Optional, Some(".google.protobuf.Duration")),
+        field("retries", 4, Type::Message, Label::Optional, Some(".google.protobuf.Int64Value")),
+        field("update_mask", 5, Type::Message, Label::Optional, Some(".google.protobuf.FieldMask")),
+        field("details", 6, Type::Message, Label::Optional, Some(".google.protobuf.Any")),
+        field("score", 7, Type::Double, Label::Optional, None),
+        field("ratio", 8, Type::Float, Label::Optional, None)
+      ],
+      .. DescriptorProto::default()
+    };
+    let json = json!({
+      "eventId": "18446744073709551615",
+      "createdAt": "2023-03-01T10:15:30.500Z",
+      "timeTaken": "1.250s",
+      "retries": "-3",
+      "updateMask": "customer.firstName,items",
+      "details": { "@type": "type.googleapis.com/orders.Item", "sku": "A-1", "quantity": 2 },
+      "score": "NaN",
+      "ratio": 1.1
+    });
+
+    let fields = json_to_message(&json, &event, &descriptors).unwrap();
+    let mut buffer = BytesMut::new();
+    DynamicMessage::new(&fields, &descriptors).write_to(&mut buffer).unwrap();
+    let decoded = decode_message(&mut buffer, &event, &descriptors).unwrap();
+    expect!(message_to_json(&decoded, &event, &descriptors).unwrap()).to(be_equal_to(json));
+
+    // the field names are also accepted
+    let fields = json_to_message(&json!({ "event_id": 1, "update_mask": "" }), &event, &descriptors).unwrap();
+    expect!(message_to_json(&fields, &event, &descriptors).unwrap()).to(be_equal_to(json!({
+      "eventId": "1", "updateMask": ""
+    })));
+  }
+
+  #[test]
+  fn json_to_message_with_invalid_json() {
+    let descriptors = descriptors();
+    let order = descriptors.file[0].message_type[0].clone();
+    expect!(json_to_message(&json!([ "1234" ]), &order, &descriptors)).to(be_err());
+    expect!(json_to_message(&json!({ "reference": "1234" }), &order, &descriptors)).to(be_err());
+    expect!(json_to_message(&json!({ "id": 1234 }), &order, &descriptors)).to(be_err());
+    expect!(json_to_message(&json!({ "status": "LOST" }), &order, &descriptors)).to(be_err());
+    expect!(json_to_message(&json!({ "items": { "sku": "A-1" } }), &order, &descriptors)).to(be_err());
+    expect!(json_to_message(&json!({ "customer": { "address": { "city": true } } }), &order, &descriptors)).to(be_err());
+  }
+
+  #[test_log::test]
+  fn example_for_message_sets_every_type_of_field() {
+    let scalar_types = [
+      ("string_field", Type::String), ("bool_field", Type::Bool), ("int32_field", Type::Int32),
+      ("int64_field", Type::Int64), ("uint32_field", Type::Uint32), ("uint64_field", Type::Uint64),
+      ("sint32_field", Type::Sint32), ("sint64_field", Type::Sint64), ("fixed32_field", Type::Fixed32),
+      ("fixed64_field", Type::Fixed64), ("sfixed32_field", Type::Sfixed32), ("sfixed64_field", Type::Sfixed64),
+      ("float_field", Type::Float), ("double_field", Type::Double), ("bytes_field", Type::Bytes)
+    ];
+    let mut sample = DescriptorProto {
+      name: Some("Sample".to_string()),
+      field: scalar_types.iter().enumerate()
+        .map(|(index, (name, field_type))| field(name, index as i32 + 1, *field_type, Label::Optional, None))
+        .collect(),
+      .. DescriptorProto::default()
+    };
+    sample.field.push(field("tags", 20, Type::String, Label::Repeated, None));
+    sample.field.push(field("labels", 21, Type::Message, Label::Repeated, Some(".test.Sample.LabelsEntry")));
+    sample.field.push(field("kind", 22, Type::Enum, Label::Optional, Some(".test.Sample.Kind")));
+    sample.field.push(field("child", 23, Type::Message, Label::Optional, Some(".test.Child")));
+    sample.field.push(FieldDescriptorProto { oneof_index: Some(0), .. field("email", 24, Type::String, Label::Optional, None) });
+    sample.field.push(FieldDescriptorProto { oneof_index: Some(0), .. field("phone", 25, Type::String, Label::Optional, None) });
+    sample.nested_type.push(DescriptorProto {
+      name: Some("LabelsEntry".to_string()),
+      field: vec![
+        field("key", 1, Type::String, Label::Optional, None),
+        field("value", 2, Type::Int32, Label::Optional, None)
+      ],
+      options: Some(MessageOptions { map_entry: Some(true), .. MessageOptions::default() }),
+      .. DescriptorProto::default()
+    });
+    sample.enum_type.push(EnumDescriptorProto {
+      name: Some("Kind".to_string()),
+      value: vec![
+        EnumValueDescriptorProto { name: Some("UNKNOWN".to_string()), number: Some(0), .. EnumValueDescriptorProto::default() },
+        EnumValueDescriptorProto { name: Some("BASIC".to_string()), number: Some(1), .. EnumValueDescriptorProto::default() }
+      ],
+      .. EnumDescriptorProto::default()
+    });
+    sample.oneof_decl.push(OneofDescriptorProto { name: Some("contact".to_string()), .. OneofDescriptorProto::default() });
+    let child = DescriptorProto {
+      name: Some("Child".to_string()),
+      field: vec![
+        field("name", 1, Type::String, Label::Optional, None),
+        field("parent", 2, Type::Message, Label::Optional, Some(".test.Sample"))
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          package: Some("test".to_string()),
+          message_type: vec![ sample.clone(), child ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+
+    let example = example_for_message(".test.Sample", &descriptors).unwrap();
+    expect!(example.clone()).to(be_equal_to(json!({
+      "stringField": "string_field",
+      "boolField": true,
+      "int32Field": -42,
+      "int64Field": "-42",
+      "uint32Field": 42,
+      "uint64Field": "42",
+      "sint32Field": -42,
+      "sint64Field": "-42",
+      "fixed32Field": 42,
+      "fixed64Field": "42",
+      "sfixed32Field": -42,
+      "sfixed64Field": "-42",
+      "floatField": 1.5,
+      "doubleField": 1.5,
+      "bytesField": "Ynl0ZXNfZmllbGQ=",
+      "tags": [ "tags" ],
+      "labels": { "key": -42 },
+      "kind": "BASIC",
+      "child": { "name": "name" },
+      "email": "email"
+    })));
+
+    // the example must be a valid message
+    let fields = json_to_message(&example, &sample, &descriptors).unwrap();
+    expect!(message_to_json(&fields, &sample, &descriptors).unwrap()).to(be_equal_to(example));
+  }
+}
@@ -4,7 +4,7 @@ use std::iter::Peekable;
 use std::slice::Iter;
 
 use anyhow::anyhow;
-use bytes::{BufMut, Bytes};
+use bytes::{Buf, BufMut, Bytes};
 use itertools::Itertools;
 use pact_models::path_exp::{DocPath, PathToken};
 use pact_models::v4::sync_message::SynchronousMessage;
@@ -14,26 +14,37 @@ use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
 use tonic::Status;
 use tracing::{debug, error, instrument, trace};
 
+use crate::conversion::{json_to_message, message_to_json};
 use crate::message_decoder::{decode_message, ProtobufField, ProtobufFieldData};
 
 #[derive(Debug, Clone)]
 pub struct PactCodec {
   input_message: DescriptorProto,
+  output_message: DescriptorProto,
   file_descriptor_set: FileDescriptorSet,
+  json: bool
 }
 
 impl PactCodec {
   pub fn new(
     file: &FileDescriptorSet,
     input_message: &DescriptorProto,
-    _output_message: &DescriptorProto,
+    output_message: &DescriptorProto,
     _message: &SynchronousMessage
   ) -> Self {
     PactCodec {
       file_descriptor_set: file.clone(),
-      input_message: input_message.clone()
+      input_message: input_message.clone(),
+      output_message: output_message.clone(),
+      json: false
     }
   }
+
+  /// Use JSON encoded messages (`application/grpc+json`) instead of the Protobuf binary encoding
+  pub fn with_json_encoding(mut self, json: bool) -> Self {
+    self.json = json;
+    self
+  }
 }
 
 impl Default for PactCodec {
@@ -136,7 +147,14 @@ impl DynamicMessage {
           },
           _ => return Err(anyhow!("Expected a 32 bit value, but field is {}", field.data))
         }
-        _ => return Err(anyhow!("Groups are not supported"))
+        WireType::StartGroup => match &field.data {
+          ProtobufFieldData::Message(m, _) | ProtobufFieldData::Unknown(m) => {
+            buffer.put_slice(m.as_slice());
+            encode_key(field.field_num, WireType::EndGroup, buffer);
+          }
+          _ => return Err(anyhow!("Expected a group, but field is {}", field.data))
+        }
+        WireType::EndGroup => return Err(anyhow!("Field {} has an end group wire type", field.field_num))
       }
     }
     Ok(())
@@ -221,11 +239,16 @@ fn find_field<'a>(fields: &'a mut [ProtobufField], field_name: &str) -> Option<&
 }
 
 #[derive(Debug, Clone)]
-pub struct DynamicMessageEncoder {}
+pub struct DynamicMessageEncoder {
+  /// Descriptor of the message, only required for JSON encoded messages
+  json_descriptor: Option<DescriptorProto>
+}
 
 impl DynamicMessageEncoder {
-  fn new(_codec: &PactCodec) -> Self {
-    DynamicMessageEncoder {}
+  fn new(codec: &PactCodec) -> Self {
+    DynamicMessageEncoder {
+      json_descriptor: if codec.json { Some(codec.output_message.clone()) } else { None }
+    }
   }
 }
 
@@ -235,24 +258,40 @@ impl Encoder for DynamicMessageEncoder {
 
   #[instrument]
   fn encode(&mut self, item: Self::Item, dst: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
-    item.write_to(dst).map_err(|err| {
+    let result = match &self.json_descriptor {
+      Some(descriptor) => encode_json_message(&item, descriptor, dst),
+      None => item.write_to(dst)
+    };
+    result.map_err(|err| {
       error!("Failed to encode the message - {err}");
       Status::invalid_argument(format!("Failed to encode the message - {err}"))
     })
   }
 }
 
+fn encode_json_message<B: BufMut>(
+  message: &DynamicMessage,
+  descriptor: &DescriptorProto,
+  buffer: &mut B
+) -> anyhow::Result<()> {
+  let json = message_to_json(message.proto_fields(), descriptor, &message.descriptors)?;
+  buffer.put_slice(serde_json::to_vec(&json)?.as_slice());
+  Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub struct DynamicMessageDecoder {
   descriptor: DescriptorProto,
-  file_descriptor_set: FileDescriptorSet
+  file_descriptor_set: FileDescriptorSet,
+  json: bool
 }
 
 impl DynamicMessageDecoder {
   pub fn new(codec: &PactCodec) -> Self {
     DynamicMessageDecoder {
       descriptor: codec.input_message.clone(),
-      file_descriptor_set: codec.file_descriptor_set.clone()
+      file_descriptor_set: codec.file_descriptor_set.clone(),
+      json: codec.json
     }
   }
 }
@@ -263,7 +302,12 @@ impl Decoder for DynamicMessageDecoder {
 
   #[instrument]
   fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
-    match decode_message(src, &self.descriptor, &self.file_descriptor_set) {
+    let result = if self.json {
+      decode_json_message(src, &self.descriptor, &self.file_descriptor_set)
+    } else {
+      decode_message(src, &self.descriptor, &self.file_descriptor_set)
+    };
+    match result {
       Ok(fields) => Ok(Some(DynamicMessage::new(&fields, &self.file_descriptor_set))),
       Err(err) => {
         error!("Failed to decode the message - {err}");
@@ -273,6 +317,17 @@ impl Decoder for DynamicMessageDecoder {
   }
 }
 
+/// Decodes a JSON encoded message (in the Protobuf JSON form) into the fields of the message
+fn decode_json_message<B: Buf>(
+  buffer: &mut B,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<Vec<ProtobufField>> {
+  let bytes = buffer.copy_to_bytes(buffer.remaining());
+  let json: serde_json::Value = serde_json::from_slice(&bytes)?;
+  json_to_message(&json, descriptor, descriptors)
+}
+
 #[cfg(test)]
 mod tests {
   use bytes::BytesMut;
@@ -281,9 +336,44 @@ mod tests {
   use prost::encoding::WireType;
   use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorSet};
 
-  use crate::dynamic_message::DynamicMessage;
+  use crate::dynamic_message::{decode_json_message, DynamicMessage, encode_json_message};
   use crate::message_decoder::{ProtobufField, ProtobufFieldData};
 
+  #[test]
+  fn json_encoded_messages_round_trip() {
+    let descriptor = DescriptorProto {
+      name: Some("MessageIn".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("in".to_string()),
+          number: Some(1),
+          r#type: Some(8),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet { file: vec![] };
+
+    let mut buffer = bytes::Bytes::from_static(br#"{"in":true}"#);
+    let fields = decode_json_message(&mut buffer, &descriptor, &descriptors).unwrap();
+    expect!(fields.clone()).to(be_equal_to(vec![
+      ProtobufField {
+        field_num: 1,
+        field_name: "in".to_string(),
+        wire_type: WireType::Varint,
+        data: ProtobufFieldData::Boolean(true)
+      }
+    ]));
+
+    let mut buffer = BytesMut::new();
+    encode_json_message(&DynamicMessage::new(&fields, &descriptors), &descriptor, &mut buffer).unwrap();
+    expect!(buffer.freeze()).to(be_equal_to(bytes::Bytes::from_static(br#"{"in":true}"#)));
+
+    let mut buffer = bytes::Bytes::from_static(br#"{"out":true}"#);
+    expect!(decode_json_message(&mut buffer, &descriptor, &descriptors)).to(be_err());
+  }
+
   #[test]
   fn dynamic_message_fetch_value_with_no_fields() {
     let fields = vec![];
@@ -10,9 +10,13 @@ pub mod matching;
 pub mod mock_server;
 pub mod tcp;
 pub mod dynamic_message;
+pub mod conversion;
 mod mock_service;
 mod verification;
 mod metadata;
+mod prototext;
+mod reflection;
+pub mod timings;
 
 pub mod built_info {
   include!(concat!(env!("OUT_DIR"), "/built.rs"));
@@ -25,15 +25,18 @@ use tower::ServiceBuilder;
 use tower_http::compression::CompressionLayer;
 use tower_http::sensitive_headers::SetSensitiveHeadersLayer;
 use tower_http::trace::{DefaultMakeSpan, TraceLayer};
-use tracing::info;
+use tracing::{info, Subscriber, warn};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
+use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::fmt::writer::MakeWriterExt;
-use tracing_subscriber::FmtSubscriber;
+use tracing_subscriber::Layer;
 use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
 use uuid::Uuid;
 
 use pact_protobuf_plugin::server::ProtobufPactPlugin;
 use pact_protobuf_plugin::tcp::TcpIncoming;
+use pact_protobuf_plugin::timings::log_handler_timings;
 
 /// Interceptor to check the server key for the request
 #[derive(Debug, Clone, Default)]
@@ -76,7 +79,8 @@ fn integer_value(v: &str) -> Result<u64, String> {
 /// output the port the server is running on as well as a server key required to access the
 /// gRPC server.
 ///
-/// Log level will be passed in using the `LOG_LEVEL` environment variable.
+/// Log level will be passed in using the `LOG_LEVEL` environment variable. Setting
+/// `PACT_LOG_FORMAT` to `json` will output the log lines as structured JSON.
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Setup the logging system based on the LOG_LEVEL environment variable
@@ -87,20 +91,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (json_non_blocking, _json_guard) = tracing_appender::non_blocking(json_appender);
 
     // Setup tracing
+    let log_format = LogFormat::parse(env::var("PACT_LOG_FORMAT").ok().as_deref());
     let formatting_layer = BunyanFormattingLayer::new("pact-protobuf-plugin".into(), json_non_blocking);
-    let subscriber = FmtSubscriber::builder()
-      .with_max_level(tracing_core::LevelFilter::from_str(log_level.as_str())
+    // Any error setting up the OTLP exporter is logged once the tracing subscriber is set up
+    let (otlp_layer, otlp_error) = match otlp_layer() {
+      Ok(layer) => (layer, None),
+      Err(err) => (None, Some(err))
+    };
+    let subscriber = tracing_subscriber::registry()
+      .with(tracing_core::LevelFilter::from_str(log_level.as_str())
         .unwrap_or(tracing_core::LevelFilter::INFO))
-      .with_thread_names(true)
-      .with_ansi(false)
-      .with_writer(non_blocking.and(std::io::stdout))
-      .finish()
+      .with(fmt_layer(log_format, non_blocking.and(std::io::stdout)))
       .with(JsonStorageLayer)
-      .with(formatting_layer);
+      .with(formatting_layer)
+      .with(otlp_layer);
 
     if let Err(err) = tracing::subscriber::set_global_default(subscriber) {
       eprintln!("WARN: Failed to initialise global tracing subscriber - {err}");
     };
+    if let Some(err) = otlp_error {
+      warn!("Failed to initialise the OTLP exporter - {err}");
+    }
 
     let app = cli();
     let matches = match app.try_get_matches() {
@@ -121,6 +132,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     let plugin = ProtobufPactPlugin::new();
+    let log_timings = plugin.log_handler_timings();
 
     // Bind to a OS provided port and create a TCP listener
     let host = plugin.host_to_bind_to()
@@ -185,9 +197,78 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
       ).await?;
 
+    if log_timings {
+      log_handler_timings();
+    }
+
+    #[cfg(feature = "otlp")]
+    opentelemetry::global::shutdown_tracer_provider();
+
     Ok(())
 }
 
+/// Format of the log lines written to standard output and the plugin.log file
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LogFormat {
+  /// Plain text lines (the default)
+  Text,
+  /// Structured JSON lines with the level, target and message
+  Json
+}
+
+impl LogFormat {
+  /// Parses the format from the `PACT_LOG_FORMAT` environment variable value
+  fn parse(value: Option<&str>) -> LogFormat {
+    match value {
+      Some(format) if format.trim().eq_ignore_ascii_case("json") => LogFormat::Json,
+      _ => LogFormat::Text
+    }
+  }
+}
+
+/// Creates the formatting layer for the log lines in the given format
+fn fmt_layer<S, W>(format: LogFormat, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+  where S: Subscriber + for<'a> LookupSpan<'a>,
+        W: for<'w> MakeWriter<'w> + Send + Sync + 'static {
+  let layer = tracing_subscriber::fmt::layer()
+    .with_thread_names(true)
+    .with_ansi(false)
+    .with_writer(writer);
+  match format {
+    LogFormat::Text => Box::new(layer),
+    LogFormat::Json => Box::new(layer.json().flatten_event(true))
+  }
+}
+
+/// Creates the layer that exports the tracing spans to an OpenTelemetry collector using OTLP. This
+/// requires the plugin to be built with the `otlp` feature, and is only enabled when the
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` environment variable is set.
+#[cfg(feature = "otlp")]
+fn otlp_layer<S>() -> anyhow::Result<Option<Box<dyn Layer<S> + Send + Sync>>>
+  where S: Subscriber + for<'a> LookupSpan<'a> {
+  use opentelemetry_otlp::WithExportConfig;
+
+  let endpoint = match env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+    Ok(endpoint) => endpoint,
+    Err(_) => return Ok(None)
+  };
+  let resource = opentelemetry::sdk::Resource::new(vec![
+    opentelemetry::KeyValue::new("service.name", "pact-protobuf-plugin")
+  ]);
+  let tracer = opentelemetry_otlp::new_pipeline()
+    .tracing()
+    .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+    .with_trace_config(opentelemetry::sdk::trace::config().with_resource(resource))
+    .install_batch(opentelemetry::runtime::Tokio)?;
+  Ok(Some(Box::new(tracing_opentelemetry::layer().with_tracer(tracer))))
+}
+
+#[cfg(not(feature = "otlp"))]
+fn otlp_layer<S>() -> anyhow::Result<Option<Box<dyn Layer<S> + Send + Sync>>>
+  where S: Subscriber + for<'a> LookupSpan<'a> {
+  Ok(None)
+}
+
 fn cli() -> Command {
   command!()
     .disable_version_flag(true)
@@ -223,10 +304,56 @@ pub fn update_access_time() {
 
 #[cfg(test)]
 mod tests {
-  use crate::cli;
+  use std::io::Write;
+  use std::sync::{Arc, Mutex};
+
+  use expectest::prelude::*;
+  use serde_json::Value;
+  use tracing_subscriber::layer::SubscriberExt;
+
+  use crate::{cli, fmt_layer, LogFormat};
 
   #[test]
   fn verify_cli() {
     cli().debug_assert();
   }
+
+  #[derive(Clone, Default)]
+  struct TestWriter(Arc<Mutex<Vec<u8>>>);
+
+  impl Write for TestWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn log_format_parse() {
+    expect!(LogFormat::parse(None)).to(be_equal_to(LogFormat::Text));
+    expect!(LogFormat::parse(Some("text"))).to(be_equal_to(LogFormat::Text));
+    expect!(LogFormat::parse(Some("json"))).to(be_equal_to(LogFormat::Json));
+    expect!(LogFormat::parse(Some("JSON"))).to(be_equal_to(LogFormat::Json));
+  }
+
+  #[test]
+  fn json_log_format_writes_structured_log_lines() {
+    let writer = TestWriter::default();
+    let make_writer = writer.clone();
+    let subscriber = tracing_subscriber::registry()
+      .with(fmt_layer(LogFormat::Json, move || make_writer.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+      tracing::info!("plugin started");
+    });
+
+    let output = String::from_utf8(writer.0.lock().unwrap().clone()).unwrap();
+    let line = output.lines().next().unwrap();
+    let json: Value = serde_json::from_str(line).unwrap();
+    expect!(json["level"].as_str()).to(be_some().value("INFO"));
+    expect!(json["target"].as_str()).to(be_some().value("pact_protobuf_plugin::tests"));
+    expect!(json["message"].as_str()).to(be_some().value("plugin started"));
+  }
 }
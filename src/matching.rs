@@ -1,11 +1,16 @@
 //! Functions for matching Protobuf messages
 
-use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Debug, Display, Formatter};
+use std::sync::{Arc, Mutex};
 
 use anyhow::anyhow;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use bytes::{Bytes, BytesMut};
 use itertools::Itertools;
+use lazy_static::lazy_static;
 use maplit::hashmap;
 use pact_matching::{BodyMatchResult, CoreMatchingContext, DiffConfig, MatchingContext, Mismatch};
 use pact_matching::json::compare_json;
@@ -13,14 +18,47 @@ use pact_matching::matchers::{match_values, Matches};
 use pact_matching::matchingrules::{compare_lists_with_matchingrule, compare_maps_with_matchingrule};
 use pact_matching::Mismatch::BodyMismatch;
 use pact_models::content_types::ContentType;
-use pact_models::matchingrules::MatchingRule;
-use pact_models::path_exp::DocPath;
+use pact_models::json_utils::{json_to_num, json_to_string};
+use pact_models::matchingrules::{MatchingRule, RuleList, RuleLogic};
+use pact_models::matchingrules::expressions::parse_matcher_def;
+use pact_models::path_exp::{DocPath, PathToken};
 use pact_models::prelude::MatchingRuleCategory;
+use pact_plugin_driver::plugin_models::PluginInteractionConfig;
+use prost::encoding::WireType;
 use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+use prost_types::field_descriptor_proto::{Label, Type};
+use regex::{Captures, Regex};
+use serde_json::Value;
 use tracing::{debug, trace, warn};
 
-use crate::message_decoder::{decode_message, ProtobufField, ProtobufFieldData};
-use crate::utils::{display_bytes, enum_name, field_data_to_json, find_message_field_by_name, find_message_type_by_name, find_service_descriptor, is_map_field, is_repeated_field, last_name};
+use crate::conversion::message_to_json;
+use crate::message_decoder::{
+  decode_message,
+  default_field_data,
+  split_message_fields,
+  ProtobufField,
+  ProtobufFieldData
+};
+use crate::utils::{
+  as_hex,
+  display_bytes,
+  enum_name,
+  enum_value_names,
+  field_option_raw_values,
+  find_field_option_number,
+  find_message_field_by_name,
+  find_message_type_by_name,
+  find_service_descriptor,
+  get_descriptor_bytes_for_interaction,
+  implicit_presence_fields,
+  is_editions_message,
+  is_empty_message,
+  is_map_field,
+  is_repeated_field,
+  last_name,
+  message_full_name,
+  well_known_json_type_to_json
+};
 
 /// Match a single Protobuf message
 pub fn match_message(
@@ -30,26 +68,138 @@ pub fn match_message(
   actual_request: &mut Bytes,
   matching_rules: &MatchingRuleCategory,
   allow_unexpected_keys: bool
+) -> anyhow::Result<BodyMatchResult> {
+  match_message_with_config(message_name, descriptors, expected_request, actual_request, matching_rules,
+    allow_unexpected_keys, &hashmap!{})
+}
+
+/// Match a single Protobuf message, using the plugin configuration for the interaction (i.e. the
+/// `protobuf` interaction configuration settings that change how the message is compared)
+pub fn match_message_with_config(
+  message_name: &str,
+  descriptors: &FileDescriptorSet,
+  expected_request: &mut Bytes,
+  actual_request: &mut Bytes,
+  matching_rules: &MatchingRuleCategory,
+  allow_unexpected_keys: bool,
+  plugin_config: &HashMap<String, PluginInteractionConfig>
 ) -> anyhow::Result<BodyMatchResult> {
   debug!("Looking for message '{}'", message_name);
-  let (message_descriptor, _) = find_message_type_by_name(message_name, descriptors)?;
+  let (message_descriptor, file_descriptor) = find_message_type_by_name(message_name, descriptors)?;
+  if is_empty_message(&message_descriptor, &file_descriptor) {
+    debug!("Message is a google.protobuf.Empty, there is nothing to compare");
+    return Ok(BodyMatchResult::Ok);
+  }
 
   let expected_message = decode_message(expected_request, &message_descriptor, descriptors)?;
   debug!("expected message = {:?}", expected_message);
 
-  let actual_message = decode_message(actual_request, &message_descriptor, descriptors)?;
-  debug!("actual message = {:?}", actual_message);
-
-  let plugin_config = hashmap!{};
   let diff_config = if allow_unexpected_keys {
     DiffConfig::AllowUnexpectedKeys
   } else {
     DiffConfig::NoUnexpectedKeys
   };
+  let plugin_config = with_redacted_fields(plugin_config, descriptors)?;
+  let plugin_config = with_field_presence(&plugin_config, descriptors)?;
   let context = CoreMatchingContext::new(diff_config, matching_rules, &plugin_config);
 
-  compare(&message_descriptor, &expected_message, &actual_message, &context,
-          expected_request, descriptors)
+  let actual_message_bytes = actual_request.clone();
+  if is_schema_only(&context) {
+    debug!("Only verifying the actual message against the schema for message '{}'", message_name);
+    let result = match_schema(&message_descriptor, &actual_message_bytes, descriptors);
+    return match_serialized_message(result, &actual_message_bytes, &context);
+  }
+  if use_streaming_comparison(&context, actual_message_bytes.len()) {
+    debug!("actual message is {} bytes, comparing it one field at a time", actual_message_bytes.len());
+    let result = compare_streaming(&message_descriptor, &expected_message, &actual_message_bytes, &context,
+      expected_request, descriptors)?;
+    return match_serialized_message(result, &actual_message_bytes, &context);
+  }
+
+  let actual_message = decode_message(actual_request, &message_descriptor, descriptors)?;
+  debug!("actual message = {:?}", actual_message);
+
+  let result = compare(&message_descriptor, &expected_message, &actual_message, &context,
+    expected_request, descriptors)?;
+  match_serialized_message(result, &actual_message_bytes, &context)
+}
+
+/// Adds the fully qualified names of the fields that have the custom field option configured with
+/// `redactionOption` set to the interaction configuration (as `redactedFields`). The option values
+/// are read from the encoded descriptors in the Pact configuration, as they are dropped when the
+/// descriptors are decoded.
+fn with_redacted_fields(
+  plugin_config: &HashMap<String, PluginInteractionConfig>,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<HashMap<String, PluginInteractionConfig>> {
+  let mut plugin_config = plugin_config.clone();
+  if let Some(config) = plugin_config.get_mut("protobuf") {
+    if let Some(option_name) = config.interaction_configuration.get(REDACTION_OPTION_CONFIG).map(json_to_string) {
+      let option_number = find_field_option_number(descriptors, option_name.as_str())
+        .ok_or_else(|| anyhow!("Did not find a field option with name '{}' in the Protobuf descriptors", option_name))?;
+      let descriptor_key = config.interaction_configuration.get("descriptorKey")
+        .map(json_to_string)
+        .ok_or_else(|| anyhow!("The descriptor key is required to look up the '{}' field option", option_name))?;
+      let pact_configuration = config.pact_configuration.iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+      let descriptor_bytes = get_descriptor_bytes_for_interaction(descriptor_key.as_str(), &pact_configuration)?;
+      let redacted_fields = field_option_raw_values(&descriptor_bytes, option_number)?.iter()
+        .filter(|(_, value)| value.is_set())
+        .map(|(field_name, _)| field_name.clone())
+        .sorted()
+        .collect_vec();
+      debug!("Fields redacted with the '{}' field option: {:?}", option_name, redacted_fields);
+      config.interaction_configuration.insert(REDACTED_FIELDS_CONFIG.to_string(), Value::from(redacted_fields));
+    }
+  }
+  Ok(plugin_config)
+}
+
+/// Adds the fully qualified names of the fields with implicit presence in any files that use
+/// Protobuf Editions to the interaction configuration (as `implicitPresenceFields`). The features
+/// are read from the encoded descriptors in the Pact configuration, as they are dropped when the
+/// descriptors are decoded.
+pub(crate) fn with_field_presence(
+  plugin_config: &HashMap<String, PluginInteractionConfig>,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<HashMap<String, PluginInteractionConfig>> {
+  let mut plugin_config = plugin_config.clone();
+  if !descriptors.file.iter().any(|file| file.syntax.as_deref() == Some("editions")) {
+    return Ok(plugin_config);
+  }
+  if let Some(config) = plugin_config.get_mut("protobuf") {
+    if let Some(descriptor_key) = config.interaction_configuration.get("descriptorKey").map(json_to_string) {
+      let pact_configuration = config.pact_configuration.iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+      let descriptor_bytes = get_descriptor_bytes_for_interaction(descriptor_key.as_str(), &pact_configuration)?;
+      let fields = implicit_presence_fields(&descriptor_bytes)?;
+      debug!("Fields with implicit presence: {:?}", fields);
+      config.interaction_configuration.insert(IMPLICIT_PRESENCE_FIELDS_CONFIG.to_string(), Value::from(fields));
+    }
+  }
+  Ok(plugin_config)
+}
+
+/// If the field has been redacted with the `redactionOption` field option. The redacted fields
+/// have fully qualified names, so are matched against the fully qualified name of the field.
+fn is_redacted_field(
+  message_descriptor: &DescriptorProto,
+  field_name: &str,
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet
+) -> bool {
+  let redacted_fields = matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(REDACTED_FIELDS_CONFIG))
+    .and_then(|fields| fields.as_array())
+    .cloned()
+    .unwrap_or_default();
+  if redacted_fields.is_empty() {
+    return false;
+  }
+  let name = format!("{}.{}", message_full_name(message_descriptor, descriptors).unwrap_or_default(), field_name);
+  redacted_fields.iter().any(|field| json_to_string(field) == name)
 }
 
 /// Match a Protobuf service call, which has an input and output message
@@ -63,6 +213,65 @@ pub fn match_service(
   allow_unexpected_keys: bool,
   content_type: &ContentType
 ) -> anyhow::Result<BodyMatchResult> {
+  match_service_with_config(service_name, method_name, descriptors, expected_request, actual_request, rules,
+    allow_unexpected_keys, content_type, &hashmap!{})
+}
+
+/// Match a Protobuf service call, using the plugin configuration for the interaction (see
+/// [match_message_with_config])
+#[allow(clippy::too_many_arguments)]
+pub fn match_service_with_config(
+  service_name: &str,
+  method_name: &str,
+  descriptors: &FileDescriptorSet,
+  expected_request: &mut Bytes,
+  actual_request: &mut Bytes,
+  rules: &MatchingRuleCategory,
+  allow_unexpected_keys: bool,
+  content_type: &ContentType,
+  plugin_config: &HashMap<String, PluginInteractionConfig>
+) -> anyhow::Result<BodyMatchResult> {
+  let (message_type, part) = lookup_service_message_part(service_name, method_name, descriptors, content_type)?;
+  match_message_with_config(message_type.as_str(), descriptors,
+                expected_request, actual_request,
+                rules, allow_unexpected_keys, &with_message_part(plugin_config, part))
+}
+
+/// Sets the part of the interaction (`request` or `response`) the message being compared is for in
+/// the interaction configuration (as `messagePart`)
+pub(crate) fn with_message_part(
+  plugin_config: &HashMap<String, PluginInteractionConfig>,
+  part: &str
+) -> HashMap<String, PluginInteractionConfig> {
+  let mut plugin_config = plugin_config.clone();
+  if let Some(config) = plugin_config.get_mut("protobuf") {
+    config.interaction_configuration.insert(MESSAGE_PART_CONFIG.to_string(), Value::String(part.to_string()));
+  }
+  plugin_config
+}
+
+/// Looks up the name of the message type (input or output) that a service call is for. If the
+/// content type has a message attribute, that is used to select the message, otherwise the
+/// method name can be suffixed with `:request` to select the input message.
+pub fn lookup_service_message_type(
+  service_name: &str,
+  method_name: &str,
+  descriptors: &FileDescriptorSet,
+  content_type: &ContentType
+) -> anyhow::Result<String> {
+  lookup_service_message_part(service_name, method_name, descriptors, content_type)
+    .map(|(message_type, _)| message_type)
+}
+
+/// Looks up the name of the message type for the service call (see [lookup_service_message_type]),
+/// along with the part of the interaction it is for (`request` for the input message, otherwise
+/// `response`)
+fn lookup_service_message_part(
+  service_name: &str,
+  method_name: &str,
+  descriptors: &FileDescriptorSet,
+  content_type: &ContentType
+) -> anyhow::Result<(String, &'static str)> {
   debug!("Looking for service '{}'", service_name);
   let (_, service_descriptor) = find_service_descriptor(descriptors, service_name)?;
   trace!("Found service descriptor with name {:?}", service_descriptor.name);
@@ -78,23 +287,266 @@ pub fn match_service(
   trace!("Found method descriptor with name {:?}", method_descriptor.name);
 
   let expected_message_type = content_type.attributes.get("message");
-  let message_type = if let Some(message_type) = expected_message_type {
-    let input_type = method_descriptor.input_type.clone().unwrap_or_default();
+  let input_type = method_descriptor.input_type.clone().unwrap_or_default();
+  let (message_type, part) = if let Some(message_type) = expected_message_type {
     if last_name(input_type.as_str()) == message_type.as_str() {
-      input_type
+      (input_type, "request")
     } else {
-      method_descriptor.output_type.clone().unwrap_or_default()
+      (method_descriptor.output_type.clone().unwrap_or_default(), "response")
     }
   } else if service_part == "request" {
-    method_descriptor.input_type.clone().unwrap_or_default()
+    (input_type, "request")
   } else {
-    method_descriptor.output_type.clone().unwrap_or_default()
+    (method_descriptor.output_type.clone().unwrap_or_default(), "response")
   };
 
-  trace!("Message type = {}", message_type);
-  match_message(last_name(message_type.as_str()), descriptors,
-                expected_request, actual_request,
-                rules, allow_unexpected_keys)
+  trace!("Message type = {}, part = {}", message_type, part);
+  Ok((last_name(message_type.as_str()).to_string(), part))
+}
+
+/// Regular expression used for the `base64` matcher. It matches standard Base64 encoded values
+/// with padding.
+pub const BASE64_REGEX: &str = "^(?:[A-Za-z0-9+/]{4})*(?:[A-Za-z0-9+/]{2}==|[A-Za-z0-9+/]{3}=)?$";
+
+/// Regular expression used for the `hex` matcher
+pub const HEX_REGEX: &str = "^[0-9a-fA-F]*$";
+
+lazy_static! {
+  static ref ENCODING_MATCHER: Regex = Regex::new(r"matching\(\s*(base64|hex)\s*,").unwrap();
+  static ref FIELD_RELATION: Regex = Regex::new(r"^\s*(==|!=|<=|>=|<|>)\s*(\S+)\s*$").unwrap();
+  static ref FILTERED_SELECTOR: Regex = Regex::new(
+    r#"^(?P<path>\$(?:\.\w+)+)\[\?\(\s*@\.(?P<field>\w+(?:\.\w+)*)\s*(?P<op>==|!=)\s*(?P<value>'[^']*'|"[^"]*"|[^\s)]+)\s*\)\]\.(?P<target>\w+(?:\.\w+)*)$"#
+  ).unwrap();
+}
+
+/// Expands any `base64` or `hex` matchers in the matching rule definition (i.e.
+/// `matching(base64, 'SGVsbG8=')`) into the equivalent regex matcher, so the rules can be used by
+/// any Pact implementation.
+pub fn expand_encoding_matchers(definition: &str) -> String {
+  ENCODING_MATCHER.replace_all(definition, |captures: &Captures| {
+    let regex = if &captures[1] == "base64" { BASE64_REGEX } else { HEX_REGEX };
+    format!("matching(regex, '{}',", regex)
+  }).to_string()
+}
+
+/// Key in the interaction configuration for a field mask that limits the fields that are compared
+pub const FIELD_MASK_CONFIG: &str = "fieldMask";
+
+/// Interaction configuration key to require fields set in the expected message to be set in the
+/// actual message, even when the expected value is the default value for the field
+pub const TREAT_DEFAULTS_AS_PRESENT_CONFIG: &str = "treatDefaultsAsPresent";
+
+/// Interaction configuration key for a regex that the serialized actual message must match. The
+/// message bytes are rendered as hex, unless the `encoding` is set to `base64`.
+pub const SERIALIZED_REGEX_CONFIG: &str = "serializedRegex";
+
+/// Interaction configuration key for the size constraints (`min` and `max` number of bytes) of
+/// the serialized actual message
+pub const SERIALIZED_SIZE_CONFIG: &str = "serializedSize";
+
+/// Interaction configuration key for the message types of any `google.protobuf.BytesValue` fields
+/// that contain embedded Protobuf messages, keyed by the path to the field
+pub const BYTES_VALUE_TYPES_CONFIG: &str = "bytesValueTypes";
+
+/// Interaction configuration key for the custom matchers to apply to fields, as a map of the path
+/// to the field to the name of a matcher registered with [register_custom_matcher]
+pub const CUSTOM_MATCHERS_CONFIG: &str = "customMatchers";
+
+/// Interaction configuration key for the type URLs that are acceptable for any `google.protobuf.Any`
+/// fields, keyed by the path to the field
+pub const ANY_TYPES_CONFIG: &str = "anyTypes";
+
+/// Interaction configuration key for the relations that must hold between fields of the actual
+/// message, as a map of the path to a field to the relation (i.e. `>= $.start_time`)
+pub const FIELD_RELATIONS_CONFIG: &str = "fieldRelations";
+
+/// Interaction configuration key for groups of fields where at least one of the fields of each
+/// group must be set in the actual message, as a list of groups of paths to the fields
+pub const PRESENCE_GROUPS_CONFIG: &str = "presenceGroups";
+
+/// Interaction configuration key for matchers that only apply to the elements of a repeated field
+/// that match a predicate, as a map of the selector (i.e. `$.items[?(@.type == 'A')].value`) to the
+/// matcher definition
+pub const FILTERED_MATCHERS_CONFIG: &str = "filteredMatchers";
+
+/// Interaction configuration key for the paths to repeated fields where the single expected value
+/// must be equal to every element of the actual field
+pub const EACH_EQUALS_CONFIG: &str = "eachEquals";
+
+/// Interaction configuration key for the default matcher (i.e. `matching(type, '')`) to apply to
+/// every field that does not have its own matching rules
+pub const DEFAULT_MATCHER_CONFIG: &str = "defaultMatcher";
+
+/// Interaction configuration key for how enum values in the actual message that are not defined
+/// for the enum are treated (`strict` or `lenient`)
+pub const UNKNOWN_ENUM_VALUES_CONFIG: &str = "unknownEnumValues";
+
+/// Interaction configuration key for the size (in bytes) above which the actual message is
+/// compared one top level field at a time, instead of decoding the whole message
+pub const STREAMING_THRESHOLD_CONFIG: &str = "streamingThreshold";
+
+/// Interaction configuration key for the string fields that are compared ignoring case. This is
+/// either `true` for all string fields, or the paths to the fields
+pub const IGNORE_CASE_CONFIG: &str = "ignoreCase";
+
+/// Interaction configuration key for the string and bytes fields where the min and max type
+/// matchers also check the length of the value. This is either `true` for all the fields, or the
+/// paths to the fields
+pub const LENGTH_FIELDS_CONFIG: &str = "lengthFields";
+
+/// Interaction configuration key for the paths to repeated fields where the expected values must be
+/// found in the actual field in the same order, but other values can appear between them
+pub const SUBSEQUENCE_CONFIG: &str = "subsequence";
+
+/// Interaction configuration key for the values that repeated enum fields must include. This is a
+/// map of the path to the field to the names of the enum values
+pub const ENUM_INCLUDES_CONFIG: &str = "enumIncludes";
+
+/// Interaction configuration key for the values that repeated enum fields must not include. This is
+/// a map of the path to the field to the names of the enum values
+pub const ENUM_EXCLUDES_CONFIG: &str = "enumExcludes";
+
+/// Interaction configuration key for a regex that the JSON rendering of the actual message must
+/// match (see [message_to_json])
+pub const JSON_REGEX_CONFIG: &str = "jsonRegex";
+
+/// Interaction configuration key for the name of a custom field option (i.e. `mypackage.redacted`)
+/// that marks fields as redacted. Fields with the option set are not compared.
+pub const REDACTION_OPTION_CONFIG: &str = "redactionOption";
+
+/// Interaction configuration key for the fully qualified names of the redacted fields. This is
+/// populated from the `redactionOption` before the messages are compared.
+pub(crate) const REDACTED_FIELDS_CONFIG: &str = "redactedFields";
+
+/// Interaction configuration key for the fully qualified names of the fields of messages from files
+/// that use Protobuf Editions that have implicit presence. This is populated from the raw
+/// descriptors before the messages are compared.
+pub(crate) const IMPLICIT_PRESENCE_FIELDS_CONFIG: &str = "implicitPresenceFields";
+
+/// Interaction configuration key for the part of the interaction (`request` or `response`) the
+/// message being compared is for. This is set before the messages are compared, and selects the
+/// configuration that is scoped to that part (see [scoped_config]).
+pub(crate) const MESSAGE_PART_CONFIG: &str = "messagePart";
+
+/// Interaction configuration key for the string fields that contain numbers formatted for a locale
+/// (i.e. `1.234,56` for `de-DE`), as a map of the path to the field to the locale
+pub const NUMBER_LOCALES_CONFIG: &str = "numberLocales";
+
+/// Interaction configuration key to only verify that the actual message conforms to the message
+/// type (see [match_schema]). The expected message and matching rules are ignored.
+pub const SCHEMA_ONLY_CONFIG: &str = "schemaOnly";
+
+/// Interaction configuration key to report any fields in the actual message that are not in the
+/// expected message as warnings when verifying the provider (see [unexpected_fields])
+pub const WARN_ON_UNEXPECTED_CONFIG: &str = "warnOnUnexpected";
+
+/// How enum values in the actual message that are not defined for the enum are treated. Without a
+/// mode, they are compared in the same way as any other enum value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnknownEnumValues {
+  /// Any unknown enum value is a mismatch, even if the matching rules for the field would accept it
+  Strict,
+  /// Unknown enum values are accepted as opaque numbers (i.e. from a newer version of the provider)
+  Lenient
+}
+
+impl UnknownEnumValues {
+  /// Parse the mode from the interaction configuration value
+  pub fn parse(value: &str) -> anyhow::Result<UnknownEnumValues> {
+    match value.to_lowercase().as_str() {
+      "strict" => Ok(UnknownEnumValues::Strict),
+      "lenient" => Ok(UnknownEnumValues::Lenient),
+      _ => Err(anyhow!("'{}' is not a valid value for {}, it must be either 'strict' or 'lenient'", value, UNKNOWN_ENUM_VALUES_CONFIG))
+    }
+  }
+}
+
+/// Custom matcher function. It is called with the expected and actual values of the field, and
+/// returns a description of the mismatch if the actual value does not match.
+pub type CustomMatcher = Arc<dyn Fn(&ProtobufFieldData, &ProtobufFieldData) -> Result<(), String> + Send + Sync>;
+
+lazy_static! {
+  static ref CUSTOM_MATCHERS: Mutex<HashMap<String, CustomMatcher>> = Mutex::new(HashMap::new());
+}
+
+/// Registers a custom matcher with the given name, replacing any matcher already registered with
+/// that name. Fields are matched with it by configuring the name for the path to the field in the
+/// `customMatchers` interaction configuration (`pact:custom-matchers`).
+pub fn register_custom_matcher<F>(name: &str, matcher: F)
+  where F: Fn(&ProtobufFieldData, &ProtobufFieldData) -> Result<(), String> + Send + Sync + 'static {
+  CUSTOM_MATCHERS.lock().unwrap().insert(name.to_string(), Arc::new(matcher));
+}
+
+/// Removes the custom matcher registered with the given name, returning true if there was one
+pub fn remove_custom_matcher(name: &str) -> bool {
+  CUSTOM_MATCHERS.lock().unwrap().remove(name).is_some()
+}
+
+/// Returns the paths from a field mask configured for an interaction. The field mask can either be
+/// in the JSON form of a `google.protobuf.FieldMask` (a comma separated string), a list of paths or
+/// an object with a `paths` attribute.
+pub fn field_mask_paths(field_mask: &Value) -> Vec<String> {
+  match field_mask {
+    Value::String(paths) => paths.split(',')
+      .map(|path| path.trim())
+      .filter(|path| !path.is_empty())
+      .map(|path| path.to_string())
+      .collect(),
+    Value::Array(paths) => paths.iter()
+      .map(json_to_string)
+      .filter(|path| !path.is_empty())
+      .collect(),
+    Value::Object(attributes) => attributes.get("paths")
+      .map(field_mask_paths)
+      .unwrap_or_default(),
+    _ => vec![]
+  }
+}
+
+/// Removes any mismatches from the result for fields that are not included in the field mask.
+/// An empty field mask includes all fields.
+pub fn apply_field_mask(result: BodyMatchResult, field_mask: &[String]) -> BodyMatchResult {
+  if field_mask.is_empty() {
+    return result;
+  }
+
+  match result {
+    BodyMatchResult::BodyMismatches(mismatches) => {
+      let mismatches: HashMap<String, Vec<Mismatch>> = mismatches.into_iter()
+        .map(|(key, mismatches)| {
+          let mismatches = mismatches.into_iter()
+            .filter(|mismatch| match mismatch {
+              BodyMismatch { path, .. } => in_field_mask(path, field_mask),
+              _ => true
+            })
+            .collect_vec();
+          (key, mismatches)
+        })
+        .filter(|(_, mismatches)| !mismatches.is_empty())
+        .collect();
+      trace!("Mismatches after applying field mask {:?} = {:?}", field_mask, mismatches);
+      if mismatches.is_empty() {
+        BodyMatchResult::Ok
+      } else {
+        BodyMatchResult::BodyMismatches(mismatches)
+      }
+    }
+    _ => result
+  }
+}
+
+/// If the path is for a field in the field mask, or for a parent message of one of the fields
+fn in_field_mask(path: &str, field_mask: &[String]) -> bool {
+  field_mask.iter().any(|mask_path| {
+    let mask_path = format!("$.{}", mask_path);
+    is_within_path(path, mask_path.as_str()) || is_within_path(mask_path.as_str(), path)
+  })
+}
+
+fn is_within_path(path: &str, parent: &str) -> bool {
+  path.strip_prefix(parent)
+    .map(|rest| rest.is_empty() || rest.starts_with('.') || rest.starts_with('['))
+    .unwrap_or(false)
 }
 
 /// Compare the expected message to the actual one
@@ -110,20 +562,121 @@ pub(crate) fn compare(
   if expected_message.is_empty() {
     Ok(BodyMatchResult::Ok)
   } else if actual_message.is_empty() {
-    Ok(BodyMatchResult::BodyMismatches(hashmap!{
-      "$".to_string() => vec![Mismatch::BodyMismatch {
-        path: "$".to_string(),
-        expected: Some(expected_message_bytes.clone()),
-        actual: None,
-        mismatch: format!("Expected message '{}' but was missing or empty", message_descriptor.name.clone().unwrap_or_default())
-      }]
-    }))
+    Ok(missing_message_result(message_descriptor, expected_message_bytes))
+  } else {
+    let result = compare_message(DocPath::root(), expected_message, actual_message, matching_context, message_descriptor, descriptors)?;
+    let result = match_field_relations(result, actual_message, message_descriptor, matching_context, descriptors)?;
+    let result = match_presence_groups(result, actual_message, message_descriptor, matching_context, descriptors)?;
+    let result = match_filtered_matchers(result, actual_message, message_descriptor, matching_context, descriptors)?;
+    match_json_regex(result, actual_message, message_descriptor, matching_context, descriptors)
+  }
+}
+
+/// Matches the compact JSON rendering of the actual message against the regex configured for the
+/// interaction (`jsonRegex` interaction configuration)
+fn match_json_regex(
+  result: BodyMatchResult,
+  actual_message: &[ProtobufField],
+  message_descriptor: &DescriptorProto,
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<BodyMatchResult> {
+  let regex = match matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(JSON_REGEX_CONFIG)) {
+    Some(regex) => json_to_string(regex),
+    None => return Ok(result)
+  };
+  let re = Regex::new(regex.as_str())
+    .map_err(|err| anyhow!("'{}' is not a valid regular expression for {} - {}", regex, JSON_REGEX_CONFIG, err))?;
+  let json = message_to_json(actual_message, message_descriptor, descriptors)?.to_string();
+  trace!(%json, "Matching the JSON rendering of the message against '{}'", regex);
+
+  if re.is_match(json.as_str()) {
+    Ok(result)
   } else {
-    compare_message(DocPath::root(), expected_message, actual_message, matching_context, message_descriptor, descriptors)
+    Ok(add_mismatches(result, vec![
+      BodyMismatch {
+        path: "$".to_string(),
+        expected: None,
+        actual: Some(json.clone().into()),
+        mismatch: format!("Expected the JSON rendering of the message '{}' to match '{}'", json, regex)
+      }
+    ]))
+  }
+}
+
+/// Compare the expected message to the encoded actual message one top level field at a time. The
+/// actual message is split into the encoded values of each field with a single scan, and then only
+/// the values of one field are decoded at any time. The encoded message is still held in memory.
+/// Field relations and filtered matchers need the whole message, so are not supported with this
+/// comparison.
+#[tracing::instrument(ret, skip_all)]
+pub(crate) fn compare_streaming(
+  message_descriptor: &DescriptorProto,
+  expected_message: &[ProtobufField],
+  actual_message_bytes: &Bytes,
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  expected_message_bytes: &Bytes,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<BodyMatchResult> {
+  if expected_message.is_empty() {
+    return Ok(BodyMatchResult::Ok);
+  } else if actual_message_bytes.is_empty() {
+    return Ok(missing_message_result(message_descriptor, expected_message_bytes));
+  }
+
+  let mut fields = split_message_fields(actual_message_bytes)?;
+  let mut result = BodyMatchResult::Ok;
+  for field_descriptor in &message_descriptor.field {
+    if let Some(field_no) = field_descriptor.number {
+      let field_no = field_no as u32;
+      let mut field_bytes = fields.remove(&field_no).unwrap_or_default();
+      trace!(field_no, "Decoding {} bytes for the field", field_bytes.len());
+      let actual = decode_message(&mut field_bytes, message_descriptor, descriptors)?;
+      let expected = expected_message.iter().filter(|value| value.field_num == field_no).collect_vec();
+      let (_, mismatches) = compare_message_field(&DocPath::root(), field_no, field_descriptor, expected,
+        actual.iter().collect(), matching_context, message_descriptor, descriptors);
+      result = add_mismatches(result, mismatches);
+    }
+  }
+  Ok(result)
+}
+
+/// If the actual message is large enough (`streamingThreshold` interaction configuration) to be
+/// compared one field at a time
+fn use_streaming_comparison(matching_context: &(dyn MatchingContext + Send + Sync), message_size: usize) -> bool {
+  let config = matching_context.plugin_configuration().get("protobuf")
+    .map(|config| config.interaction_configuration.clone())
+    .unwrap_or_default();
+  match json_to_num(config.get(STREAMING_THRESHOLD_CONFIG).cloned()) {
+    Some(threshold) if message_size > threshold => {
+      if config.contains_key(FIELD_RELATIONS_CONFIG) || config.contains_key(FILTERED_MATCHERS_CONFIG) ||
+        config.contains_key(JSON_REGEX_CONFIG) || config.contains_key(PRESENCE_GROUPS_CONFIG) {
+        warn!("Field relations, presence groups, filtered matchers and JSON regexes need the whole message, so the message will not be compared one field at a time");
+        false
+      } else {
+        true
+      }
+    }
+    _ => false
   }
 }
 
-/// Compare the fields of the expected and actual messages
+/// Mismatch result for when the expected message was not received
+fn missing_message_result(message_descriptor: &DescriptorProto, expected_message_bytes: &Bytes) -> BodyMatchResult {
+  BodyMatchResult::BodyMismatches(hashmap!{
+    "$".to_string() => vec![Mismatch::BodyMismatch {
+      path: "$".to_string(),
+      expected: Some(expected_message_bytes.clone()),
+      actual: None,
+      mismatch: format!("Expected message '{}' but was missing or empty", message_descriptor.name.clone().unwrap_or_default())
+    }]
+  })
+}
+
+/// Compare the fields of the expected and actual messages. The comparison is driven by the fields
+/// of the message type, so any fields in the actual message that are not defined for the type
+/// (i.e. added in a newer version of the schema) are ignored.
 #[tracing::instrument(ret,
   skip_all,
   fields(%path)
@@ -150,49 +703,10 @@ pub fn compare_message(
     });
 
   for (field_no, (field_descriptor, expected, actual)) in fields {
-    let field_name = field_descriptor.name
-      .clone()
-      .unwrap_or_else(|| {
-        warn!("Field number {} does not have a field name in the descriptor, will use the number", field_no);
-        field_no.to_string()
-      });
-    let field_path = path.join(&field_name);
-    trace!(%field_name, field_no, "Comparing message field {:?} => {:?}", expected, actual);
-
-    if is_map_field(message_descriptor, field_descriptor) {
-      trace!(%field_name, field_no, "field is a map field");
-      let map_comparison = compare_map_field(&field_path, field_descriptor, expected, actual, matching_context, descriptors);
-      if !map_comparison.is_empty() {
-        results.insert(field_path.to_string(), map_comparison);
-      }
-    } else if is_repeated_field(field_descriptor) {
-      trace!(%field_name, field_no, "field is a repeated field");
-      let e = expected.iter().map(|f| (*f).clone()).collect_vec();
-      let a = actual.iter().map(|f| (*f).clone()).collect_vec();
-      let repeated_comparison = compare_repeated_field(&field_path, field_descriptor, &e, &a, matching_context, descriptors);
-      if !repeated_comparison.is_empty() {
-        results.insert(field_path.to_string(), repeated_comparison);
-      }
-    } else if let Some(expected_value) = expected.first() {
-      let actual_value = actual.first().map(|v| (*v).clone()).unwrap_or_else(|| {
-        // Need to compare against the default values, as gRPC lib may have skipped sending the field if it was a default
-        expected_value.default_field_value(field_descriptor)
-      });
-
-      let comparison = compare_field(&field_path, expected_value, field_descriptor, &actual_value, matching_context, descriptors);
-      if !comparison.is_empty() {
-        results.insert(field_path.to_string(), comparison);
-      }
-    } else if !actual.is_empty() && matching_context.config() == DiffConfig::NoUnexpectedKeys {
-      trace!(field_name = field_name.as_str(), field_no, "actual field list is not empty");
-      results.insert(field_path.to_string(), vec![
-        BodyMismatch {
-          path: field_path.to_string(),
-          expected: None,
-          actual: actual.first().map(|field_data| Bytes::from(field_data.data.as_bytes())),
-          mismatch: format!("Expected field '{}' to be missing, but received a value for it", field_name)
-        }
-      ]);
+    let (field_path, mismatches) = compare_message_field(&path, field_no, field_descriptor, expected, actual,
+      matching_context, message_descriptor, descriptors);
+    if !mismatches.is_empty() {
+      results.insert(field_path, mismatches);
     }
   }
 
@@ -203,489 +717,2078 @@ pub fn compare_message(
   }
 }
 
-/// Compare a simple field (non-map and non-repeated)
-#[tracing::instrument(ret,
-  skip_all,
-  fields(%path, %field, %actual)
-)]
-fn compare_field(
+/// Compare the expected and actual values of a single field of a message, returning the path of
+/// the field and any mismatches
+#[allow(clippy::too_many_arguments)]
+fn compare_message_field(
   path: &DocPath,
-  field: &ProtobufField,
-  descriptor: &FieldDescriptorProto,
-  actual: &ProtobufField,
+  field_no: u32,
+  field_descriptor: &FieldDescriptorProto,
+  expected: Vec<&ProtobufField>,
+  actual: Vec<&ProtobufField>,
   matching_context: &(dyn MatchingContext + Send + Sync),
+  message_descriptor: &DescriptorProto,
   descriptors: &FileDescriptorSet
-) -> Vec<Mismatch> {
-  match (&field.data, &actual.data) {
-    (ProtobufFieldData::String(s1), ProtobufFieldData::String(s2)) => {
-      trace!("Comparing string values");
-      let s1 = s1.clone();
-      let s2 = s2.clone();
-      compare_value(path, field, &s1, &s2, s1.as_str(), s2.as_str(), matching_context)
-    },
-    (ProtobufFieldData::Boolean(b1), ProtobufFieldData::Boolean(b2)) => {
-      trace!("Comparing boolean values");
-      compare_value(path, field, *b1, *b2, b1.to_string().as_str(), b2.to_string().as_str(), matching_context)
-    },
-    (ProtobufFieldData::UInteger32(n1), ProtobufFieldData::UInteger32(n2)) => {
-      trace!("Comparing UInteger32 values");
-      compare_value(path, field, *n1 as u64, *n2 as u64, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
-    },
-    (ProtobufFieldData::Integer32(n1), ProtobufFieldData::Integer32(n2)) => {
-      trace!("Comparing Integer32 values");
-      compare_value(path, field, *n1, *n2, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
-    },
-    (ProtobufFieldData::UInteger64(n1), ProtobufFieldData::UInteger64(n2)) => {
-      trace!("Comparing UInteger64 values");
-      compare_value(path, field, *n1, *n2, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
-    },
-    (ProtobufFieldData::Integer64(n1), ProtobufFieldData::Integer64(n2)) => {
-      trace!("Comparing Integer64 values");
-      compare_value(path, field, *n1, *n2, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
-    },
-    (ProtobufFieldData::Float(n1), ProtobufFieldData::Float(n2)) => {
-      trace!("Comparing Float values");
-      compare_value(path, field, *n1 as f64, *n2 as f64, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
-    },
-    (ProtobufFieldData::Double(n1), ProtobufFieldData::Double(n2)) => {
-      trace!("Comparing Double values");
-      compare_value(path, field, *n1, *n2, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
-    },
-    (ProtobufFieldData::Bytes(b1), ProtobufFieldData::Bytes(b2)) => {
-      trace!("Comparing byte arrays");
-      let b1_str = display_bytes(b1);
-      let b2_str = display_bytes(b2);
-      compare_value(path, field, b1.as_slice(), b2.as_slice(), b1_str.as_str(), b2_str.as_str(), matching_context)
-    },
-    (ProtobufFieldData::Enum(b1, descriptor), ProtobufFieldData::Enum(b2, _)) => {
-      trace!("Comparing Enum values");
-      let enum_1 = enum_name(*b1, descriptor);
-      let enum_2 = enum_name(*b2, descriptor);
-      compare_value(path, field, &enum_1, &enum_2, enum_1.as_str(), enum_2.as_str(), matching_context)
-    },
-    (ProtobufFieldData::Message(b1, message_descriptor), ProtobufFieldData::Message(b2, _)) => {
-      trace!("Comparing embedded messages");
-      let mut expected_bytes = BytesMut::from(b1.as_slice());
-      let expected_message = match decode_message(&mut expected_bytes, message_descriptor, descriptors) {
-        Ok(message) => message,
-        Err(err) => {
-          return vec![
-            BodyMismatch {
-              path: path.to_string(),
-              expected: Some(field.data.to_string().into()),
-              actual: Some(actual.data.to_string().into()),
-              mismatch: format!("Could not decode expected message field {} - {}", descriptor.name.clone().unwrap_or_else(|| "unknown".to_string()), err)
-            }
-          ];
+) -> (String, Vec<Mismatch>) {
+  let field_name = field_descriptor.name
+    .clone()
+    .unwrap_or_else(|| {
+      warn!("Field number {} does not have a field name in the descriptor, will use the number", field_no);
+      field_no.to_string()
+    });
+  let field_path = path.join(&field_name);
+  trace!(%field_name, field_no, "Comparing message field {:?} => {:?}", expected, actual);
+
+  if is_redacted_field(message_descriptor, &field_name, matching_context, descriptors) {
+    trace!(%field_name, field_no, "field is redacted, not comparing it");
+    return (field_path.to_string(), vec![]);
+  }
+
+  if let Some(mismatches) = compare_not_empty(&field_path, &field_name, field_descriptor, &actual,
+    message_descriptor, matching_context) {
+    return (field_path.to_string(), mismatches);
+  }
+
+  if is_empty_or_unset(message_descriptor, field_descriptor, &expected, &actual, &field_path, matching_context) {
+    trace!(%field_name, field_no, "repeated field is empty or unset in both messages");
+    return (field_path.to_string(), vec![]);
+  }
+
+  let mismatches = if is_map_field(message_descriptor, field_descriptor) {
+    trace!(%field_name, field_no, "field is a map field");
+    compare_map_field(&field_path, field_descriptor, expected, actual, matching_context, descriptors)
+  } else if is_repeated_field(field_descriptor) {
+    trace!(%field_name, field_no, "field is a repeated field");
+    let e = expected.iter().map(|f| (*f).clone()).collect_vec();
+    let a = actual.iter().map(|f| (*f).clone()).collect_vec();
+    compare_repeated_field(&field_path, field_descriptor, &e, &a, matching_context, descriptors)
+  } else if let Some(expected_value) = expected.first() {
+    if actual.is_empty() && (treat_defaults_as_present(matching_context) ||
+      has_explicit_field_presence(message_descriptor, &field_name, matching_context, descriptors)) {
+      trace!(%field_name, field_no, "actual field is missing, and the field has explicit presence");
+      vec![
+        BodyMismatch {
+          path: field_path.to_string(),
+          expected: Some(Bytes::from(expected_value.data.as_bytes())),
+          actual: None,
+          mismatch: format!("Expected field '{}' to be set to {}, but it was not received", field_name, expected_value.data)
         }
-      };
-      let mut actual_bytes = BytesMut::from(b2.as_slice());
-      let actual_message = match decode_message(&mut actual_bytes, message_descriptor, descriptors) {
-        Ok(message) => message,
-        Err(err) => {
-          return vec![
-            BodyMismatch {
-              path: path.to_string(),
-              expected: Some(field.data.to_string().into()),
-              actual: Some(actual.data.to_string().into()),
-              mismatch: format!("Could not decode actual message field {} - {}", descriptor.name.clone().unwrap_or_else(|| "unknown".to_string()), err)
-            }
-          ];
-        }
-      };
-      match &descriptor.type_name {
-        Some(name) => match name.as_str() {
-          ".google.protobuf.BytesValue" => {
-            debug!("Field is a Protobuf BytesValue");
-            let expected_field_data = find_message_field_by_name(message_descriptor, expected_message, "value");
-            let actual_field_data = find_message_field_by_name(message_descriptor, actual_message, "value");
-            let b1 = expected_field_data.map(|f| match f.data {
-              ProtobufFieldData::Bytes(b) => b,
-              _ => vec![]
-            }).unwrap_or_default();
-            let b1_str = display_bytes(&b1);
-            let b2 = actual_field_data.map(|f| match f.data {
-              ProtobufFieldData::Bytes(b) => b,
-              _ => vec![]
-            }).unwrap_or_default();
-            let b2_str = display_bytes(&b2);
-            compare_value(path, field, b1, b2, b1_str.as_str(), b2_str.as_str(), matching_context)
-          }
-          ".google.protobuf.Struct" => {
-            debug!("Field is a Protobuf Struct, will compare it as JSON");
-            let expected_json = match field_data_to_json(expected_message, message_descriptor, descriptors) {
-              Ok(j) => j,
-              Err(err) => {
-                return vec![
-                  BodyMismatch {
-                    path: path.to_string(),
-                    expected: Some(field.data.to_string().into()),
-                    actual: Some(actual.data.to_string().into()),
-                    mismatch: format!("Could not decode expected message field {} - {}", descriptor.name.clone().unwrap_or_else(|| "unknown".to_string()), err)
-                  }
-                ];
-              }
-            };
-            let actual_json = match field_data_to_json(actual_message, message_descriptor, descriptors) {
-              Ok(j) => j,
-              Err(err) => {
-                return vec![
-                  BodyMismatch {
-                    path: path.to_string(),
-                    expected: Some(field.data.to_string().into()),
-                    actual: Some(actual.data.to_string().into()),
-                    mismatch: format!("Could not decode actual message field {} - {}", descriptor.name.clone().unwrap_or_else(|| "unknown".to_string()), err)
-                  }
-                ];
-              }
-            };
-
-            match compare_json(path, &expected_json, &actual_json, matching_context) {
-              Ok(_) => vec![],
-              Err(err) => err
-            }
-          }
-          _ => {
-            debug!("Field is a normal message");
-            match compare_message(path.clone(), &expected_message, &actual_message, matching_context, message_descriptor, descriptors) {
-              Ok(result) => match result {
-                BodyMatchResult::Ok => vec![],
-                BodyMatchResult::BodyTypeMismatch { message, .. } => vec![
-                  BodyMismatch {
-                    path: path.to_string(),
-                    expected: Some(name.clone().into()),
-                    actual: Some(name.clone().into()),
-                    mismatch: message
-                  }
-                ],
-                BodyMatchResult::BodyMismatches(mismatches) => mismatches.values().flatten().cloned().collect()
-              }
-              Err(err) => vec![
-                BodyMismatch {
-                  path: path.to_string(),
-                  expected: Some(name.clone().into()),
-                  actual: Some(name.clone().into()),
-                  mismatch: err.to_string()
-                }
-              ]
-            }
-          }
-        }
-        None => vec![
-          BodyMismatch {
-            path: path.to_string(),
-            expected: Some(field.data.to_string().into()),
-            actual: Some(actual.data.to_string().into()),
-            mismatch: format!("Message field {} type name is not set, can not compare it", descriptor.name.clone().unwrap_or_else(|| "unknown".to_string()))
-          }
-        ]
-      }
+      ]
+    } else {
+      let actual_value = actual.first().map(|v| (*v).clone()).unwrap_or_else(|| {
+        // Need to compare against the default values, as gRPC lib may have skipped sending the field if it was a default
+        expected_value.default_field_value(field_descriptor)
+      });
+      compare_field(&field_path, expected_value, field_descriptor, &actual_value, matching_context, descriptors)
     }
-    _ => vec![
+  } else if !actual.is_empty() && matching_context.config() == DiffConfig::NoUnexpectedKeys {
+    trace!(field_name = field_name.as_str(), field_no, "actual field list is not empty");
+    vec![
       BodyMismatch {
-        path: path.to_string(),
-        expected: Some(field.data.to_string().into()),
-        actual: Some(actual.data.to_string().into()),
-        mismatch: format!("Expected and actual field have different types: {} and {}",
-                          field, actual)
+        path: field_path.to_string(),
+        expected: None,
+        actual: actual.first().map(|field_data| Bytes::from(field_data.data.as_bytes())),
+        mismatch: format!("Expected field '{}' to be missing, but received a value for it", field_name)
       }
     ]
-  }
+  } else {
+    vec![]
+  };
+  (field_path.to_string(), mismatches)
 }
 
-/// Compares the actual value to the expected one.
-fn compare_value<T>(
+/// Applies a not empty matcher (`notEmpty`) defined directly against a string, bytes, repeated or
+/// map field. A field that is not set has its default (empty) value, so is also a mismatch. Returns
+/// `None` if the field does not have the matcher or is not one of these kinds of fields, and when
+/// the value is not empty but there are other matchers for the field to apply.
+fn compare_not_empty(
   path: &DocPath,
-  field: &ProtobufField,
-  expected: T,
-  actual: T,
-  expected_str: &str,
-  actual_str: &str,
-  matching_context: &dyn MatchingContext
-) -> Vec<Mismatch> where T: Clone + Debug + Matches<T> {
-  trace!("compare_value({}, {:?}, {}, {})", path, field, expected_str, actual_str);
+  field_name: &str,
+  field_descriptor: &FieldDescriptorProto,
+  actual: &[&ProtobufField],
+  message_descriptor: &DescriptorProto,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Option<Vec<Mismatch>> {
+  if !matching_context.matcher_is_defined(path) {
+    return None;
+  }
+  let rules = field_matchers(path, matching_context);
+  if rules.cascaded || rules.rule_logic != RuleLogic::And || !rules.rules.contains(&MatchingRule::NotEmpty) {
+    return None;
+  }
 
-  if matching_context.matcher_is_defined(path) {
-    debug!("compare_value: Matcher defined for path '{}' and values {:?} -> {:?}", path, expected, actual);
-    match match_values(path, &matching_context.select_best_matcher(path), expected, actual) {
-      Ok(_) => vec![],
-      Err(mismatches) => mismatches.iter().map(|m| BodyMismatch {
-        path: path.to_string(),
-        expected: Some(expected_str.as_bytes().to_vec().into()),
-        actual: Some(actual_str.as_bytes().to_vec().into()),
-        mismatch: m.clone()
-      }).collect()
-    }
+  let empty = if is_map_field(message_descriptor, field_descriptor) || is_repeated_field(field_descriptor) {
+    actual.is_empty()
   } else {
-    debug!("compare_value: No matcher defined for path '{}', using equality", path);
-    match expected.matches_with(actual, &MatchingRule::Equality, false) {
-      Ok(_) => vec![],
-      Err(err) => vec![BodyMismatch {
-        path: path.to_string(),
-        expected: Some(expected_str.as_bytes().to_vec().into()),
-        actual: Some(actual_str.as_bytes().to_vec().into()),
-        mismatch: err.to_string()
-      }]
+    match field_descriptor.r#type() {
+      Type::String | Type::Bytes => actual.first().map(|field| field.data.as_bytes().is_empty()).unwrap_or(true),
+      _ => return None
     }
+  };
+  trace!(%path, empty, "Applied the not empty matcher");
+
+  if empty {
+    Some(vec![
+      BodyMismatch {
+        path: path.to_string(),
+        expected: None,
+        actual: None,
+        mismatch: format!("Expected field '{}' to not be empty", field_name)
+      }
+    ])
+  } else if rules.rules.len() == 1 {
+    Some(vec![])
+  } else {
+    None
   }
 }
 
-/// Compare a repeated field
-#[tracing::instrument(ret, skip_all, fields(%path))]
-fn compare_repeated_field(
-  path: &DocPath,
-  descriptor: &FieldDescriptorProto,
-  expected_fields: &[ProtobufField],
-  actual_fields: &[ProtobufField],
+/// If a field that is set in the expected message must also be set in the actual message, even
+/// if the expected value is the default value (`treatDefaultsAsPresent` interaction
+/// configuration). By default, a missing field is compared using the default value for the field,
+/// as proto3 implementations will not send fields set to their default values.
+fn treat_defaults_as_present(matching_context: &(dyn MatchingContext + Send + Sync)) -> bool {
+  matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(TREAT_DEFAULTS_AS_PRESENT_CONFIG))
+    .map(|value| match value {
+      Value::Bool(b) => *b,
+      Value::String(s) => s.eq_ignore_ascii_case("true"),
+      _ => false
+    })
+    .unwrap_or(false)
+}
+
+/// If the field of a message from a file that uses Protobuf Editions has explicit presence, so a
+/// field set in the expected message must also be received. The edition default for the
+/// `field_presence` feature is explicit presence, and the fields where it resolves to implicit
+/// presence are set in the interaction configuration (`implicitPresenceFields`, see
+/// [with_field_presence]).
+fn has_explicit_field_presence(
+  message_descriptor: &DescriptorProto,
+  field_name: &str,
   matching_context: &(dyn MatchingContext + Send + Sync),
   descriptors: &FileDescriptorSet
-) -> Vec<Mismatch> {
-  trace!(">>> compare_repeated_field({}, {:?}, {:?})", path, expected_fields, actual_fields);
+) -> bool {
+  if !is_editions_message(message_descriptor, descriptors) {
+    return false;
+  }
+  let name = format!("{}.{}", message_full_name(message_descriptor, descriptors).unwrap_or_default(), field_name);
+  !matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(IMPLICIT_PRESENCE_FIELDS_CONFIG))
+    .and_then(|fields| fields.as_array())
+    .map(|fields| fields.iter().any(|field| json_to_string(field) == name))
+    .unwrap_or(false)
+}
 
-  let mut result = vec![];
+/// If only the schema of the actual message is verified (`schemaOnly` interaction configuration).
+/// This only applies to the response, so the request message is always compared with the expected one.
+fn is_schema_only(matching_context: &(dyn MatchingContext + Send + Sync)) -> bool {
+  let config = match matching_context.plugin_configuration().get("protobuf") {
+    Some(config) => &config.interaction_configuration,
+    None => return false
+  };
+  if config.get(MESSAGE_PART_CONFIG).map(json_to_string).as_deref() == Some("request") {
+    return false;
+  }
+  config.get(SCHEMA_ONLY_CONFIG)
+    .map(|value| match value {
+      Value::Bool(b) => *b,
+      Value::String(s) => s.eq_ignore_ascii_case("true"),
+      _ => false
+    })
+    .unwrap_or(false)
+}
 
-  if matching_context.matcher_is_defined(path) {
-    debug!("compare_repeated_field: Matcher defined for path '{}'", path);
-    let rules = matching_context.select_best_matcher(path);
-    for matcher in &rules.rules {
-      if let Err(comparison) = compare_lists_with_matchingrule(matcher, path,
-        expected_fields, actual_fields, matching_context, rules.cascaded, &mut |field_path, expected, actual, context| {
-          let comparison = compare_field(field_path, expected, descriptor, actual, context, descriptors);
-          if comparison.is_empty() {
-            Ok(())
-          } else {
-            Err(comparison)
-          }
-        }) {
-        result.extend(comparison);
+/// Verifies the actual message against the message type only, for when the example values are
+/// not known. The message must decode, and all the fields marked as required (proto2) must be set,
+/// including the fields of any embedded messages that are set.
+pub fn match_schema(
+  message_descriptor: &DescriptorProto,
+  actual_message_bytes: &Bytes,
+  descriptors: &FileDescriptorSet
+) -> BodyMatchResult {
+  let message_name = message_descriptor.name.clone().unwrap_or_default();
+  let mismatches = match decode_message(&mut actual_message_bytes.clone(), message_descriptor, descriptors) {
+    Ok(fields) => missing_required_fields(&DocPath::root(), &fields, message_descriptor, descriptors),
+    Err(err) => vec![
+      BodyMismatch {
+        path: "$".to_string(),
+        expected: None,
+        actual: Some(actual_message_bytes.clone()),
+        mismatch: format!("Expected a valid '{}' message, but it could not be decoded - {}", message_name, err)
       }
-    }
-  } else if expected_fields.is_empty() && !actual_fields.is_empty() {
-    debug!("Expected an empty list, but actual has {} fields", actual_fields.len());
-    result.push(Mismatch::BodyMismatch {
-      path: path.to_string(),
-      expected: None,
-      actual: None,
-      mismatch: format!("Expected repeated field '{}' to be empty but received {} values",
-        descriptor.name.clone().unwrap_or_else(|| descriptor.number.unwrap_or_default().to_string()),
-        actual_fields.len()
-      )
-    })
-  } else {
-    trace!("Comparing repeated fields as a list");
-    result.extend(compare_list_content(path, descriptor, expected_fields, actual_fields, matching_context, descriptors));
-    if expected_fields.len() != actual_fields.len() {
-      result.push(Mismatch::BodyMismatch {
-        path: path.to_string(),
+    ]
+  };
+  add_mismatches(BodyMatchResult::Ok, mismatches)
+}
+
+fn missing_required_fields(
+  path: &DocPath,
+  fields: &[ProtobufField],
+  message_descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> Vec<Mismatch> {
+  let mut mismatches = vec![];
+  for field_descriptor in &message_descriptor.field {
+    let field_name = field_descriptor.name.clone().unwrap_or_default();
+    let field_path = path.join(field_name.as_str());
+    let values = fields.iter()
+      .filter(|field| Some(field.field_num as i32) == field_descriptor.number)
+      .collect_vec();
+    if values.is_empty() && field_descriptor.label() == Label::Required {
+      mismatches.push(BodyMismatch {
+        path: field_path.to_string(),
         expected: None,
         actual: None,
-        mismatch: format!("Expected repeated field '{}' to have {} values but received {} values",
-          descriptor.name.clone().unwrap_or_else(|| descriptor.number.unwrap_or_default().to_string()),
-          expected_fields.len(),
-          actual_fields.len()
-        )
-      })
+        mismatch: format!("Expected required field '{}' to be set, but it was missing", field_name)
+      });
+    }
+    for value in values {
+      if let ProtobufFieldData::Message(bytes, descriptor) = &value.data {
+        match decode_message(&mut BytesMut::from(bytes.as_slice()), descriptor, descriptors) {
+          Ok(embedded) => mismatches.extend(missing_required_fields(&field_path, &embedded, descriptor, descriptors)),
+          Err(err) => mismatches.push(BodyMismatch {
+            path: field_path.to_string(),
+            expected: None,
+            actual: Some(Bytes::from(bytes.clone())),
+            mismatch: format!("Expected a valid '{}' message for field '{}', but it could not be decoded - {}",
+              descriptor.name.clone().unwrap_or_default(), field_name, err)
+          })
+        }
+      }
     }
   }
+  mismatches
+}
 
-  result
+/// Applies the size constraints (`serializedSize` interaction configuration) and the regex
+/// configured for the serialized form of the message (`serializedRegex` interaction configuration)
+/// to the actual message bytes, adding any mismatch to the result.
+pub(crate) fn match_serialized_message(
+  result: BodyMatchResult,
+  actual_message_bytes: &[u8],
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> anyhow::Result<BodyMatchResult> {
+  let result = match_serialized_size(result, actual_message_bytes, matching_context)?;
+  let config = match matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(SERIALIZED_REGEX_CONFIG)) {
+    Some(config) => config.clone(),
+    None => return Ok(result)
+  };
+  let (regex, encoding) = match &config {
+    Value::Object(map) => (
+      map.get("regex").map(json_to_string).unwrap_or_default(),
+      map.get("encoding").map(json_to_string).unwrap_or_else(|| "hex".to_string())
+    ),
+    _ => (json_to_string(&config), "hex".to_string())
+  };
+  let serialized = match encoding.to_lowercase().as_str() {
+    "hex" => as_hex(actual_message_bytes),
+    "base64" => BASE64.encode(actual_message_bytes),
+    _ => return Err(anyhow!("'{}' is not a valid encoding for {}, it must be either 'hex' or 'base64'", encoding, SERIALIZED_REGEX_CONFIG))
+  };
+  let re = Regex::new(regex.as_str())
+    .map_err(|err| anyhow!("'{}' is not a valid regular expression for {} - {}", regex, SERIALIZED_REGEX_CONFIG, err))?;
+  trace!(%encoding, %serialized, "Matching the serialized message against '{}'", regex);
+
+  if re.is_match(serialized.as_str()) {
+    Ok(result)
+  } else {
+    let mismatch = BodyMismatch {
+      path: "$".to_string(),
+      expected: None,
+      actual: Some(Bytes::copy_from_slice(actual_message_bytes)),
+      mismatch: format!("Expected the serialized message ({}) '{}' to match '{}'", encoding, serialized, regex)
+    };
+    match result {
+      BodyMatchResult::BodyMismatches(mut mismatches) => {
+        mismatches.entry("$".to_string()).or_default().push(mismatch);
+        Ok(BodyMatchResult::BodyMismatches(mismatches))
+      }
+      BodyMatchResult::Ok => Ok(BodyMatchResult::BodyMismatches(hashmap!{ "$".to_string() => vec![ mismatch ] })),
+      result => Ok(result)
+    }
+  }
 }
 
-/// Compare a map field
-fn compare_map_field(
-  path: &DocPath,
-  descriptor: &FieldDescriptorProto,
-  expected_fields: Vec<&ProtobufField>,
-  actual_fields: Vec<&ProtobufField>,
-  matching_context: &(dyn MatchingContext + Send + Sync),
-  descriptors: &FileDescriptorSet
-) -> Vec<Mismatch> {
-  trace!(">> compare_map_field('{}', {:?}, {:?})", path, expected_fields, actual_fields);
+/// Checks the number of bytes of the serialized actual message against the minimum and maximum
+/// sizes configured with `serializedSize`, adding any mismatch to the result.
+fn match_serialized_size(
+  result: BodyMatchResult,
+  actual_message_bytes: &[u8],
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> anyhow::Result<BodyMatchResult> {
+  let (min, max) = match matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(SERIALIZED_SIZE_CONFIG)) {
+    Some(Value::Object(map)) => (json_to_num(map.get("min").cloned()), json_to_num(map.get("max").cloned())),
+    Some(value) => return Err(anyhow!("{} must be a map with the min and/or max number of bytes, got {}", SERIALIZED_SIZE_CONFIG, value)),
+    None => return Ok(result)
+  };
 
-  let mut result = vec![];
+  let size = actual_message_bytes.len();
+  trace!(size, ?min, ?max, "Checking the size of the serialized message");
+  let mut mismatches = vec![];
+  if let Some(min) = min {
+    if size < min {
+      mismatches.push(BodyMismatch {
+        path: "$".to_string(),
+        expected: None,
+        actual: Some(Bytes::copy_from_slice(actual_message_bytes)),
+        mismatch: format!("Expected the serialized message to be at least {} bytes, but it was {} bytes", min, size)
+      });
+    }
+  }
+  if let Some(max) = max {
+    if size > max {
+      mismatches.push(BodyMismatch {
+        path: "$".to_string(),
+        expected: None,
+        actual: Some(Bytes::copy_from_slice(actual_message_bytes)),
+        mismatch: format!("Expected the serialized message to be at most {} bytes, but it was {} bytes", max, size)
+      });
+    }
+  }
 
-  if expected_fields.is_empty() && !actual_fields.is_empty() && matching_context.config() == DiffConfig::NoUnexpectedKeys {
-    result.push(Mismatch::BodyMismatch {
-      path: path.to_string(),
-      expected: None,
-      actual: None,
-      mismatch: format!("Expected repeated field '{}' to be empty but received {} values",
-        descriptor.name.clone().unwrap_or_else(|| descriptor.number.unwrap_or_default().to_string()),
-        actual_fields.len()
-      )
-    });
-  } else {
-    let expected_map = expected_fields.iter()
-      .filter_map(|f| {
-        match &f.data {
-          ProtobufFieldData::Message(d, descriptor) => decode_message_map_entry(descriptor, d, descriptors).ok(),
-          _ => None
-        }
-      })
-      .collect::<BTreeMap<String, MapEntry>>();
-    let actual_map = actual_fields.iter()
-      .filter_map(|f| {
-        match &f.data {
-          ProtobufFieldData::Message(d, descriptor) => decode_message_map_entry(descriptor, d, descriptors).ok(),
-          _ => None
+  Ok(add_mismatches(result, mismatches))
+}
+
+/// Value of a field that can be compared to the value of another field
+#[derive(Clone, Debug, PartialEq)]
+enum RelationValue {
+  Integer(i128),
+  Float(f64),
+  Text(String)
+}
+
+impl PartialOrd for RelationValue {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    match (self, other) {
+      (RelationValue::Integer(a), RelationValue::Integer(b)) => a.partial_cmp(b),
+      (RelationValue::Integer(a), RelationValue::Float(b)) => (*a as f64).partial_cmp(b),
+      (RelationValue::Float(a), RelationValue::Integer(b)) => a.partial_cmp(&(*b as f64)),
+      (RelationValue::Float(a), RelationValue::Float(b)) => a.partial_cmp(b),
+      (RelationValue::Text(a), RelationValue::Text(b)) => a.partial_cmp(b),
+      _ => None
+    }
+  }
+}
+
+impl Display for RelationValue {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      RelationValue::Integer(n) => write!(f, "{}", n),
+      RelationValue::Float(n) => write!(f, "{}", n),
+      RelationValue::Text(s) => write!(f, "'{}'", s)
+    }
+  }
+}
+
+/// Looks up the values of the field at the path (made up of only field names) in the message,
+/// decoding any embedded messages along the path. Returns the values (there will be more than one
+/// for a repeated field), the descriptor for the field and the descriptor of the message it is in.
+fn field_values_at_path(
+  path: &str,
+  message: &[ProtobufField],
+  message_descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<(Vec<ProtobufField>, FieldDescriptorProto, DescriptorProto)> {
+  let doc_path = DocPath::new(path)?;
+  let names = doc_path.tokens().iter()
+    .filter(|token| !matches!(token, PathToken::Root))
+    .map(|token| match token {
+      PathToken::Field(name) => Ok(name.clone()),
+      _ => Err(anyhow!("'{}' is not a valid path, only field names are supported", path))
+    })
+    .collect::<anyhow::Result<Vec<_>>>()?;
+  let (field_name, parents) = names.split_last()
+    .ok_or_else(|| anyhow!("'{}' does not refer to a field", path))?;
+
+  let mut fields = message.to_vec();
+  let mut descriptor = message_descriptor.clone();
+  for name in parents {
+    let field_descriptor = descriptor.field.iter()
+      .find(|field| field.name.as_deref() == Some(name.as_str()))
+      .ok_or_else(|| anyhow!("Message {} does not have a field '{}'", descriptor.name.clone().unwrap_or_default(), name))?;
+    match fields.iter().rev().find(|field| field.field_num as i32 == field_descriptor.number.unwrap_or_default()) {
+      Some(ProtobufField { data: ProtobufFieldData::Message(bytes, embedded_descriptor), .. }) => {
+        let mut bytes = BytesMut::from(bytes.as_slice());
+        let embedded = decode_message(&mut bytes, embedded_descriptor, descriptors)?;
+        descriptor = embedded_descriptor.clone();
+        fields = embedded;
+      }
+      Some(_) => return Err(anyhow!("Field '{}' in '{}' is not a message field", name, path)),
+      None => return Err(anyhow!("Field '{}' in '{}' is not set", name, path))
+    }
+  }
+
+  let field_descriptor = descriptor.field.iter()
+    .find(|field| field.name.as_deref() == Some(field_name.as_str()))
+    .cloned()
+    .ok_or_else(|| anyhow!("Message {} does not have a field '{}'", descriptor.name.clone().unwrap_or_default(), field_name))?;
+  let values = fields.into_iter()
+    .filter(|field| field.field_num as i32 == field_descriptor.number.unwrap_or_default())
+    .collect();
+  Ok((values, field_descriptor, descriptor))
+}
+
+/// Looks up the value of the field at the path in the message. A field that is not set has its
+/// default value. `google.protobuf.Timestamp` and `google.protobuf.Duration` fields are compared
+/// using their total number of nanoseconds.
+fn relation_value(
+  path: &str,
+  message: &[ProtobufField],
+  message_descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<RelationValue> {
+  let (values, field_descriptor, descriptor) = field_values_at_path(path, message, message_descriptor, descriptors)?;
+  let data = match values.last() {
+    Some(field) => field.data.clone(),
+    None => default_field_data(&field_descriptor, &descriptor, descriptors)
+      .map(|(data, _)| data)
+      .ok_or_else(|| anyhow!("Field '{}' is not set", path))?
+  };
+  match data {
+    ProtobufFieldData::Boolean(b) => Ok(RelationValue::Integer(b as i128)),
+    ProtobufFieldData::UInteger32(n) => Ok(RelationValue::Integer(n as i128)),
+    ProtobufFieldData::Integer32(n) => Ok(RelationValue::Integer(n as i128)),
+    ProtobufFieldData::UInteger64(n) => Ok(RelationValue::Integer(n as i128)),
+    ProtobufFieldData::Integer64(n) => Ok(RelationValue::Integer(n as i128)),
+    ProtobufFieldData::Enum(n, _) => Ok(RelationValue::Integer(n as i128)),
+    ProtobufFieldData::Float(n) => Ok(RelationValue::Float(n as f64)),
+    ProtobufFieldData::Double(n) => Ok(RelationValue::Float(n)),
+    ProtobufFieldData::String(s) => Ok(RelationValue::Text(s)),
+    ProtobufFieldData::Message(bytes, embedded_descriptor)
+      if matches!(embedded_descriptor.name.as_deref(), Some("Timestamp") | Some("Duration")) => {
+      let mut bytes = BytesMut::from(bytes.as_slice());
+      let embedded = decode_message(&mut bytes, &embedded_descriptor, descriptors)?;
+      let seconds = embedded.iter().rev().find(|field| field.field_num == 1)
+        .map(|field| match field.data {
+          ProtobufFieldData::Integer64(n) => n as i128,
+          _ => 0
+        })
+        .unwrap_or_default();
+      let nanos = embedded.iter().rev().find(|field| field.field_num == 2)
+        .map(|field| match field.data {
+          ProtobufFieldData::Integer32(n) => n as i128,
+          _ => 0
+        })
+        .unwrap_or_default();
+      Ok(RelationValue::Integer(seconds * 1_000_000_000 + nanos))
+    }
+    data => Err(anyhow!("Field '{}' ({}) can not be used in a field relation", path, data.type_name()))
+  }
+}
+
+/// Checks the relations configured between fields of the actual message (`fieldRelations`
+/// interaction configuration), adding a mismatch to the result for each one that does not hold.
+/// A relation is an operator (`==`, `!=`, `<`, `<=`, `>` or `>=`) and the path to the other field.
+fn match_field_relations(
+  result: BodyMatchResult,
+  actual_message: &[ProtobufField],
+  message_descriptor: &DescriptorProto,
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<BodyMatchResult> {
+  let relations = match scoped_config(matching_context, FIELD_RELATIONS_CONFIG) {
+    Some(Value::Object(relations)) => relations,
+    Some(value) => return Err(anyhow!("{} must be a map of field paths to relations, got {}", FIELD_RELATIONS_CONFIG, value)),
+    None => return Ok(result)
+  };
+
+  let field_path = |path: &str| if path.starts_with('$') { path.to_string() } else { format!("$.{}", path) };
+  let mut mismatches = vec![];
+  for (path, relation) in &relations {
+    let relation = json_to_string(relation);
+    let captures = FIELD_RELATION.captures(relation.as_str())
+      .ok_or_else(|| anyhow!("'{}' is not a valid relation for field '{}', it must be an operator (==, !=, <, <=, > or >=) followed by the path to another field", relation, path))?;
+    let operator = captures.get(1).map(|m| m.as_str()).unwrap_or_default();
+    let path = field_path(path);
+    let other_path = field_path(captures.get(2).map(|m| m.as_str()).unwrap_or_default());
+    let values = relation_value(path.as_str(), actual_message, message_descriptor, descriptors)
+      .and_then(|value| relation_value(other_path.as_str(), actual_message, message_descriptor, descriptors)
+        .map(|other_value| (value, other_value)));
+    let (value, other_value) = match values {
+      Ok(values) => values,
+      Err(err) => {
+        mismatches.push(BodyMismatch {
+          path: path.clone(),
+          expected: None,
+          actual: None,
+          mismatch: format!("Could not check the relation '{}' for field '{}' - {}", relation, path, err)
+        });
+        continue;
+      }
+    };
+    trace!(%path, %operator, %other_path, "Checking field relation {} {} {}", value, operator, other_value);
+
+    let holds = match value.partial_cmp(&other_value) {
+      Some(ordering) => match operator {
+        "==" => ordering == Ordering::Equal,
+        "!=" => ordering != Ordering::Equal,
+        "<" => ordering == Ordering::Less,
+        "<=" => ordering != Ordering::Greater,
+        ">" => ordering == Ordering::Greater,
+        _ => ordering != Ordering::Less
+      },
+      None => false
+    };
+    if !holds {
+      mismatches.push(BodyMismatch {
+        path: path.clone(),
+        expected: Some(other_value.to_string().into()),
+        actual: Some(value.to_string().into()),
+        mismatch: format!("Expected field '{}' ({}) to be {} field '{}' ({})", path, value, operator, other_path, other_value)
+      });
+    }
+  }
+
+  Ok(add_mismatches(result, mismatches))
+}
+
+/// Returns the interaction configuration value for the key. The value can be scoped to the part of
+/// the interaction being compared (i.e. `{ "request": ..., "response": ... }`), in which case only
+/// the value for that part is returned. Without a part (i.e. the message of a message
+/// interaction), the value for the response is used.
+fn scoped_config(matching_context: &(dyn MatchingContext + Send + Sync), key: &str) -> Option<Value> {
+  let config = &matching_context.plugin_configuration().get("protobuf")?.interaction_configuration;
+  match config.get(key)? {
+    Value::Object(parts) if is_scoped_value(parts) => {
+      let part = config.get(MESSAGE_PART_CONFIG).map(json_to_string).unwrap_or_else(|| "response".to_string());
+      parts.get(part.as_str()).cloned()
+    }
+    value => Some(value.clone())
+  }
+}
+
+/// If the configuration value is keyed by the part of the interaction (`request` or `response`)
+fn is_scoped_value(value: &serde_json::Map<String, Value>) -> bool {
+  !value.is_empty() && value.iter()
+    .all(|(key, value)| (key == "request" || key == "response") && (value.is_object() || value.is_array()))
+}
+
+/// Checks the groups of fields configured for the interaction (`presenceGroups` interaction
+/// configuration, which can be scoped to the request or response), adding a mismatch to the result
+/// for each group where none of the fields are set in the actual message. A field in an embedded
+/// message that is not set is also not set.
+fn match_presence_groups(
+  result: BodyMatchResult,
+  actual_message: &[ProtobufField],
+  message_descriptor: &DescriptorProto,
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<BodyMatchResult> {
+  let groups = match scoped_config(matching_context, PRESENCE_GROUPS_CONFIG) {
+    Some(Value::Array(groups)) => groups,
+    Some(value) => return Err(anyhow!("{} must be a list of groups of field paths, got {}", PRESENCE_GROUPS_CONFIG, value)),
+    None => return Ok(result)
+  };
+
+  let field_path = |path: &str| if path.starts_with('$') { path.to_string() } else { format!("$.{}", path) };
+  let mut mismatches = vec![];
+  for group in &groups {
+    let paths = match group {
+      Value::Array(paths) => paths.iter().map(|path| field_path(json_to_string(path).as_str())).collect_vec(),
+      _ => return Err(anyhow!("Presence group must be a list of field paths, got {}", group))
+    };
+    let any_set = paths.iter().any(|path| {
+      match field_values_at_path(path, actual_message, message_descriptor, descriptors) {
+        Ok((values, _, _)) => !values.is_empty(),
+        Err(err) => {
+          trace!(%path, "Field is not set - {}", err);
+          false
         }
-      })
-      .collect::<BTreeMap<String, MapEntry>>();
+      }
+    });
+    trace!(?paths, any_set, "Checked presence group");
+    if !any_set {
+      let fields = paths.iter().map(|path| format!("'{}'", path)).join(", ");
+      mismatches.push(BodyMismatch {
+        path: "$".to_string(),
+        expected: None,
+        actual: None,
+        mismatch: format!("Expected at least one of the fields {} to be set, but none were", fields)
+      });
+    }
+  }
 
-    if matching_context.matcher_is_defined(path) {
-      debug!("compare_map_field: matcher defined for path '{}'", path);
-      let rules = matching_context.select_best_matcher(path);
-      for matcher in &rules.rules {
-        trace!("compare_map_field: matcher = {:?}", matcher);
-        if let Err(comparison) = compare_maps_with_matchingrule(matcher, rules.cascaded, path,
-          &expected_map, &actual_map, matching_context, &mut |field_path, expected, actual, context| {
-            let comparison = compare_field(field_path, &expected.value, &expected.field_descriptor, &actual.value, context, descriptors);
-            if comparison.is_empty() {
-              Ok(())
-            } else {
-              Err(comparison)
-            }
-          }) {
-          result.extend(comparison);
+  Ok(add_mismatches(result, mismatches))
+}
+
+/// Adds the mismatches to the result, keyed by the path of each mismatch
+fn add_mismatches(result: BodyMatchResult, mismatches: Vec<Mismatch>) -> BodyMatchResult {
+  if mismatches.is_empty() {
+    return result;
+  }
+  match result {
+    BodyMatchResult::BodyMismatches(mut results) => {
+      for mismatch in mismatches {
+        if let BodyMismatch { path, .. } = &mismatch {
+          results.entry(path.clone()).or_default().push(mismatch);
         }
       }
-    } else {
-      debug!("compare_map_field: no matcher defined for path '{}'", path);
-      debug!("                   expected keys {:?}", expected_map.keys());
-      debug!("                   actual keys {:?}", actual_map.keys());
-      let expected_keys = expected_map.keys().cloned().collect();
-      let actual_keys = actual_map.keys().cloned().collect();
-      if let Err(mismatches) = matching_context.match_keys(path, &expected_keys, &actual_keys) {
-        result.extend(mismatches);
+      BodyMatchResult::BodyMismatches(results)
+    }
+    BodyMatchResult::Ok => BodyMatchResult::BodyMismatches(mismatches.into_iter()
+      .into_group_map_by(|mismatch| match mismatch {
+        BodyMismatch { path, .. } => path.clone(),
+        _ => "$".to_string()
+      })),
+    result => result
+  }
+}
+
+/// Returns the value of the field as a string, for use with filtered matchers. Enum values are
+/// their names.
+fn field_value_string(data: &ProtobufFieldData) -> String {
+  match data {
+    ProtobufFieldData::String(s) => s.clone(),
+    ProtobufFieldData::Enum(n, descriptor) => enum_name(*n, descriptor),
+    _ => data.to_string()
+  }
+}
+
+/// Applies the matchers configured for the elements of repeated message fields that match a
+/// predicate (`filteredMatchers` interaction configuration). The selector is the path to the
+/// repeated field, a predicate on a field of the element (`==` or `!=` a value) and the path
+/// to the field in the element to apply the matcher to. The values are compared as strings.
+fn match_filtered_matchers(
+  result: BodyMatchResult,
+  actual_message: &[ProtobufField],
+  message_descriptor: &DescriptorProto,
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<BodyMatchResult> {
+  let filtered_matchers = match matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(FILTERED_MATCHERS_CONFIG)) {
+    Some(Value::Object(matchers)) => matchers.clone(),
+    Some(value) => return Err(anyhow!("{} must be a map of selectors to matcher definitions, got {}", FILTERED_MATCHERS_CONFIG, value)),
+    None => return Ok(result)
+  };
+
+  let mut mismatches = vec![];
+  for (selector, definition) in &filtered_matchers {
+    let captures = FILTERED_SELECTOR.captures(selector.as_str())
+      .ok_or_else(|| anyhow!("'{}' is not a valid selector, it must be in the form $.field[?(@.field == 'value')].field", selector))?;
+    let path = &captures["path"];
+    let predicate_path = format!("$.{}", &captures["field"]);
+    let operator = &captures["op"];
+    let predicate_value = captures["value"].trim_matches(|c| c == '\'' || c == '"');
+    let target_path = format!("$.{}", &captures["target"]);
+    let definition = parse_matcher_def(json_to_string(definition).as_str())?;
+    let rules = RuleList {
+      rules: definition.rules.iter().filter_map(|rule| rule.clone().left()).collect(),
+      rule_logic: RuleLogic::And,
+      cascaded: false
+    };
+
+    let (elements, _, _) = field_values_at_path(path, actual_message, message_descriptor, descriptors)?;
+    for (index, element) in elements.iter().enumerate() {
+      let (bytes, element_descriptor) = match &element.data {
+        ProtobufFieldData::Message(bytes, element_descriptor) => (bytes, element_descriptor),
+        _ => return Err(anyhow!("'{}' in selector '{}' is not a repeated message field", path, selector))
+      };
+      let mut bytes = BytesMut::from(bytes.as_slice());
+      let fields = decode_message(&mut bytes, element_descriptor, descriptors)?;
+
+      let (values, field_descriptor, descriptor) = field_values_at_path(predicate_path.as_str(), &fields, element_descriptor, descriptors)?;
+      let value = values.last().map(|field| field.data.clone())
+        .or_else(|| default_field_data(&field_descriptor, &descriptor, descriptors).map(|(data, _)| data))
+        .map(|data| field_value_string(&data))
+        .unwrap_or_default();
+      if (operator == "==") != (value == predicate_value) {
+        trace!(%selector, index, "Element does not match the predicate, skipping it");
+        continue;
       }
-      for (key, value) in &expected_map {
-        let entry_path = path.join(key);
-        if let Some(actual) = actual_map.get(key.as_str()) {
-          result.extend(compare_field(&entry_path, &value.value, &value.field_descriptor, &actual.value, matching_context, descriptors));
-        } else {
-          result.push(Mismatch::BodyMismatch {
-            path: path.to_string(),
-            expected: None,
-            actual: None,
-            mismatch: format!("Expected map field '{}' to have entry '{}', but was missing",
-              descriptor.name.clone().unwrap_or_else(|| descriptor.number.unwrap_or_default().to_string()),
-              key
-            )
+
+      let (values, field_descriptor, descriptor) = field_values_at_path(target_path.as_str(), &fields, element_descriptor, descriptors)?;
+      let actual = values.last().map(|field| field.data.clone())
+        .or_else(|| default_field_data(&field_descriptor, &descriptor, descriptors).map(|(data, _)| data))
+        .map(|data| field_value_string(&data))
+        .unwrap_or_default();
+      let element_path = format!("{}[{}].{}", path, index, &captures["target"]);
+      let expected = definition.value.clone();
+      if let Err(errors) = match_values(&DocPath::new(element_path.as_str())?, &rules, &expected, &actual) {
+        for error in errors {
+          mismatches.push(BodyMismatch {
+            path: element_path.clone(),
+            expected: Some(expected.clone().into()),
+            actual: Some(actual.clone().into()),
+            mismatch: error
           });
         }
       }
     }
   }
 
-  result
+  Ok(add_mismatches(result, mismatches))
 }
 
-/// Struct to represent a protobuf map entry
-#[derive(Clone, Debug)]
-struct MapEntry {
-  pub field_descriptor: FieldDescriptorProto,
-  pub value: ProtobufField
+/// Returns the message type configured for a `google.protobuf.BytesValue` field at the given path
+/// (`bytesValueTypes` interaction configuration), if the bytes contain an embedded Protobuf message.
+fn bytes_value_type(path: &DocPath, matching_context: &(dyn MatchingContext + Send + Sync)) -> Option<String> {
+  matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(BYTES_VALUE_TYPES_CONFIG))
+    .and_then(|value| match value {
+      Value::Object(map) => map.get(&path.to_string()).or_else(|| map.get(path.to_string().trim_start_matches("$.")))
+        .and_then(|t| t.as_str())
+        .map(|t| t.to_string()),
+      _ => None
+    })
 }
 
-impl Display for MapEntry {
-  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-    write!(f, "{}", self.value)
-  }
+/// Returns the type URLs configured as acceptable for a `google.protobuf.Any` field at the given path
+/// (`anyTypes` interaction configuration). The value can be a single type URL or a list of them.
+fn any_type_urls(path: &DocPath, matching_context: &(dyn MatchingContext + Send + Sync)) -> Option<Vec<String>> {
+  matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(ANY_TYPES_CONFIG))
+    .and_then(|value| match value {
+      Value::Object(map) => map.get(&path.to_string()).or_else(|| map.get(path.to_string().trim_start_matches("$.")))
+        .map(|urls| match urls {
+          Value::Array(list) => list.iter().map(json_to_string).collect(),
+          _ => vec![ json_to_string(urls) ]
+        }),
+      _ => None
+    })
 }
 
-/// Decodes an embedded entry message into a key and value part
-fn decode_message_map_entry(
-  descriptor: &DescriptorProto,
-  data: &[u8],
-  descriptors: &FileDescriptorSet
-) -> anyhow::Result<(String, MapEntry)> {
-  let message = decode_message(&mut BytesMut::from(data), descriptor, descriptors)?;
-  let key = message.iter().find(|field| field.field_num == 1)
-    .ok_or_else(|| anyhow!("Did not find the key value when decoding map entry {}", descriptor.name.clone().unwrap_or_else(|| "unknown".to_string())))?;
-  let value = message.iter().find(|field| field.field_num == 2)
-    .ok_or_else(|| anyhow!("Did not find the value value when decoding map entry {}", descriptor.name.clone().unwrap_or_else(|| "unknown".to_string())))?;
-  let value_descriptor = find_field_descriptor(value, descriptor)
-    .ok_or_else(|| anyhow!("Did not find the field descriptor for the value field of the map entry"))?;
-  let key_str = match &key.data {
-    ProtobufFieldData::String(s) => s.clone(),
-    _ => key.data.to_string()
+/// Returns the type URL and value from the fields of a decoded `google.protobuf.Any` message
+fn any_type_url_and_value(descriptor: &DescriptorProto, fields: &[ProtobufField]) -> (String, Vec<u8>) {
+  let type_url = match find_message_field_by_name(descriptor, fields.to_vec(), "type_url").map(|f| f.data) {
+    Some(ProtobufFieldData::String(url)) => url,
+    _ => String::default()
+  };
+  let value = match find_message_field_by_name(descriptor, fields.to_vec(), "value").map(|f| f.data) {
+    Some(ProtobufFieldData::Bytes(value)) => value,
+    _ => vec![]
   };
-  Ok((key_str, MapEntry { field_descriptor: value_descriptor, value: value.clone() } ))
+  (type_url, value)
 }
 
-/// Compares the items in the actual list against the expected
-#[tracing::instrument(ret, skip_all, fields(%path))]
-fn compare_list_content(
+/// Compares a `google.protobuf.Any` field that can contain any of the given types. The actual type
+/// URL must be one of them. If it is the same type as the expected message, the embedded messages
+/// are compared, otherwise the actual message only needs to be a valid message of its type.
+fn compare_any(
   path: &DocPath,
-  descriptor: &FieldDescriptorProto,
+  type_urls: &[String],
+  descriptor: &DescriptorProto,
   expected: &[ProtobufField],
   actual: &[ProtobufField],
   matching_context: &(dyn MatchingContext + Send + Sync),
   descriptors: &FileDescriptorSet
 ) -> Vec<Mismatch> {
-  let mut result = vec![];
-  for (index, value) in expected.iter().enumerate() {
-    let ps = index.to_string();
-    debug!("Comparing list item {} with value '{:?}' to '{:?}'", index, actual.get(index), value);
-    let p = path.join(ps);
-    if index < actual.len() {
-      result.extend(compare_field(&p, value, descriptor, actual.get(index).unwrap(), matching_context, descriptors));
-    } else if !matching_context.matcher_is_defined(&p) {
-      result.push(Mismatch::BodyMismatch {
+  let (expected_type_url, expected_value) = any_type_url_and_value(descriptor, expected);
+  let (actual_type_url, actual_value) = any_type_url_and_value(descriptor, actual);
+
+  if !type_urls.contains(&actual_type_url) {
+    return vec![
+      BodyMismatch {
         path: path.to_string(),
-        expected: Some(Bytes::from(value.data.to_string())),
-        actual: None,
-        mismatch: format!("Expected field {}({})={} but was missing", descriptor.name.clone().unwrap_or_default(), value.field_num, value.data)
+        expected: Some(type_urls.join(", ").into()),
+        actual: Some(actual_type_url.clone().into()),
+        mismatch: format!("Expected the Any type URL to be one of [{}], but was '{}'", type_urls.join(", "), actual_type_url)
+      }
+    ];
+  }
+
+  let type_name = actual_type_url.split('/').last().unwrap_or_default();
+  if actual_type_url == expected_type_url {
+    trace!(%type_name, "Any field has the expected type, comparing the embedded messages");
+    compare_embedded_message(path, type_name, &expected_value, &actual_value, matching_context, descriptors)
+  } else {
+    trace!(%type_name, "Any field has a different acceptable type, checking it is a valid message");
+    let message_name = type_name.split('.').last().unwrap_or(type_name);
+    let result = find_message_type_by_name(message_name, descriptors)
+      .and_then(|(message_descriptor, _)| {
+        let mut bytes = BytesMut::from(actual_value.as_slice());
+        decode_message(&mut bytes, &message_descriptor, descriptors)
       });
+    match result {
+      Ok(_) => vec![],
+      Err(err) => vec![
+        BodyMismatch {
+          path: path.to_string(),
+          expected: Some(expected_type_url.into()),
+          actual: Some(actual_type_url.clone().into()),
+          mismatch: format!("Could not decode the actual embedded {} message - {}", type_name, err)
+        }
+      ]
     }
   }
-  result
 }
 
-/// Find the field descriptor in the message descriptor for the given field value
-fn find_field_descriptor(field: &ProtobufField, descriptor: &DescriptorProto) -> Option<FieldDescriptorProto> {
-  descriptor.field.iter()
-    .find(|field_desc| field_desc.number.unwrap_or_default() == field.field_num as i32)
-    .cloned()
+/// Returns how unknown enum values are treated (`unknownEnumValues` interaction configuration)
+fn unknown_enum_values(matching_context: &(dyn MatchingContext + Send + Sync)) -> anyhow::Result<Option<UnknownEnumValues>> {
+  matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(UNKNOWN_ENUM_VALUES_CONFIG))
+    .map(|value| UnknownEnumValues::parse(json_to_string(value).as_str()))
+    .transpose()
 }
 
-#[cfg(test)]
-mod tests {
-  use base64::Engine;
-  use base64::engine::general_purpose::STANDARD as BASE64;
-  use expectest::prelude::*;
-  use pact_models::matchingrules::expressions::{MatchingRuleDefinition, ValueType};
-  use pact_models::{matchingrules, matchingrules_list};
-  use prost::encoding::WireType;
-  use prost::Message;
-  use prost_types::{DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto, FileDescriptorSet, MessageOptions};
-  use prost_types::field_descriptor_proto::Label::{Optional, Repeated};
-  use prost_types::field_descriptor_proto::Type::{Enum, String};
-
-  use crate::message_decoder::ProtobufField;
+/// Returns the name of the custom matcher configured for the field at the given path
+/// (`customMatchers` interaction configuration)
+fn custom_matcher_name(path: &DocPath, matching_context: &(dyn MatchingContext + Send + Sync)) -> Option<String> {
+  matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(CUSTOM_MATCHERS_CONFIG))
+    .and_then(|value| match value {
+      Value::Object(map) => map.get(&path.to_string()).or_else(|| map.get(path.to_string().trim_start_matches("$.")))
+        .and_then(|name| name.as_str())
+        .map(|name| name.to_string()),
+      _ => None
+    })
+}
 
-  use super::*;
+/// Compares the field values using the registered custom matcher with the given name
+fn apply_custom_matcher(
+  path: &DocPath,
+  name: &str,
+  field: &ProtobufField,
+  actual: &ProtobufField
+) -> Vec<Mismatch> {
+  let matcher = CUSTOM_MATCHERS.lock().unwrap().get(name).cloned();
+  let result = match matcher {
+    Some(matcher) => matcher(&field.data, &actual.data),
+    None => Err(format!("No custom matcher has been registered with the name '{}'", name))
+  };
+  match result {
+    Ok(_) => vec![],
+    Err(mismatch) => vec![BodyMismatch {
+      path: path.to_string(),
+      expected: Some(field.data.to_string().into()),
+      actual: Some(actual.data.to_string().into()),
+      mismatch
+    }]
+  }
+}
 
-  const DESCRIPTORS: &'static str = "CuIFChxnb29nbGUvcHJvdG9idWYvc3RydWN0LnByb3RvEg9nb29\
-    nbGUucHJvdG9idWYimAEKBlN0cnVjdBI7CgZmaWVsZHMYASADKAsyIy5nb29nbGUucHJvdG9idWYuU3RydWN0LkZpZWxkc\
-    0VudHJ5UgZmaWVsZHMaUQoLRmllbGRzRW50cnkSEAoDa2V5GAEgASgJUgNrZXkSLAoFdmFsdWUYAiABKAsyFi5nb29nbGU\
-    ucHJvdG9idWYuVmFsdWVSBXZhbHVlOgI4ASKyAgoFVmFsdWUSOwoKbnVsbF92YWx1ZRgBIAEoDjIaLmdvb2dsZS5wcm90b2\
-    J1Zi5OdWxsVmFsdWVIAFIJbnVsbFZhbHVlEiMKDG51bWJlcl92YWx1ZRgCIAEoAUgAUgtudW1iZXJWYWx1ZRIjCgxzdHJpb\
-    mdfdmFsdWUYAyABKAlIAFILc3RyaW5nVmFsdWUSHwoKYm9vbF92YWx1ZRgEIAEoCEgAUglib29sVmFsdWUSPAoMc3RydWN0\
-    X3ZhbHVlGAUgASgLMhcuZ29vZ2xlLnByb3RvYnVmLlN0cnVjdEgAUgtzdHJ1Y3RWYWx1ZRI7CgpsaXN0X3ZhbHVlGAYgASg\
-    LMhouZ29vZ2xlLnByb3RvYnVmLkxpc3RWYWx1ZUgAUglsaXN0VmFsdWVCBgoEa2luZCI7CglMaXN0VmFsdWUSLgoGdmFsdW\
-    VzGAEgAygLMhYuZ29vZ2xlLnByb3RvYnVmLlZhbHVlUgZ2YWx1ZXMqGwoJTnVsbFZhbHVlEg4KCk5VTExfVkFMVUUQAEJ/C\
-    hNjb20uZ29vZ2xlLnByb3RvYnVmQgtTdHJ1Y3RQcm90b1ABWi9nb29nbGUuZ29sYW5nLm9yZy9wcm90b2J1Zi90eXBlcy9r\
-    bm93bi9zdHJ1Y3RwYvgBAaICA0dQQqoCHkdvb2dsZS5Qcm90b2J1Zi5XZWxsS25vd25UeXBlc2IGcHJvdG8zCoYECh5nb29\
-    nbGUvcHJvdG9idWYvd3JhcHBlcnMucHJvdG8SD2dvb2dsZS5wcm90b2J1ZiIjCgtEb3VibGVWYWx1ZRIUCgV2YWx1ZRgBIA\
-    EoAVIFdmFsdWUiIgoKRmxvYXRWYWx1ZRIUCgV2YWx1ZRgBIAEoAlIFdmFsdWUiIgoKSW50NjRWYWx1ZRIUCgV2YWx1ZRgBI\
-    AEoA1IFdmFsdWUiIwoLVUludDY0VmFsdWUSFAoFdmFsdWUYASABKARSBXZhbHVlIiIKCkludDMyVmFsdWUSFAoFdmFsdWUY\
-    ASABKAVSBXZhbHVlIiMKC1VJbnQzMlZhbHVlEhQKBXZhbHVlGAEgASgNUgV2YWx1ZSIhCglCb29sVmFsdWUSFAoFdmFsdWU\
-    YASABKAhSBXZhbHVlIiMKC1N0cmluZ1ZhbHVlEhQKBXZhbHVlGAEgASgJUgV2YWx1ZSIiCgpCeXRlc1ZhbHVlEhQKBXZhbH\
-    VlGAEgASgMUgV2YWx1ZUKDAQoTY29tLmdvb2dsZS5wcm90b2J1ZkINV3JhcHBlcnNQcm90b1ABWjFnb29nbGUuZ29sYW5nL\
-    m9yZy9wcm90b2J1Zi90eXBlcy9rbm93bi93cmFwcGVyc3Bi+AEBogIDR1BCqgIeR29vZ2xlLlByb3RvYnVmLldlbGxLbm93\
-    blR5cGVzYgZwcm90bzMKvgEKG2dvb2dsZS9wcm90b2J1Zi9lbXB0eS5wcm90bxIPZ29vZ2xlLnByb3RvYnVmIgcKBUVtcHR\
-    5Qn0KE2NvbS5nb29nbGUucHJvdG9idWZCCkVtcHR5UHJvdG9QAVouZ29vZ2xlLmdvbGFuZy5vcmcvcHJvdG9idWYvdHlwZX\
+/// Decodes the bytes from a `google.protobuf.BytesValue` field as the given message type, and
+/// compares the embedded messages
+fn compare_embedded_message(
+  path: &DocPath,
+  type_name: &str,
+  expected: &[u8],
+  actual: &[u8],
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet
+) -> Vec<Mismatch> {
+  let message_name = type_name.split('.').last().unwrap_or(type_name);
+  let message_descriptor = match find_message_type_by_name(message_name, descriptors) {
+    Ok((descriptor, _)) => descriptor,
+    Err(err) => return vec![
+      BodyMismatch {
+        path: path.to_string(),
+        expected: Some(Bytes::copy_from_slice(expected)),
+        actual: Some(Bytes::copy_from_slice(actual)),
+        mismatch: format!("Could not find the message type {} for the embedded message - {}", type_name, err)
+      }
+    ]
+  };
+  let mut expected_bytes = BytesMut::from(expected);
+  let expected_message = match decode_message(&mut expected_bytes, &message_descriptor, descriptors) {
+    Ok(message) => message,
+    Err(err) => return vec![
+      BodyMismatch {
+        path: path.to_string(),
+        expected: Some(Bytes::copy_from_slice(expected)),
+        actual: Some(Bytes::copy_from_slice(actual)),
+        mismatch: format!("Could not decode the expected embedded {} message - {}", type_name, err)
+      }
+    ]
+  };
+  let mut actual_bytes = BytesMut::from(actual);
+  let actual_message = match decode_message(&mut actual_bytes, &message_descriptor, descriptors) {
+    Ok(message) => message,
+    Err(err) => return vec![
+      BodyMismatch {
+        path: path.to_string(),
+        expected: Some(Bytes::copy_from_slice(expected)),
+        actual: Some(Bytes::copy_from_slice(actual)),
+        mismatch: format!("Could not decode the actual embedded {} message - {}", type_name, err)
+      }
+    ]
+  };
+  match compare_message(path.clone(), &expected_message, &actual_message, matching_context, &message_descriptor, descriptors) {
+    Ok(BodyMatchResult::Ok) => vec![],
+    Ok(BodyMatchResult::BodyTypeMismatch { message, .. }) => vec![
+      BodyMismatch {
+        path: path.to_string(),
+        expected: Some(type_name.to_string().into()),
+        actual: Some(type_name.to_string().into()),
+        mismatch: message
+      }
+    ],
+    Ok(BodyMatchResult::BodyMismatches(mismatches)) => mismatches.values().flatten().cloned().collect(),
+    Err(err) => vec![
+      BodyMismatch {
+        path: path.to_string(),
+        expected: Some(type_name.to_string().into()),
+        actual: Some(type_name.to_string().into()),
+        mismatch: err.to_string()
+      }
+    ]
+  }
+}
+
+/// In proto3, an empty repeated or map field and an unset one are the same (neither are sent on
+/// the wire, or an empty packed field may be sent). Both are normalised to an unset field, so if
+/// the field has no values in both messages it does not need to be compared, unless there is a
+/// matcher defined directly against the field (i.e. a minimum length).
+fn is_empty_or_unset(
+  message_descriptor: &DescriptorProto,
+  field_descriptor: &FieldDescriptorProto,
+  expected: &[&ProtobufField],
+  actual: &[&ProtobufField],
+  path: &DocPath,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> bool {
+  let repeated = is_repeated_field(field_descriptor) || is_map_field(message_descriptor, field_descriptor);
+  repeated && expected.is_empty() && actual.is_empty() &&
+    (!matching_context.matcher_is_defined(path) || matching_context.select_best_matcher(path).cascaded) &&
+    enum_membership(path, matching_context).is_none()
+}
+
+/// Compare a simple field (non-map and non-repeated)
+#[tracing::instrument(ret,
+  skip_all,
+  fields(%path, %field, %actual)
+)]
+fn compare_field(
+  path: &DocPath,
+  field: &ProtobufField,
+  descriptor: &FieldDescriptorProto,
+  actual: &ProtobufField,
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet
+) -> Vec<Mismatch> {
+  if let Some(name) = custom_matcher_name(path, matching_context) {
+    trace!(%name, "Comparing values with a custom matcher");
+    return apply_custom_matcher(path, &name, field, actual);
+  }
+
+  match (&field.data, &actual.data) {
+    (ProtobufFieldData::String(s1), ProtobufFieldData::String(s2)) => {
+      trace!("Comparing string values");
+      let locale = number_locale(path, matching_context)
+        .filter(|_| compared_with_equality(path, matching_context));
+      let (v1, v2) = if let Some(locale) = locale {
+        trace!(%locale, "Comparing the string values as numbers formatted for the locale");
+        match locale_numbers(s1, s2, locale.as_str()) {
+          Ok(values) => values,
+          Err(err) => return vec![
+            BodyMismatch {
+              path: path.to_string(),
+              expected: Some(s1.clone().into()),
+              actual: Some(s2.clone().into()),
+              mismatch: err.to_string()
+            }
+          ]
+        }
+      } else if is_ignore_case_field(path, matching_context) && compared_with_equality(path, matching_context) {
+        trace!("Ignoring the case of the string values");
+        (s1.to_lowercase(), s2.to_lowercase())
+      } else {
+        (s1.clone(), s2.clone())
+      };
+      let mut mismatches = compare_value(path, field, &v1, &v2, s1.as_str(), s2.as_str(), matching_context);
+      mismatches.extend(compare_string_length(path, s2.as_str(), matching_context));
+      mismatches
+    },
+    (ProtobufFieldData::Boolean(b1), ProtobufFieldData::Boolean(b2)) => {
+      trace!("Comparing boolean values");
+      compare_value(path, field, *b1, *b2, b1.to_string().as_str(), b2.to_string().as_str(), matching_context)
+    },
+    (ProtobufFieldData::UInteger32(n1), ProtobufFieldData::UInteger32(n2)) => {
+      trace!("Comparing UInteger32 values");
+      compare_value(path, field, *n1 as u64, *n2 as u64, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
+    },
+    (ProtobufFieldData::Integer32(n1), ProtobufFieldData::Integer32(n2)) => {
+      trace!("Comparing Integer32 values");
+      compare_value(path, field, *n1, *n2, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
+    },
+    (ProtobufFieldData::UInteger64(n1), ProtobufFieldData::UInteger64(n2)) => {
+      trace!("Comparing UInteger64 values");
+      compare_value(path, field, *n1, *n2, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
+    },
+    (ProtobufFieldData::Integer64(n1), ProtobufFieldData::Integer64(n2)) => {
+      trace!("Comparing Integer64 values");
+      compare_value(path, field, *n1, *n2, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
+    },
+    (ProtobufFieldData::Float(n1), ProtobufFieldData::Float(n2)) if n1.is_nan() && n2.is_nan() && !matching_context.matcher_is_defined(path) => {
+      trace!("Both Float values are NaN");
+      vec![]
+    },
+    (ProtobufFieldData::Float(n1), ProtobufFieldData::Float(n2)) => {
+      trace!("Comparing Float values");
+      compare_value(path, field, *n1 as f64, *n2 as f64, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
+    },
+    (ProtobufFieldData::Double(n1), ProtobufFieldData::Double(n2)) if n1.is_nan() && n2.is_nan() && !matching_context.matcher_is_defined(path) => {
+      trace!("Both Double values are NaN");
+      vec![]
+    },
+    (ProtobufFieldData::Double(n1), ProtobufFieldData::Double(n2)) => {
+      trace!("Comparing Double values");
+      compare_value(path, field, *n1, *n2, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
+    },
+    (ProtobufFieldData::Bytes(b1), ProtobufFieldData::Bytes(b2)) => {
+      trace!("Comparing byte arrays");
+      let b1_str = display_bytes(b1);
+      let b2_str = display_bytes(b2);
+      // The min and max type matchers also compare the length of the byte arrays, so a value with
+      // the wrong length is only reported once, as a length mismatch
+      let length_mismatches = compare_bytes_length(path, b2, matching_context);
+      if length_mismatches.is_empty() {
+        compare_value(path, field, b1.as_slice(), b2.as_slice(), b1_str.as_str(), b2_str.as_str(), matching_context)
+      } else {
+        length_mismatches
+      }
+    },
+    (ProtobufFieldData::Enum(b1, descriptor), ProtobufFieldData::Enum(b2, _)) => {
+      trace!("Comparing Enum values");
+      if !descriptor.value.iter().any(|value| value.number == Some(*b2)) {
+        match unknown_enum_values(matching_context) {
+          Ok(Some(UnknownEnumValues::Strict)) => return vec![
+            BodyMismatch {
+              path: path.to_string(),
+              expected: Some(field.data.to_string().into()),
+              actual: Some(b2.to_string().into()),
+              mismatch: format!("Expected a value defined for enum {}, but received the unknown value {}",
+                descriptor.name.clone().unwrap_or_default(), b2)
+            }
+          ],
+          Ok(Some(UnknownEnumValues::Lenient)) => {
+            trace!("Actual enum value {} is not defined for the enum, accepting it", b2);
+            return vec![];
+          }
+          Ok(None) => {}
+          Err(err) => return vec![
+            BodyMismatch {
+              path: path.to_string(),
+              expected: None,
+              actual: None,
+              mismatch: err.to_string()
+            }
+          ]
+        }
+      }
+      let enum_1 = enum_name(*b1, descriptor);
+      let enum_2 = enum_name(*b2, descriptor);
+      let mismatches = compare_value(path, field, &enum_1, &enum_2, enum_1.as_str(), enum_2.as_str(), matching_context);
+      if !mismatches.is_empty() && enum_value_names(*b2, descriptor).iter()
+        .filter(|alias| **alias != enum_2)
+        .any(|alias| compare_value(path, field, &enum_1, alias, enum_1.as_str(), alias.as_str(), matching_context).is_empty()) {
+        trace!("Actual enum value {} matched using one of its aliases", enum_2);
+        vec![]
+      } else {
+        mismatches
+      }
+    },
+    (ProtobufFieldData::Message(b1, message_descriptor), ProtobufFieldData::Message(b2, _)) => {
+      trace!("Comparing embedded messages");
+      let mut expected_bytes = BytesMut::from(b1.as_slice());
+      let expected_message = match decode_message(&mut expected_bytes, message_descriptor, descriptors) {
+        Ok(message) => message,
+        Err(err) => {
+          return vec![
+            BodyMismatch {
+              path: path.to_string(),
+              expected: Some(field.data.to_string().into()),
+              actual: Some(actual.data.to_string().into()),
+              mismatch: format!("Could not decode expected message field {} - {}", descriptor.name.clone().unwrap_or_else(|| "unknown".to_string()), err)
+            }
+          ];
+        }
+      };
+      let mut actual_bytes = BytesMut::from(b2.as_slice());
+      let actual_message = match decode_message(&mut actual_bytes, message_descriptor, descriptors) {
+        Ok(message) => message,
+        Err(err) => {
+          return vec![
+            BodyMismatch {
+              path: path.to_string(),
+              expected: Some(field.data.to_string().into()),
+              actual: Some(actual.data.to_string().into()),
+              mismatch: format!("Could not decode actual message field {} - {}", descriptor.name.clone().unwrap_or_else(|| "unknown".to_string()), err)
+            }
+          ];
+        }
+      };
+      match &descriptor.type_name {
+        Some(name) => match name.as_str() {
+          ".google.protobuf.BytesValue" => {
+            debug!("Field is a Protobuf BytesValue");
+            let expected_field_data = find_message_field_by_name(message_descriptor, expected_message, "value");
+            let actual_field_data = find_message_field_by_name(message_descriptor, actual_message, "value");
+            let b1 = expected_field_data.map(|f| match f.data {
+              ProtobufFieldData::Bytes(b) => b,
+              _ => vec![]
+            }).unwrap_or_default();
+            let b2 = actual_field_data.map(|f| match f.data {
+              ProtobufFieldData::Bytes(b) => b,
+              _ => vec![]
+            }).unwrap_or_default();
+            match bytes_value_type(path, matching_context) {
+              Some(type_name) => {
+                debug!("BytesValue is configured to contain an embedded {} message", type_name);
+                compare_embedded_message(path, &type_name, &b1, &b2, matching_context, descriptors)
+              }
+              None => {
+                let b1_str = display_bytes(&b1);
+                let b2_str = display_bytes(&b2);
+                compare_value(path, field, b1, b2, b1_str.as_str(), b2_str.as_str(), matching_context)
+              }
+            }
+          }
+          ".google.protobuf.Any" if any_type_urls(path, matching_context).is_some() => {
+            let type_urls = any_type_urls(path, matching_context).unwrap_or_default();
+            debug!("Field is a Protobuf Any that can contain any of {:?}", type_urls);
+            compare_any(path, &type_urls, message_descriptor, &expected_message, &actual_message, matching_context, descriptors)
+          }
+          ".google.protobuf.Struct" | ".google.protobuf.Value" | ".google.protobuf.ListValue" => {
+            debug!("Field is a Protobuf {}, will compare it as JSON", name);
+            let expected_json = match well_known_json_type_to_json(name, b1) {
+              Ok(j) => j,
+              Err(err) => {
+                return vec![
+                  BodyMismatch {
+                    path: path.to_string(),
+                    expected: Some(field.data.to_string().into()),
+                    actual: Some(actual.data.to_string().into()),
+                    mismatch: format!("Could not decode expected message field {} - {}", descriptor.name.clone().unwrap_or_else(|| "unknown".to_string()), err)
+                  }
+                ];
+              }
+            };
+            let mut actual_json = match well_known_json_type_to_json(name, b2) {
+              Ok(j) => j,
+              Err(err) => {
+                return vec![
+                  BodyMismatch {
+                    path: path.to_string(),
+                    expected: Some(field.data.to_string().into()),
+                    actual: Some(actual.data.to_string().into()),
+                    mismatch: format!("Could not decode actual message field {} - {}", descriptor.name.clone().unwrap_or_else(|| "unknown".to_string()), err)
+                  }
+                ];
+              }
+            };
+
+            null_as_absent(&expected_json, &mut actual_json);
+            match compare_json(path, &expected_json, &actual_json, matching_context) {
+              Ok(_) => vec![],
+              Err(err) => err
+            }
+          }
+          _ => {
+            debug!("Field is a normal message");
+            match compare_message(path.clone(), &expected_message, &actual_message, matching_context, message_descriptor, descriptors) {
+              Ok(result) => match result {
+                BodyMatchResult::Ok => vec![],
+                BodyMatchResult::BodyTypeMismatch { message, .. } => vec![
+                  BodyMismatch {
+                    path: path.to_string(),
+                    expected: Some(name.clone().into()),
+                    actual: Some(name.clone().into()),
+                    mismatch: message
+                  }
+                ],
+                BodyMatchResult::BodyMismatches(mismatches) => mismatches.values().flatten().cloned().collect()
+              }
+              Err(err) => vec![
+                BodyMismatch {
+                  path: path.to_string(),
+                  expected: Some(name.clone().into()),
+                  actual: Some(name.clone().into()),
+                  mismatch: err.to_string()
+                }
+              ]
+            }
+          }
+        }
+        None => vec![
+          BodyMismatch {
+            path: path.to_string(),
+            expected: Some(field.data.to_string().into()),
+            actual: Some(actual.data.to_string().into()),
+            mismatch: format!("Message field {} type name is not set, can not compare it", descriptor.name.clone().unwrap_or_else(|| "unknown".to_string()))
+          }
+        ]
+      }
+    }
+    _ => vec![
+      BodyMismatch {
+        path: path.to_string(),
+        expected: Some(field.data.to_string().into()),
+        actual: Some(actual.data.to_string().into()),
+        mismatch: format!("Expected and actual field have different types: {} and {}",
+                          field, actual)
+      }
+    ]
+  }
+}
+
+/// In Protobuf JSON, a `null` value means the field is absent. Any expected `null` values where
+/// the actual field is missing are set to `null` in the actual JSON, so they will match, while
+/// an actual value that is present will still result in a mismatch.
+fn null_as_absent(expected: &Value, actual: &mut Value) {
+  if let (Value::Object(expected), Value::Object(actual)) = (expected, actual) {
+    for (key, value) in expected {
+      match value {
+        Value::Null => {
+          actual.entry(key.clone()).or_insert(Value::Null);
+        }
+        Value::Object(_) => if let Some(actual_value) = actual.get_mut(key) {
+          null_as_absent(value, actual_value);
+        }
+        _ => {}
+      }
+    }
+  }
+}
+
+/// Returns the matching rules to apply to a field value. When both a type and a regex matcher
+/// are defined for the field, the regex matcher is the more specific one and takes precedence, so
+/// the type matcher is dropped. Otherwise a failing regex would be ignored when the rules are
+/// combined with OR.
+fn field_matchers(path: &DocPath, matching_context: &dyn MatchingContext) -> RuleList {
+  let mut rules = matching_context.select_best_matcher(path);
+  if rules.rules.iter().any(|rule| matches!(rule, MatchingRule::Regex(_))) &&
+    rules.rules.contains(&MatchingRule::Type) {
+    trace!("Both a type and regex matcher are defined for '{}', regex matcher takes precedence", path);
+    rules.rules.retain(|rule| *rule != MatchingRule::Type);
+  }
+  rules
+}
+
+/// If the values at the path are compared with plain equality, as there are no matchers that apply
+/// to the path or the only matcher is the equality matcher
+fn compared_with_equality(path: &DocPath, matching_context: &dyn MatchingContext) -> bool {
+  let rules = if matching_context.matcher_is_defined(path) {
+    Some(field_matchers(path, matching_context))
+  } else {
+    default_matchers(matching_context)
+  };
+  rules.map(|rules| rules.rules.iter().all(|rule| *rule == MatchingRule::Equality)).unwrap_or(true)
+}
+
+/// Compares the actual value to the expected one.
+fn compare_value<T>(
+  path: &DocPath,
+  field: &ProtobufField,
+  expected: T,
+  actual: T,
+  expected_str: &str,
+  actual_str: &str,
+  matching_context: &dyn MatchingContext
+) -> Vec<Mismatch> where T: Clone + Debug + Matches<T> {
+  trace!("compare_value({}, {:?}, {}, {})", path, field, expected_str, actual_str);
+
+  let rules = if matching_context.matcher_is_defined(path) {
+    debug!("compare_value: Matcher defined for path '{}' and values {:?} -> {:?}", path, expected, actual);
+    Some(field_matchers(path, matching_context))
+  } else {
+    default_matchers(matching_context)
+  };
+
+  if let Some(rules) = rules {
+    match match_values(path, &rules, expected, actual) {
+      Ok(_) => vec![],
+      Err(mismatches) => mismatches.iter().map(|m| BodyMismatch {
+        path: path.to_string(),
+        expected: Some(expected_str.as_bytes().to_vec().into()),
+        actual: Some(actual_str.as_bytes().to_vec().into()),
+        mismatch: m.clone()
+      }).collect()
+    }
+  } else {
+    debug!("compare_value: No matcher defined for path '{}', using equality", path);
+    match expected.matches_with(actual, &MatchingRule::Equality, false) {
+      Ok(_) => vec![],
+      Err(err) => vec![BodyMismatch {
+        path: path.to_string(),
+        expected: Some(expected_str.as_bytes().to_vec().into()),
+        actual: Some(actual_str.as_bytes().to_vec().into()),
+        mismatch: err.to_string()
+      }]
+    }
+  }
+}
+
+/// Returns the rules of the default matcher configured for the interaction (`defaultMatcher`
+/// interaction configuration). These apply to any field that does not have its own matching rules.
+fn default_matchers(matching_context: &dyn MatchingContext) -> Option<RuleList> {
+  let definition = matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(DEFAULT_MATCHER_CONFIG))
+    .map(json_to_string)?;
+  match parse_matcher_def(definition.as_str()) {
+    Ok(definition) => {
+      trace!("compare_value: Using the default matcher {:?}", definition.rules);
+      Some(RuleList {
+        rules: definition.rules.iter().filter_map(|rule| rule.clone().left()).collect(),
+        rule_logic: RuleLogic::And,
+        cascaded: false
+      })
+    }
+    Err(err) => {
+      warn!("'{}' is not a valid matcher definition for {}, ignoring it - {}", definition, DEFAULT_MATCHER_CONFIG, err);
+      None
+    }
+  }
+}
+
+/// Checks the length of a string value against any min or max type matchers defined directly
+/// against the field, if the field is configured with `lengthFields`. When matching with Pact,
+/// these matchers only check the type of a string value, so the length is checked here and any
+/// failures are reported along with the failures from the other matchers for the field.
+fn compare_string_length(
+  path: &DocPath,
+  actual: &str,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Vec<Mismatch> {
+  compare_length(path, actual, actual.chars().count(), "characters", actual.as_bytes(), matching_context)
+}
+
+/// Checks the number of bytes in a bytes value against any min or max type matchers defined
+/// directly against the field (i.e. for fixed size hashes or keys). A min and max type matcher with
+/// the same value checks for an exact length.
+fn compare_bytes_length(
+  path: &DocPath,
+  actual: &[u8],
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Vec<Mismatch> {
+  compare_length(path, display_bytes(actual).as_str(), actual.len(), "bytes", actual, matching_context)
+}
+
+/// Checks the length of the actual value against the min and max type matchers for the field, if
+/// the field is configured to have its length checked
+fn compare_length(
+  path: &DocPath,
+  display: &str,
+  length: usize,
+  unit: &str,
+  actual: &[u8],
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Vec<Mismatch> {
+  if !matching_context.matcher_is_defined(path) || !is_length_field(path, matching_context) {
+    return vec![];
+  }
+  let rules = field_matchers(path, matching_context);
+  if rules.cascaded || rules.rule_logic != RuleLogic::And {
+    return vec![];
+  }
+
+  let mut mismatches = vec![];
+  for rule in &rules.rules {
+    let (min, max) = match rule {
+      MatchingRule::MinType(min) => (Some(*min), None),
+      MatchingRule::MaxType(max) => (None, Some(*max)),
+      MatchingRule::MinMaxType(min, max) => (Some(*min), Some(*max)),
+      _ => continue
+    };
+    if let (Some(min), Some(max)) = (min, max) {
+      if min == max {
+        if length != min {
+          mismatches.push(format!("Expected '{}' to have exactly {} {} but it has {}", display, min, unit, length));
+        }
+        continue;
+      }
+    }
+    if let Some(min) = min.filter(|min| length < *min) {
+      mismatches.push(format!("Expected '{}' to have at least {} {} but it has {}", display, min, unit, length));
+    }
+    if let Some(max) = max.filter(|max| length > *max) {
+      mismatches.push(format!("Expected '{}' to have at most {} {} but it has {}", display, max, unit, length));
+    }
+  }
+
+  mismatches.into_iter()
+    .map(|mismatch| BodyMismatch {
+      path: path.to_string(),
+      expected: None,
+      actual: Some(Bytes::copy_from_slice(actual)),
+      mismatch
+    })
+    .collect()
+}
+
+/// Compare a repeated field
+#[tracing::instrument(ret, skip_all, fields(%path))]
+fn compare_repeated_field(
+  path: &DocPath,
+  descriptor: &FieldDescriptorProto,
+  expected_fields: &[ProtobufField],
+  actual_fields: &[ProtobufField],
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet
+) -> Vec<Mismatch> {
+  trace!(">>> compare_repeated_field({}, {:?}, {:?})", path, expected_fields, actual_fields);
+
+  let mut result = vec![];
+
+  // Repeated fields are compared strictly (exact count, with each item compared positionally)
+  // unless a matcher is defined directly against the field. Matchers cascaded from a parent
+  // path do not change how the list is compared.
+  let rules = matching_context.select_best_matcher(path);
+  if matching_context.matcher_is_defined(path) && !rules.cascaded {
+    debug!("compare_repeated_field: Matcher defined for path '{}'", path);
+    if expected_fields.is_empty() {
+      result.extend(compare_list_size(path, descriptor, actual_fields.len(), &rules.rules));
+    }
+    for matcher in &rules.rules {
+      if let Err(comparison) = compare_lists_with_matchingrule(matcher, path,
+        expected_fields, actual_fields, matching_context, rules.cascaded, &mut |field_path, expected, actual, context| {
+          let comparison = compare_field(field_path, expected, descriptor, actual, context, descriptors);
+          if comparison.is_empty() {
+            Ok(())
+          } else {
+            Err(comparison)
+          }
+        }) {
+        result.extend(comparison);
+      }
+    }
+  } else if expected_fields.len() == 1 && is_each_equals_field(path, matching_context) {
+    debug!("Comparing each of the {} values of repeated field '{}' to the expected value", actual_fields.len(), path);
+    let expected = &expected_fields[0];
+    if actual_fields.is_empty() {
+      result.push(Mismatch::BodyMismatch {
+        path: path.to_string(),
+        expected: Some(Bytes::from(expected.data.as_bytes())),
+        actual: None,
+        mismatch: format!("Expected each value of repeated field '{}' to equal {}, but received no values",
+          descriptor.name.clone().unwrap_or_else(|| descriptor.number.unwrap_or_default().to_string()), expected.data)
+      });
+    }
+    for (index, actual) in actual_fields.iter().enumerate() {
+      result.extend(compare_field(&path.join(index.to_string()), expected, descriptor, actual, matching_context, descriptors));
+    }
+  } else if is_subsequence_field(path, matching_context) {
+    debug!("Looking for the {} expected values of repeated field '{}' in order", expected_fields.len(), path);
+    result.extend(compare_subsequence(path, descriptor, expected_fields, actual_fields, matching_context, descriptors));
+  } else if let Some((includes, excludes)) = enum_membership(path, matching_context) {
+    debug!("Checking that repeated field '{}' includes {:?} and excludes {:?}", path, includes, excludes);
+    result.extend(compare_enum_membership(path, descriptor, actual_fields, &includes, &excludes));
+  } else if expected_fields.is_empty() && !actual_fields.is_empty() {
+    debug!("Expected an empty list, but actual has {} fields", actual_fields.len());
+    result.push(Mismatch::BodyMismatch {
+      path: path.to_string(),
+      expected: None,
+      actual: None,
+      mismatch: format!("Expected repeated field '{}' to be empty but received {} values",
+        descriptor.name.clone().unwrap_or_else(|| descriptor.number.unwrap_or_default().to_string()),
+        actual_fields.len()
+      )
+    })
+  } else {
+    trace!("Comparing repeated fields as a list, requiring the same number of items");
+    result.extend(compare_list_content(path, descriptor, expected_fields, actual_fields, matching_context, descriptors));
+    if expected_fields.len() != actual_fields.len() {
+      result.push(Mismatch::BodyMismatch {
+        path: path.to_string(),
+        expected: None,
+        actual: None,
+        mismatch: format!("Expected repeated field '{}' to have {} values but received {} values",
+          descriptor.name.clone().unwrap_or_else(|| descriptor.number.unwrap_or_default().to_string()),
+          expected_fields.len(),
+          actual_fields.len()
+        )
+      })
+    }
+  }
+
+  result
+}
+
+/// Checks that the expected values are found in the actual values in the same order. Each expected
+/// value is matched to the first actual value after the previous match that it is equal to.
+fn compare_subsequence(
+  path: &DocPath,
+  descriptor: &FieldDescriptorProto,
+  expected_fields: &[ProtobufField],
+  actual_fields: &[ProtobufField],
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet
+) -> Vec<Mismatch> {
+  let mut start = 0;
+  for (index, expected) in expected_fields.iter().enumerate() {
+    let found = actual_fields.iter().enumerate().skip(start)
+      .find(|(actual_index, actual)| {
+        let field_path = path.join(actual_index.to_string());
+        compare_field(&field_path, expected, descriptor, actual, matching_context, descriptors).is_empty()
+      });
+    match found {
+      Some((actual_index, _)) => {
+        trace!("Expected value {} matched actual value {}", index, actual_index);
+        start = actual_index + 1;
+      }
+      None => return vec![
+        Mismatch::BodyMismatch {
+          path: path.to_string(),
+          expected: Some(expected.data.to_string().into()),
+          actual: None,
+          mismatch: format!("Expected repeated field '{}' to contain the expected values in order, but could not find expected value {} ({})",
+            descriptor.name.clone().unwrap_or_else(|| descriptor.number.unwrap_or_default().to_string()),
+            index, expected.data)
+        }
+      ]
+    }
+  }
+  vec![]
+}
+
+/// If the single expected value of the repeated field at the given path must be equal to every
+/// element of the actual field (`eachEquals` interaction configuration)
+fn is_each_equals_field(path: &DocPath, matching_context: &(dyn MatchingContext + Send + Sync)) -> bool {
+  matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(EACH_EQUALS_CONFIG))
+    .map(|value| is_configured_path(path, value))
+    .unwrap_or(false)
+}
+
+/// Checks that the actual values of a repeated field include all the values that must be included,
+/// and none of the values that must be excluded. Enum values are compared by their names.
+fn compare_enum_membership(
+  path: &DocPath,
+  descriptor: &FieldDescriptorProto,
+  actual_fields: &[ProtobufField],
+  includes: &[String],
+  excludes: &[String]
+) -> Vec<Mismatch> {
+  let field_name = descriptor.name.clone().unwrap_or_else(|| descriptor.number.unwrap_or_default().to_string());
+  // Enum values with aliases can be configured using any of the names
+  let actual_values = actual_fields.iter()
+    .map(|field| match &field.data {
+      ProtobufFieldData::Enum(value, descriptor) => enum_value_names(*value, descriptor),
+      data => vec![ field_value_string(data) ]
+    })
+    .collect_vec();
+  let mut result = vec![];
+
+  for value in includes {
+    if !actual_values.iter().any(|names| names.contains(value)) {
+      result.push(BodyMismatch {
+        path: path.to_string(),
+        expected: Some(value.clone().into()),
+        actual: None,
+        mismatch: format!("Expected repeated field '{}' to include the value {}", field_name, value)
+      });
+    }
+  }
+  for value in excludes {
+    if let Some(index) = actual_values.iter().position(|names| names.contains(value)) {
+      result.push(BodyMismatch {
+        path: path.join(index.to_string()).to_string(),
+        expected: None,
+        actual: Some(value.clone().into()),
+        mismatch: format!("Expected repeated field '{}' to not include the value {}, but it was received at index {}",
+          field_name, value, index)
+      });
+    }
+  }
+
+  result
+}
+
+/// Returns the values the repeated field at the given path must include and must not include
+/// (`enumIncludes` and `enumExcludes` interaction configuration), if either are configured for it
+fn enum_membership(
+  path: &DocPath,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Option<(Vec<String>, Vec<String>)> {
+  let path = path.to_string();
+  let values_for = |key: &str| matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(key))
+    .and_then(|config| config.as_object())
+    .and_then(|fields| fields.iter()
+      .find(|(p, _)| p.as_str() == path || p.as_str() == path.trim_start_matches("$."))
+      .map(|(_, values)| match values {
+        Value::Array(values) => values.iter().map(json_to_string).collect_vec(),
+        _ => vec![ json_to_string(values) ]
+      }));
+  match (values_for(ENUM_INCLUDES_CONFIG), values_for(ENUM_EXCLUDES_CONFIG)) {
+    (None, None) => None,
+    (includes, excludes) => Some((includes.unwrap_or_default(), excludes.unwrap_or_default()))
+  }
+}
+
+/// If the expected values of the repeated field at the given path must be found in the actual
+/// field in the same order, with any other values in between (`subsequence` interaction configuration)
+fn is_subsequence_field(path: &DocPath, matching_context: &(dyn MatchingContext + Send + Sync)) -> bool {
+  matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(SUBSEQUENCE_CONFIG))
+    .map(|value| is_configured_path(path, value))
+    .unwrap_or(false)
+}
+
+/// If the path is one of the paths in the configuration value (either a single path or a list of paths)
+fn is_configured_path(path: &DocPath, value: &Value) -> bool {
+  let path = path.to_string();
+  match value {
+    Value::Array(paths) => paths.iter()
+      .map(json_to_string)
+      .any(|p| p == path || p == path.trim_start_matches("$.")),
+    _ => json_to_string(value) == path
+  }
+}
+
+/// If the string field at the given path must be compared ignoring case (`ignoreCase` interaction
+/// configuration). This only applies when the values are compared with equality, any other matchers
+/// are given the original values.
+fn is_ignore_case_field(path: &DocPath, matching_context: &(dyn MatchingContext + Send + Sync)) -> bool {
+  matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(IGNORE_CASE_CONFIG))
+    .map(|value| match value {
+      Value::Bool(ignore_case) => *ignore_case,
+      _ => is_configured_path(path, value)
+    })
+    .unwrap_or(false)
+}
+
+/// If the min and max type matchers for the string or bytes field at the given path also check the
+/// length of the value (`lengthFields` interaction configuration)
+fn is_length_field(path: &DocPath, matching_context: &(dyn MatchingContext + Send + Sync)) -> bool {
+  matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(LENGTH_FIELDS_CONFIG))
+    .map(|value| match value {
+      Value::Bool(length_fields) => *length_fields,
+      _ => is_configured_path(path, value)
+    })
+    .unwrap_or(false)
+}
+
+/// Returns the locale of the numbers in the string field at the given path (`numberLocales`
+/// interaction configuration)
+fn number_locale(path: &DocPath, matching_context: &(dyn MatchingContext + Send + Sync)) -> Option<String> {
+  let path = path.to_string();
+  matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(NUMBER_LOCALES_CONFIG))
+    .and_then(|value| value.as_object())
+    .and_then(|locales| locales.get(path.as_str()).or_else(|| locales.get(path.trim_start_matches("$."))))
+    .map(json_to_string)
+}
+
+/// Converts the expected and actual values of a string field to numbers in the canonical form, so
+/// they can be compared. The actual value must be formatted for the locale, while the expected value
+/// can either be in the canonical form (i.e. `1234.56`) or also formatted for the locale.
+fn locale_numbers(expected: &str, actual: &str, locale: &str) -> anyhow::Result<(String, String)> {
+  let expected = match expected.trim().parse::<f64>() {
+    Ok(number) => number,
+    Err(_) => parse_locale_number(expected, locale)
+      .map_err(|err| anyhow!("Expected value '{}' is not a number - {}", expected, err))?
+  };
+  let actual = parse_locale_number(actual, locale)
+    .map_err(|err| anyhow!("Actual value '{}' is not a number - {}", actual, err))?;
+  Ok((expected.to_string(), actual.to_string()))
+}
+
+/// Parses a number formatted for the locale. Only the decimal and grouping separators of the locale
+/// are considered. There can be at most one decimal separator, and the grouping separators must
+/// separate the whole number part into groups of three digits (i.e. `1.234.567,89`).
+pub(crate) fn parse_locale_number(value: &str, locale: &str) -> anyhow::Result<f64> {
+  let (decimal, grouping) = locale_separators(locale)?;
+  let invalid = || anyhow!("'{}' is not a number formatted for the locale '{}'", value, locale);
+  let trimmed = value.trim();
+  let (sign, unsigned) = match trimmed.strip_prefix(['-', '+']) {
+    Some(rest) => (&trimmed[..1], rest),
+    None => ("", trimmed)
+  };
+  let mut parts = unsigned.split(decimal);
+  let whole = parts.next().unwrap_or_default();
+  let fraction = parts.next();
+  if parts.next().is_some() {
+    return Err(invalid());
+  }
+
+  let groups = whole.split(|ch| grouping.contains(&ch)).collect_vec();
+  let valid_groups = groups.iter().enumerate().all(|(index, group)| {
+    let digits = group.chars().all(|ch| ch.is_ascii_digit());
+    if groups.len() == 1 {
+      digits && (!group.is_empty() || fraction.map(|f| !f.is_empty()).unwrap_or(false))
+    } else if index == 0 {
+      digits && (1..=3).contains(&group.len())
+    } else {
+      digits && group.len() == 3
+    }
+  });
+  let valid_fraction = fraction
+    .map(|fraction| !fraction.is_empty() && fraction.chars().all(|ch| ch.is_ascii_digit()))
+    .unwrap_or(true);
+  if !valid_groups || !valid_fraction {
+    return Err(invalid());
+  }
+
+  let normalised = match fraction {
+    Some(fraction) => format!("{}{}.{}", sign, groups.concat(), fraction),
+    None => format!("{}{}", sign, groups.concat())
+  };
+  normalised.parse::<f64>().map_err(|_| invalid())
+}
+
+/// Returns the decimal separator and the grouping separators for the locale (i.e. `de-DE` or `fr_FR`)
+fn locale_separators(locale: &str) -> anyhow::Result<(char, Vec<char>)> {
+  let locale = locale.replace('_', "-").to_lowercase();
+  let (language, region) = locale.split_once('-').unwrap_or((locale.as_str(), ""));
+  let spaces = vec![' ', '\u{a0}', '\u{202f}'];
+  match (language, region) {
+    ("de" | "it", "ch") | ("rm", _) => Ok(('.', vec!['\'', '\u{2019}'])),
+    ("en" | "ja" | "zh" | "ko" | "th" | "he" | "hi" | "ms", _) => Ok(('.', vec![','])),
+    ("es", "mx" | "us") => Ok(('.', vec![','])),
+    ("de" | "es" | "it" | "nl" | "pt" | "da" | "id" | "tr" | "el" | "ro" | "hr" | "sl" | "sr", _) => Ok((',', vec!['.'])),
+    ("fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "no" | "nn" | "uk" | "hu" | "bg" | "lt" | "lv" | "et", _) => Ok((',', spaces)),
+    _ => Err(anyhow!("The number format for the locale '{}' is not known", locale))
+  }
+}
+
+/// Compare a map field
+fn compare_map_field(
+  path: &DocPath,
+  descriptor: &FieldDescriptorProto,
+  expected_fields: Vec<&ProtobufField>,
+  actual_fields: Vec<&ProtobufField>,
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet
+) -> Vec<Mismatch> {
+  trace!(">> compare_map_field('{}', {:?}, {:?})", path, expected_fields, actual_fields);
+
+  let mut result = vec![];
+
+  if expected_fields.is_empty() && !actual_fields.is_empty() && matching_context.config() == DiffConfig::NoUnexpectedKeys {
+    result.push(Mismatch::BodyMismatch {
+      path: path.to_string(),
+      expected: None,
+      actual: None,
+      mismatch: format!("Expected repeated field '{}' to be empty but received {} values",
+        descriptor.name.clone().unwrap_or_else(|| descriptor.number.unwrap_or_default().to_string()),
+        actual_fields.len()
+      )
+    });
+  } else {
+    let expected_map = expected_fields.iter()
+      .filter_map(|f| {
+        match &f.data {
+          ProtobufFieldData::Message(d, descriptor) => decode_message_map_entry(descriptor, d, descriptors).ok(),
+          _ => None
+        }
+      })
+      .collect::<BTreeMap<String, MapEntry>>();
+    let actual_map = actual_fields.iter()
+      .filter_map(|f| {
+        match &f.data {
+          ProtobufFieldData::Message(d, descriptor) => decode_message_map_entry(descriptor, d, descriptors).ok(),
+          _ => None
+        }
+      })
+      .collect::<BTreeMap<String, MapEntry>>();
+
+    if matching_context.matcher_is_defined(path) {
+      debug!("compare_map_field: matcher defined for path '{}'", path);
+      let rules = matching_context.select_best_matcher(path);
+      for matcher in &rules.rules {
+        trace!("compare_map_field: matcher = {:?}", matcher);
+        if let Err(comparison) = compare_maps_with_matchingrule(matcher, rules.cascaded, path,
+          &expected_map, &actual_map, matching_context, &mut |field_path, expected, actual, context| {
+            let comparison = compare_field(field_path, &expected.value, &expected.field_descriptor, &actual.value, context, descriptors);
+            if comparison.is_empty() {
+              Ok(())
+            } else {
+              Err(comparison)
+            }
+          }) {
+          result.extend(comparison);
+        }
+      }
+    } else {
+      debug!("compare_map_field: no matcher defined for path '{}'", path);
+      debug!("                   expected keys {:?}", expected_map.keys());
+      debug!("                   actual keys {:?}", actual_map.keys());
+      let expected_keys = expected_map.keys().cloned().collect();
+      let actual_keys = actual_map.keys().cloned().collect();
+      if let Err(mismatches) = matching_context.match_keys(path, &expected_keys, &actual_keys) {
+        result.extend(mismatches);
+      }
+      let entry_context = map_entry_context(path, matching_context);
+      for (key, value) in &expected_map {
+        let entry_path = path.join(key);
+        if let Some(actual) = actual_map.get(key.as_str()) {
+          result.extend(compare_field(&entry_path, &value.value, &value.field_descriptor, &actual.value, entry_context.as_ref(), descriptors));
+        } else {
+          result.push(Mismatch::BodyMismatch {
+            path: path.to_string(),
+            expected: None,
+            actual: None,
+            mismatch: format!("Expected map field '{}' to have entry '{}', but was missing",
+              descriptor.name.clone().unwrap_or_else(|| descriptor.number.unwrap_or_default().to_string()),
+              key
+            )
+          });
+        }
+      }
+    }
+  }
+
+  result
+}
+
+/// Returns a matching context to use for the entries of a map field. Map entries are keyed by
+/// field names, so any `[*]` wildcard directly after the map field in a matching rule path (i.e.
+/// `$.users[*].roles[*]`) will not match the entries. This adds a copy of these rules with the
+/// wildcard replaced with `*`, so the rules are applied to all the entries of the map.
+fn map_entry_context(
+  path: &DocPath,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Box<dyn MatchingContext + Send + Sync> {
+  let path_tokens = path.tokens();
+  let mut matchers = matching_context.matchers().clone();
+  let map_rules = matchers.rules.iter()
+    .filter_map(|(rule_path, rules)| {
+      let rule_tokens = rule_path.tokens();
+      if rule_tokens.len() > path_tokens.len() &&
+        rule_tokens[path_tokens.len()] == PathToken::StarIndex &&
+        rule_tokens.iter().zip(path_tokens.iter()).all(|(rule_token, token)| path_token_matches(rule_token, token)) {
+        let mut entry_path = DocPath::root();
+        for (index, token) in rule_tokens.iter().enumerate().skip(1) {
+          if index == path_tokens.len() {
+            entry_path.push_star();
+          } else {
+            entry_path.push(token.clone());
+          }
+        }
+        trace!("Adding rules for map entries with path {} from {}", entry_path, rule_path);
+        Some((entry_path, rules.clone()))
+      } else {
+        None
+      }
+    })
+    .collect_vec();
+
+  if map_rules.is_empty() {
+    matching_context.clone_with(matching_context.matchers())
+  } else {
+    for (entry_path, rules) in map_rules {
+      matchers.rules.entry(entry_path).or_insert(rules);
+    }
+    matching_context.clone_with(&matchers)
+  }
+}
+
+/// If the token from a matching rule path matches the token from an actual path
+fn path_token_matches(rule_token: &PathToken, token: &PathToken) -> bool {
+  match (rule_token, token) {
+    (PathToken::Star, _) => true,
+    (PathToken::StarIndex, PathToken::Index(_)) => true,
+    _ => rule_token == token
+  }
+}
+
+/// Struct to represent a protobuf map entry
+#[derive(Clone, Debug)]
+struct MapEntry {
+  pub field_descriptor: FieldDescriptorProto,
+  pub value: ProtobufField
+}
+
+impl Display for MapEntry {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.value)
+  }
+}
+
+/// Decodes an embedded entry message into a key and value part
+fn decode_message_map_entry(
+  descriptor: &DescriptorProto,
+  data: &[u8],
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<(String, MapEntry)> {
+  let message = decode_message(&mut BytesMut::from(data), descriptor, descriptors)?;
+  let key = map_entry_field(&message, 1, descriptor, descriptors)?;
+  let value = map_entry_field(&message, 2, descriptor, descriptors)?;
+  let value_descriptor = find_field_descriptor(&value, descriptor)
+    .ok_or_else(|| anyhow!("Did not find the field descriptor for the value field of the map entry"))?;
+  let key_str = match &key.data {
+    ProtobufFieldData::String(s) => s.clone(),
+    _ => key.data.to_string()
+  };
+  Ok((key_str, MapEntry { field_descriptor: value_descriptor, value } ))
+}
+
+/// Returns the key (field 1) or value (field 2) of a decoded map entry. The entry is compared by
+/// value and not by how it was serialized, so a key or value set to the default value that was
+/// not written is treated as the default value, and if the field was written more than once,
+/// the last value is used.
+fn map_entry_field(
+  entry: &[ProtobufField],
+  field_num: u32,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<ProtobufField> {
+  if let Some(field) = entry.iter().rev().find(|field| field.field_num == field_num) {
+    return Ok(field.clone());
+  }
+
+  let entry_name = descriptor.name.clone().unwrap_or_else(|| "unknown".to_string());
+  let field_descriptor = descriptor.field.iter().find(|field| field.number == Some(field_num as i32))
+    .ok_or_else(|| anyhow!("Did not find the descriptor for field {} of map entry {}", field_num, entry_name))?;
+  let (data, wire_type) = if field_descriptor.r#type() == Type::Message {
+    let type_name = field_descriptor.type_name.clone().unwrap_or_default();
+    let (message_descriptor, _) = find_message_type_by_name(last_name(type_name.as_str()), descriptors)?;
+    (ProtobufFieldData::Message(vec![], message_descriptor), WireType::LengthDelimited)
+  } else {
+    default_field_data(field_descriptor, descriptor, descriptors)
+      .ok_or_else(|| anyhow!("Could not determine the default value for field {} of map entry {}", field_num, entry_name))?
+  };
+  trace!(field_num, %entry_name, "Map entry field was not set, using the default value {}", data);
+  Ok(ProtobufField {
+    field_num,
+    field_name: field_descriptor.name.clone().unwrap_or_default(),
+    wire_type,
+    data
+  })
+}
+
+/// Checks the number of items in the actual list against any min or max type matchers. The matching
+/// rules are only applied to the items of a list when the expected list has items, so this is used
+/// when the expected list is empty or unset.
+fn compare_list_size(
+  path: &DocPath,
+  descriptor: &FieldDescriptorProto,
+  size: usize,
+  rules: &[MatchingRule]
+) -> Vec<Mismatch> {
+  let field_name = descriptor.name.clone().unwrap_or_else(|| descriptor.number.unwrap_or_default().to_string());
+  rules.iter()
+    .filter_map(|rule| match rule {
+      MatchingRule::MinType(min) if size < *min =>
+        Some(format!("Expected repeated field '{}' to have at least {} values but received {}", field_name, min, size)),
+      MatchingRule::MaxType(max) if size > *max =>
+        Some(format!("Expected repeated field '{}' to have at most {} values but received {}", field_name, max, size)),
+      MatchingRule::MinMaxType(min, _) if size < *min =>
+        Some(format!("Expected repeated field '{}' to have at least {} values but received {}", field_name, min, size)),
+      MatchingRule::MinMaxType(_, max) if size > *max =>
+        Some(format!("Expected repeated field '{}' to have at most {} values but received {}", field_name, max, size)),
+      _ => None
+    })
+    .map(|mismatch| BodyMismatch {
+      path: path.to_string(),
+      expected: None,
+      actual: None,
+      mismatch
+    })
+    .collect()
+}
+
+/// Compares the items in the actual list against the expected
+#[tracing::instrument(ret, skip_all, fields(%path))]
+fn compare_list_content(
+  path: &DocPath,
+  descriptor: &FieldDescriptorProto,
+  expected: &[ProtobufField],
+  actual: &[ProtobufField],
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet
+) -> Vec<Mismatch> {
+  let mut result = vec![];
+  for (index, value) in expected.iter().enumerate() {
+    let ps = index.to_string();
+    debug!("Comparing list item {} with value '{:?}' to '{:?}'", index, actual.get(index), value);
+    let p = path.join(ps);
+    if index < actual.len() {
+      result.extend(compare_field(&p, value, descriptor, actual.get(index).unwrap(), matching_context, descriptors));
+    } else if !matching_context.matcher_is_defined(&p) {
+      result.push(Mismatch::BodyMismatch {
+        path: path.to_string(),
+        expected: Some(Bytes::from(value.data.to_string())),
+        actual: None,
+        mismatch: format!("Expected field {}({})={} but was missing", descriptor.name.clone().unwrap_or_default(), value.field_num, value.data)
+      });
+    }
+  }
+  result
+}
+
+/// Returns a description of each field set in the actual message that is not set in the expected
+/// message, or is not defined in the message descriptor. Embedded messages that are set in both
+/// messages are also checked. These are not mismatches, and are only used for reporting.
+pub fn unexpected_fields(
+  path: &DocPath,
+  expected: &[ProtobufField],
+  actual: &[ProtobufField],
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> Vec<String> {
+  let mut result = vec![];
+
+  for field_num in actual.iter().map(|field| field.field_num).unique() {
+    let actual_field = actual.iter().rev().find(|field| field.field_num == field_num);
+    let expected_field = expected.iter().rev().find(|field| field.field_num == field_num);
+    match actual_field.and_then(|field| find_field_descriptor(field, descriptor)) {
+      Some(field_descriptor) => {
+        let field_path = path.join(field_descriptor.name.clone().unwrap_or_default());
+        match expected_field {
+          None => result.push(format!("Received field '{}' that was not in the expected message", field_path)),
+          Some(expected_field) => if !is_repeated_field(&field_descriptor) {
+            if let (ProtobufFieldData::Message(expected_bytes, message_descriptor), Some(ProtobufFieldData::Message(actual_bytes, _))) =
+              (&expected_field.data, actual_field.map(|field| &field.data)) {
+              let expected_message = decode_message(&mut BytesMut::from(expected_bytes.as_slice()), message_descriptor, descriptors);
+              let actual_message = decode_message(&mut BytesMut::from(actual_bytes.as_slice()), message_descriptor, descriptors);
+              if let (Ok(expected_message), Ok(actual_message)) = (expected_message, actual_message) {
+                result.extend(unexpected_fields(&field_path, &expected_message, &actual_message, message_descriptor, descriptors));
+              }
+            }
+          }
+        }
+      }
+      None => result.push(format!("Received field number {} at '{}' that is not defined for message '{}'",
+        field_num, path, descriptor.name.clone().unwrap_or_default()))
+    }
+  }
+
+  result
+}
+
+/// Find the field descriptor in the message descriptor for the given field value
+fn find_field_descriptor(field: &ProtobufField, descriptor: &DescriptorProto) -> Option<FieldDescriptorProto> {
+  descriptor.field.iter()
+    .find(|field_desc| field_desc.number.unwrap_or_default() == field.field_num as i32)
+    .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+  use base64::Engine;
+  use base64::engine::general_purpose::STANDARD as BASE64;
+  use expectest::prelude::*;
+  use maplit::btreemap;
+  use pact_models::matchingrules::expressions::{MatchingRuleDefinition, parse_matcher_def, ValueType};
+  use pact_models::{matchingrules, matchingrules_list};
+  use pact_models::matchingrules::RuleLogic;
+  use prost::encoding::WireType;
+  use prost::Message;
+  use prost_types::{DescriptorProto, EnumDescriptorProto, EnumOptions, EnumValueDescriptorProto, FieldDescriptorProto, FileDescriptorSet, MessageOptions};
+  use prost_types::field_descriptor_proto::Label::{Optional, Repeated};
+  use prost_types::field_descriptor_proto::Type::{Enum, String};
+
+  use crate::message_decoder::ProtobufField;
+
+  use super::*;
+
+  const DESCRIPTORS: &'static str = "CuIFChxnb29nbGUvcHJvdG9idWYvc3RydWN0LnByb3RvEg9nb29\
+    nbGUucHJvdG9idWYimAEKBlN0cnVjdBI7CgZmaWVsZHMYASADKAsyIy5nb29nbGUucHJvdG9idWYuU3RydWN0LkZpZWxkc\
+    0VudHJ5UgZmaWVsZHMaUQoLRmllbGRzRW50cnkSEAoDa2V5GAEgASgJUgNrZXkSLAoFdmFsdWUYAiABKAsyFi5nb29nbGU\
+    ucHJvdG9idWYuVmFsdWVSBXZhbHVlOgI4ASKyAgoFVmFsdWUSOwoKbnVsbF92YWx1ZRgBIAEoDjIaLmdvb2dsZS5wcm90b2\
+    J1Zi5OdWxsVmFsdWVIAFIJbnVsbFZhbHVlEiMKDG51bWJlcl92YWx1ZRgCIAEoAUgAUgtudW1iZXJWYWx1ZRIjCgxzdHJpb\
+    mdfdmFsdWUYAyABKAlIAFILc3RyaW5nVmFsdWUSHwoKYm9vbF92YWx1ZRgEIAEoCEgAUglib29sVmFsdWUSPAoMc3RydWN0\
+    X3ZhbHVlGAUgASgLMhcuZ29vZ2xlLnByb3RvYnVmLlN0cnVjdEgAUgtzdHJ1Y3RWYWx1ZRI7CgpsaXN0X3ZhbHVlGAYgASg\
+    LMhouZ29vZ2xlLnByb3RvYnVmLkxpc3RWYWx1ZUgAUglsaXN0VmFsdWVCBgoEa2luZCI7CglMaXN0VmFsdWUSLgoGdmFsdW\
+    VzGAEgAygLMhYuZ29vZ2xlLnByb3RvYnVmLlZhbHVlUgZ2YWx1ZXMqGwoJTnVsbFZhbHVlEg4KCk5VTExfVkFMVUUQAEJ/C\
+    hNjb20uZ29vZ2xlLnByb3RvYnVmQgtTdHJ1Y3RQcm90b1ABWi9nb29nbGUuZ29sYW5nLm9yZy9wcm90b2J1Zi90eXBlcy9r\
+    bm93bi9zdHJ1Y3RwYvgBAaICA0dQQqoCHkdvb2dsZS5Qcm90b2J1Zi5XZWxsS25vd25UeXBlc2IGcHJvdG8zCoYECh5nb29\
+    nbGUvcHJvdG9idWYvd3JhcHBlcnMucHJvdG8SD2dvb2dsZS5wcm90b2J1ZiIjCgtEb3VibGVWYWx1ZRIUCgV2YWx1ZRgBIA\
+    EoAVIFdmFsdWUiIgoKRmxvYXRWYWx1ZRIUCgV2YWx1ZRgBIAEoAlIFdmFsdWUiIgoKSW50NjRWYWx1ZRIUCgV2YWx1ZRgBI\
+    AEoA1IFdmFsdWUiIwoLVUludDY0VmFsdWUSFAoFdmFsdWUYASABKARSBXZhbHVlIiIKCkludDMyVmFsdWUSFAoFdmFsdWUY\
+    ASABKAVSBXZhbHVlIiMKC1VJbnQzMlZhbHVlEhQKBXZhbHVlGAEgASgNUgV2YWx1ZSIhCglCb29sVmFsdWUSFAoFdmFsdWU\
+    YASABKAhSBXZhbHVlIiMKC1N0cmluZ1ZhbHVlEhQKBXZhbHVlGAEgASgJUgV2YWx1ZSIiCgpCeXRlc1ZhbHVlEhQKBXZhbH\
+    VlGAEgASgMUgV2YWx1ZUKDAQoTY29tLmdvb2dsZS5wcm90b2J1ZkINV3JhcHBlcnNQcm90b1ABWjFnb29nbGUuZ29sYW5nL\
+    m9yZy9wcm90b2J1Zi90eXBlcy9rbm93bi93cmFwcGVyc3Bi+AEBogIDR1BCqgIeR29vZ2xlLlByb3RvYnVmLldlbGxLbm93\
+    blR5cGVzYgZwcm90bzMKvgEKG2dvb2dsZS9wcm90b2J1Zi9lbXB0eS5wcm90bxIPZ29vZ2xlLnByb3RvYnVmIgcKBUVtcHR\
+    5Qn0KE2NvbS5nb29nbGUucHJvdG9idWZCCkVtcHR5UHJvdG9QAVouZ29vZ2xlLmdvbGFuZy5vcmcvcHJvdG9idWYvdHlwZX\
     Mva25vd24vZW1wdHlwYvgBAaICA0dQQqoCHkdvb2dsZS5Qcm90b2J1Zi5XZWxsS25vd25UeXBlc2IGcHJvdG8zCv0iCgxwb\
     HVnaW4ucHJvdG8SDmlvLnBhY3QucGx1Z2luGhxnb29nbGUvcHJvdG9idWYvc3RydWN0LnByb3RvGh5nb29nbGUvcHJvdG9i\
     dWYvd3JhcHBlcnMucHJvdG8aG2dvb2dsZS9wcm90b2J1Zi9lbXB0eS5wcm90byJVChFJbml0UGx1Z2luUmVxdWVzdBImCg5\
@@ -751,300 +2854,2806 @@ mod tests {
     ZRJiCg9HZW5lcmF0ZUNvbnRlbnQSJi5pby5wYWN0LnBsdWdpbi5HZW5lcmF0ZUNvbnRlbnRSZXF1ZXN0GicuaW8ucGFjdC5\
     wbHVnaW4uR2VuZXJhdGVDb250ZW50UmVzcG9uc2VCEFoOaW8ucGFjdC5wbHVnaW5iBnByb3RvMw==";
 
+  /// Plugin configuration for the Protobuf plugin with the given interaction configuration
+  fn protobuf_config(interaction_configuration: HashMap<std::string::String, serde_json::Value>) -> HashMap<std::string::String, PluginInteractionConfig> {
+    hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: hashmap!{},
+        interaction_configuration
+      }
+    }
+  }
+
+  /// Matching context with the matching rules and the interaction configuration for the Protobuf plugin
+  fn context_with_config(
+    rules: &MatchingRuleCategory,
+    interaction_configuration: HashMap<std::string::String, serde_json::Value>
+  ) -> CoreMatchingContext {
+    CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, rules, &protobuf_config(interaction_configuration))
+  }
+
+  #[test_log::test]
+  fn compare_message_where_the_actual_field_is_missing_due_it_being_the_default_enum_value() {
+    let bytes = BASE64.decode(DESCRIPTORS).unwrap();
+    let bytes1 = Bytes::copy_from_slice(bytes.as_slice());
+    let fds = FileDescriptorSet::decode(bytes1).unwrap();
+
+    let (message_descriptor, _) = find_message_type_by_name("InitPluginResponse", &fds).unwrap();
+
+    let path = DocPath::new("$").unwrap();
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &matchingrules_list! {
+      "body"; 
+      "$.catalogue.type" => [ MatchingRule::Regex("CONTENT_MATCHER|CONTENT_GENERATOR".into()) ],
+      "$.catalogue.key" => [ MatchingRule::NotEmpty ],
+      "$.catalogue.*" => [ MatchingRule::Type ],
+      "$.catalogue" => [ MatchingRule::Values ]
+    }, &hashmap!{});
+
+    let descriptor = DescriptorProto { 
+      name: Some("CatalogueEntry".to_string()), 
+      field: [
+        FieldDescriptorProto { 
+          name: Some("type".to_string()), 
+          number: Some(1), 
+          label: Some(Optional as i32), 
+          r#type: Some(Enum as i32), 
+          type_name: Some(".io.pact.plugin.CatalogueEntry.EntryType".to_string()), 
+          extendee: None, 
+          default_value: None, 
+          oneof_index: None, 
+          json_name: Some("type".to_string()), 
+          options: None, 
+          proto3_optional: None 
+        }, 
+        FieldDescriptorProto { 
+          name: Some("key".to_string()), 
+          number: Some(2), 
+          label: Some(Optional as i32), 
+          r#type: Some(String as i32), 
+          type_name: None, 
+          extendee: None, 
+          default_value: None, 
+          oneof_index: None, 
+          json_name: Some("key".to_string()), 
+          options: None, 
+          proto3_optional: None 
+        }, 
+        FieldDescriptorProto { 
+          name: Some("values".to_string()), 
+          number: Some(3), 
+          label: Some(Repeated as i32), 
+          r#type: Some(prost_types::field_descriptor_proto::Type::Message as i32), 
+          type_name: Some(".io.pact.plugin.CatalogueEntry.ValuesEntry".to_string()), 
+          extendee: None, 
+          default_value: None, 
+          oneof_index: None, 
+          json_name: Some("values".to_string()), 
+          options: None, 
+          proto3_optional: None 
+        }
+      ].to_vec(), 
+      extension: Default::default(), 
+      nested_type: [
+        DescriptorProto { 
+          name: Some("ValuesEntry".to_string()), 
+          field: [
+            FieldDescriptorProto { 
+              name: Some("key".to_string()), 
+              number: Some(1), 
+              label: Some(Optional as i32), 
+              r#type: Some(String as i32), 
+              type_name: None, 
+              extendee: None, 
+              default_value: None, 
+              oneof_index: None, 
+              json_name: Some("key".to_string()), 
+              options: None, 
+              proto3_optional: None 
+            }, 
+            FieldDescriptorProto { 
+              name: Some("value".to_string()), 
+              number: Some(2), 
+              label: Some(Optional as i32), 
+              r#type: Some(String as i32), 
+              type_name: None, 
+              extendee: None, 
+              default_value: None, 
+              oneof_index: None, 
+              json_name: Some("value".to_string()), 
+              options: None, 
+              proto3_optional: None 
+            }
+          ].to_vec(), 
+          extension: Default::default(), 
+          nested_type: Default::default(), 
+          enum_type: Default::default(), 
+          extension_range: Default::default(), 
+          oneof_decl: Default::default(), 
+          options: Some(MessageOptions { 
+            message_set_wire_format: None, 
+            no_standard_descriptor_accessor: None, 
+            deprecated: None, 
+            map_entry: Some(true), 
+            uninterpreted_option: Default::default() 
+          }), 
+          reserved_range: Default::default(), 
+          reserved_name: Default::default() 
+        }
+      ].to_vec(), 
+      enum_type: [
+        EnumDescriptorProto { 
+          name: Some("EntryType".to_string()), 
+          value: [
+            EnumValueDescriptorProto { 
+              name: Some("CONTENT_MATCHER".to_string()), 
+              number: Some(0), 
+              options: None 
+            }, 
+            EnumValueDescriptorProto { 
+              name: Some("CONTENT_GENERATOR".to_string()), 
+              number: Some(1), 
+              options: None 
+            }, 
+            EnumValueDescriptorProto { 
+              name: Some("TRANSPORT".to_string()), 
+              number: Some(2), 
+              options: None 
+            }, 
+            EnumValueDescriptorProto { 
+              name: Some("MATCHER".to_string()), 
+              number: Some(3), 
+              options: None 
+            }, 
+            EnumValueDescriptorProto { 
+              name: Some("INTERACTION".to_string()), 
+              number: Some(4), 
+              options: None 
+            }
+          ].to_vec(), 
+          options: None, 
+          reserved_range: Default::default(), 
+          reserved_name: Default::default() 
+        }
+      ].to_vec(), 
+      extension_range: Default::default(), 
+      oneof_decl: Default::default(), 
+      options: None, 
+      reserved_range: Default::default(), 
+      reserved_name: Default::default() 
+    };
+    let expected = vec![
+      ProtobufField { 
+        field_num: 1,
+        field_name: "catalogue".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::Message(vec![8, 0, 18, 4, 116, 101, 115, 116], descriptor.clone()) 
+      }
+    ];
+    let actual = vec![
+        ProtobufField { 
+          field_num: 1,
+          field_name: "catalogue".to_string(),
+          wire_type: WireType::LengthDelimited,
+          data: ProtobufFieldData::Message(vec![18, 8, 112, 114, 111, 116, 111, 98, 117, 102, 26, 54, 10, 13, 99, 111, 110, 116, 101, 110, 116, 45, 116, 121, 112, 101, 115, 18, 37, 97, 112, 112, 108, 105, 99, 97, 116, 105, 111, 110, 47, 112, 114, 111, 116, 111, 98, 117, 102, 59, 97, 112, 112, 108, 105, 99, 97, 116, 105, 111, 110, 47, 103, 114, 112, 99], descriptor.clone())
+        }
+    ];
+
+    let result = compare_message(
+      path,
+      &expected,
+      &actual,
+      &context,
+      &message_descriptor,
+      &fds,
+    ).unwrap();
+
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_repeated_field_and_each_value_matcher() {
+    let descriptors = base64::engine::general_purpose::STANDARD.decode(
+      "CogCCgxzaW1wbGUucHJvdG8iGwoJTWVzc2FnZUluEg4KAmluGAEgASgIUgJpbiIeCgpNZXNzYWdlT3V0EhAKA291\
+    dBgBIAEoCFIDb3V0IicKD1ZhbHVlc01lc3NhZ2VJbhIUCgV2YWx1ZRgBIAMoCVIFdmFsdWUiKAoQVmFsdWVzTWVzc2FnZU\
+    91dBIUCgV2YWx1ZRgBIAMoCVIFdmFsdWUyYAoEVGVzdBIkCgdHZXRUZXN0EgouTWVzc2FnZUluGgsuTWVzc2FnZU91dCIA\
+    EjIKCUdldFZhbHVlcxIQLlZhbHVlc01lc3NhZ2VJbhoRLlZhbHVlc01lc3NhZ2VPdXQiAGIGcHJvdG8z").unwrap();
+    let fds = FileDescriptorSet::decode(descriptors.as_slice()).unwrap();
+
+    let (message_descriptor, _) = find_message_type_by_name("ValuesMessageIn", &fds).unwrap();
+
+    let path = DocPath::new("$").unwrap();
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &matchingrules_list! {
+      "body";
+      "$.value" => [
+        MatchingRule::EachValue(MatchingRuleDefinition::new("00000000000000000000000000000000".to_string(), ValueType::Unknown, MatchingRule::Type, None))
+      ]
+    }, &hashmap!{});
+    let expected = vec![
+      ProtobufField {
+        field_num: 1,
+        field_name: "value".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::String("00000000000000000000000000000000".to_string())
+      }
+    ];
+    let actual = vec![
+      ProtobufField {
+        field_num: 1,
+        field_name: "value".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::String("value1".to_string())
+      },
+      ProtobufField {
+        field_num: 1,
+        field_name: "value".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::String("value2".to_string())
+      }
+    ];
+
+    let result = compare_message(
+      path,
+      &expected,
+      &actual,
+      &context,
+      &message_descriptor,
+      &fds,
+    ).unwrap();
+
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_repeated_string_field_and_each_value_matcher_with_a_regex() {
+    let descriptors = base64::engine::general_purpose::STANDARD.decode(
+      "CusDChBlYWNoX3ZhbHVlLnByb3RvIhsKCU1lc3NhZ2VJbhIOCgJpbhgBIAEoCFICaW4iVQoKTWVzc2FnZU91d\
+      BJHChRyZXNvdXJjZV9wZXJtaXNzaW9ucxgBIAMoCzIULlJlc291cmNlUGVybWlzc2lvbnNSE3Jlc291cmNlUGVybWlzc\
+      2lvbnMiXQoTUmVzb3VyY2VQZXJtaXNzaW9ucxIlCghyZXNvdXJjZRgBIAEoCzIJLlJlc291cmNlUghyZXNvdXJjZRIfC\
+      gZlZmZlY3QYAiABKAsyBy5FZmZlY3RSBmVmZmVjdCJ3CghSZXNvdXJjZRIxChRhcHBsaWNhdGlvbl9yZXNvdXJjZRgBI\
+      AEoCVITYXBwbGljYXRpb25SZXNvdXJjZRIgCgtwZXJtaXNzaW9ucxgCIAMoCVILcGVybWlzc2lvbnMSFgoGZ3JvdXBzG\
+      AMgAygJUgZncm91cHMiLQoGRWZmZWN0EiMKBnJlc3VsdBgBIAEoDjILLkVmZmVjdEVudW1SBnJlc3VsdComCgpFZmZlY\
+      3RFbnVtEhgKFEVORk9SQ0VfRUZGRUNUX0FMTE9XEAAyLAoEVGVzdBIkCgdHZXRUZXN0EgouTWVzc2FnZUluGgsuTWVzc\
+      2FnZU91dCIAYgZwcm90bzM=").unwrap();
+    let fds = FileDescriptorSet::decode(descriptors.as_slice()).unwrap();
+
+    let (message_descriptor, _) = find_message_type_by_name("Resource", &fds).unwrap();
+
+    let each_value = MatchingRule::EachValue(MatchingRuleDefinition::new("foo".to_string(), ValueType::Unknown, MatchingRule::Type, None));
+    let each_value_groups = MatchingRule::EachValue(MatchingRuleDefinition::new(
+      "00000000000000000000000000000000".to_string(), ValueType::Unknown,
+      MatchingRule::Regex(r"[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}|\*".to_string()), None));
+    let matching_rules = matchingrules! {
+      "body" => {
+        "$.resource_permissions.*.resource.application_resource" => [ MatchingRule::Type ],
+        "$.resource_permissions" => [ MatchingRule::Values ],
+        "$.resource_permissions.*" => [ MatchingRule::Type ],
+        "$.resource_permissions.*.resource.permissions" => [ each_value ],
+        "$.resource_permissions.*.resource.groups" => [ each_value_groups ]
+      }
+    };
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys,
+      &matching_rules.rules_for_category("body").unwrap(), &hashmap!{});
+    let expected = vec![
+      ProtobufField {
+        field_num: 3,
+        field_name: "groups".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::String("*".to_string())
+      }
+    ];
+    let actual = vec![
+      ProtobufField {
+        field_num: 3,
+        field_name: "groups".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::String("*".to_string())
+      },
+      ProtobufField {
+        field_num: 3,
+        field_name: "groups".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::String("*".to_string())
+      }
+    ];
+
+    let path = DocPath::new("$.resource_permissions.*.resource").unwrap();
+    let result = compare_message(
+      path,
+      &expected,
+      &actual,
+      &context,
+      &message_descriptor,
+      &fds,
+    ).unwrap();
+
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[test]
+  fn null_as_absent_test() {
+    let expected = serde_json::json!({ "a": null, "b": { "c": null }, "d": 1 });
+
+    let mut actual = serde_json::json!({ "b": {}, "d": 1 });
+    null_as_absent(&expected, &mut actual);
+    expect!(actual).to(be_equal_to(serde_json::json!({ "a": null, "b": { "c": null }, "d": 1 })));
+
+    let mut actual = serde_json::json!({ "a": "present", "b": { "c": 100 }, "d": 1 });
+    null_as_absent(&expected, &mut actual);
+    expect!(actual.clone()).to(be_equal_to(serde_json::json!({ "a": "present", "b": { "c": 100 }, "d": 1 })));
+
+    let context = CoreMatchingContext::default();
+    let result = compare_json(&DocPath::root(), &expected, &actual, &context);
+    expect!(result.unwrap_err().len()).to(be_equal_to(2));
+  }
+
+  #[test_log::test]
+  fn compare_struct_field_as_json_with_nested_key_matcher() {
+    let struct_field = |a: prost_types::value::Kind| {
+      let value = prost_types::Struct {
+        fields: btreemap! {
+          "a".to_string() => prost_types::Value { kind: Some(a) },
+          "b".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::StructValue(prost_types::Struct {
+            fields: btreemap! {
+              "c".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::NumberValue(1.0)) }
+            }
+          })) }
+        }
+      };
+      ProtobufField {
+        field_num: 1,
+        field_name: "value".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::Message(value.encode_to_vec(), DescriptorProto {
+          name: Some("Struct".to_string()),
+          .. DescriptorProto::default()
+        })
+      }
+    };
+    let descriptor = FieldDescriptorProto {
+      name: Some("value".to_string()),
+      number: Some(1),
+      r#type: Some(prost_types::field_descriptor_proto::Type::Message as i32),
+      type_name: Some(".google.protobuf.Struct".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &matchingrules_list! {
+      "body"; "$.value.a" => [ MatchingRule::Type ]
+    }, &hashmap!{});
+    let path = DocPath::new("$.value").unwrap();
+    let descriptors = FileDescriptorSet { file: vec![] };
+
+    let expected = struct_field(prost_types::value::Kind::StringValue("x".to_string()));
+    let actual = struct_field(prost_types::value::Kind::StringValue("y".to_string()));
+    let result = compare_field(&path, &expected, &descriptor, &actual, &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+
+    let actual = struct_field(prost_types::value::Kind::NumberValue(10.0));
+    let result = compare_field(&path, &expected, &descriptor, &actual, &context, &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+  }
+
+  #[test_log::test]
+  fn compare_non_finite_floating_point_values() {
+    let value_field = |kind: prost_types::value::Kind| ProtobufField {
+      field_num: 1,
+      field_name: "value".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::Message(prost_types::Value { kind: Some(kind) }.encode_to_vec(), DescriptorProto {
+        name: Some("Value".to_string()),
+        .. DescriptorProto::default()
+      })
+    };
+    let descriptor = FieldDescriptorProto {
+      name: Some("value".to_string()),
+      number: Some(1),
+      r#type: Some(prost_types::field_descriptor_proto::Type::Message as i32),
+      type_name: Some(".google.protobuf.Value".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let context = CoreMatchingContext::default();
+    let path = DocPath::new("$.value").unwrap();
+    let descriptors = FileDescriptorSet { file: vec![] };
+
+    let expected = value_field(prost_types::value::Kind::StringValue("NaN".to_string()));
+    let actual = value_field(prost_types::value::Kind::NumberValue(f64::NAN));
+    let result = compare_field(&path, &expected, &descriptor, &actual, &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+
+    let expected = value_field(prost_types::value::Kind::StringValue("-Infinity".to_string()));
+    let actual = value_field(prost_types::value::Kind::NumberValue(f64::NEG_INFINITY));
+    let result = compare_field(&path, &expected, &descriptor, &actual, &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+
+    let actual = value_field(prost_types::value::Kind::NumberValue(f64::INFINITY));
+    let result = compare_field(&path, &expected, &descriptor, &actual, &context, &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+
+    let double_field = |value: f64| ProtobufField {
+      field_num: 1,
+      field_name: "value".to_string(),
+      wire_type: WireType::SixtyFourBit,
+      data: ProtobufFieldData::Double(value)
+    };
+    let descriptor = FieldDescriptorProto {
+      name: Some("value".to_string()),
+      number: Some(1),
+      r#type: Some(prost_types::field_descriptor_proto::Type::Double as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let result = compare_field(&path, &double_field(f64::NAN), &descriptor, &double_field(f64::NAN),
+      &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+    let result = compare_field(&path, &double_field(f64::NAN), &descriptor, &double_field(1.0),
+      &context, &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+  }
+
+  #[test_log::test]
+  fn compare_integer_boundary_values() {
+    let field = |data: ProtobufFieldData| ProtobufField {
+      field_num: 1,
+      field_name: "value".to_string(),
+      wire_type: WireType::Varint,
+      data
+    };
+    let descriptor = FieldDescriptorProto {
+      name: Some("value".to_string()),
+      number: Some(1),
+      .. FieldDescriptorProto::default()
+    };
+    let context = CoreMatchingContext::default();
+    let path = DocPath::new("$.value").unwrap();
+    let descriptors = FileDescriptorSet { file: vec![] };
+
+    let result = compare_field(&path, &field(ProtobufFieldData::UInteger64(u64::MAX)), &descriptor,
+      &field(ProtobufFieldData::UInteger64(u64::MAX)), &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+    let result = compare_field(&path, &field(ProtobufFieldData::UInteger64(u64::MAX)), &descriptor,
+      &field(ProtobufFieldData::UInteger64(u64::MAX - 1)), &context, &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+
+    let result = compare_field(&path, &field(ProtobufFieldData::Integer64(i64::MIN)), &descriptor,
+      &field(ProtobufFieldData::Integer64(i64::MIN)), &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+    let result = compare_field(&path, &field(ProtobufFieldData::Integer64(i64::MIN)), &descriptor,
+      &field(ProtobufFieldData::Integer64(i64::MIN + 1)), &context, &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+
+    let result = compare_field(&path, &field(ProtobufFieldData::Integer32(-1)), &descriptor,
+      &field(ProtobufFieldData::Integer32(-1)), &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+    let result = compare_field(&path, &field(ProtobufFieldData::Integer64(-1)), &descriptor,
+      &field(ProtobufFieldData::Integer64(-1)), &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+  }
+
+  fn luhn_valid(number: &str) -> bool {
+    let digits = number.chars().rev().map(|c| c.to_digit(10)).collect::<Option<Vec<u32>>>();
+    digits.map(|digits| digits.iter().enumerate()
+      .map(|(index, digit)| if index % 2 == 1 {
+        let doubled = digit * 2;
+        if doubled > 9 { doubled - 9 } else { doubled }
+      } else {
+        *digit
+      })
+      .sum::<u32>() % 10 == 0)
+      .unwrap_or(false)
+  }
+
+  #[test_log::test]
+  fn compare_field_with_a_custom_matcher() {
+    register_custom_matcher("luhn", |_, actual| match actual {
+      ProtobufFieldData::String(number) if luhn_valid(number) => Ok(()),
+      _ => Err(format!("Expected {} to be a valid card number", actual))
+    });
+
+    let field = |number: &str| ProtobufField {
+      field_num: 1,
+      field_name: "card".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(number.to_string())
+    };
+    let descriptor = FieldDescriptorProto {
+      name: Some("card".to_string()),
+      number: Some(1),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let context = context_with_config(&MatchingRuleCategory::empty("body"), hashmap!{
+      CUSTOM_MATCHERS_CONFIG.to_string() => serde_json::json!({ "$.card": "luhn" })
+    });
+    let path = DocPath::new("$.card").unwrap();
+    let descriptors = FileDescriptorSet { file: vec![] };
+
+    let result = compare_field(&path, &field("4111111111111111"), &descriptor, &field("5555555555554444"),
+      &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+
+    let result = compare_field(&path, &field("4111111111111111"), &descriptor, &field("4111111111111112"),
+      &context, &descriptors);
+    expect!(result).to(be_equal_to(vec![BodyMismatch {
+      path: "$.card".to_string(),
+      expected: Some("\"4111111111111111\"".into()),
+      actual: Some("\"4111111111111112\"".into()),
+      mismatch: "Expected \"4111111111111112\" to be a valid card number".to_string()
+    }]));
+
+    expect!(remove_custom_matcher("luhn")).to(be_true());
+    let result = compare_field(&path, &field("4111111111111111"), &descriptor, &field("4111111111111111"),
+      &context, &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+  }
+
+  #[test_log::test]
+  fn compare_field_reports_all_the_matcher_failures() {
+    let field = |name: &str| ProtobufField {
+      field_num: 1,
+      field_name: "name".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(name.to_string())
+    };
+    let descriptor = FieldDescriptorProto {
+      name: Some("name".to_string()),
+      number: Some(1),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let mut rules = MatchingRuleCategory::empty("body");
+    rules.add_rule(DocPath::new("$.name").unwrap(), MatchingRule::Regex("^[a-z]+$".to_string()), RuleLogic::And);
+    rules.add_rule(DocPath::new("$.name").unwrap(), MatchingRule::MinType(5), RuleLogic::And);
+    let context = context_with_config(&rules, hashmap!{
+      LENGTH_FIELDS_CONFIG.to_string() => serde_json::json!(["$.name"])
+    });
+    let path = DocPath::new("$.name").unwrap();
+    let descriptors = FileDescriptorSet { file: vec![] };
+
+    let result = compare_field(&path, &field("harry"), &descriptor, &field("sally"), &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+
+    let result = compare_field(&path, &field("harry"), &descriptor, &field("abc"), &context, &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+
+    let result = compare_field(&path, &field("harry"), &descriptor, &field("AB1"), &context, &descriptors);
+    expect!(result.len()).to(be_equal_to(2));
+    let mismatches = result.iter()
+      .map(|mismatch| mismatch.description())
+      .collect_vec();
+    expect!(mismatches.iter().any(|m| m.contains("^[a-z]+$"))).to(be_true());
+    expect!(mismatches.iter().any(|m| m.contains("Expected 'AB1' to have at least 5 characters but it has 3"))).to(be_true());
+
+    // Without the length fields configuration, the min type matcher only checks the type
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &rules, &hashmap!{});
+    let result = compare_field(&path, &field("harry"), &descriptor, &field("AB1"), &context, &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+  }
+
+  #[test_log::test]
+  fn compare_bytes_field_with_a_length_matcher() {
+    let field = |length: usize| ProtobufField {
+      field_num: 1,
+      field_name: "hash".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::Bytes(vec![0xAB; length])
+    };
+    let descriptor = FieldDescriptorProto {
+      name: Some("hash".to_string()),
+      number: Some(1),
+      r#type: Some(Type::Bytes as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let context_for = |rule: MatchingRule| {
+      let mut rules = MatchingRuleCategory::empty("body");
+      rules.add_rule(DocPath::new("$.hash").unwrap(), rule, RuleLogic::And);
+      context_with_config(&rules, hashmap!{
+        LENGTH_FIELDS_CONFIG.to_string() => serde_json::json!(true)
+      })
+    };
+    let path = DocPath::new("$.hash").unwrap();
+    let descriptors = FileDescriptorSet { file: vec![] };
+
+    let exact = context_for(MatchingRule::MinMaxType(32, 32));
+    let result = compare_field(&path, &field(32), &descriptor, &field(32), &exact, &descriptors);
+    expect!(result.iter()).to(be_empty());
+    let result = compare_field(&path, &field(32), &descriptor, &field(31), &exact, &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+    expect!(result[0].description().contains("to have exactly 32 bytes but it has 31")).to(be_true());
+
+    let result = compare_field(&path, &field(32), &descriptor, &field(16), &context_for(MatchingRule::MinType(20)), &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+    expect!(result[0].description().contains("to have at least 20 bytes but it has 16")).to(be_true());
+    let result = compare_field(&path, &field(32), &descriptor, &field(64), &context_for(MatchingRule::MaxType(48)), &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+    expect!(result[0].description().contains("to have at most 48 bytes but it has 64")).to(be_true());
+  }
+
+  #[test_log::test]
+  fn compare_message_with_a_field_mask() {
+    let field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(String as i32),
+      label: Some(Optional as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Message".to_string()),
+      field: vec![ field("a", 1), field("b", 2), field("c", 3) ],
+      .. DescriptorProto::default()
+    };
+    let message = |a: &str, b: &str, c: &str| {
+      [(1, "a", a), (2, "b", b), (3, "c", c)].iter()
+        .map(|(num, name, value)| ProtobufField {
+          field_num: *num,
+          field_name: name.to_string(),
+          wire_type: WireType::LengthDelimited,
+          data: ProtobufFieldData::String(value.to_string())
+        })
+        .collect_vec()
+    };
+    let context = CoreMatchingContext::default();
+    let descriptors = FileDescriptorSet { file: vec![] };
+
+    let expected = message("a", "b", "c");
+    let actual = message("x", "b", "z");
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(2));
+
+    let field_mask = field_mask_paths(&serde_json::json!("b"));
+    expect!(apply_field_mask(result.clone(), &field_mask)).to(be_equal_to(BodyMatchResult::Ok));
+
+    let field_mask = field_mask_paths(&serde_json::json!(["a"]));
+    let masked = apply_field_mask(result, &field_mask);
+    let paths = masked.mismatches().iter()
+      .map(|mismatch| match mismatch {
+        BodyMismatch { path, .. } => path.clone(),
+        _ => "".to_string()
+      })
+      .collect_vec();
+    expect!(paths).to(be_equal_to(vec!["$.a".to_string()]));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_an_actual_message_missing_a_default_valued_field() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Message".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          r#type: Some(String as i32),
+          label: Some(Optional as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let expected = vec![
+      ProtobufField {
+        field_num: 1,
+        field_name: "name".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::String("".to_string())
+      }
+    ];
+    let actual = vec![];
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let context_for = |treat_defaults_as_present: bool| {
+      context_with_config(&MatchingRuleCategory::empty("body"), hashmap!{
+        TREAT_DEFAULTS_AS_PRESENT_CONFIG.to_string() => serde_json::Value::Bool(treat_defaults_as_present)
+      })
+    };
+
+    let result = compare_message(DocPath::root(), &expected, &actual, &context_for(false),
+      &message_descriptor, &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = compare_message(DocPath::root(), &expected, &actual, &context_for(true),
+      &message_descriptor, &descriptors).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.name".to_string(),
+        expected: Some(Bytes::new()),
+        actual: None,
+        mismatch: "Expected field 'name' to be set to \"\", but it was not received".to_string()
+      }
+    ]));
+  }
+
+  #[test_log::test]
+  fn compare_field_with_an_unknown_enum_value() {
+    let enum_descriptor = EnumDescriptorProto {
+      name: Some("Status".to_string()),
+      value: vec![
+        EnumValueDescriptorProto { name: Some("UNKNOWN".to_string()), number: Some(0), options: None },
+        EnumValueDescriptorProto { name: Some("ACTIVE".to_string()), number: Some(1), options: None }
+      ],
+      .. EnumDescriptorProto::default()
+    };
+    let descriptor = FieldDescriptorProto {
+      name: Some("status".to_string()),
+      number: Some(1),
+      r#type: Some(Enum as i32),
+      type_name: Some(".Status".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let field = |value: i32| ProtobufField {
+      field_num: 1,
+      field_name: "status".to_string(),
+      wire_type: WireType::Varint,
+      data: ProtobufFieldData::Enum(value, enum_descriptor.clone())
+    };
+    let path = DocPath::new("$.status").unwrap();
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules_list! {
+      "body"; "$.status" => [ MatchingRule::Type ]
+    };
+    let context_for = |mode: &str| context_with_config(&rules, hashmap!{
+      UNKNOWN_ENUM_VALUES_CONFIG.to_string() => serde_json::json!(mode)
+    });
+
+    let result = compare_field(&path, &field(1), &descriptor, &field(7), &context_for("strict"), &descriptors);
+    expect!(result).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.status".to_string(),
+        expected: Some("ACTIVE".into()),
+        actual: Some("7".into()),
+        mismatch: "Expected a value defined for enum Status, but received the unknown value 7".to_string()
+      }
+    ]));
+    let result = compare_field(&path, &field(1), &descriptor, &field(1), &context_for("strict"), &descriptors);
+    expect!(result.iter()).to(be_empty());
+
+    let result = compare_field(&path, &field(1), &descriptor, &field(7), &context_for("lenient"), &descriptors);
+    expect!(result.iter()).to(be_empty());
+  }
+
+  #[test_log::test]
+  fn compare_string_field_ignoring_case() {
+    let descriptor = FieldDescriptorProto {
+      name: Some("code".to_string()),
+      number: Some(1),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let field = |value: &str| ProtobufField {
+      field_num: 1,
+      field_name: "code".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(value.to_string())
+    };
+    let path = DocPath::new("$.code").unwrap();
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let context_for = |ignore_case: serde_json::Value| context_with_config(&MatchingRuleCategory::empty("body"), hashmap!{
+      IGNORE_CASE_CONFIG.to_string() => ignore_case
+    });
+
+    let result = compare_field(&path, &field("ABC"), &descriptor, &field("abc"), &context_for(serde_json::json!(true)), &descriptors);
+    expect!(result.iter()).to(be_empty());
+    let result = compare_field(&path, &field("ABC"), &descriptor, &field("abc"), &context_for(serde_json::json!(["$.code"])), &descriptors);
+    expect!(result.iter()).to(be_empty());
+    let result = compare_field(&path, &field("ABC"), &descriptor, &field("abd"), &context_for(serde_json::json!(true)), &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+
+    let result = compare_field(&path, &field("ABC"), &descriptor, &field("abc"), &context_for(serde_json::json!(false)), &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+    let result = compare_field(&path, &field("ABC"), &descriptor, &field("abc"), &context_for(serde_json::json!(["$.other"])), &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+
+    // matchers get the original values
+    let rules = matchingrules_list! {
+      "body"; "$.code" => [ MatchingRule::Regex("^[A-Z]+$".to_string()) ]
+    };
+    let context = context_with_config(&rules, hashmap!{
+      IGNORE_CASE_CONFIG.to_string() => serde_json::json!(true)
+    });
+    let result = compare_field(&path, &field("ABC"), &descriptor, &field("abc"), &context, &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+    let result = compare_field(&path, &field("ABC"), &descriptor, &field("XYZ"), &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+  }
+
+  #[test_log::test]
+  fn compare_string_field_with_locale_formatted_numbers() {
+    let descriptor = FieldDescriptorProto {
+      name: Some("amount".to_string()),
+      number: Some(1),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let field = |value: &str| ProtobufField {
+      field_num: 1,
+      field_name: "amount".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(value.to_string())
+    };
+    let path = DocPath::new("$.amount").unwrap();
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let context_for = |locale: &str| context_with_config(&MatchingRuleCategory::empty("body"), hashmap!{
+      NUMBER_LOCALES_CONFIG.to_string() => serde_json::json!({ "$.amount": locale })
+    });
+
+    let result = compare_field(&path, &field("1234.56"), &descriptor, &field("1.234,56"), &context_for("de-DE"), &descriptors);
+    expect!(result.iter()).to(be_empty());
+    let result = compare_field(&path, &field("1.234,56"), &descriptor, &field("1.234,56"), &context_for("de-DE"), &descriptors);
+    expect!(result.iter()).to(be_empty());
+    let result = compare_field(&path, &field("1234.56"), &descriptor, &field("1\u{202f}234,56"), &context_for("fr-FR"), &descriptors);
+    expect!(result.iter()).to(be_empty());
+    let result = compare_field(&path, &field("1234.56"), &descriptor, &field("1.234,57"), &context_for("de-DE"), &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+    let result = compare_field(&path, &field("1234.56"), &descriptor, &field("abc"), &context_for("de-DE"), &descriptors);
+    expect!(result).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.amount".to_string(),
+        expected: Some("1234.56".into()),
+        actual: Some("abc".into()),
+        mismatch: "Actual value 'abc' is not a number - 'abc' is not a number formatted for the locale 'de-DE'".to_string()
+      }
+    ]));
+
+    // The locale is only applied when the values are compared with equality
+    let rules = matchingrules_list! {
+      "body"; "$.amount" => [ MatchingRule::Regex(r"^\d\.\d{3},\d{2}$".to_string()) ]
+    };
+    let context = context_with_config(&rules, hashmap!{
+      NUMBER_LOCALES_CONFIG.to_string() => serde_json::json!({ "$.amount": "de-DE" })
+    });
+    let result = compare_field(&path, &field("1.234,56"), &descriptor, &field("9.876,54"), &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+
+    expect!(parse_locale_number("1,234.56", "en-US").unwrap()).to(be_equal_to(1234.56));
+    expect!(parse_locale_number("1'234.56", "de-CH").unwrap()).to(be_equal_to(1234.56));
+    expect!(parse_locale_number("-1.234.567,5", "de-DE").unwrap()).to(be_equal_to(-1234567.5));
+    expect!(parse_locale_number("1234,56", "de-DE").unwrap()).to(be_equal_to(1234.56));
+    expect!(parse_locale_number(",5", "de-DE").unwrap()).to(be_equal_to(0.5));
+    expect!(parse_locale_number("1.234,56", "xx")).to(be_err());
+    expect!(parse_locale_number("1,2,3", "de-DE")).to(be_err());
+    expect!(parse_locale_number("12.34,5", "de-DE")).to(be_err());
+    expect!(parse_locale_number("1234.567,5", "de-DE")).to(be_err());
+    expect!(parse_locale_number(".234,5", "de-DE")).to(be_err());
+    expect!(parse_locale_number("1,23.4", "de-DE")).to(be_err());
+    expect!(parse_locale_number("1e5", "en-US")).to(be_err());
+    expect!(parse_locale_number("", "en-US")).to(be_err());
+  }
+
+  #[test_log::test]
+  fn match_message_ignores_redacted_fields() {
+    use prost::encoding::{encode_key, encode_varint};
+
+    let field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(Optional as i32),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let append = crate::utils::tests::append_length_delimited;
+
+    // message Person { string name = 1; string ssn = 2 [(redacted) = true]; }
+    let mut ssn_field = field("ssn", 2).encode_to_vec();
+    let mut options = vec![];
+    encode_key(50001, WireType::Varint, &mut options);
+    encode_varint(1, &mut options);
+    append(&mut ssn_field, 8, &options);
+    let mut message = DescriptorProto {
+      name: Some("Person".to_string()),
+      field: vec![ field("name", 1) ],
+      .. DescriptorProto::default()
+    }.encode_to_vec();
+    append(&mut message, 2, &ssn_field);
+    let mut file = prost_types::FileDescriptorProto {
+      name: Some("person.proto".to_string()),
+      package: Some("test".to_string()),
+      extension: vec![
+        FieldDescriptorProto {
+          extendee: Some(".google.protobuf.FieldOptions".to_string()),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Bool as i32),
+          .. field("redacted", 50001)
+        }
+      ],
+      syntax: Some("proto3".to_string()),
+      .. prost_types::FileDescriptorProto::default()
+    }.encode_to_vec();
+    append(&mut file, 4, &message);
+    let mut descriptor_bytes = vec![];
+    append(&mut descriptor_bytes, 1, &file);
+    let descriptor_key = format!("{:x}", md5::compute(&descriptor_bytes));
+    let descriptors = FileDescriptorSet::decode(descriptor_bytes.as_slice()).unwrap();
+
+    let plugin_config = |interaction_configuration: HashMap<std::string::String, serde_json::Value>| hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: hashmap!{
+          descriptor_key.clone() => serde_json::json!({ "protoDescriptors": BASE64.encode(&descriptor_bytes) })
+        },
+        interaction_configuration
+      }
+    };
+    let person = |name: &str, ssn: &str| {
+      let mut buffer = vec![];
+      prost::encoding::string::encode(1, &name.to_string(), &mut buffer);
+      prost::encoding::string::encode(2, &ssn.to_string(), &mut buffer);
+      Bytes::from(buffer)
+    };
+
+    let redacted = plugin_config(hashmap!{
+      "descriptorKey".to_string() => serde_json::json!(descriptor_key),
+      REDACTION_OPTION_CONFIG.to_string() => serde_json::json!("test.redacted")
+    });
+    let result = match_message_with_config("Person", &descriptors, &mut person("Fred", "111-11-1111"),
+      &mut person("Fred", "222-22-2222"), &MatchingRuleCategory::empty("body"), false, &redacted).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+    let result = match_message_with_config("Person", &descriptors, &mut person("Fred", "111-11-1111"),
+      &mut person("Bob", "222-22-2222"), &MatchingRuleCategory::empty("body"), false, &redacted).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
+
+    let result = match_message_with_config("Person", &descriptors, &mut person("Fred", "111-11-1111"),
+      &mut person("Fred", "222-22-2222"), &MatchingRuleCategory::empty("body"), false, &plugin_config(hashmap!{})).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
+  }
+
+  #[test]
+  fn is_redacted_field_compares_the_fully_qualified_name() {
+    let person = DescriptorProto {
+      name: Some("Person".to_string()),
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        prost_types::FileDescriptorProto {
+          name: Some("person.proto".to_string()),
+          package: Some("test".to_string()),
+          message_type: vec![ person.clone() ],
+          .. prost_types::FileDescriptorProto::default()
+        }
+      ]
+    };
+    let context_for = |fields: serde_json::Value| context_with_config(&MatchingRuleCategory::empty("body"), hashmap!{
+      REDACTED_FIELDS_CONFIG.to_string() => fields
+    });
+
+    expect!(is_redacted_field(&person, "ssn", &context_for(serde_json::json!(["test.Person.ssn"])), &descriptors)).to(be_true());
+    expect!(is_redacted_field(&person, "ssn", &context_for(serde_json::json!(["other.test.Person.ssn"])), &descriptors)).to(be_false());
+    expect!(is_redacted_field(&person, "ssn", &context_for(serde_json::json!(["Person.ssn"])), &descriptors)).to(be_false());
+    expect!(is_redacted_field(&person, "name", &context_for(serde_json::json!(["test.Person.ssn"])), &descriptors)).to(be_false());
+  }
+
+  #[test_log::test]
+  fn compare_message_with_a_default_matcher() {
+    let field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Customer".to_string()),
+      field: vec![ field("name", 1), field("id", 2) ],
+      .. DescriptorProto::default()
+    };
+    let value = |number: u32, name: &str, value: &str| ProtobufField {
+      field_num: number,
+      field_name: name.to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(value.to_string())
+    };
+    let expected = vec![ value(1, "name", "Fred"), value(2, "id", "100") ];
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules_list! {
+      "body"; "$.id" => [ MatchingRule::Regex("^[0-9]+$".to_string()) ]
+    };
+    let context = context_with_config(&rules, hashmap!{
+      DEFAULT_MATCHER_CONFIG.to_string() => serde_json::json!("matching(type, '')")
+    });
+
+    let actual = vec![ value(1, "name", "Mary"), value(2, "id", "200") ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let actual = vec![ value(1, "name", "Mary"), value(2, "id", "X200") ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors).unwrap();
+    let paths = result.mismatches().iter()
+      .map(|mismatch| match mismatch {
+        BodyMismatch { path, .. } => path.clone(),
+        _ => "".to_string()
+      })
+      .collect_vec();
+    expect!(paths).to(be_equal_to(vec!["$.id".to_string()]));
+  }
+
+  #[test_log::test]
+  fn compare_repeated_field_where_the_expected_value_applies_to_each_element() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Values".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("values".to_string()),
+          number: Some(1),
+          r#type: Some(String as i32),
+          label: Some(Repeated as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let field = |value: &str| ProtobufField {
+      field_num: 1,
+      field_name: "values".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(value.to_string())
+    };
+    let expected = vec![ field("a") ];
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let context = context_with_config(&MatchingRuleCategory::empty("body"), hashmap!{
+      EACH_EQUALS_CONFIG.to_string() => serde_json::json!(["$.values"])
+    });
+
+    let actual = vec![ field("a"), field("a"), field("a") ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let actual = vec![ field("a"), field("b"), field("a") ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors).unwrap();
+    let paths = result.mismatches().iter()
+      .map(|mismatch| match mismatch {
+        BodyMismatch { path, .. } => path.clone(),
+        _ => "".to_string()
+      })
+      .collect_vec();
+    expect!(paths).to(be_equal_to(vec!["$.values[1]".to_string()]));
+
+    let result = compare_message(DocPath::root(), &expected, &[], &context,
+      &message_descriptor, &descriptors).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.values".to_string(),
+        expected: Some(Bytes::from("a")),
+        actual: None,
+        mismatch: "Expected each value of repeated field 'values' to equal \"a\", but received no values".to_string()
+      }
+    ]));
+  }
+
+  #[test_log::test]
+  fn compare_repeated_field_where_the_expected_values_are_a_subsequence() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Values".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("values".to_string()),
+          number: Some(1),
+          r#type: Some(String as i32),
+          label: Some(Repeated as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let field = |value: &str| ProtobufField {
+      field_num: 1,
+      field_name: "values".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(value.to_string())
+    };
+    let expected = vec![ field("a"), field("c") ];
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let context = context_with_config(&MatchingRuleCategory::empty("body"), hashmap!{
+      SUBSEQUENCE_CONFIG.to_string() => serde_json::json!(["$.values"])
+    });
+
+    let actual = vec![ field("a"), field("b"), field("c"), field("d") ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let actual = vec![ field("c"), field("b"), field("a") ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.values".to_string(),
+        expected: Some("\"c\"".into()),
+        actual: None,
+        mismatch: "Expected repeated field 'values' to contain the expected values in order, but could not find expected value 1 (\"c\")".to_string()
+      }
+    ]));
+  }
+
+  #[test_log::test]
+  fn compare_repeated_enum_field_with_included_and_excluded_values() {
+    let enum_descriptor = EnumDescriptorProto {
+      name: Some("Feature".to_string()),
+      value: vec![
+        EnumValueDescriptorProto { name: Some("NONE".to_string()), number: Some(0), options: None },
+        EnumValueDescriptorProto { name: Some("SEARCH".to_string()), number: Some(1), options: None },
+        EnumValueDescriptorProto { name: Some("EXPORT".to_string()), number: Some(2), options: None },
+        EnumValueDescriptorProto { name: Some("BETA".to_string()), number: Some(3), options: None }
+      ],
+      .. EnumDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Account".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("features".to_string()),
+          number: Some(1),
+          r#type: Some(Enum as i32),
+          label: Some(Repeated as i32),
+          type_name: Some(".Feature".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let field = |value: i32| ProtobufField {
+      field_num: 1,
+      field_name: "features".to_string(),
+      wire_type: WireType::Varint,
+      data: ProtobufFieldData::Enum(value, enum_descriptor.clone())
+    };
+    let expected = vec![ field(1) ];
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let context = context_with_config(&MatchingRuleCategory::empty("body"), hashmap!{
+      ENUM_INCLUDES_CONFIG.to_string() => serde_json::json!({ "$.features": ["SEARCH"] }),
+      ENUM_EXCLUDES_CONFIG.to_string() => serde_json::json!({ "$.features": ["BETA"] })
+    });
+
+    let actual = vec![ field(2), field(1) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let actual = vec![ field(1), field(3) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.features[1]".to_string(),
+        expected: None,
+        actual: Some("BETA".into()),
+        mismatch: "Expected repeated field 'features' to not include the value BETA, but it was received at index 1".to_string()
+      }
+    ]));
+
+    let actual = vec![ field(2) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.features".to_string(),
+        expected: Some("SEARCH".into()),
+        actual: None,
+        mismatch: "Expected repeated field 'features' to include the value SEARCH".to_string()
+      }
+    ]));
+  }
+
+  #[test_log::test]
+  fn compare_enum_field_with_aliased_values() {
+    // enum Status { option allow_alias = true; UNKNOWN = 0; STARTED = 1; RUNNING = 1; STOPPED = 2; }
+    let enum_descriptor = EnumDescriptorProto {
+      name: Some("Status".to_string()),
+      value: vec![
+        EnumValueDescriptorProto { name: Some("UNKNOWN".to_string()), number: Some(0), options: None },
+        EnumValueDescriptorProto { name: Some("STARTED".to_string()), number: Some(1), options: None },
+        EnumValueDescriptorProto { name: Some("RUNNING".to_string()), number: Some(1), options: None },
+        EnumValueDescriptorProto { name: Some("STOPPED".to_string()), number: Some(2), options: None }
+      ],
+      options: Some(EnumOptions { allow_alias: Some(true), .. EnumOptions::default() }),
+      .. EnumDescriptorProto::default()
+    };
+    let descriptor = FieldDescriptorProto {
+      name: Some("status".to_string()),
+      number: Some(1),
+      r#type: Some(Enum as i32),
+      type_name: Some(".Status".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let field = |value: i32| ProtobufField {
+      field_num: 1,
+      field_name: "status".to_string(),
+      wire_type: WireType::Varint,
+      data: ProtobufFieldData::Enum(value, enum_descriptor.clone())
+    };
+    let path = DocPath::new("$.status").unwrap();
+    let descriptors = FileDescriptorSet { file: vec![] };
+
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &MatchingRuleCategory::empty("body"), &hashmap!{});
+    let result = compare_field(&path, &field(1), &descriptor, &field(1), &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+
+    // The expected value was defined with the alias RUNNING, which the actual value matches
+    let mut rules = MatchingRuleCategory::empty("body");
+    rules.add_rule(DocPath::new("$.status").unwrap(), MatchingRule::Regex("^RUNNING$".to_string()), RuleLogic::And);
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &rules, &hashmap!{});
+    let result = compare_field(&path, &field(1), &descriptor, &field(1), &context, &descriptors);
+    expect!(result.iter()).to(be_empty());
+    let result = compare_field(&path, &field(1), &descriptor, &field(2), &context, &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+
+    let field_descriptor = FieldDescriptorProto { label: Some(Repeated as i32), .. descriptor.clone() };
+    let result = compare_enum_membership(&path, &field_descriptor, &[ field(1) ], &[ "RUNNING".to_string() ], &[]);
+    expect!(result.iter()).to(be_empty());
+    let result = compare_enum_membership(&path, &field_descriptor, &[ field(1) ], &[], &[ "RUNNING".to_string() ]);
+    expect!(result.len()).to(be_equal_to(1));
+  }
+
+  #[test_log::test]
+  fn compare_with_a_regex_for_the_json_rendering_of_the_message() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Ticket".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("id".to_string()),
+          number: Some(1),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("note".to_string()),
+          number: Some(2),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let field = |num: u32, name: &str, value: &str| ProtobufField {
+      field_num: num,
+      field_name: name.to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(value.to_string())
+    };
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let expected = vec![ field(1, "id", "1") ];
+    let rules = matchingrules_list! {
+      "body"; "$.id" => [ MatchingRule::Type ]
+    };
+    let context = context_with_config(&rules, hashmap!{
+      JSON_REGEX_CONFIG.to_string() => serde_json::json!("\"note\":\"[^\"]*URGENT")
+    });
+
+    let actual = vec![ field(1, "id", "2"), field(2, "note", "Marked URGENT by support") ];
+    let result = compare(&message_descriptor, &expected, &actual, &context, &Bytes::new(), &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let actual = vec![ field(1, "id", "2"), field(2, "note", "Can wait") ];
+    let result = compare(&message_descriptor, &expected, &actual, &context, &Bytes::new(), &descriptors).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$".to_string(),
+        expected: None,
+        actual: Some("{\"id\":\"2\",\"note\":\"Can wait\"}".into()),
+        mismatch: "Expected the JSON rendering of the message '{\"id\":\"2\",\"note\":\"Can wait\"}' to match '\"note\":\"[^\"]*URGENT'".to_string()
+      }
+    ]));
+  }
+
+  #[test_log::test]
+  fn compare_message_from_an_editions_file_with_explicit_field_presence() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Account".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("balance".to_string()),
+          number: Some(1),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Int64 as i32),
+          label: Some(Optional as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors_for = |syntax: &str| FileDescriptorSet {
+      file: vec![
+        prost_types::FileDescriptorProto {
+          name: Some("account.proto".to_string()),
+          syntax: Some(syntax.to_string()),
+          message_type: vec![ message_descriptor.clone() ],
+          .. prost_types::FileDescriptorProto::default()
+        }
+      ]
+    };
+    let expected = vec![
+      ProtobufField {
+        field_num: 1,
+        field_name: "balance".to_string(),
+        wire_type: WireType::Varint,
+        data: ProtobufFieldData::Integer64(0)
+      }
+    ];
+    let actual = vec![];
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys,
+      &MatchingRuleCategory::empty("body"), &hashmap!{});
+
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors_for("proto3")).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors_for("editions")).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.balance".to_string(),
+        expected: Some(Bytes::from(0i64.to_le_bytes().to_vec())),
+        actual: None,
+        mismatch: "Expected field 'balance' to be set to 0, but it was not received".to_string()
+      }
+    ]));
+  }
+
+  #[test_log::test]
+  fn match_message_resolves_the_field_presence_feature_from_the_editions_file() {
+    let descriptor_bytes = std::fs::read("tests/descriptors/editions.pb").unwrap();
+    let descriptor_key = format!("{:x}", md5::compute(&descriptor_bytes));
+    let descriptors = FileDescriptorSet::decode(descriptor_bytes.as_slice()).unwrap();
+    let plugin_config = hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: hashmap!{
+          descriptor_key.clone() => serde_json::json!({
+            "protoDescriptors": BASE64.encode(&descriptor_bytes),
+            "protoFile": "editions.proto"
+          })
+        },
+        interaction_configuration: hashmap!{
+          "descriptorKey".to_string() => serde_json::json!(descriptor_key)
+        }
+      }
+    };
+    let mut expected = BytesMut::new();
+    prost::encoding::string::encode(1, &"Fred".to_string(), &mut expected);
+    prost::encoding::int64::encode(2, &0, &mut expected);
+    prost::encoding::int64::encode(3, &0, &mut expected);
+    let mut actual = BytesMut::new();
+    prost::encoding::string::encode(1, &"Fred".to_string(), &mut actual);
+
+    let result = match_message_with_config("Account", &descriptors, &mut expected.freeze(),
+      &mut actual.freeze(), &MatchingRuleCategory::empty("body"), true, &plugin_config).unwrap();
+
+    // balance has implicit presence from the file features, but limit has explicit presence
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.limit".to_string(),
+        expected: Some(Bytes::from(0i64.to_le_bytes().to_vec())),
+        actual: None,
+        mismatch: "Expected field 'limit' to be set to 0, but it was not received".to_string()
+      }
+    ]));
+  }
+
+  #[test_log::test]
+  fn compare_field_with_an_embedded_message_in_a_bytes_value() {
+    let bytes_value_descriptor = DescriptorProto {
+      name: Some("BytesValue".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("value".to_string()),
+          number: Some(1),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Bytes as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let inner_descriptor = DescriptorProto {
+      name: Some("Inner".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        prost_types::FileDescriptorProto {
+          name: Some("inner.proto".to_string()),
+          message_type: vec![ inner_descriptor ],
+          .. prost_types::FileDescriptorProto::default()
+        }
+      ]
+    };
+    let descriptor = FieldDescriptorProto {
+      name: Some("payload".to_string()),
+      number: Some(1),
+      r#type: Some(prost_types::field_descriptor_proto::Type::Message as i32),
+      type_name: Some(".google.protobuf.BytesValue".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    // BytesValue { value: Inner { name } }
+    let field = |name: &str| {
+      let mut data = vec![0x0a, name.len() as u8 + 2, 0x0a, name.len() as u8];
+      data.extend_from_slice(name.as_bytes());
+      ProtobufField {
+        field_num: 1,
+        field_name: "payload".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::Message(data, bytes_value_descriptor.clone())
+      }
+    };
+    let path = DocPath::new("$.payload").unwrap();
+    let mut rules = MatchingRuleCategory::empty("body");
+    rules.add_rule(DocPath::new("$.payload.name").unwrap(), MatchingRule::Regex("^[a-z]+$".to_string()), RuleLogic::And);
+    let context = context_with_config(&rules, hashmap!{
+      BYTES_VALUE_TYPES_CONFIG.to_string() => serde_json::json!({ "$.payload": "Inner" })
+    });
+
+    let expected = field("abc");
+    expect!(compare_field(&path, &expected, &descriptor, &field("xyz"), &context, &descriptors).iter()).to(be_empty());
+    let result = compare_field(&path, &expected, &descriptor, &field("123"), &context, &descriptors);
+    let paths = result.iter()
+      .map(|mismatch| match mismatch {
+        BodyMismatch { path, .. } => path.clone(),
+        _ => "".to_string()
+      })
+      .collect_vec();
+    expect!(paths).to(be_equal_to(vec!["$.payload.name".to_string()]));
+
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &rules, &hashmap!{});
+    let result = compare_field(&path, &expected, &descriptor, &field("xyz"), &context, &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+  }
+
+  #[test_log::test]
+  fn compare_with_a_field_relation() {
+    let descriptor = DescriptorProto {
+      name: Some("Event".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("start_time".to_string()),
+          number: Some(1),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Int64 as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("end_time".to_string()),
+          number: Some(2),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Int64 as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let event = |start: i64, end: i64| vec![
+      ProtobufField {
+        field_num: 1,
+        field_name: "start_time".to_string(),
+        wire_type: WireType::Varint,
+        data: ProtobufFieldData::Integer64(start)
+      },
+      ProtobufField {
+        field_num: 2,
+        field_name: "end_time".to_string(),
+        wire_type: WireType::Varint,
+        data: ProtobufFieldData::Integer64(end)
+      }
+    ];
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules! {
+      "body" => {
+        "$.start_time" => [ MatchingRule::Type ],
+        "$.end_time" => [ MatchingRule::Type ]
+      }
+    }.rules_for_category("body").unwrap();
+    let context = context_with_config(&rules, hashmap!{
+      FIELD_RELATIONS_CONFIG.to_string() => serde_json::json!({ "$.end_time": ">= $.start_time" })
+    });
+
+    let expected = event(100, 200);
+    let result = compare(&descriptor, &expected, &event(300, 400), &context, &Bytes::new(), &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = compare(&descriptor, &expected, &event(300, 200), &context, &Bytes::new(), &descriptors).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.end_time".to_string(),
+        expected: Some("300".into()),
+        actual: Some("200".into()),
+        mismatch: "Expected field '$.end_time' (200) to be >= field '$.start_time' (300)".to_string()
+      }
+    ]));
+  }
+
+  #[test_log::test]
+  fn compare_with_field_relations_scoped_to_the_request_or_response() {
+    let field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(Type::Int64 as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("Event".to_string()),
+      field: vec![ field("start_time", 1), field("end_time", 2) ],
+      .. DescriptorProto::default()
+    };
+    let event = |start: i64, end: i64| vec![
+      ProtobufField {
+        field_num: 1,
+        field_name: "start_time".to_string(),
+        wire_type: WireType::Varint,
+        data: ProtobufFieldData::Integer64(start)
+      },
+      ProtobufField {
+        field_num: 2,
+        field_name: "end_time".to_string(),
+        wire_type: WireType::Varint,
+        data: ProtobufFieldData::Integer64(end)
+      }
+    ];
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules! {
+      "body" => {
+        "$.start_time" => [ MatchingRule::Type ],
+        "$.end_time" => [ MatchingRule::Type ]
+      }
+    }.rules_for_category("body").unwrap();
+    let plugin_config = protobuf_config(hashmap!{
+      FIELD_RELATIONS_CONFIG.to_string() => serde_json::json!({
+        "response": { "$.end_time": ">= $.start_time" }
+      })
+    });
+
+    let expected = event(100, 200);
+    let request_config = with_message_part(&plugin_config, "request");
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &rules, &request_config);
+    let result = compare(&descriptor, &expected, &event(300, 200), &context, &Bytes::new(), &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let response_config = with_message_part(&plugin_config, "response");
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &rules, &response_config);
+    let result = compare(&descriptor, &expected, &event(300, 200), &context, &Bytes::new(), &descriptors).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
+  }
+
+  #[test_log::test]
+  fn compare_with_a_field_relation_to_a_field_that_does_not_exist() {
+    let descriptor = DescriptorProto {
+      name: Some("Event".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("start_time".to_string()),
+          number: Some(1),
+          r#type: Some(Type::Int64 as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let event = vec![
+      ProtobufField {
+        field_num: 1,
+        field_name: "start_time".to_string(),
+        wire_type: WireType::Varint,
+        data: ProtobufFieldData::Integer64(100)
+      }
+    ];
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules! {
+      "body" => { "$.start_time" => [ MatchingRule::Type ] }
+    }.rules_for_category("body").unwrap();
+    let context = context_with_config(&rules, hashmap!{
+      FIELD_RELATIONS_CONFIG.to_string() => serde_json::json!({ "$.end_time": ">= $.start_time" })
+    });
+
+    let result = compare(&descriptor, &event, &event, &context, &Bytes::new(), &descriptors).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.end_time".to_string(),
+        expected: None,
+        actual: None,
+        mismatch: "Could not check the relation '>= $.start_time' for field '$.end_time' - Message Event does not have a field 'end_time'".to_string()
+      }
+    ]));
+  }
+
   #[test_log::test]
-  fn compare_message_where_the_actual_field_is_missing_due_it_being_the_default_enum_value() {
-    let bytes = BASE64.decode(DESCRIPTORS).unwrap();
-    let bytes1 = Bytes::copy_from_slice(bytes.as_slice());
-    let fds = FileDescriptorSet::decode(bytes1).unwrap();
+  fn compare_with_a_presence_group() {
+    let field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("Contact".to_string()),
+      field: vec![ field("name", 1), field("email", 2), field("phone", 3) ],
+      .. DescriptorProto::default()
+    };
+    let value = |number: u32, name: &str, value: &str| ProtobufField {
+      field_num: number,
+      field_name: name.to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(value.to_string())
+    };
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules! {
+      "body" => {
+        "$.email" => [ MatchingRule::Type ],
+        "$.phone" => [ MatchingRule::Type ]
+      }
+    }.rules_for_category("body").unwrap();
+    let context = context_with_config(&rules, hashmap!{
+      PRESENCE_GROUPS_CONFIG.to_string() => serde_json::json!([ [ "$.email", "phone" ] ])
+    });
 
-    let (message_descriptor, _) = find_message_type_by_name("InitPluginResponse", &fds).unwrap();
+    let expected = [ value(1, "name", "Fred") ];
+    let result = compare(&descriptor, &expected, &expected, &context, &Bytes::new(), &descriptors).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$".to_string(),
+        expected: None,
+        actual: None,
+        mismatch: "Expected at least one of the fields '$.email', '$.phone' to be set, but none were".to_string()
+      }
+    ]));
 
-    let path = DocPath::new("$").unwrap();
+    let actual = [ value(1, "name", "Fred"), value(3, "phone", "555-1234") ];
+    let result = compare(&descriptor, &expected, &actual, &context, &Bytes::new(), &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[test_log::test]
+  fn compare_with_presence_groups_scoped_to_the_request_or_response() {
+    let field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("Contact".to_string()),
+      field: vec![ field("name", 1), field("email", 2), field("phone", 3) ],
+      .. DescriptorProto::default()
+    };
+    let name = ProtobufField {
+      field_num: 1,
+      field_name: "name".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String("Fred".to_string())
+    };
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules! {
+      "body" => { "$.name" => [ MatchingRule::Type ] }
+    }.rules_for_category("body").unwrap();
+    let plugin_config = protobuf_config(hashmap!{
+      PRESENCE_GROUPS_CONFIG.to_string() => serde_json::json!({
+        "request": [ [ "email", "phone" ] ]
+      })
+    });
+
+    let expected = [ name ];
+    let response_config = with_message_part(&plugin_config, "response");
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &rules, &response_config);
+    let result = compare(&descriptor, &expected, &expected, &context, &Bytes::new(), &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let request_config = with_message_part(&plugin_config, "request");
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &rules, &request_config);
+    let result = compare(&descriptor, &expected, &expected, &context, &Bytes::new(), &descriptors).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
+  }
+
+  #[test_log::test]
+  fn filtered_matchers_only_apply_to_the_elements_matching_the_predicate() {
+    let item_descriptor = DescriptorProto {
+      name: Some("Item".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("type".to_string()),
+          number: Some(1),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("value".to_string()),
+          number: Some(2),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("Order".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("items".to_string()),
+          number: Some(1),
+          label: Some(Repeated as i32),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Message as i32),
+          type_name: Some(".Item".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let item = |item_type: &str, value: &str| {
+      let mut data = vec![0x0a, item_type.len() as u8];
+      data.extend_from_slice(item_type.as_bytes());
+      data.extend_from_slice(&[0x12, value.len() as u8]);
+      data.extend_from_slice(value.as_bytes());
+      ProtobufField {
+        field_num: 1,
+        field_name: "items".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::Message(data, item_descriptor.clone())
+      }
+    };
+    let actual = vec![ item("A", "123"), item("B", "abc"), item("A", "x1") ];
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let context = context_with_config(&MatchingRuleCategory::empty("body"), hashmap!{
+      FILTERED_MATCHERS_CONFIG.to_string() => serde_json::json!({
+        "$.items[?(@.type == 'A')].value": "matching(regex, '^[0-9]+$', '100')"
+      })
+    });
+
+    let result = match_filtered_matchers(BodyMatchResult::Ok, &actual, &descriptor, &context, &descriptors).unwrap();
+    let paths = result.mismatches().iter()
+      .map(|mismatch| match mismatch {
+        BodyMismatch { path, .. } => path.clone(),
+        _ => "".to_string()
+      })
+      .collect_vec();
+    expect!(paths).to(be_equal_to(vec!["$.items[2].value".to_string()]));
+
+    let actual = vec![ item("A", "123"), item("B", "abc") ];
+    let result = match_filtered_matchers(BodyMatchResult::Ok, &actual, &descriptor, &context, &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[test_log::test]
+  fn compare_repeated_message_field_ignores_fields_not_defined_for_the_expected_type() {
+    let item_descriptor = DescriptorProto {
+      name: Some("Item".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("Order".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("items".to_string()),
+          number: Some(1),
+          label: Some(Repeated as i32),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Message as i32),
+          type_name: Some(".Item".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    // A newer version of Item has a quantity (2) and a note (3) field
+    let item = |name: &str, added_fields: bool| {
+      let mut data = vec![0x0a, name.len() as u8];
+      data.extend_from_slice(name.as_bytes());
+      if added_fields {
+        data.extend_from_slice(&[0x10, 42, 0x1a, 4]);
+        data.extend_from_slice(b"note");
+      }
+      ProtobufField {
+        field_num: 1,
+        field_name: "items".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::Message(data, item_descriptor.clone())
+      }
+    };
+    let expected = vec![ item("a", false), item("b", false) ];
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &MatchingRuleCategory::empty("body"), &hashmap!{});
+
+    let actual = vec![ item("a", true), item("b", true) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context, &descriptor, &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let actual = vec![ item("a", true), item("c", true) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context, &descriptor, &descriptors).unwrap();
+    let paths = result.mismatches().iter()
+      .map(|mismatch| match mismatch {
+        BodyMismatch { path, .. } => path.clone(),
+        _ => "".to_string()
+      })
+      .collect_vec();
+    expect!(paths).to(be_equal_to(vec!["$.items[1].name".to_string()]));
+  }
+
+  #[test_log::test]
+  fn compare_proto2_message_with_a_group_field() {
+    // syntax = "proto2";
+    // message SearchResponse { optional group Result = 1 { optional string url = 2; optional string title = 3; } }
+    let result_descriptor = DescriptorProto {
+      name: Some("Result".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("url".to_string()),
+          number: Some(2),
+          label: Some(Optional as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("title".to_string()),
+          number: Some(3),
+          label: Some(Optional as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("SearchResponse".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("result".to_string()),
+          number: Some(1),
+          label: Some(Optional as i32),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Group as i32),
+          type_name: Some(".SearchResponse.Result".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      nested_type: vec![ result_descriptor ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet { file: vec![] };
+    // The group is encoded between a start group (1, 3) and an end group (1, 4) tag
+    let message = |url: &str, title: &str| {
+      let mut bytes = vec![0x0b, 0x12, url.len() as u8];
+      bytes.extend_from_slice(url.as_bytes());
+      bytes.extend_from_slice(&[0x1a, title.len() as u8]);
+      bytes.extend_from_slice(title.as_bytes());
+      bytes.push(0x0c);
+      decode_message(&mut Bytes::from(bytes), &descriptor, &descriptors).unwrap()
+    };
+    let expected = message("https://example.com", "Example");
+    expect!(expected.first().map(|field| field.wire_type)).to(be_some().value(WireType::StartGroup));
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &MatchingRuleCategory::empty("body"), &hashmap!{});
+
+    let result = compare(&descriptor, &expected, &message("https://example.com", "Example"), &context, &Bytes::new(), &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = compare(&descriptor, &expected, &message("https://example.org", "Example"), &context, &Bytes::new(), &descriptors).unwrap();
+    let paths = result.mismatches().iter()
+      .map(|mismatch| match mismatch {
+        BodyMismatch { path, .. } => path.clone(),
+        _ => "".to_string()
+      })
+      .collect_vec();
+    expect!(paths).to(be_equal_to(vec!["$.result.url".to_string()]));
+  }
+
+  #[test_log::test]
+  fn match_message_with_only_the_schema() {
+    // syntax = "proto2";
+    // message Item { required string sku = 1; }
+    // message Order { required int32 id = 1; optional string note = 2; optional Item item = 3; }
+    let item_descriptor = DescriptorProto {
+      name: Some("Item".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("sku".to_string()),
+          number: Some(1),
+          label: Some(Label::Required as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let order_descriptor = DescriptorProto {
+      name: Some("Order".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("id".to_string()),
+          number: Some(1),
+          label: Some(Label::Required as i32),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Int32 as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("note".to_string()),
+          number: Some(2),
+          label: Some(Optional as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("item".to_string()),
+          number: Some(3),
+          label: Some(Optional as i32),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Message as i32),
+          type_name: Some(".test.Item".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        prost_types::FileDescriptorProto {
+          name: Some("order.proto".to_string()),
+          package: Some("test".to_string()),
+          message_type: vec![ item_descriptor, order_descriptor ],
+          syntax: Some("proto2".to_string()),
+          .. prost_types::FileDescriptorProto::default()
+        }
+      ]
+    };
+    let order = |id: Option<u8>, sku: Option<&str>| {
+      let mut bytes = vec![];
+      if let Some(id) = id {
+        bytes.extend_from_slice(&[0x08, id]);
+      }
+      if let Some(sku) = sku {
+        let mut item = vec![];
+        if !sku.is_empty() {
+          item.extend_from_slice(&[0x0a, sku.len() as u8]);
+          item.extend_from_slice(sku.as_bytes());
+        }
+        bytes.extend_from_slice(&[0x1a, item.len() as u8]);
+        bytes.extend(item);
+      }
+      Bytes::from(bytes)
+    };
+    let schema_only = protobuf_config(hashmap!{ SCHEMA_ONLY_CONFIG.to_string() => serde_json::json!(true) });
+    let rules = MatchingRuleCategory::empty("body");
+    let paths = |result: BodyMatchResult| result.mismatches().iter()
+      .map(|mismatch| match mismatch {
+        BodyMismatch { path, .. } => path.clone(),
+        _ => "".to_string()
+      })
+      .sorted()
+      .collect_vec();
+
+    // A well-formed response with no example values
+    let result = match_message_with_config("Order", &descriptors, &mut Bytes::new(), &mut order(Some(100), Some("ABC-1")),
+      &rules, true, &schema_only).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    // Any example values are ignored
+    let result = match_message_with_config("Order", &descriptors, &mut order(Some(1), None), &mut order(Some(100), None),
+      &rules, true, &schema_only).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+    let result = match_message("Order", &descriptors, &mut order(Some(1), None), &mut order(Some(100), None),
+      &rules, true).unwrap();
+    expect!(paths(result)).to(be_equal_to(vec!["$.id".to_string()]));
+
+    let result = match_message_with_config("Order", &descriptors, &mut Bytes::new(), &mut order(None, Some("")),
+      &rules, true, &schema_only).unwrap();
+    expect!(paths(result)).to(be_equal_to(vec!["$.id".to_string(), "$.item.sku".to_string()]));
+
+    let result = match_message_with_config("Order", &descriptors, &mut Bytes::new(), &mut Bytes::from(vec![0x1a, 0x05, 0x0a]),
+      &rules, true, &schema_only).unwrap();
+    expect!(paths(result)).to(be_equal_to(vec!["$".to_string()]));
+
+    // The request message is still compared with the expected one
+    let result = match_message_with_config("Order", &descriptors, &mut order(Some(1), None), &mut order(Some(100), None),
+      &rules, true, &with_message_part(&schema_only, "request")).unwrap();
+    expect!(paths(result)).to(be_equal_to(vec!["$.id".to_string()]));
+    let result = match_message_with_config("Order", &descriptors, &mut order(Some(1), None), &mut order(Some(100), None),
+      &rules, true, &with_message_part(&schema_only, "response")).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_not_empty_matchers() {
+    // message Profile { string name = 1; bytes avatar = 2; repeated string tags = 3; map<string, string> labels = 4; }
+    let entry_field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(Optional as i32),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("Profile".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          label: Some(Optional as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("avatar".to_string()),
+          number: Some(2),
+          label: Some(Optional as i32),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Bytes as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("tags".to_string()),
+          number: Some(3),
+          label: Some(Repeated as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("labels".to_string()),
+          number: Some(4),
+          label: Some(Repeated as i32),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Message as i32),
+          type_name: Some(".Profile.LabelsEntry".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      nested_type: vec![
+        DescriptorProto {
+          name: Some("LabelsEntry".to_string()),
+          field: vec![ entry_field("key", 1), entry_field("value", 2) ],
+          options: Some(MessageOptions { map_entry: Some(true), .. MessageOptions::default() }),
+          .. DescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let profile = |name: &str, avatar: &[u8], tag: &str, label: (&str, &str)| {
+      let mut bytes = vec![0x0a, name.len() as u8];
+      bytes.extend_from_slice(name.as_bytes());
+      bytes.extend_from_slice(&[0x12, avatar.len() as u8]);
+      bytes.extend_from_slice(avatar);
+      bytes.extend_from_slice(&[0x1a, tag.len() as u8]);
+      bytes.extend_from_slice(tag.as_bytes());
+      bytes.extend_from_slice(&[0x22, (label.0.len() + label.1.len() + 4) as u8, 0x0a, label.0.len() as u8]);
+      bytes.extend_from_slice(label.0.as_bytes());
+      bytes.extend_from_slice(&[0x12, label.1.len() as u8]);
+      bytes.extend_from_slice(label.1.as_bytes());
+      decode_message(&mut Bytes::from(bytes), &descriptor, &descriptors).unwrap()
+    };
+    let expected = profile("Fred", &[1, 2, 3], "admin", ("team", "core"));
     let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &matchingrules_list! {
-      "body"; 
-      "$.catalogue.type" => [ MatchingRule::Regex("CONTENT_MATCHER|CONTENT_GENERATOR".into()) ],
-      "$.catalogue.key" => [ MatchingRule::NotEmpty ],
-      "$.catalogue.*" => [ MatchingRule::Type ],
-      "$.catalogue" => [ MatchingRule::Values ]
+      "body";
+      "$.name" => [ MatchingRule::NotEmpty ],
+      "$.avatar" => [ MatchingRule::NotEmpty ],
+      "$.tags" => [ MatchingRule::NotEmpty ],
+      "$.labels" => [ MatchingRule::NotEmpty ]
     }, &hashmap!{});
 
-    let descriptor = DescriptorProto { 
-      name: Some("CatalogueEntry".to_string()), 
-      field: [
-        FieldDescriptorProto { 
-          name: Some("type".to_string()), 
-          number: Some(1), 
-          label: Some(Optional as i32), 
-          r#type: Some(Enum as i32), 
-          type_name: Some(".io.pact.plugin.CatalogueEntry.EntryType".to_string()), 
-          extendee: None, 
-          default_value: None, 
-          oneof_index: None, 
-          json_name: Some("type".to_string()), 
-          options: None, 
-          proto3_optional: None 
-        }, 
-        FieldDescriptorProto { 
-          name: Some("key".to_string()), 
-          number: Some(2), 
-          label: Some(Optional as i32), 
-          r#type: Some(String as i32), 
-          type_name: None, 
-          extendee: None, 
-          default_value: None, 
-          oneof_index: None, 
-          json_name: Some("key".to_string()), 
-          options: None, 
-          proto3_optional: None 
-        }, 
-        FieldDescriptorProto { 
-          name: Some("values".to_string()), 
-          number: Some(3), 
-          label: Some(Repeated as i32), 
-          r#type: Some(prost_types::field_descriptor_proto::Type::Message as i32), 
-          type_name: Some(".io.pact.plugin.CatalogueEntry.ValuesEntry".to_string()), 
-          extendee: None, 
-          default_value: None, 
-          oneof_index: None, 
-          json_name: Some("values".to_string()), 
-          options: None, 
-          proto3_optional: None 
+    let result = compare_message(DocPath::root(), &expected, &profile("Bob", &[4, 5], "user", ("region", "eu")),
+      &context, &descriptor, &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    // proto3 does not send empty values, so the fields are not set in the actual message
+    let result = compare_message(DocPath::root(), &expected, &[], &context, &descriptor, &descriptors).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(|mismatch| match mismatch {
+        BodyMismatch { path, mismatch, .. } => (path.clone(), mismatch.clone()),
+        _ => ("".to_string(), "".to_string())
+      })
+      .sorted()
+      .collect_vec();
+    expect!(mismatches).to(be_equal_to(vec![
+      ("$.avatar".to_string(), "Expected field 'avatar' to not be empty".to_string()),
+      ("$.labels".to_string(), "Expected field 'labels' to not be empty".to_string()),
+      ("$.name".to_string(), "Expected field 'name' to not be empty".to_string()),
+      ("$.tags".to_string(), "Expected field 'tags' to not be empty".to_string())
+    ]));
+  }
+
+  #[test_log::test]
+  fn compare_any_field_accepting_more_than_one_type() {
+    let any_descriptor = DescriptorProto {
+      name: Some("Any".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("type_url".to_string()),
+          number: Some(1),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("value".to_string()),
+          number: Some(2),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Bytes as i32),
+          .. FieldDescriptorProto::default()
         }
-      ].to_vec(), 
-      extension: Default::default(), 
-      nested_type: [
-        DescriptorProto { 
-          name: Some("ValuesEntry".to_string()), 
-          field: [
-            FieldDescriptorProto { 
-              name: Some("key".to_string()), 
-              number: Some(1), 
-              label: Some(Optional as i32), 
-              r#type: Some(String as i32), 
-              type_name: None, 
-              extendee: None, 
-              default_value: None, 
-              oneof_index: None, 
-              json_name: Some("key".to_string()), 
-              options: None, 
-              proto3_optional: None 
-            }, 
-            FieldDescriptorProto { 
-              name: Some("value".to_string()), 
-              number: Some(2), 
-              label: Some(Optional as i32), 
-              r#type: Some(String as i32), 
-              type_name: None, 
-              extendee: None, 
-              default_value: None, 
-              oneof_index: None, 
-              json_name: Some("value".to_string()), 
-              options: None, 
-              proto3_optional: None 
-            }
-          ].to_vec(), 
-          extension: Default::default(), 
-          nested_type: Default::default(), 
-          enum_type: Default::default(), 
-          extension_range: Default::default(), 
-          oneof_decl: Default::default(), 
-          options: Some(MessageOptions { 
-            message_set_wire_format: None, 
-            no_standard_descriptor_accessor: None, 
-            deprecated: None, 
-            map_entry: Some(true), 
-            uninterpreted_option: Default::default() 
-          }), 
-          reserved_range: Default::default(), 
-          reserved_name: Default::default() 
+      ],
+      .. DescriptorProto::default()
+    };
+    let inner_descriptor = DescriptorProto {
+      name: Some("Inner".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
         }
-      ].to_vec(), 
-      enum_type: [
-        EnumDescriptorProto { 
-          name: Some("EntryType".to_string()), 
-          value: [
-            EnumValueDescriptorProto { 
-              name: Some("CONTENT_MATCHER".to_string()), 
-              number: Some(0), 
-              options: None 
-            }, 
-            EnumValueDescriptorProto { 
-              name: Some("CONTENT_GENERATOR".to_string()), 
-              number: Some(1), 
-              options: None 
-            }, 
-            EnumValueDescriptorProto { 
-              name: Some("TRANSPORT".to_string()), 
-              number: Some(2), 
-              options: None 
-            }, 
-            EnumValueDescriptorProto { 
-              name: Some("MATCHER".to_string()), 
-              number: Some(3), 
-              options: None 
-            }, 
-            EnumValueDescriptorProto { 
-              name: Some("INTERACTION".to_string()), 
-              number: Some(4), 
-              options: None 
-            }
-          ].to_vec(), 
-          options: None, 
-          reserved_range: Default::default(), 
-          reserved_name: Default::default() 
+      ],
+      .. DescriptorProto::default()
+    };
+    let other_descriptor = DescriptorProto {
+      name: Some("Other".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("id".to_string()),
+          number: Some(1),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Int32 as i32),
+          .. FieldDescriptorProto::default()
         }
-      ].to_vec(), 
-      extension_range: Default::default(), 
-      oneof_decl: Default::default(), 
-      options: None, 
-      reserved_range: Default::default(), 
-      reserved_name: Default::default() 
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        prost_types::FileDescriptorProto {
+          name: Some("inner.proto".to_string()),
+          message_type: vec![ inner_descriptor, other_descriptor ],
+          .. prost_types::FileDescriptorProto::default()
+        }
+      ]
     };
-    let expected = vec![
-      ProtobufField { 
+    let descriptor = FieldDescriptorProto {
+      name: Some("detail".to_string()),
+      number: Some(1),
+      r#type: Some(prost_types::field_descriptor_proto::Type::Message as i32),
+      type_name: Some(".google.protobuf.Any".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    // Any { type_url, value }
+    let field = |type_url: &str, value: &[u8]| {
+      let mut data = vec![0x0a, type_url.len() as u8];
+      data.extend_from_slice(type_url.as_bytes());
+      data.extend_from_slice(&[0x12, value.len() as u8]);
+      data.extend_from_slice(value);
+      ProtobufField {
         field_num: 1,
-        field_name: "catalogue".to_string(),
+        field_name: "detail".to_string(),
         wire_type: WireType::LengthDelimited,
-        data: ProtobufFieldData::Message(vec![8, 0, 18, 4, 116, 101, 115, 116], descriptor.clone()) 
+        data: ProtobufFieldData::Message(data, any_descriptor.clone())
       }
-    ];
-    let actual = vec![
-        ProtobufField { 
-          field_num: 1,
-          field_name: "catalogue".to_string(),
-          wire_type: WireType::LengthDelimited,
-          data: ProtobufFieldData::Message(vec![18, 8, 112, 114, 111, 116, 111, 98, 117, 102, 26, 54, 10, 13, 99, 111, 110, 116, 101, 110, 116, 45, 116, 121, 112, 101, 115, 18, 37, 97, 112, 112, 108, 105, 99, 97, 116, 105, 111, 110, 47, 112, 114, 111, 116, 111, 98, 117, 102, 59, 97, 112, 112, 108, 105, 99, 97, 116, 105, 111, 110, 47, 103, 114, 112, 99], descriptor.clone())
+    };
+    let inner = |name: &str| {
+      let mut data = vec![0x0a, name.len() as u8];
+      data.extend_from_slice(name.as_bytes());
+      data
+    };
+    let path = DocPath::new("$.detail").unwrap();
+    let mut rules = MatchingRuleCategory::empty("body");
+    rules.add_rule(DocPath::new("$.detail.name").unwrap(), MatchingRule::Regex("^[a-z]+$".to_string()), RuleLogic::And);
+    let context = context_with_config(&rules, hashmap!{
+      ANY_TYPES_CONFIG.to_string() => serde_json::json!({
+        "$.detail": [ "type.googleapis.com/Inner", "type.googleapis.com/Other" ]
+      })
+    });
+
+    let expected = field("type.googleapis.com/Inner", &inner("abc"));
+    expect!(compare_field(&path, &expected, &descriptor, &field("type.googleapis.com/Inner", &inner("xyz")), &context, &descriptors).iter()).to(be_empty());
+    expect!(compare_field(&path, &expected, &descriptor, &field("type.googleapis.com/Other", &[0x08, 0x2a]), &context, &descriptors).iter()).to(be_empty());
+
+    let result = compare_field(&path, &expected, &descriptor, &field("type.googleapis.com/Inner", &inner("123")), &context, &descriptors);
+    expect!(result.len()).to(be_equal_to(1));
+    let result = compare_field(&path, &expected, &descriptor, &field("type.googleapis.com/Unknown", &inner("xyz")), &context, &descriptors);
+    expect!(result).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.detail".to_string(),
+        expected: Some("type.googleapis.com/Inner, type.googleapis.com/Other".into()),
+        actual: Some("type.googleapis.com/Unknown".into()),
+        mismatch: "Expected the Any type URL to be one of [type.googleapis.com/Inner, type.googleapis.com/Other], but was 'type.googleapis.com/Unknown'".to_string()
+      }
+    ]));
+  }
+
+  #[test_log::test]
+  fn compare_field_applies_the_regex_matcher_over_the_type_matcher() {
+    let descriptor = FieldDescriptorProto {
+      name: Some("value".to_string()),
+      number: Some(1),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let field = |value: &str| ProtobufField {
+      field_num: 1,
+      field_name: "value".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(value.to_string())
+    };
+    let path = DocPath::new("$.value").unwrap();
+    let descriptors = FileDescriptorSet { file: vec![] };
+
+    for logic in [RuleLogic::And, RuleLogic::Or] {
+      let mut rules = MatchingRuleCategory::empty("body");
+      rules.add_rule(path.clone(), MatchingRule::Type, logic);
+      rules.add_rule(path.clone(), MatchingRule::Regex("^\\d+$".to_string()), logic);
+      let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &rules, &hashmap!{});
+
+      let expected = field("100");
+      expect!(compare_field(&path, &expected, &descriptor, &field("200"), &context, &descriptors).iter()).to(be_empty());
+      let result = compare_field(&path, &expected, &descriptor, &field("abc"), &context, &descriptors);
+      expect!(result.len()).to(be_equal_to(1));
+    }
+  }
+
+  #[test]
+  fn expand_encoding_matchers_test() {
+    expect!(expand_encoding_matchers("matching(type, 'SGVsbG8=')")).to(be_equal_to("matching(type, 'SGVsbG8=')"));
+    expect!(expand_encoding_matchers("matching(base64, 'SGVsbG8=')"))
+      .to(be_equal_to(format!("matching(regex, '{}', 'SGVsbG8=')", BASE64_REGEX)));
+    expect!(expand_encoding_matchers("eachValue(matching(hex, 'ff00'))"))
+      .to(be_equal_to(format!("eachValue(matching(regex, '{}', 'ff00'))", HEX_REGEX)));
+  }
+
+  #[test_log::test]
+  fn compare_string_field_with_encoding_matchers() {
+    let descriptor = FieldDescriptorProto {
+      name: Some("value".to_string()),
+      number: Some(1),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let field = |value: &str| ProtobufField {
+      field_num: 1,
+      field_name: "value".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(value.to_string())
+    };
+    let path = DocPath::new("$.value").unwrap();
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let context_for = |definition: &str| {
+      let definition = parse_matcher_def(expand_encoding_matchers(definition).as_str()).unwrap();
+      let rule = definition.rules.first().unwrap().clone().left().unwrap();
+      CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &matchingrules_list! {
+        "body"; "$.value" => [ rule ]
+      }, &hashmap!{})
+    };
+
+    let context = context_for("matching(base64, 'SGVsbG8=')");
+    let expected = field("SGVsbG8=");
+    expect!(compare_field(&path, &expected, &descriptor, &field("d29ybGQ="), &context, &descriptors).iter()).to(be_empty());
+    expect!(compare_field(&path, &expected, &descriptor, &field("Zm9vYmFy"), &context, &descriptors).iter()).to(be_empty());
+    expect!(compare_field(&path, &expected, &descriptor, &field("not base64!"), &context, &descriptors).iter()).to_not(be_empty());
+    expect!(compare_field(&path, &expected, &descriptor, &field("SGVsbG8"), &context, &descriptors).iter()).to_not(be_empty());
+
+    let context = context_for("matching(hex, 'ff00')");
+    let expected = field("ff00");
+    expect!(compare_field(&path, &expected, &descriptor, &field("0123456789abcdefABCDEF"), &context, &descriptors).iter()).to(be_empty());
+    expect!(compare_field(&path, &expected, &descriptor, &field("0xff"), &context, &descriptors).iter()).to_not(be_empty());
+    expect!(compare_field(&path, &expected, &descriptor, &field("ghij"), &context, &descriptors).iter()).to_not(be_empty());
+  }
+
+  #[test_log::test]
+  fn compare_message_with_repeated_field_requires_the_same_number_of_items() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Names".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("names".to_string()),
+          number: Some(1),
+          label: Some(Repeated as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
         }
-    ];
+      ],
+      .. DescriptorProto::default()
+    };
+    let names = |values: &[&str]| values.iter()
+      .map(|value| ProtobufField {
+        field_num: 1,
+        field_name: "names".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::String(value.to_string())
+      })
+      .collect_vec();
+    let descriptors = FileDescriptorSet { file: vec![] };
+    let expected = names(&["a", "b"]);
+    let actual = names(&["a", "b", "c"]);
 
-    let result = compare_message(
-      path,
-      &expected,
-      &actual,
-      &context,
-      &message_descriptor,
-      &fds,
-    ).unwrap();
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys,
+      &MatchingRuleCategory::empty("body"), &hashmap!{});
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.names".to_string(),
+        expected: None,
+        actual: None,
+        mismatch: "Expected repeated field 'names' to have 2 values but received 3 values".to_string()
+      }
+    ]));
 
+    // A type matcher cascaded from the root does not relax the item count
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &matchingrules_list! {
+      "body"; "$" => [ MatchingRule::Type ]
+    }, &hashmap!{});
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
+
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &matchingrules_list! {
+      "body"; "$.names" => [ MatchingRule::MinType(1) ]
+    }, &hashmap!{});
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &descriptors).unwrap();
     expect!(result).to(be_equal_to(BodyMatchResult::Ok));
   }
 
   #[test_log::test]
-  fn compare_message_with_repeated_field_and_each_value_matcher() {
-    let descriptors = base64::engine::general_purpose::STANDARD.decode(
-      "CogCCgxzaW1wbGUucHJvdG8iGwoJTWVzc2FnZUluEg4KAmluGAEgASgIUgJpbiIeCgpNZXNzYWdlT3V0EhAKA291\
-    dBgBIAEoCFIDb3V0IicKD1ZhbHVlc01lc3NhZ2VJbhIUCgV2YWx1ZRgBIAMoCVIFdmFsdWUiKAoQVmFsdWVzTWVzc2FnZU\
-    91dBIUCgV2YWx1ZRgBIAMoCVIFdmFsdWUyYAoEVGVzdBIkCgdHZXRUZXN0EgouTWVzc2FnZUluGgsuTWVzc2FnZU91dCIA\
-    EjIKCUdldFZhbHVlcxIQLlZhbHVlc01lc3NhZ2VJbhoRLlZhbHVlc01lc3NhZ2VPdXQiAGIGcHJvdG8z").unwrap();
-    let fds = FileDescriptorSet::decode(descriptors.as_slice()).unwrap();
+  fn compare_message_with_an_empty_repeated_field_and_an_omitted_one() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Values".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          label: Some(Optional as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("values".to_string()),
+          number: Some(2),
+          label: Some(Repeated as i32),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Int32 as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet { file: vec![] };
 
-    let (message_descriptor, _) = find_message_type_by_name("ValuesMessageIn", &fds).unwrap();
+    let mut omitted = vec![];
+    prost::encoding::string::encode(1, &"test".to_string(), &mut omitted);
+    // An empty packed list is encoded as a zero length field
+    let mut empty = omitted.clone();
+    prost::encoding::encode_key(2, WireType::LengthDelimited, &mut empty);
+    prost::encoding::encode_varint(0, &mut empty);
+    let empty = decode_message(&mut BytesMut::from(empty.as_slice()), &message_descriptor, &descriptors).unwrap();
+    let omitted = decode_message(&mut BytesMut::from(omitted.as_slice()), &message_descriptor, &descriptors).unwrap();
+
+    for config in [DiffConfig::AllowUnexpectedKeys, DiffConfig::NoUnexpectedKeys] {
+      let context = CoreMatchingContext::new(config, &MatchingRuleCategory::empty("body"), &hashmap!{});
+      expect!(compare_message(DocPath::root(), &empty, &omitted, &context, &message_descriptor, &descriptors).unwrap())
+        .to(be_equal_to(BodyMatchResult::Ok));
+      expect!(compare_message(DocPath::root(), &omitted, &empty, &context, &message_descriptor, &descriptors).unwrap())
+        .to(be_equal_to(BodyMatchResult::Ok));
+    }
 
-    let path = DocPath::new("$").unwrap();
     let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &matchingrules_list! {
-      "body";
-      "$.value" => [
-        MatchingRule::EachValue(MatchingRuleDefinition::new("00000000000000000000000000000000".to_string(), ValueType::Unknown, MatchingRule::Type, None))
-      ]
+      "body"; "$.values" => [ MatchingRule::MinType(1) ]
     }, &hashmap!{});
-    let expected = vec![
-      ProtobufField {
-        field_num: 1,
-        field_name: "value".to_string(),
-        wire_type: WireType::LengthDelimited,
-        data: ProtobufFieldData::String("00000000000000000000000000000000".to_string())
-      }
-    ];
-    let actual = vec![
-      ProtobufField {
-        field_num: 1,
-        field_name: "value".to_string(),
-        wire_type: WireType::LengthDelimited,
-        data: ProtobufFieldData::String("value1".to_string())
-      },
-      ProtobufField {
-        field_num: 1,
-        field_name: "value".to_string(),
-        wire_type: WireType::LengthDelimited,
-        data: ProtobufFieldData::String("value2".to_string())
-      }
-    ];
+    let result = compare_message(DocPath::root(), &empty, &omitted, &context, &message_descriptor, &descriptors).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
+  }
 
-    let result = compare_message(
-      path,
-      &expected,
-      &actual,
-      &context,
-      &message_descriptor,
-      &fds,
-    ).unwrap();
+  #[test_log::test]
+  fn match_message_always_matches_empty_messages() {
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        prost_types::FileDescriptorProto {
+          name: Some("google/protobuf/empty.proto".to_string()),
+          package: Some("google.protobuf".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Empty".to_string()),
+              .. DescriptorProto::default()
+            }
+          ],
+          .. prost_types::FileDescriptorProto::default()
+        }
+      ]
+    };
+    let mut unexpected_field = vec![];
+    prost::encoding::string::encode(1, &"unexpected".to_string(), &mut unexpected_field);
 
+    let result = match_message("Empty", &descriptors, &mut Bytes::new(), &mut Bytes::new(),
+      &MatchingRuleCategory::empty("body"), false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+    let result = match_message("Empty", &descriptors, &mut Bytes::new(), &mut Bytes::from(unexpected_field),
+      &MatchingRuleCategory::empty("body"), false).unwrap();
     expect!(result).to(be_equal_to(BodyMatchResult::Ok));
   }
 
   #[test_log::test]
-  fn compare_message_with_repeated_string_field_and_each_value_matcher_with_a_regex() {
-    let descriptors = base64::engine::general_purpose::STANDARD.decode(
-      "CusDChBlYWNoX3ZhbHVlLnByb3RvIhsKCU1lc3NhZ2VJbhIOCgJpbhgBIAEoCFICaW4iVQoKTWVzc2FnZU91d\
-      BJHChRyZXNvdXJjZV9wZXJtaXNzaW9ucxgBIAMoCzIULlJlc291cmNlUGVybWlzc2lvbnNSE3Jlc291cmNlUGVybWlzc\
-      2lvbnMiXQoTUmVzb3VyY2VQZXJtaXNzaW9ucxIlCghyZXNvdXJjZRgBIAEoCzIJLlJlc291cmNlUghyZXNvdXJjZRIfC\
-      gZlZmZlY3QYAiABKAsyBy5FZmZlY3RSBmVmZmVjdCJ3CghSZXNvdXJjZRIxChRhcHBsaWNhdGlvbl9yZXNvdXJjZRgBI\
-      AEoCVITYXBwbGljYXRpb25SZXNvdXJjZRIgCgtwZXJtaXNzaW9ucxgCIAMoCVILcGVybWlzc2lvbnMSFgoGZ3JvdXBzG\
-      AMgAygJUgZncm91cHMiLQoGRWZmZWN0EiMKBnJlc3VsdBgBIAEoDjILLkVmZmVjdEVudW1SBnJlc3VsdComCgpFZmZlY\
-      3RFbnVtEhgKFEVORk9SQ0VfRUZGRUNUX0FMTE9XEAAyLAoEVGVzdBIkCgdHZXRUZXN0EgouTWVzc2FnZUluGgsuTWVzc\
-      2FnZU91dCIAYgZwcm90bzM=").unwrap();
-    let fds = FileDescriptorSet::decode(descriptors.as_slice()).unwrap();
+  fn match_message_with_a_regex_over_the_serialized_message() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Tagged".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("tag".to_string()),
+          number: Some(1),
+          label: Some(Optional as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        prost_types::FileDescriptorProto {
+          name: Some("tagged.proto".to_string()),
+          message_type: vec![ message_descriptor ],
+          .. prost_types::FileDescriptorProto::default()
+        }
+      ]
+    };
+    let encode = |tag: &str| {
+      let mut buffer = vec![];
+      prost::encoding::string::encode(1, &tag.to_string(), &mut buffer);
+      Bytes::from(buffer)
+    };
+    let rules = matchingrules_list! {
+      "body"; "$.tag" => [ MatchingRule::Type ]
+    };
+    let plugin_config = |config: serde_json::Value| protobuf_config(hashmap!{
+      SERIALIZED_REGEX_CONFIG.to_string() => config
+    });
 
-    let (message_descriptor, _) = find_message_type_by_name("Resource", &fds).unwrap();
+    // "MARKER" is 4d41524b4552 in hex
+    let config = plugin_config(serde_json::json!("4d41524b4552"));
+    let result = match_message_with_config("Tagged", &descriptors, &mut encode("test"), &mut encode("a-MARKER-value"),
+      &rules, true, &config).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+    let result = match_message_with_config("Tagged", &descriptors, &mut encode("test"), &mut encode("no marker"),
+      &rules, true, &config).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
 
-    let each_value = MatchingRule::EachValue(MatchingRuleDefinition::new("foo".to_string(), ValueType::Unknown, MatchingRule::Type, None));
-    let each_value_groups = MatchingRule::EachValue(MatchingRuleDefinition::new(
-      "00000000000000000000000000000000".to_string(), ValueType::Unknown,
-      MatchingRule::Regex(r"[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}|\*".to_string()), None));
-    let matching_rules = matchingrules! {
-      "body" => {
-        "$.resource_permissions.*.resource.application_resource" => [ MatchingRule::Type ],
-        "$.resource_permissions" => [ MatchingRule::Values ],
-        "$.resource_permissions.*" => [ MatchingRule::Type ],
-        "$.resource_permissions.*.resource.permissions" => [ each_value ],
-        "$.resource_permissions.*.resource.groups" => [ each_value_groups ]
+    let config = plugin_config(serde_json::json!({ "regex": "TUFSS0VS", "encoding": "base64" }));
+    let result = match_message_with_config("Tagged", &descriptors, &mut encode("test"), &mut encode("xMARKER"),
+      &rules, true, &config).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[test_log::test]
+  fn match_message_with_serialized_size_constraints() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Tagged".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("tag".to_string()),
+          number: Some(1),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        prost_types::FileDescriptorProto {
+          name: Some("tagged.proto".to_string()),
+          message_type: vec![ message_descriptor ],
+          .. prost_types::FileDescriptorProto::default()
+        }
+      ]
+    };
+    let encode = |tag: &str| {
+      let mut buffer = vec![];
+      prost::encoding::string::encode(1, &tag.to_string(), &mut buffer);
+      Bytes::from(buffer)
+    };
+    let rules = matchingrules_list! {
+      "body"; "$.tag" => [ MatchingRule::Type ]
+    };
+    let config = protobuf_config(hashmap!{
+      SERIALIZED_SIZE_CONFIG.to_string() => serde_json::json!({ "min": 4, "max": 10 })
+    });
+
+    let result = match_message_with_config("Tagged", &descriptors, &mut encode("test"), &mut encode("small"),
+      &rules, true, &config).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = match_message_with_config("Tagged", &descriptors, &mut encode("test"), &mut encode("this is too large"),
+      &rules, true, &config).unwrap();
+    let mismatches = result.mismatches().iter().map(|mismatch| mismatch.description()).collect_vec();
+    expect!(mismatches.len()).to(be_equal_to(1));
+    expect!(mismatches[0].contains("Expected the serialized message to be at most 10 bytes, but it was 19 bytes")).to(be_true());
+  }
+
+  #[test_log::test]
+  fn match_message_compares_large_messages_one_field_at_a_time() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Batch".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          label: Some(Optional as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("values".to_string()),
+          number: Some(2),
+          label: Some(Repeated as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        prost_types::FileDescriptorProto {
+          name: Some("batch.proto".to_string()),
+          syntax: Some("proto3".to_string()),
+          message_type: vec![ message_descriptor ],
+          .. prost_types::FileDescriptorProto::default()
+        }
+      ]
+    };
+    let encode = |name: &str, count: usize| {
+      let mut buffer = vec![];
+      prost::encoding::string::encode(1, &name.to_string(), &mut buffer);
+      let values = (0..count).map(|i| format!("value-{}", i)).collect_vec();
+      prost::encoding::string::encode_repeated(2, &values, &mut buffer);
+      Bytes::from(buffer)
+    };
+    let rules = matchingrules_list! {
+      "body"; "$.values" => [ MatchingRule::MinType(1) ], "$.values[*]" => [ MatchingRule::Type ]
+    };
+    let config = |threshold: Option<usize>| protobuf_config(threshold
+      .map(|threshold| hashmap!{ STREAMING_THRESHOLD_CONFIG.to_string() => serde_json::json!(threshold) })
+      .unwrap_or_default());
+
+    // the values of the repeated field are contiguous, so are not copied from the message buffer
+    let actual = encode("batch", 10_000);
+    let fields = split_message_fields(&actual).unwrap();
+    let field_bytes = fields.get(&2).unwrap();
+    let actual_range = actual.as_ptr() as usize..actual.as_ptr() as usize + actual.len();
+    expect!(actual_range.contains(&(field_bytes.as_ptr() as usize))).to(be_true());
+    expect!(field_bytes.len()).to(be_less_than(actual.len()));
+
+    let result = match_message_with_config("Batch", &descriptors, &mut encode("batch", 1), &mut actual.clone(),
+      &rules, true, &config(Some(1024))).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let streamed = match_message_with_config("Batch", &descriptors, &mut encode("batch", 1), &mut encode("other", 10_000),
+      &rules, true, &config(Some(1024))).unwrap();
+    let decoded = match_message_with_config("Batch", &descriptors, &mut encode("batch", 1), &mut encode("other", 10_000),
+      &rules, true, &config(None)).unwrap();
+    expect!(streamed.mismatches().len()).to(be_equal_to(1));
+    expect!(streamed.mismatches()).to(be_equal_to(decoded.mismatches()));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_reserved_fields_and_extension_ranges() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Reserved".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          label: Some(Optional as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      reserved_range: vec![
+        prost_types::descriptor_proto::ReservedRange { start: Some(2), end: Some(4) }
+      ],
+      extension_range: vec![
+        prost_types::descriptor_proto::ExtensionRange { start: Some(100), end: Some(200), options: None }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet { file: vec![] };
+
+    let mut expected = vec![];
+    prost::encoding::string::encode(1, &"test".to_string(), &mut expected);
+    let mut actual = expected.clone();
+    prost::encoding::int32::encode(2, &100, &mut actual);
+    prost::encoding::string::encode(3, &"old value".to_string(), &mut actual);
+    prost::encoding::int32::encode(150, &1, &mut actual);
+    let expected = decode_message(&mut BytesMut::from(expected.as_slice()), &message_descriptor, &descriptors).unwrap();
+    let actual = decode_message(&mut BytesMut::from(actual.as_slice()), &message_descriptor, &descriptors).unwrap();
+    expect!(actual.iter().map(|f| f.field_num).collect::<Vec<_>>()).to(be_equal_to(vec![1, 150]));
+
+    for config in [DiffConfig::AllowUnexpectedKeys, DiffConfig::NoUnexpectedKeys] {
+      let context = CoreMatchingContext::new(config, &MatchingRuleCategory::empty("body"), &hashmap!{});
+      expect!(compare_message(DocPath::root(), &expected, &actual, &context, &message_descriptor, &descriptors).unwrap())
+        .to(be_equal_to(BodyMatchResult::Ok));
+    }
+  }
+
+  #[test_log::test]
+  fn compare_map_fields_regardless_of_how_the_entries_were_serialized() {
+    let counts_entry_descriptor = DescriptorProto {
+      name: Some("CountsEntry".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("key".to_string()),
+          number: Some(1),
+          label: Some(Optional as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("value".to_string()),
+          number: Some(2),
+          label: Some(Optional as i32),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Int32 as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      options: Some(MessageOptions {
+        map_entry: Some(true),
+        .. MessageOptions::default()
+      }),
+      .. DescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Counts".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("counts".to_string()),
+          number: Some(1),
+          label: Some(Repeated as i32),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Message as i32),
+          type_name: Some(".Counts.CountsEntry".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      nested_type: vec![ counts_entry_descriptor ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        prost_types::FileDescriptorProto {
+          name: Some("counts.proto".to_string()),
+          message_type: vec![ message_descriptor.clone() ],
+          .. prost_types::FileDescriptorProto::default()
+        }
+      ]
+    };
+
+    // Each entry is written with the key and value in the given order. When skipping defaults,
+    // values of 0 are not written, as a Protobuf implementation would do.
+    let message = |entries: &[(&str, i32)], value_first: bool, skip_defaults: bool| {
+      let mut buffer = vec![];
+      for (key, value) in entries {
+        let mut entry = vec![];
+        let write_value = !(skip_defaults && *value == 0);
+        if value_first && write_value {
+          prost::encoding::int32::encode(2, value, &mut entry);
+        }
+        prost::encoding::string::encode(1, &key.to_string(), &mut entry);
+        if !value_first && write_value {
+          prost::encoding::int32::encode(2, value, &mut entry);
+        }
+        prost::encoding::bytes::encode(1, &entry, &mut buffer);
       }
+      decode_message(&mut BytesMut::from(buffer.as_slice()), &message_descriptor, &descriptors).unwrap()
     };
-    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys,
-      &matching_rules.rules_for_category("body").unwrap(), &hashmap!{});
-    let expected = vec![
+    let expected = message(&[("a", 1), ("b", 0), ("c", 3)], false, false);
+    let actual = message(&[("c", 3), ("b", 0), ("a", 1)], true, true);
+
+    for config in [DiffConfig::AllowUnexpectedKeys, DiffConfig::NoUnexpectedKeys] {
+      let context = CoreMatchingContext::new(config, &MatchingRuleCategory::empty("body"), &hashmap!{});
+      expect!(compare_message(DocPath::root(), &expected, &actual, &context, &message_descriptor, &descriptors).unwrap())
+        .to(be_equal_to(BodyMatchResult::Ok));
+    }
+
+    let actual = message(&[("c", 3), ("b", 2), ("a", 1)], true, true);
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &MatchingRuleCategory::empty("body"), &hashmap!{});
+    let result = compare_message(DocPath::root(), &expected, &actual, &context, &message_descriptor, &descriptors).unwrap();
+    expect!(result).to_not(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_wildcards_through_repeated_and_map_fields() {
+    let roles_entry_descriptor = DescriptorProto {
+      name: Some("RolesEntry".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("key".to_string()),
+          number: Some(1),
+          label: Some(Optional as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("value".to_string()),
+          number: Some(2),
+          label: Some(Optional as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      options: Some(MessageOptions {
+        map_entry: Some(true),
+        .. MessageOptions::default()
+      }),
+      .. DescriptorProto::default()
+    };
+    let user_descriptor = DescriptorProto {
+      name: Some("User".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("roles".to_string()),
+          number: Some(1),
+          label: Some(Repeated as i32),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Message as i32),
+          type_name: Some(".User.RolesEntry".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      nested_type: vec![ roles_entry_descriptor ],
+      .. DescriptorProto::default()
+    };
+    let users_descriptor = DescriptorProto {
+      name: Some("Users".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("users".to_string()),
+          number: Some(1),
+          label: Some(Repeated as i32),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Message as i32),
+          type_name: Some(".User".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        prost_types::FileDescriptorProto {
+          name: Some("users.proto".to_string()),
+          message_type: vec![ user_descriptor.clone(), users_descriptor.clone() ],
+          .. prost_types::FileDescriptorProto::default()
+        }
+      ]
+    };
+
+    let user = |roles: &[(&str, &str)]| {
+      let mut buffer = vec![];
+      for (key, value) in roles {
+        let mut entry = vec![];
+        prost::encoding::string::encode(1, &key.to_string(), &mut entry);
+        prost::encoding::string::encode(2, &value.to_string(), &mut entry);
+        prost::encoding::bytes::encode(1, &entry, &mut buffer);
+      }
       ProtobufField {
-        field_num: 3,
-        field_name: "groups".to_string(),
+        field_num: 1,
+        field_name: "users".to_string(),
         wire_type: WireType::LengthDelimited,
-        data: ProtobufFieldData::String("*".to_string())
+        data: ProtobufFieldData::Message(buffer, user_descriptor.clone())
       }
+    };
+    let expected = vec![
+      user(&[("admin", "read"), ("owner", "write")]),
+      user(&[("admin", "read")])
     ];
     let actual = vec![
-      ProtobufField {
-        field_num: 3,
-        field_name: "groups".to_string(),
-        wire_type: WireType::LengthDelimited,
-        data: ProtobufFieldData::String("*".to_string())
-      },
-      ProtobufField {
-        field_num: 3,
-        field_name: "groups".to_string(),
-        wire_type: WireType::LengthDelimited,
-        data: ProtobufFieldData::String("*".to_string())
-      }
+      user(&[("admin", "none"), ("owner", "all")]),
+      user(&[("admin", "write")])
     ];
 
-    let path = DocPath::new("$.resource_permissions.*.resource").unwrap();
-    let result = compare_message(
-      path,
-      &expected,
-      &actual,
-      &context,
-      &message_descriptor,
-      &fds,
-    ).unwrap();
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &matchingrules_list! {
+      "body"; "$.users[*].roles[*]" => [ MatchingRule::Type ]
+    }, &hashmap!{});
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &users_descriptor, &descriptors).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
 
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &matchingrules_list! {
+      "body"; "$.users[*].roles.*" => [ MatchingRule::Type ]
+    }, &hashmap!{});
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &users_descriptor, &descriptors).unwrap();
     expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &matchingrules_list! {
+      "body"; "$.users[*].roles.admin" => [ MatchingRule::Type ]
+    }, &hashmap!{});
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &users_descriptor, &descriptors).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
   }
 }
@@ -2,6 +2,7 @@
 
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
+use std::fmt::Display;
 
 use anyhow::anyhow;
 use bytes::{BufMut, Bytes, BytesMut};
@@ -26,6 +27,28 @@ pub enum MessageFieldValueType {
   Repeated
 }
 
+/// How values configured for integer fields are treated when constructing a message
+/// (`pact:number-types`)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NumberTypes {
+  /// Values for integer fields must be integers
+  #[default]
+  Strict,
+  /// Floating point values without a fractional part (i.e. `5.0`) are accepted for integer fields
+  Lenient
+}
+
+impl NumberTypes {
+  /// Parse the mode from the test configuration value
+  pub fn parse(value: &str) -> anyhow::Result<NumberTypes> {
+    match value.to_lowercase().as_str() {
+      "strict" => Ok(NumberTypes::Strict),
+      "lenient" => Ok(NumberTypes::Lenient),
+      _ => Err(anyhow!("'{}' is not a valid value for pact:number-types, it must be either 'strict' or 'lenient'", value))
+    }
+  }
+}
+
 /// Inner struct to store the values for a field
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) struct FieldValueInner {
@@ -49,6 +72,8 @@ pub struct MessageBuilder {
   /// Message name
   pub message_name: String,
   pub(crate) fields: BTreeMap<String, FieldValueInner>,
+  /// How values configured for integer fields are treated
+  pub number_types: NumberTypes
 }
 
 impl MessageBuilder {
@@ -58,7 +83,8 @@ impl MessageBuilder {
       file_descriptor: file_descriptor.clone(),
       descriptor: descriptor.clone(),
       message_name: message_name.to_string(),
-      fields: btreemap!{}
+      fields: btreemap!{},
+      number_types: NumberTypes::default()
     }
   }
 
@@ -272,7 +298,8 @@ impl MessageBuilder {
                   field_type: MessageFieldValueType::Normal,
                   proto_type: value_proto.r#type()
                 }
-              }
+              },
+              number_types: self.number_types
             }))
           }
         }).collect();
@@ -333,85 +360,85 @@ impl MessageBuilder {
       match field_value.proto_type {
         Type::Double => {
           let values = field_value.values.iter()
-            .map(|v| v.rtype.as_f64().unwrap_or_default())
-              .collect::<Vec<f64>>();
+            .map(|v| v.rtype.as_f64())
+              .collect::<anyhow::Result<Vec<f64>>>()?;
           prost::encoding::double::encode_packed(tag as u32, &values, buffer);
           Ok(())
         }
         Type::Float => {
           let values = field_value.values.iter()
-              .map(|v| v.rtype.as_f32().unwrap_or_default())
-              .collect::<Vec<f32>>();
+              .map(|v| v.rtype.as_f32())
+              .collect::<anyhow::Result<Vec<f32>>>()?;
           prost::encoding::float::encode_packed(tag as u32, &values, buffer);
           Ok(())
         }
         Type::Int64 => {
           let values = field_value.values.iter()
-              .map(|v| v.rtype.as_i64().unwrap_or_default())
-              .collect::<Vec<i64>>();
+              .map(|v| v.rtype.as_i64())
+              .collect::<anyhow::Result<Vec<i64>>>()?;
           prost::encoding::int64::encode_packed(tag as u32, &values, buffer);
           Ok(())
         }
         Type::Uint64 => {
           let values = field_value.values.iter()
-              .map(|v| v.rtype.as_u64().unwrap_or_default())
-              .collect::<Vec<u64>>();
+              .map(|v| v.rtype.as_u64())
+              .collect::<anyhow::Result<Vec<u64>>>()?;
           prost::encoding::uint64::encode_packed(tag as u32, &values, buffer);
           Ok(())
         }
         Type::Int32 => {
           let values = field_value.values.iter()
-              .map(|v| v.rtype.as_i32().unwrap_or_default())
-              .collect::<Vec<i32>>();
+              .map(|v| v.rtype.as_i32())
+              .collect::<anyhow::Result<Vec<i32>>>()?;
           prost::encoding::int32::encode_packed(tag as u32, &values, buffer);
           Ok(())
         }
         Type::Fixed64 => {
           let values = field_value.values.iter()
-              .map(|v| v.rtype.as_u64().unwrap_or_default())
-              .collect::<Vec<u64>>();
+              .map(|v| v.rtype.as_u64())
+              .collect::<anyhow::Result<Vec<u64>>>()?;
           prost::encoding::fixed64::encode_packed(tag as u32, &values, buffer);
           Ok(())
         }
         Type::Fixed32 => {
           let values = field_value.values.iter()
-              .map(|v| v.rtype.as_u32().unwrap_or_default())
-              .collect::<Vec<u32>>();
+              .map(|v| v.rtype.as_u32())
+              .collect::<anyhow::Result<Vec<u32>>>()?;
           prost::encoding::fixed32::encode_packed(tag as u32, &values, buffer);
           Ok(())
         }
         Type::Uint32 => {
           let values = field_value.values.iter()
-              .map(|v| v.rtype.as_u32().unwrap_or_default())
-              .collect::<Vec<u32>>();
+              .map(|v| v.rtype.as_u32())
+              .collect::<anyhow::Result<Vec<u32>>>()?;
           prost::encoding::uint32::encode_packed(tag as u32, &values, buffer);
           Ok(())
         }
         Type::Sfixed32 => {
           let values = field_value.values.iter()
-              .map(|v| v.rtype.as_i32().unwrap_or_default())
-              .collect::<Vec<i32>>();
+              .map(|v| v.rtype.as_i32())
+              .collect::<anyhow::Result<Vec<i32>>>()?;
           prost::encoding::sfixed32::encode_packed(tag as u32, &values, buffer);
           Ok(())
         }
         Type::Sfixed64 => {
           let values = field_value.values.iter()
-              .map(|v| v.rtype.as_i64().unwrap_or_default())
-              .collect::<Vec<i64>>();
+              .map(|v| v.rtype.as_i64())
+              .collect::<anyhow::Result<Vec<i64>>>()?;
           prost::encoding::sfixed64::encode_packed(tag as u32, &values, buffer);
           Ok(())
         }
         Type::Sint32 => {
           let values = field_value.values.iter()
-              .map(|v| v.rtype.as_i32().unwrap_or_default())
-              .collect::<Vec<i32>>();
+              .map(|v| v.rtype.as_i32())
+              .collect::<anyhow::Result<Vec<i32>>>()?;
           prost::encoding::sint32::encode_packed(tag as u32, &values, buffer);
           Ok(())
         }
         Type::Sint64 => {
           let values = field_value.values.iter()
-              .map(|v| v.rtype.as_i64().unwrap_or_default())
-              .collect::<Vec<i64>>();
+              .map(|v| v.rtype.as_i64())
+              .collect::<anyhow::Result<Vec<i64>>>()?;
           prost::encoding::sint64::encode_packed(tag as u32, &values, buffer);
           Ok(())
         }
@@ -514,62 +541,62 @@ impl RType {
     }
   }
 
-  /// Convert this value to a u64
+  /// Convert this value to a u64. This will fail with an error if the value is out of range.
   pub fn as_u64(&self) -> anyhow::Result<u64> {
     match self {
       RType::String(s) => s.parse::<u64>().map_err(|err| anyhow!(err)),
       RType::Boolean(b) => Ok(*b as u64),
-      RType::UInteger32(u) => Ok(*u as u64),
-      RType::Integer32(i) => Ok(*i as u64),
+      RType::UInteger32(u) => Ok(u64::from(*u)),
+      RType::Integer32(i) => u64::try_from(*i).map_err(|_| out_of_range(i, "u64")),
       RType::UInteger64(u) => Ok(*u),
-      RType::Integer64(i) => Ok(*i as u64),
-      RType::Float(f) => Ok(*f as u64),
-      RType::Double(d) => Ok(*d as u64),
+      RType::Integer64(i) => u64::try_from(*i).map_err(|_| out_of_range(i, "u64")),
+      RType::Float(f) => Ok(float_in_range(*f as f64, 0.0, 2f64.powi(64), "u64")? as u64),
+      RType::Double(d) => Ok(float_in_range(*d, 0.0, 2f64.powi(64), "u64")? as u64),
       _ => Err(anyhow!("Can't convert {:?} to u64", self))
     }
   }
 
-  /// Convert this value to a i64
+  /// Convert this value to a i64. This will fail with an error if the value is out of range.
   pub fn as_i64(&self) -> anyhow::Result<i64> {
     match self {
       RType::String(s) => s.parse::<i64>().map_err(|err| anyhow!(err)),
       RType::Boolean(b) => Ok(*b as i64),
-      RType::UInteger32(u) => Ok(*u as i64),
-      RType::Integer32(i) => Ok(*i as i64),
-      RType::UInteger64(u) => Ok(*u as i64),
+      RType::UInteger32(u) => Ok(i64::from(*u)),
+      RType::Integer32(i) => Ok(i64::from(*i)),
+      RType::UInteger64(u) => i64::try_from(*u).map_err(|_| out_of_range(u, "i64")),
       RType::Integer64(i) => Ok(*i),
-      RType::Float(f) => Ok(*f as i64),
-      RType::Double(d) => Ok(*d as i64),
+      RType::Float(f) => Ok(float_in_range(*f as f64, -(2f64.powi(63)), 2f64.powi(63), "i64")? as i64),
+      RType::Double(d) => Ok(float_in_range(*d, -(2f64.powi(63)), 2f64.powi(63), "i64")? as i64),
       _ => Err(anyhow!("Can't convert {:?} to i64", self))
     }
   }
 
-  /// Convert this value to a u32
+  /// Convert this value to a u32. This will fail with an error if the value is out of range.
   pub fn as_u32(&self) -> anyhow::Result<u32> {
     match self {
       RType::String(s) => s.parse::<u32>().map_err(|err| anyhow!(err)),
       RType::Boolean(b) => Ok(*b as u32),
       RType::UInteger32(u) => Ok(*u),
-      RType::Integer32(i) => Ok(*i as u32),
-      RType::UInteger64(u) => Ok(*u as u32),
-      RType::Integer64(i) => Ok(*i as u32),
-      RType::Float(f) => Ok(*f as u32),
-      RType::Double(d) => Ok(*d as u32),
+      RType::Integer32(i) => u32::try_from(*i).map_err(|_| out_of_range(i, "u32")),
+      RType::UInteger64(u) => u32::try_from(*u).map_err(|_| out_of_range(u, "u32")),
+      RType::Integer64(i) => u32::try_from(*i).map_err(|_| out_of_range(i, "u32")),
+      RType::Float(f) => Ok(float_in_range(*f as f64, 0.0, 2f64.powi(32), "u32")? as u32),
+      RType::Double(d) => Ok(float_in_range(*d, 0.0, 2f64.powi(32), "u32")? as u32),
       _ => Err(anyhow!("Can't convert {:?} to u32", self))
     }
   }
 
-  /// Convert this value to a i32
+  /// Convert this value to a i32. This will fail with an error if the value is out of range.
   pub fn as_i32(&self) -> anyhow::Result<i32> {
     match self {
       RType::String(s) => s.parse::<i32>().map_err(|err| anyhow!(err)),
       RType::Boolean(b) => Ok(*b as i32),
-      RType::UInteger32(u) => Ok(*u as i32),
+      RType::UInteger32(u) => i32::try_from(*u).map_err(|_| out_of_range(u, "i32")),
       RType::Integer32(i) => Ok(*i),
-      RType::UInteger64(u) => Ok(*u as i32),
-      RType::Integer64(i) => Ok(*i as i32),
-      RType::Float(f) => Ok(*f as i32),
-      RType::Double(d) => Ok(*d as i32),
+      RType::UInteger64(u) => i32::try_from(*u).map_err(|_| out_of_range(u, "i32")),
+      RType::Integer64(i) => i32::try_from(*i).map_err(|_| out_of_range(i, "i32")),
+      RType::Float(f) => Ok(float_in_range(*f as f64, -(2f64.powi(31)), 2f64.powi(31), "i32")? as i32),
+      RType::Double(d) => Ok(float_in_range(*d, -(2f64.powi(31)), 2f64.powi(31), "i32")? as i32),
       _ => Err(anyhow!("Can't convert {:?} to i32", self))
     }
   }
@@ -606,6 +633,22 @@ impl RType {
   }
 }
 
+fn out_of_range<T: Display>(value: T, type_name: &str) -> anyhow::Error {
+  anyhow!("{} is out of range for a {} value", value, type_name)
+}
+
+/// Truncates the floating point value, checking that it falls within the range of the integer type.
+/// The upper bound is exclusive, as the maximum values of the 64 bit types can not be represented
+/// exactly as a double.
+fn float_in_range(value: f64, lower: f64, upper: f64, type_name: &str) -> anyhow::Result<f64> {
+  let truncated = value.trunc();
+  if truncated.is_finite() && truncated >= lower && truncated < upper {
+    Ok(truncated)
+  } else {
+    Err(out_of_range(value, type_name))
+  }
+}
+
 /// Value of a message field
 #[derive(Clone, Debug, PartialEq)]
 pub struct MessageFieldValue {
@@ -1762,4 +1805,112 @@ mod tests {
     let result = message_builder.encode_message().unwrap();
     expect!(result.to_vec()).to(be_equal_to(expected));
   }
+
+  #[test]
+  fn rtype_integer_conversions_at_the_boundaries() {
+    expect!(RType::UInteger64(u64::MAX).as_u64().unwrap()).to(be_equal_to(u64::MAX));
+    expect!(RType::UInteger64(u64::MAX).as_i64()).to(be_err());
+    expect!(RType::UInteger64(u64::MAX).as_u32()).to(be_err());
+    expect!(RType::String("18446744073709551615".to_string()).as_u64().unwrap()).to(be_equal_to(u64::MAX));
+    expect!(RType::Double(u64::MAX as f64).as_u64()).to(be_err());
+
+    expect!(RType::Integer64(i64::MIN).as_i64().unwrap()).to(be_equal_to(i64::MIN));
+    expect!(RType::Integer64(i64::MIN).as_u64()).to(be_err());
+    expect!(RType::Integer64(i64::MIN).as_i32()).to(be_err());
+    expect!(RType::String("-9223372036854775808".to_string()).as_i64().unwrap()).to(be_equal_to(i64::MIN));
+    expect!(RType::Double(i64::MIN as f64).as_i64().unwrap()).to(be_equal_to(i64::MIN));
+
+    expect!(RType::Integer32(-1).as_i32().unwrap()).to(be_equal_to(-1));
+    expect!(RType::Integer32(-1).as_i64().unwrap()).to(be_equal_to(-1));
+    expect!(RType::Integer64(-1).as_i32().unwrap()).to(be_equal_to(-1));
+    expect!(RType::Integer32(-1).as_u32()).to(be_err());
+    expect!(RType::Integer64(-1).as_u64()).to(be_err());
+    expect!(RType::String("-1".to_string()).as_u64()).to(be_err());
+    expect!(RType::Double(-1.0).as_u32()).to(be_err());
+  }
+
+  #[test_log::test]
+  fn encode_integer_boundary_values() {
+    let sint64_field = FieldDescriptorProto {
+      name: Some("minus_one_sint64".to_string()),
+      number: Some(4),
+      label: Some(Optional as i32),
+      r#type: Some(field_descriptor_proto::Type::Sint64 as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let fields = vec![
+      u64_field_descriptor!("max_u64", 1),
+      i64_field_descriptor!("min_i64", 2),
+      i32_field_descriptor!("minus_one_i32", 3),
+      sint64_field,
+      i64_field_descriptor!("minus_one_i64", 5)
+    ];
+    let descriptor = DescriptorProto {
+      name: Some("Boundaries".to_string()),
+      field: fields.clone(),
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("boundaries.proto".to_string()),
+      message_type: vec![ descriptor.clone() ],
+      .. FileDescriptorProto::default()
+    };
+
+    let mut message = MessageBuilder::new(&descriptor, "Boundaries", &file_descriptor);
+    message.set_field_value(&fields[0], "max_u64", MessageFieldValue::uinteger_64("max_u64", "18446744073709551615").unwrap());
+    message.set_field_value(&fields[1], "min_i64", MessageFieldValue::integer_64("min_i64", "-9223372036854775808").unwrap());
+    message.set_field_value(&fields[2], "minus_one_i32", MessageFieldValue::integer_32("minus_one_i32", "-1").unwrap());
+    message.set_field_value(&fields[3], "minus_one_sint64", MessageFieldValue::integer_64("minus_one_sint64", "-1").unwrap());
+    message.set_field_value(&fields[4], "minus_one_i64", MessageFieldValue::integer_64("minus_one_i64", "-1").unwrap());
+
+    let mut bytes = message.encode_message().unwrap();
+    let file_descriptor_set = FileDescriptorSet { file: vec![ file_descriptor ] };
+    let result = decode_message(&mut bytes, &descriptor, &file_descriptor_set).unwrap();
+
+    let values = result.iter()
+      .sorted_by_key(|field| field.field_num)
+      .map(|field| field.data.clone())
+      .collect_vec();
+    expect!(values).to(be_equal_to(vec![
+      ProtobufFieldData::UInteger64(u64::MAX),
+      ProtobufFieldData::Integer64(i64::MIN),
+      ProtobufFieldData::Integer32(-1),
+      ProtobufFieldData::Integer64(-1),
+      ProtobufFieldData::Integer64(-1)
+    ]));
+  }
+
+  #[test_log::test]
+  fn encode_packed_field_with_an_out_of_range_value() {
+    let field = FieldDescriptorProto {
+      name: Some("values".to_string()),
+      number: Some(1),
+      label: Some(field_descriptor_proto::Label::Repeated as i32),
+      r#type: Some(field_descriptor_proto::Type::Uint32 as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("Values".to_string()),
+      field: vec![ field.clone() ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("values.proto".to_string()),
+      message_type: vec![ descriptor.clone() ],
+      .. FileDescriptorProto::default()
+    };
+
+    let mut message = MessageBuilder::new(&descriptor, "Values", &file_descriptor);
+    message.add_repeated_field_value(&field, "values", MessageFieldValue {
+      name: "values".to_string(),
+      raw_value: Some("1".to_string()),
+      rtype: RType::UInteger32(1)
+    });
+    message.add_repeated_field_value(&field, "values", MessageFieldValue {
+      name: "values".to_string(),
+      raw_value: Some("-1".to_string()),
+      rtype: RType::Integer32(-1)
+    });
+    expect!(message.encode_message()).to(be_err());
+  }
 }
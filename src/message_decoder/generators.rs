@@ -1,11 +1,10 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 
 use anyhow::anyhow;
+use bytes::Bytes;
 use chrono::{DateTime, Local};
 use pact_models::generators::{
-  generate_ascii_string,
-  generate_decimal,
-  generate_hexadecimal,
   generate_value_from_context,
   GenerateValue,
   Generator,
@@ -19,13 +18,531 @@ use pact_models::generators::datetime_expressions::{
 };
 use pact_models::json_utils::{get_field_as_string, json_to_string};
 use pact_models::time_utils::{parse_pattern, to_chrono_pattern};
+use prost::encoding::WireType;
+use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet};
+use prost_types::field_descriptor_proto::Type;
+use rand::distributions::Alphanumeric;
 use rand::prelude::*;
+use rand::rngs::StdRng;
 use regex::{Captures, Regex};
 use serde_json::Value;
-use tracing::{debug, warn};
+use tracing::{debug, trace, warn};
 use uuid::Uuid;
 
-use crate::message_decoder::ProtobufFieldData;
+use crate::message_decoder::{ProtobufField, ProtobufFieldData};
+use crate::utils::{
+  find_enum_by_name,
+  find_enum_by_name_in_message,
+  find_message_type_by_name,
+  find_message_type_by_qualified_name,
+  has_explicit_presence,
+  is_repeated_field,
+  last_name,
+  RawFieldValue,
+  read_raw_fields
+};
+
+/// Key in the interaction configuration to populate any unset fields when generating the contents
+pub const FILL_DEFAULTS_CONFIG: &str = "fillDefaults";
+
+/// Key in the interaction configuration for the name of the custom field option that contains
+/// example values to use for unset fields when generating the contents
+pub const EXAMPLE_OPTION_CONFIG: &str = "exampleOption";
+
+/// Key in the interaction configuration for the example values to use for unset fields when
+/// generating the contents. This is a JSON object keyed by the path to the field.
+pub const EXAMPLE_VALUES_CONFIG: &str = "exampleValues";
+
+/// Key in the interaction configuration for the name of the field option with the validation rules
+/// (i.e. `validate.rules` from protoc-gen-validate) that generated values should satisfy
+pub const VALIDATION_OPTION_CONFIG: &str = "validationOption";
+
+/// Key in the interaction configuration for the seed for the random values of the generators, so
+/// the same values are generated each time the contents are generated
+pub const GENERATOR_SEED_CONFIG: &str = "generatorSeed";
+
+/// Key in the interaction configuration for how wrapper type fields (i.e. `google.protobuf.StringValue`)
+/// are generated. This is a JSON object keyed by the path to the field, with either `null` for the
+/// field to be absent, or `present` for the field to be set (with the default value if not given).
+pub const WRAPPER_NULLS_CONFIG: &str = "wrapperNulls";
+
+/// Fully qualified names of the wrapper types for the scalar values
+const WRAPPER_TYPES: [&str; 9] = [
+  ".google.protobuf.DoubleValue",
+  ".google.protobuf.FloatValue",
+  ".google.protobuf.Int64Value",
+  ".google.protobuf.UInt64Value",
+  ".google.protobuf.Int32Value",
+  ".google.protobuf.UInt32Value",
+  ".google.protobuf.BoolValue",
+  ".google.protobuf.StringValue",
+  ".google.protobuf.BytesValue"
+];
+
+/// Environment variable for the seed for the random values of the generators. The seed from the
+/// interaction configuration takes precedence.
+pub const GENERATOR_SEED_ENV_VAR: &str = "PACT_PROTOBUF_GENERATOR_SEED";
+
+thread_local! {
+  static SEEDED_RNG: RefCell<Option<StdRng>> = const { RefCell::new(None) };
+}
+
+/// Runs the function with the random values of the generators produced from the given seed. With
+/// no seed, the values are random each time.
+pub fn with_generator_seed<T>(seed: Option<u64>, f: impl FnOnce() -> T) -> T {
+  let previous = SEEDED_RNG.with(|rng| rng.replace(seed.map(StdRng::seed_from_u64)));
+  let result = f();
+  SEEDED_RNG.with(|rng| rng.replace(previous));
+  result
+}
+
+/// Calls the function with the seeded random number generator if a seed has been set, otherwise
+/// with the thread local one
+fn with_rng<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+  SEEDED_RNG.with(|rng| match rng.borrow_mut().as_mut() {
+    Some(rng) => f(rng),
+    None => f(&mut thread_rng())
+  })
+}
+
+fn random_decimal(digits: usize) -> String {
+  with_rng(|rng| match digits {
+    0 => String::new(),
+    1 => rng.gen_range(0..10).to_string(),
+    _ => {
+      let mut value = rng.gen_range(1..10).to_string();
+      for _ in 1..digits {
+        value.push(char::from(b'0' + rng.gen_range(0..10u8)));
+      }
+      let point = rng.gen_range(1..digits);
+      value.insert(point, '.');
+      value
+    }
+  })
+}
+
+fn random_hexadecimal(digits: usize) -> String {
+  const HEX_CHARS: &[u8] = b"0123456789ABCDEF";
+  with_rng(|rng| (0..digits).map(|_| char::from(HEX_CHARS[rng.gen_range(0..HEX_CHARS.len())])).collect())
+}
+
+fn random_ascii_string(size: usize) -> String {
+  with_rng(|rng| (0..size).map(|_| char::from(rng.sample(Alphanumeric))).collect())
+}
+
+fn random_uuid() -> Uuid {
+  uuid::Builder::from_random_bytes(with_rng(|rng| rng.gen())).into_uuid()
+}
+
+/// Populates any fields of the message that are not set. Fields that have explicit presence (see
+/// [has_explicit_presence]) are set to the default value for the field type, and embedded message
+/// fields are set to an empty message. Fields without explicit presence (i.e. proto3 scalar fields
+/// that are not `optional`) are set to an example value (see [implicit_presence_example]), as a
+/// default value for them is never sent on the wire. Repeated, map and oneOf fields are not populated.
+pub fn populate_unset_fields(
+  fields: &mut Vec<ProtobufField>,
+  descriptor: &DescriptorProto,
+  file_descriptor: &FileDescriptorProto,
+  descriptors: &FileDescriptorSet
+) {
+  for field_descriptor in &descriptor.field {
+    let field_num = field_descriptor.number.unwrap_or_default() as u32;
+    let is_one_of = field_descriptor.oneof_index.is_some() && !field_descriptor.proto3_optional.unwrap_or(false);
+    if is_repeated_field(field_descriptor) || is_one_of || fields.iter().any(|field| field.field_num == field_num) {
+      continue;
+    }
+
+    let field_data = if field_descriptor.r#type() == Type::Message {
+      empty_message_field_data(field_descriptor, descriptors)
+    } else if has_explicit_presence(field_descriptor, file_descriptor) {
+      default_field_data(field_descriptor, descriptor, descriptors)
+    } else {
+      implicit_presence_example(field_descriptor, descriptor, descriptors)
+    };
+    if let Some((data, wire_type)) = field_data {
+      let field_name = field_descriptor.name.clone().unwrap_or_default();
+      trace!(%field_name, field_num, "Populating unset field with {}", data);
+      fields.push(ProtobufField {
+        field_num,
+        field_name,
+        wire_type,
+        data
+      });
+    }
+  }
+}
+
+/// Returns an example value for a field without explicit presence, which must not be the default
+/// value for the field type so that it is sent on the wire. Numeric fields are set to 1, boolean
+/// fields to true, string and bytes fields to the name of the field and enum fields to the first
+/// value that is not zero. Enum fields where all the values are zero are not populated.
+fn implicit_presence_example(
+  field_descriptor: &FieldDescriptorProto,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> Option<(ProtobufFieldData, WireType)> {
+  let field_name = field_descriptor.name.clone().unwrap_or_default();
+  let example = match default_field_data(field_descriptor, descriptor, descriptors)?.0 {
+    ProtobufFieldData::String(_) | ProtobufFieldData::Bytes(_) => field_name,
+    ProtobufFieldData::Boolean(_) => "true".to_string(),
+    ProtobufFieldData::Enum(_, enum_descriptor) => match enum_descriptor.value.iter()
+      .find(|value| value.number.unwrap_or_default() != 0)
+      .and_then(|value| value.name.clone()) {
+      Some(name) => name,
+      None => {
+        warn!("Enum {:?} for field {} does not have a value that is not zero, not populating it", enum_descriptor.name, field_name);
+        return None;
+      }
+    },
+    _ => "1".to_string()
+  };
+  example_field_data(field_descriptor, descriptor, descriptors, example.as_str())
+}
+
+/// Returns an empty message for an embedded message field
+fn empty_message_field_data(
+  field_descriptor: &FieldDescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> Option<(ProtobufFieldData, WireType)> {
+  let type_name = field_descriptor.type_name.clone().unwrap_or_default();
+  match find_message_type_by_qualified_name(type_name.as_str(), descriptors) {
+    Ok((message_descriptor, _)) => Some((ProtobufFieldData::Message(vec![], message_descriptor), WireType::LengthDelimited)),
+    Err(err) => {
+      warn!("Did not find the message type {} for field {:?}, not populating it - {}", type_name, field_descriptor.name, err);
+      None
+    }
+  }
+}
+
+/// Populates any scalar fields of the message that are not set and do not have a generator with
+/// the example value from the field options or the `exampleValues` configuration. Examples are
+/// keyed by the fully qualified field name.
+pub fn populate_example_values(
+  fields: &mut Vec<ProtobufField>,
+  message_name: &str,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet,
+  examples: &HashMap<String, String>,
+  generated_fields: &[String]
+) {
+  for field_descriptor in &descriptor.field {
+    let field_num = field_descriptor.number.unwrap_or_default() as u32;
+    let field_name = field_descriptor.name.clone().unwrap_or_default();
+    if is_repeated_field(field_descriptor) || generated_fields.contains(&field_name) ||
+      fields.iter().any(|field| field.field_num == field_num) {
+      continue;
+    }
+
+    if let Some(example) = examples.get(&format!("{}.{}", message_name, field_name)) {
+      match example_field_data(field_descriptor, descriptor, descriptors, example) {
+        Some((data, wire_type)) => {
+          trace!(%field_name, field_num, "Populating unset field with example value {}", data);
+          fields.push(ProtobufField {
+            field_num,
+            field_name,
+            wire_type,
+            data
+          });
+        }
+        None => warn!("Example value '{}' is not valid for field {}, not populating it", example, field_name)
+      }
+    }
+  }
+}
+
+/// Returns the example values from the `exampleValues` configuration, keyed by the fully qualified
+/// field name in the same way as the examples from the field options. Only the fields of the message
+/// (i.e. `$.name`) are supported.
+pub fn example_values_from_config(config: &Value, message_name: &str) -> anyhow::Result<HashMap<String, String>> {
+  let values = config.as_object()
+    .ok_or_else(|| anyhow!("Example values must be a map of field path to value, got {}", config))?;
+  let mut examples = HashMap::new();
+  for (path, value) in values {
+    let field_name = path.strip_prefix("$.").unwrap_or(path);
+    if field_name.is_empty() || field_name.contains('.') {
+      warn!("Example value for '{}' is not for a field of the message, ignoring it", path);
+    } else {
+      examples.insert(format!("{}.{}", message_name, field_name), json_to_string(value));
+    }
+  }
+  Ok(examples)
+}
+
+/// How a wrapper type field is generated
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapperNullability {
+  /// The field is absent (null)
+  Null,
+  /// The field is present, with the default value if not set
+  Present
+}
+
+/// Returns the nullability of the wrapper fields from the `wrapperNulls` configuration, keyed by
+/// the field name. Only the fields of the message (i.e. `$.name`) are supported.
+pub fn wrapper_nullability_from_config(config: &Value) -> anyhow::Result<HashMap<String, WrapperNullability>> {
+  let values = config.as_object()
+    .ok_or_else(|| anyhow!("Wrapper nulls must be a map of field path to either null or 'present', got {}", config))?;
+  let mut nullability = HashMap::new();
+  for (path, value) in values {
+    let field_name = path.strip_prefix("$.").unwrap_or(path);
+    if field_name.is_empty() || field_name.contains('.') {
+      warn!("Wrapper null setting for '{}' is not for a field of the message, ignoring it", path);
+      continue;
+    }
+    let setting = match value {
+      Value::Null => WrapperNullability::Null,
+      Value::String(s) if s.eq_ignore_ascii_case("null") => WrapperNullability::Null,
+      Value::String(s) if s.eq_ignore_ascii_case("present") => WrapperNullability::Present,
+      _ => return Err(anyhow!("Wrapper null setting for '{}' must be either null or 'present', got {}", path, value))
+    };
+    nullability.insert(field_name.to_string(), setting);
+  }
+  Ok(nullability)
+}
+
+/// Applies the nullability settings to the wrapper type fields of the message. Fields set to null
+/// are removed, and fields set to present are populated with an empty wrapper (i.e. the default
+/// value) if they are not already set.
+pub fn apply_wrapper_nullability(
+  fields: &mut Vec<ProtobufField>,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet,
+  nullability: &HashMap<String, WrapperNullability>
+) {
+  for (field_name, setting) in nullability {
+    let field_descriptor = match descriptor.field.iter().find(|field| field.name.as_ref() == Some(field_name)) {
+      Some(field_descriptor) => field_descriptor,
+      None => {
+        warn!("Message does not have a field '{}', ignoring the wrapper null setting", field_name);
+        continue;
+      }
+    };
+    let type_name = field_descriptor.type_name.clone().unwrap_or_default();
+    if is_repeated_field(field_descriptor) || !WRAPPER_TYPES.contains(&type_name.as_str()) {
+      warn!("Field '{}' is not a wrapper type field, ignoring the wrapper null setting", field_name);
+      continue;
+    }
+
+    let field_num = field_descriptor.number.unwrap_or_default() as u32;
+    match setting {
+      WrapperNullability::Null => {
+        trace!(%field_name, field_num, "Removing wrapper field");
+        fields.retain(|field| field.field_num != field_num);
+      }
+      WrapperNullability::Present => if !fields.iter().any(|field| field.field_num == field_num) {
+        match find_message_type_by_name(last_name(type_name.as_str()), descriptors) {
+          Ok((wrapper_descriptor, _)) => {
+            trace!(%field_name, field_num, "Populating wrapper field with the default value");
+            fields.push(ProtobufField {
+              field_num,
+              field_name: field_name.clone(),
+              wire_type: WireType::LengthDelimited,
+              data: ProtobufFieldData::Message(vec![], wrapper_descriptor)
+            });
+          }
+          Err(err) => warn!("Could not find the wrapper type {}, not populating field '{}' - {}", type_name, field_name, err)
+        }
+      }
+    }
+  }
+}
+
+fn example_field_data(
+  field_descriptor: &FieldDescriptorProto,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet,
+  example: &str
+) -> Option<(ProtobufFieldData, WireType)> {
+  let (data, wire_type) = default_field_data(field_descriptor, descriptor, descriptors)?;
+  let data = match data {
+    ProtobufFieldData::String(_) => ProtobufFieldData::String(example.to_string()),
+    ProtobufFieldData::Boolean(_) => ProtobufFieldData::Boolean(example.parse().ok()?),
+    ProtobufFieldData::UInteger32(_) => ProtobufFieldData::UInteger32(example.parse().ok()?),
+    ProtobufFieldData::Integer32(_) => ProtobufFieldData::Integer32(example.parse().ok()?),
+    ProtobufFieldData::UInteger64(_) => ProtobufFieldData::UInteger64(example.parse().ok()?),
+    ProtobufFieldData::Integer64(_) => ProtobufFieldData::Integer64(example.parse().ok()?),
+    ProtobufFieldData::Float(_) => ProtobufFieldData::Float(example.parse().ok()?),
+    ProtobufFieldData::Double(_) => ProtobufFieldData::Double(example.parse().ok()?),
+    ProtobufFieldData::Bytes(_) => ProtobufFieldData::Bytes(example.as_bytes().to_vec()),
+    ProtobufFieldData::Enum(_, enum_descriptor) => {
+      let value = enum_descriptor.value.iter()
+        .find(|v| v.name.as_deref() == Some(example))
+        .and_then(|v| v.number)
+        .or_else(|| example.parse().ok())?;
+      ProtobufFieldData::Enum(value, enum_descriptor)
+    }
+    _ => return None
+  };
+  Some((data, wire_type))
+}
+
+/// Simple constraints from the validation rules of a field that generated values should satisfy.
+/// Only the length of string and bytes values, and the range of numeric values are supported.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationConstraints {
+  /// Minimum length of a string or bytes value
+  pub min_len: Option<u64>,
+  /// Maximum length of a string or bytes value
+  pub max_len: Option<u64>,
+  /// Minimum (inclusive) numeric value
+  pub min: Option<i64>,
+  /// Maximum (inclusive) numeric value
+  pub max: Option<i64>
+}
+
+impl ValidationConstraints {
+  /// Parses the constraints from an encoded protoc-gen-validate `FieldRules` message
+  pub fn parse(rules: Bytes) -> anyhow::Result<ValidationConstraints> {
+    let mut constraints = ValidationConstraints::default();
+    for (field_num, value) in read_raw_fields(rules)? {
+      if let RawFieldValue::LengthDelimited(type_rules) = value {
+        match field_num {
+          // rules for the numeric types, from float (1) to sfixed64 (12)
+          1..=12 => constraints.numeric_bounds(type_rules, field_num)?,
+          // string and bytes rules
+          14 | 15 => constraints.length_bounds(type_rules, field_num)?,
+          _ => trace!(field_num, "Ignoring unsupported validation rules")
+        }
+      }
+    }
+    Ok(constraints)
+  }
+
+  fn numeric_bounds(&mut self, rules: Bytes, rules_type: u32) -> anyhow::Result<()> {
+    for (field_num, value) in read_raw_fields(rules)? {
+      let bound = match (rules_type, value) {
+        (1, RawFieldValue::Fixed32(v)) => f32::from_bits(v) as f64,
+        (2, RawFieldValue::Fixed64(v)) => f64::from_bits(v),
+        (3 | 4, RawFieldValue::Varint(v)) => v as i64 as f64,
+        (5 | 6, RawFieldValue::Varint(v)) => v as f64,
+        (7 | 8, RawFieldValue::Varint(v)) => ((v >> 1) as i64 ^ -((v & 1) as i64)) as f64,
+        (9, RawFieldValue::Fixed32(v)) => v as f64,
+        (10, RawFieldValue::Fixed64(v)) => v as f64,
+        (11, RawFieldValue::Fixed32(v)) => v as i32 as f64,
+        (12, RawFieldValue::Fixed64(v)) => v as i64 as f64,
+        _ => continue
+      };
+      // generated numbers are whole numbers, so exclusive bounds move to the next one
+      match field_num {
+        1 => {
+          self.min = Some(bound.ceil() as i64);
+          self.max = Some(bound.floor() as i64);
+        }
+        2 => self.max = Some(bound.ceil() as i64 - 1),
+        3 => self.max = Some(bound.floor() as i64),
+        4 => self.min = Some(bound.floor() as i64 + 1),
+        5 => self.min = Some(bound.ceil() as i64),
+        _ => {}
+      }
+    }
+    Ok(())
+  }
+
+  fn length_bounds(&mut self, rules: Bytes, rules_type: u32) -> anyhow::Result<()> {
+    // the len rule is field 19 for strings and 13 for bytes
+    let len_field = if rules_type == 14 { 19 } else { 13 };
+    for (field_num, value) in read_raw_fields(rules)? {
+      if let RawFieldValue::Varint(v) = value {
+        match field_num {
+          2 => self.min_len = Some(v),
+          3 => self.max_len = Some(v),
+          n if n == len_field => {
+            self.min_len = Some(v);
+            self.max_len = Some(v);
+          }
+          _ => {}
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Adjusts the generator so the values it generates satisfy the constraints
+  pub fn constrain(&self, generator: Generator) -> Generator {
+    match generator {
+      Generator::RandomString(size) => Generator::RandomString(self.constrain_length(size)),
+      Generator::RandomHexadecimal(digits) => Generator::RandomHexadecimal(self.constrain_length(digits)),
+      Generator::RandomInt(min, max) => {
+        let lower = self.min.unwrap_or(i64::MIN).clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        let upper = self.max.unwrap_or(i64::MAX).clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        let (min, max) = (min.clamp(lower, upper.max(lower)), max.clamp(lower, upper.max(lower)));
+        Generator::RandomInt(min, max)
+      }
+      _ => generator
+    }
+  }
+
+  fn constrain_length(&self, length: u16) -> u16 {
+    let length = match self.min_len {
+      Some(min_len) if (length as u64) < min_len => min_len.min(u16::MAX as u64) as u16,
+      _ => length
+    };
+    match self.max_len {
+      Some(max_len) if (length as u64) > max_len => max_len as u16,
+      _ => length
+    }
+  }
+}
+
+/// Resolves the fully qualified field name (i.e. `package.Message.field`) for a path in the message
+pub fn qualified_field_name(
+  path: &str,
+  message_name: &str,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> Option<String> {
+  let mut segments = path.strip_prefix("$.")?.split('.').peekable();
+  let mut message_name = message_name.to_string();
+  let mut descriptor = descriptor.clone();
+  while let Some(segment) = segments.next() {
+    let field = descriptor.field.iter().find(|field| field.name.as_deref() == Some(segment))?;
+    if segments.peek().is_none() {
+      return Some(format!("{}.{}", message_name, segment));
+    }
+    let type_name = field.type_name.clone()?;
+    descriptor = find_message_type_by_name(last_name(type_name.as_str()), descriptors).ok()?.0;
+    message_name = type_name.trim_start_matches('.').to_string();
+  }
+  None
+}
+
+/// Returns the default value and wire type for a scalar or enum field. Returns None for message
+/// fields, or if the enum for the field can not be found.
+pub fn default_field_data(
+  field_descriptor: &FieldDescriptorProto,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> Option<(ProtobufFieldData, WireType)> {
+  let (data, wire_type) = match field_descriptor.r#type() {
+    Type::Double => (ProtobufFieldData::Double(0.0), WireType::SixtyFourBit),
+    Type::Float => (ProtobufFieldData::Float(0.0), WireType::ThirtyTwoBit),
+    Type::Int64 | Type::Sint64 => (ProtobufFieldData::Integer64(0), WireType::Varint),
+    Type::Uint64 => (ProtobufFieldData::UInteger64(0), WireType::Varint),
+    Type::Int32 | Type::Sint32 => (ProtobufFieldData::Integer32(0), WireType::Varint),
+    Type::Uint32 => (ProtobufFieldData::UInteger32(0), WireType::Varint),
+    Type::Fixed64 => (ProtobufFieldData::UInteger64(0), WireType::SixtyFourBit),
+    Type::Sfixed64 => (ProtobufFieldData::Integer64(0), WireType::SixtyFourBit),
+    Type::Fixed32 => (ProtobufFieldData::UInteger32(0), WireType::ThirtyTwoBit),
+    Type::Sfixed32 => (ProtobufFieldData::Integer32(0), WireType::ThirtyTwoBit),
+    Type::Bool => (ProtobufFieldData::Boolean(false), WireType::Varint),
+    Type::String => (ProtobufFieldData::String(String::default()), WireType::LengthDelimited),
+    Type::Bytes => (ProtobufFieldData::Bytes(vec![]), WireType::LengthDelimited),
+    Type::Enum => {
+      let enum_type_name = field_descriptor.type_name.clone().unwrap_or_default();
+      let enum_descriptor = find_enum_by_name_in_message(&descriptor.enum_type, enum_type_name.as_str())
+        .or_else(|| find_enum_by_name(descriptors, enum_type_name.as_str()));
+      match enum_descriptor {
+        Some(enum_descriptor) => (ProtobufFieldData::Enum(0, enum_descriptor), WireType::Varint),
+        None => {
+          warn!("Did not find the enum {} for field {:?}, not populating it", enum_type_name, field_descriptor.name);
+          return None;
+        }
+      }
+    }
+    Type::Message | Type::Group => return None
+  };
+  Some((data.default_field_value(field_descriptor), wire_type))
+}
 
 impl GenerateValue<ProtobufFieldData> for Generator {
   fn generate_value(&self,
@@ -35,29 +552,29 @@ impl GenerateValue<ProtobufFieldData> for Generator {
   ) -> anyhow::Result<ProtobufFieldData> {
     let result = match self {
       Generator::RandomInt(min, max) => {
-        let rand_int = thread_rng().gen_range(*min..max.saturating_add(1));
+        let rand_int = with_rng(|rng| rng.gen_range(*min..max.saturating_add(1)));
         match value {
           ProtobufFieldData::String(_) => Ok(ProtobufFieldData::String(rand_int.to_string())),
           ProtobufFieldData::Double(_) => Ok(ProtobufFieldData::Double(rand_int as f64)),
           ProtobufFieldData::Float(_) => Ok(ProtobufFieldData::Float(rand_int as f32)),
           ProtobufFieldData::Integer64(_) => Ok(ProtobufFieldData::Integer64(rand_int as i64)),
           ProtobufFieldData::Integer32(_) => Ok(ProtobufFieldData::Integer32(rand_int)),
-          ProtobufFieldData::UInteger64(_) => Ok(ProtobufFieldData::UInteger64(rand_int as u64)),
-          ProtobufFieldData::UInteger32(_) => Ok(ProtobufFieldData::UInteger32(rand_int as u32)),
+          ProtobufFieldData::UInteger64(_) => Ok(ProtobufFieldData::UInteger64(u64::try_from(rand_int)?)),
+          ProtobufFieldData::UInteger32(_) => Ok(ProtobufFieldData::UInteger32(u32::try_from(rand_int)?)),
           _ => Err(anyhow!("Could not generate a random int from {}", value))
         }
       },
       Generator::Uuid(format) => match value {
         ProtobufFieldData::String(_) => match format.unwrap_or_default() {
-          UuidFormat::Simple => Ok(ProtobufFieldData::String(Uuid::new_v4().as_simple().to_string())),
-          UuidFormat::LowerCaseHyphenated => Ok(ProtobufFieldData::String(Uuid::new_v4().as_hyphenated().to_string())),
-          UuidFormat::UpperCaseHyphenated => Ok(ProtobufFieldData::String(Uuid::new_v4().as_hyphenated().to_string().to_uppercase())),
-          UuidFormat::Urn => Ok(ProtobufFieldData::String(Uuid::new_v4().as_urn().to_string()))
+          UuidFormat::Simple => Ok(ProtobufFieldData::String(random_uuid().as_simple().to_string())),
+          UuidFormat::LowerCaseHyphenated => Ok(ProtobufFieldData::String(random_uuid().as_hyphenated().to_string())),
+          UuidFormat::UpperCaseHyphenated => Ok(ProtobufFieldData::String(random_uuid().as_hyphenated().to_string().to_uppercase())),
+          UuidFormat::Urn => Ok(ProtobufFieldData::String(random_uuid().as_urn().to_string()))
         },
         _ => Err(anyhow!("Could not generate a UUID from {}", value))
       },
       Generator::RandomDecimal(digits) => {
-        let decimal = generate_decimal(*digits as usize);
+        let decimal = random_decimal(*digits as usize);
         match value {
           ProtobufFieldData::String(_) => Ok(ProtobufFieldData::String(decimal)),
           ProtobufFieldData::Double(_) => Ok(ProtobufFieldData::Double(decimal.parse()?)),
@@ -70,11 +587,11 @@ impl GenerateValue<ProtobufFieldData> for Generator {
         }
       },
       Generator::RandomHexadecimal(digits) => match value {
-        ProtobufFieldData::String(_) => Ok(ProtobufFieldData::String(generate_hexadecimal(*digits as usize))),
+        ProtobufFieldData::String(_) => Ok(ProtobufFieldData::String(random_hexadecimal(*digits as usize))),
         _ => Err(anyhow!("Could not generate a random hexadecimal from {}", value))
       },
       Generator::RandomString(size) => match value {
-        ProtobufFieldData::String(_) => Ok(ProtobufFieldData::String(generate_ascii_string(*size as usize))),
+        ProtobufFieldData::String(_) => Ok(ProtobufFieldData::String(random_ascii_string(*size as usize))),
         _ => Err(anyhow!("Could not generate a random string from {}", value))
       },
       Generator::Regex(ref regex) => match value {
@@ -83,7 +600,7 @@ impl GenerateValue<ProtobufFieldData> for Generator {
           match parser.parse(regex) {
             Ok(hir) => {
               let gen = rand_regex::Regex::with_hir(hir, 20).unwrap();
-              Ok(ProtobufFieldData::String(rand::thread_rng().sample::<String, _>(gen)))
+              Ok(ProtobufFieldData::String(with_rng(|rng| rng.sample::<String, _>(gen))))
             },
             Err(err) => {
               warn!("'{}' is not a valid regular expression - {}", regex, err);
@@ -172,7 +689,7 @@ impl GenerateValue<ProtobufFieldData> for Generator {
         })
       },
       Generator::RandomBoolean => {
-        let b = thread_rng().gen::<bool>();
+        let b = with_rng(|rng| rng.gen::<bool>());
         match value {
           ProtobufFieldData::String(_) => Ok(ProtobufFieldData::String(b.to_string())),
           ProtobufFieldData::Boolean(_) => Ok(ProtobufFieldData::Boolean(b)),
@@ -258,11 +775,213 @@ mod tests {
   use maplit::hashmap;
   use pact_matching::generators::DefaultVariantMatcher;
   use pact_models::generators::{GenerateValue, Generator, UuidFormat, VariantMatcher};
-  use prost_types::{DescriptorProto, EnumDescriptorProto};
+  use itertools::Itertools;
+  use prost::encoding::WireType;
+  use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet};
+  use prost_types::field_descriptor_proto::{Label, Type};
   use regex::Regex;
   use serde_json::Value;
 
-  use crate::message_decoder::ProtobufFieldData;
+  use crate::message_decoder::{ProtobufField, ProtobufFieldData};
+  use crate::message_decoder::generators::populate_unset_fields;
+
+  #[test_log::test]
+  fn populate_unset_fields_test() {
+    let field = |name: &str, number: i32, field_type: Type| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(field_type as i32),
+      label: Some(Label::Optional as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("Scalars".to_string()),
+      field: vec![
+        field("double", 1, Type::Double),
+        field("float", 2, Type::Float),
+        field("int64", 3, Type::Int64),
+        field("uint64", 4, Type::Uint64),
+        field("int32", 5, Type::Int32),
+        field("fixed64", 6, Type::Fixed64),
+        field("fixed32", 7, Type::Fixed32),
+        field("bool", 8, Type::Bool),
+        field("string", 9, Type::String),
+        field("bytes", 10, Type::Bytes),
+        field("uint32", 11, Type::Uint32),
+        field("sfixed32", 12, Type::Sfixed32),
+        field("sfixed64", 13, Type::Sfixed64),
+        field("sint32", 14, Type::Sint32),
+        field("sint64", 15, Type::Sint64),
+        FieldDescriptorProto {
+          label: Some(Label::Repeated as i32),
+          .. field("repeated", 16, Type::String)
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let proto2_file = FileDescriptorProto {
+      name: Some("scalars.proto".to_string()),
+      syntax: Some("proto2".to_string()),
+      message_type: vec![ descriptor.clone() ],
+      .. FileDescriptorProto::default()
+    };
+    let mut fields = vec![
+      ProtobufField {
+        field_num: 9,
+        field_name: "string".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::String("set".to_string())
+      }
+    ];
+
+    populate_unset_fields(&mut fields, &descriptor, &proto2_file, &FileDescriptorSet { file: vec![] });
+
+    let field_nums = fields.iter().map(|field| field.field_num).sorted().collect_vec();
+    expect!(field_nums).to(be_equal_to((1..=15u32).collect_vec()));
+    let string_field = fields.iter().find(|field| field.field_num == 9).unwrap();
+    expect!(&string_field.data).to(be_equal_to(&ProtobufFieldData::String("set".to_string())));
+    let bool_field = fields.iter().find(|field| field.field_num == 8).unwrap();
+    expect!(&bool_field.data).to(be_equal_to(&ProtobufFieldData::Boolean(false)));
+    expect!(bool_field.wire_type).to(be_equal_to(WireType::Varint));
+    let double_field = fields.iter().find(|field| field.field_num == 1).unwrap();
+    expect!(double_field.wire_type).to(be_equal_to(WireType::SixtyFourBit));
+  }
+
+  #[test_log::test]
+  fn populate_unset_fields_populates_proto3_fields_with_implicit_presence_with_an_example_value() {
+    let field = |name: &str, number: i32, field_type: Type| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(field_type as i32),
+      label: Some(Label::Optional as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let child = DescriptorProto {
+      name: Some("Child".to_string()),
+      field: vec![ field("name", 1, Type::String) ],
+      .. DescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("Parent".to_string()),
+      field: vec![
+        field("implicit", 1, Type::String),
+        FieldDescriptorProto {
+          proto3_optional: Some(true),
+          oneof_index: Some(0),
+          .. field("optional", 2, Type::Int32)
+        },
+        FieldDescriptorProto {
+          type_name: Some(".test.Child".to_string()),
+          .. field("child", 3, Type::Message)
+        }
+      ],
+      oneof_decl: vec![
+        prost_types::OneofDescriptorProto { name: Some("_optional".to_string()), .. prost_types::OneofDescriptorProto::default() }
+      ],
+      .. DescriptorProto::default()
+    };
+    let file = FileDescriptorProto {
+      name: Some("test.proto".to_string()),
+      package: Some("test".to_string()),
+      syntax: Some("proto3".to_string()),
+      message_type: vec![ descriptor.clone(), child.clone() ],
+      .. FileDescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet { file: vec![ file.clone() ] };
+    let mut fields = vec![];
+
+    populate_unset_fields(&mut fields, &descriptor, &file, &descriptors);
+
+    expect!(fields).to(be_equal_to(vec![
+      ProtobufField {
+        field_num: 1,
+        field_name: "implicit".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::String("implicit".to_string())
+      },
+      ProtobufField {
+        field_num: 2,
+        field_name: "optional".to_string(),
+        wire_type: WireType::Varint,
+        data: ProtobufFieldData::Integer32(0)
+      },
+      ProtobufField {
+        field_num: 3,
+        field_name: "child".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::Message(vec![], child)
+      }
+    ]));
+  }
+
+  #[test_log::test]
+  fn populate_unset_fields_populates_all_the_scalar_fields_of_a_proto3_message() {
+    let field = |name: &str, number: i32, field_type: Type| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(field_type as i32),
+      label: Some(Label::Optional as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let status = prost_types::EnumDescriptorProto {
+      name: Some("Status".to_string()),
+      value: vec![
+        prost_types::EnumValueDescriptorProto { name: Some("UNKNOWN".to_string()), number: Some(0), .. Default::default() },
+        prost_types::EnumValueDescriptorProto { name: Some("ACTIVE".to_string()), number: Some(1), .. Default::default() }
+      ],
+      .. prost_types::EnumDescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("Scalars".to_string()),
+      field: vec![
+        field("double", 1, Type::Double),
+        field("float", 2, Type::Float),
+        field("int64", 3, Type::Int64),
+        field("uint64", 4, Type::Uint64),
+        field("int32", 5, Type::Int32),
+        field("bool", 6, Type::Bool),
+        field("string", 7, Type::String),
+        field("bytes", 8, Type::Bytes),
+        field("sint32", 9, Type::Sint32),
+        FieldDescriptorProto {
+          type_name: Some(".test.Scalars.Status".to_string()),
+          .. field("status", 10, Type::Enum)
+        },
+        FieldDescriptorProto {
+          label: Some(Label::Repeated as i32),
+          .. field("repeated", 11, Type::String)
+        }
+      ],
+      enum_type: vec![ status.clone() ],
+      .. DescriptorProto::default()
+    };
+    let file = FileDescriptorProto {
+      name: Some("scalars.proto".to_string()),
+      package: Some("test".to_string()),
+      syntax: Some("proto3".to_string()),
+      message_type: vec![ descriptor.clone() ],
+      .. FileDescriptorProto::default()
+    };
+    let mut fields = vec![];
+
+    populate_unset_fields(&mut fields, &descriptor, &file, &FileDescriptorSet { file: vec![ file.clone() ] });
+
+    let values = fields.iter()
+      .map(|field| (field.field_num, field.data.clone()))
+      .collect_vec();
+    expect!(values).to(be_equal_to(vec![
+      (1, ProtobufFieldData::Double(1.0)),
+      (2, ProtobufFieldData::Float(1.0)),
+      (3, ProtobufFieldData::Integer64(1)),
+      (4, ProtobufFieldData::UInteger64(1)),
+      (5, ProtobufFieldData::Integer32(1)),
+      (6, ProtobufFieldData::Boolean(true)),
+      (7, ProtobufFieldData::String("string".to_string())),
+      (8, ProtobufFieldData::Bytes("bytes".as_bytes().to_vec())),
+      (9, ProtobufFieldData::Integer32(1)),
+      (10, ProtobufFieldData::Enum(1, status))
+    ]));
+  }
 
   #[test_log::test]
   fn generate_datetime() {
@@ -1,13 +1,14 @@
 //! Decoder for encoded Protobuf messages using the descriptors
 
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::mem;
 use std::str::from_utf8;
 
 use anyhow::anyhow;
-use bytes::{Buf, Bytes, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use itertools::Itertools;
-use prost::encoding::{decode_key, decode_varint, encode_varint, WireType};
+use prost::encoding::{decode_key, decode_varint, encode_key, encode_varint, WireType};
 use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet};
 use prost_types::field_descriptor_proto::Type;
 use tracing::{debug, error, trace, warn};
@@ -24,6 +25,26 @@ use crate::utils::{
 
 mod generators;
 
+pub use generators::{
+  apply_wrapper_nullability,
+  default_field_data,
+  EXAMPLE_OPTION_CONFIG,
+  EXAMPLE_VALUES_CONFIG,
+  example_values_from_config,
+  FILL_DEFAULTS_CONFIG,
+  GENERATOR_SEED_CONFIG,
+  GENERATOR_SEED_ENV_VAR,
+  populate_example_values,
+  populate_unset_fields,
+  qualified_field_name,
+  VALIDATION_OPTION_CONFIG,
+  ValidationConstraints,
+  with_generator_seed,
+  WRAPPER_NULLS_CONFIG,
+  wrapper_nullability_from_config,
+  WrapperNullability
+};
+
 /// Decoded Protobuf field
 #[derive(Clone, Debug, PartialEq)]
 pub struct ProtobufField {
@@ -322,12 +343,7 @@ pub fn decode_message<B>(
             match t {
               Type::String => vec![ (ProtobufFieldData::String(from_utf8(&data_buffer)?.to_string()), wire_type) ],
               Type::Message => {
-                let type_name = field_descriptor.type_name.as_ref().map(|v| last_name(v.as_str()).to_string());
-                let message_proto = descriptor.nested_type.iter()
-                  .find(|message_descriptor| message_descriptor.name == type_name)
-                  .cloned()
-                  .or_else(|| find_message_type_by_name(&type_name.unwrap_or_default(), descriptors).map(|(m, _)| m).ok())
-                  .ok_or_else(|| anyhow!("Did not find the embedded message {:?} for the field {} in the Protobuf descriptor", field_descriptor.type_name, field_num))?;
+                let message_proto = embedded_message_descriptor(&field_descriptor, descriptor, descriptors)?;
                 vec![ (ProtobufFieldData::Message(data_buffer.to_vec(), message_proto), wire_type) ]
               }
               Type::Bytes => vec![ (ProtobufFieldData::Bytes(data_buffer.to_vec()), wire_type) ],
@@ -356,6 +372,17 @@ pub fn decode_message<B>(
               }
             }
           }
+          WireType::StartGroup => {
+            let group = read_group(field_num, buffer)?;
+            let t: Type = field_descriptor.r#type();
+            if t == Type::Group {
+              let group_proto = embedded_message_descriptor(&field_descriptor, descriptor, descriptors)?;
+              vec![ (ProtobufFieldData::Message(group.to_vec(), group_proto), wire_type) ]
+            } else {
+              error!("Was expecting {:?} but received a group", t);
+              vec![ (ProtobufFieldData::Unknown(group.to_vec()), wire_type) ]
+            }
+          }
           _ => return Err(anyhow!("Messages with {:?} wire type fields are not supported", wire_type))
         };
 
@@ -370,7 +397,14 @@ pub fn decode_message<B>(
         }
       }
       Err(err) => {
-        warn!("Was not able to decode field: {}", err);
+        let reserved = is_reserved_field(field_num as i32, descriptor);
+        if reserved {
+          trace!(field_num, "field number is reserved, skipping it");
+        } else if is_extension_field(field_num as i32, descriptor) {
+          trace!(field_num, "field number is in an extension range, it will not be compared");
+        } else {
+          warn!("Was not able to decode field: {}", err);
+        }
         let data = match wire_type {
           WireType::Varint => decode_varint(buffer)?.to_le_bytes().to_vec(),
           WireType::SixtyFourBit => buffer.get_u64().to_le_bytes().to_vec(),
@@ -382,8 +416,12 @@ pub fn decode_message<B>(
             buf.freeze().to_vec()
           }
           WireType::ThirtyTwoBit => buffer.get_u32().to_le_bytes().to_vec(),
+          WireType::StartGroup => read_group(field_num, buffer)?.to_vec(),
           _ => return Err(anyhow!("Messages with {:?} wire type fields are not supported", wire_type))
         };
+        if reserved {
+          continue;
+        }
         fields.push(ProtobufField {
           field_num,
           field_name: "unknown".to_string(),
@@ -397,6 +435,125 @@ pub fn decode_message<B>(
   Ok(fields.iter().sorted_by(|a, b| Ord::cmp(&a.field_num, &b.field_num)).cloned().collect())
 }
 
+/// Finds the descriptor for the type of an embedded message or group field, either nested in the
+/// message or from the descriptors
+fn embedded_message_descriptor(
+  field_descriptor: &FieldDescriptorProto,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<DescriptorProto> {
+  let type_name = field_descriptor.type_name.as_ref().map(|v| last_name(v.as_str()).to_string());
+  descriptor.nested_type.iter()
+    .find(|message_descriptor| message_descriptor.name == type_name)
+    .cloned()
+    .or_else(|| find_message_type_by_name(&type_name.unwrap_or_default(), descriptors).map(|(m, _)| m).ok())
+    .ok_or_else(|| anyhow!("Did not find the embedded message {:?} for the field {} in the Protobuf descriptor",
+      field_descriptor.type_name, field_descriptor.number.unwrap_or_default()))
+}
+
+/// Reads the fields of a (proto2) group, up to the end group tag for the field. The group start tag
+/// must have already been read. Returns the encoded fields without the end group tag, which can be
+/// decoded in the same way as an embedded message.
+fn read_group<B>(field_num: u32, buffer: &mut B) -> anyhow::Result<Bytes> where B: Buf {
+  let mut group = BytesMut::new();
+  loop {
+    if !buffer.has_remaining() {
+      return Err(anyhow!("Group field {} does not have an end group tag", field_num));
+    }
+    let (num, wire_type) = decode_key(buffer)?;
+    let length = match wire_type {
+      WireType::EndGroup if num == field_num => return Ok(group.freeze()),
+      WireType::EndGroup => return Err(anyhow!("Expected the end of group field {}, but got the end of group field {}", field_num, num)),
+      WireType::StartGroup => {
+        let inner = read_group(num, buffer)?;
+        encode_key(num, wire_type, &mut group);
+        group.put_slice(&inner);
+        encode_key(num, WireType::EndGroup, &mut group);
+        continue;
+      }
+      WireType::Varint => {
+        let varint = decode_varint(buffer)?;
+        encode_key(num, wire_type, &mut group);
+        encode_varint(varint, &mut group);
+        continue;
+      }
+      WireType::LengthDelimited => {
+        let length = decode_varint(buffer)?;
+        encode_key(num, wire_type, &mut group);
+        encode_varint(length, &mut group);
+        length as usize
+      }
+      WireType::SixtyFourBit => {
+        encode_key(num, wire_type, &mut group);
+        8
+      }
+      WireType::ThirtyTwoBit => {
+        encode_key(num, wire_type, &mut group);
+        4
+      }
+    };
+    if buffer.remaining() < length {
+      return Err(anyhow!("Insufficient data remaining ({} bytes) to read {} bytes for field {}", buffer.remaining(), length, num));
+    }
+    group.put_slice(&buffer.copy_to_bytes(length));
+  }
+}
+
+/// Splits the message into the encoded bytes (including the keys) of the values of each top level
+/// field, keyed by the field number. This is done with a single pass over the message, without
+/// decoding any of the fields. If the values of a field are contiguous, the returned bytes share
+/// the memory of the message buffer, otherwise they are copied into a new buffer.
+pub fn split_message_fields(message: &Bytes) -> anyhow::Result<HashMap<u32, Bytes>> {
+  let mut buffer = message.clone();
+  let mut ranges: HashMap<u32, Vec<(usize, usize)>> = HashMap::new();
+
+  while buffer.has_remaining() {
+    let start = message.len() - buffer.remaining();
+    let (num, wire_type) = decode_key(&mut buffer)?;
+    let length = match wire_type {
+      WireType::Varint => {
+        decode_varint(&mut buffer)?;
+        0
+      }
+      WireType::SixtyFourBit => 8,
+      WireType::LengthDelimited => decode_varint(&mut buffer)? as usize,
+      WireType::ThirtyTwoBit => 4,
+      WireType::StartGroup => {
+        read_group(num, &mut buffer)?;
+        0
+      }
+      _ => return Err(anyhow!("Messages with {:?} wire type fields are not supported", wire_type))
+    };
+    if buffer.remaining() < length {
+      return Err(anyhow!("Insufficient data remaining ({} bytes) to read {} bytes for field {}", buffer.remaining(), length, num));
+    }
+    buffer.advance(length);
+    let end = message.len() - buffer.remaining();
+
+    let field_ranges = ranges.entry(num).or_default();
+    match field_ranges.last_mut() {
+      Some(range) if range.1 == start => range.1 = end,
+      _ => field_ranges.push((start, end))
+    }
+  }
+
+  Ok(ranges.into_iter()
+    .map(|(num, ranges)| {
+      let bytes = match ranges.as_slice() {
+        [(start, end)] => message.slice(*start..*end),
+        _ => {
+          let mut buffer = BytesMut::with_capacity(ranges.iter().map(|(start, end)| end - start).sum());
+          for (start, end) in ranges.iter() {
+            buffer.extend_from_slice(&message[*start..*end]);
+          }
+          buffer.freeze()
+        }
+      };
+      (num, bytes)
+    })
+    .collect())
+}
+
 fn decode_packed_field(field: FieldDescriptorProto, data: &mut Bytes) -> anyhow::Result<Vec<(ProtobufFieldData, WireType)>> {
   let mut values = vec![];
   let t: Type = field.r#type();
@@ -490,6 +647,18 @@ fn find_field_descriptor(field_num: i32, descriptor: &DescriptorProto) -> anyhow
     .ok_or_else(|| anyhow!("Did not find a field with number {} in the descriptor", field_num))
 }
 
+/// If the field number falls in one of the reserved ranges of the message
+fn is_reserved_field(field_num: i32, descriptor: &DescriptorProto) -> bool {
+  descriptor.reserved_range.iter()
+    .any(|range| range.start.unwrap_or_default() <= field_num && field_num < range.end.unwrap_or_default())
+}
+
+/// If the field number falls in one of the extension ranges of the message
+fn is_extension_field(field_num: i32, descriptor: &DescriptorProto) -> bool {
+  descriptor.extension_range.iter()
+    .any(|range| range.start.unwrap_or_default() <= field_num && field_num < range.end.unwrap_or_default())
+}
+
 #[cfg(test)]
 mod tests {
   use bytes::{BufMut, Bytes, BytesMut};
@@ -512,7 +681,7 @@ mod tests {
     u32_field_descriptor,
     u64_field_descriptor
   };
-  use crate::message_decoder::{decode_message, ProtobufFieldData};
+  use crate::message_decoder::{decode_message, split_message_fields, ProtobufFieldData};
   use crate::protobuf::tests::DESCRIPTOR_WITH_ENUM_BYTES;
 
   const FIELD_1_MESSAGE: [u8; 2] = [8, 1];
@@ -1207,4 +1376,71 @@ mod tests {
     expect!(field_result.wire_type).to(be_equal_to(WireType::Varint));
     expect!(&field_result.data).to(be_equal_to(&ProtobufFieldData::Enum(1, enum_proto.clone())));
   }
+
+  #[test_log::test]
+  fn decode_message_with_a_group_field() {
+    use prost_types::FieldDescriptorProto;
+    use prost_types::field_descriptor_proto::Type;
+
+    let group_descriptor = DescriptorProto {
+      name: Some("Result".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("id".to_string()),
+          number: Some(2),
+          r#type: Some(Type::Int32 as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("Search".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("result".to_string()),
+          number: Some(1),
+          r#type: Some(Type::Group as i32),
+          type_name: Some(".Search.Result".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      nested_type: vec![ group_descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+
+    // result { id: 150 }, followed by an unknown group field 3 with a nested group 4
+    let message_bytes: &[u8] = &[0x0b, 0x10, 0x96, 0x01, 0x0c, 0x1b, 0x23, 0x08, 0x01, 0x24, 0x1c];
+    let mut buffer = Bytes::from(message_bytes);
+    let result = decode_message(&mut buffer, &descriptor, &FileDescriptorSet { file: vec![] }).unwrap();
+    expect!(result.len()).to(be_equal_to(2));
+
+    let field_result = result.first().unwrap();
+    expect!(field_result.field_num).to(be_equal_to(1));
+    expect!(field_result.wire_type).to(be_equal_to(WireType::StartGroup));
+    expect!(&field_result.data).to(be_equal_to(&ProtobufFieldData::Message(vec![0x10, 0x96, 0x01], group_descriptor)));
+    let unknown_field = result.last().unwrap();
+    expect!(&unknown_field.data).to(be_equal_to(&ProtobufFieldData::Unknown(vec![0x23, 0x08, 0x01, 0x24])));
+
+    let mut encoded = BytesMut::new();
+    crate::dynamic_message::DynamicMessage::new(&result, &FileDescriptorSet { file: vec![] })
+      .write_to(&mut encoded).unwrap();
+    expect!(encoded.to_vec()).to(be_equal_to(message_bytes.to_vec()));
+
+    let mut buffer = Bytes::from_static(&[0x0b, 0x10, 0x01]);
+    expect!(decode_message(&mut buffer, &descriptor, &FileDescriptorSet { file: vec![] })).to(be_err());
+  }
+
+  #[test]
+  fn split_message_fields_splits_the_message_in_one_pass() {
+    // field 1 = 1, field 2 = 55, field 1 = 2
+    let message = Bytes::from_static(&[8, 1, 16, 55, 8, 2]);
+    let fields = split_message_fields(&message).unwrap();
+    expect!(fields.len()).to(be_equal_to(2));
+    expect!(fields.get(&1).unwrap().to_vec()).to(be_equal_to(vec![8, 1, 8, 2]));
+    expect!(fields.get(&2).unwrap().to_vec()).to(be_equal_to(vec![16, 55]));
+
+    let message = Bytes::from_static(&[16]);
+    expect!(split_message_fields(&message)).to(be_err());
+  }
 }
@@ -1,34 +1,70 @@
 //! Module for dealing with gRPC metadata (as per https://grpc.io/docs/what-is-grpc/core-concepts/#metadata).
 
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 
 use ansi_term::Colour::{Green, Red};
 use ansi_term::Style;
 use anyhow::anyhow;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::Bytes;
 use itertools::{Either, Itertools};
 use maplit::hashmap;
 use pact_matching::{CoreMatchingContext, matchers, MatchingContext, Mismatch};
+use pact_matching::json::compare_json;
 use pact_matching::matchers::Matches;
 use pact_models::generators::Generator;
 use pact_models::json_utils::json_to_string;
 use pact_models::matchingrules::{MatchingRule, MatchingRuleCategory, RuleLogic};
-use pact_models::matchingrules::expressions::{is_matcher_def, MatchingRuleDefinition, parse_matcher_def};
+use pact_models::matchingrules::expressions::{is_matcher_def, MatchingRuleDefinition, parse_matcher_def, ValueType};
 use pact_models::path_exp::DocPath;
 use pact_models::v4::message_parts::MessageContents;
-use pact_plugin_driver::utils::proto_value_to_string;
+use pact_plugin_driver::utils::{proto_value_to_json, proto_value_to_string};
+use prost::Message;
 use prost_types::Value;
+use prost_types::value::Kind;
+use rand::Rng;
+use serde_json::json;
 use tonic::{Code, Status};
-use tonic::metadata::{Ascii, MetadataMap, MetadataValue};
+use tonic::metadata::{Ascii, Binary, MetadataMap, MetadataValue};
 use tracing::instrument;
-use tracing::log::trace;
+use tracing::log::{trace, warn};
 
 use crate::utils::proto_value_to_map;
 
+/// Response metadata key used to configure the error detail message for an error response
+pub const GRPC_STATUS_DETAILS_CONFIG: &str = "grpc-status-details";
+/// Metadata key for the encoded error details of a gRPC status
+pub const GRPC_STATUS_DETAILS: &str = "grpc-status-details-bin";
+
+/// Request metadata key of the client user-agent
+pub const USER_AGENT_KEY: &str = "user-agent";
+
+/// Expected metadata value for a key that must not be present in the actual metadata
+pub const ABSENT_METADATA_VALUE: &str = "absent()";
+
+/// The `google.rpc.Status` message that is sent as the `grpc-status-details-bin` trailer
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RpcStatus {
+  /// Status code
+  #[prost(int32, tag = "1")]
+  pub code: i32,
+  /// Error message
+  #[prost(string, tag = "2")]
+  pub message: String,
+  /// Error detail messages
+  #[prost(message, repeated, tag = "3")]
+  pub details: Vec<prost_types::Any>
+}
+
 #[derive(Clone, Debug)]
 pub struct MessageMetadata {
   pub matching_rules: MatchingRuleCategory,
   pub generators: HashMap<String, Generator>,
   pub values: HashMap<String, String>,
+  /// Metadata values declared as JSON (configured with a map or list), which are matched as JSON
+  pub json_values: HashMap<String, serde_json::Value>
 }
 
 #[instrument(ret, level = "trace")]
@@ -42,8 +78,19 @@ pub fn process_metadata(metadata_config: Option<&Value>) -> anyhow::Result<Optio
       let mut matching_rules = MatchingRuleCategory::empty("metadata");
       let mut generators = hashmap!{};
       let mut values = hashmap!{};
+      let mut json_values = hashmap!{};
 
       for (key, value) in &metadata_map {
+        if key == GRPC_STATUS_DETAILS_CONFIG {
+          // Error details need the Protobuf descriptors, so are built by the caller
+          continue;
+        }
+        if let Some(Kind::StructValue(_)) | Some(Kind::ListValue(_)) = &value.kind {
+          let path = DocPath::root().join(key);
+          let json = process_json_metadata(&path, &proto_value_to_json(value), &mut matching_rules, &mut generators)?;
+          json_values.insert(key.clone(), json);
+          continue;
+        }
         let str_value = proto_value_to_string(value).unwrap_or_default();
         if is_matcher_def(str_value.as_str()) {
           let mrd = parse_matcher_def(str_value.as_str())?;
@@ -67,18 +114,104 @@ pub fn process_metadata(metadata_config: Option<&Value>) -> anyhow::Result<Optio
       Ok(Some(MessageMetadata {
         matching_rules,
         generators,
-        values
+        values,
+        json_values
       }))
     }
   }
 }
 
+/// Adds a regex matcher for the `user-agent` request metadata configured with `pact:user-agent`.
+/// Any configured `user-agent` value is kept as the example, otherwise one is generated from the regex.
+pub fn add_user_agent_matcher(
+  metadata: Option<MessageMetadata>,
+  user_agent: Option<&Value>
+) -> anyhow::Result<Option<MessageMetadata>> {
+  match user_agent.and_then(proto_value_to_string) {
+    None => Ok(metadata),
+    Some(regex) => {
+      let hir = regex_syntax::ParserBuilder::new().unicode(false).build().parse(unanchored(regex.as_str()))
+        .map_err(|err| anyhow!("'{}' is not a valid regex for the user-agent - {}", regex, err))?;
+      let mut metadata = metadata.unwrap_or_else(|| MessageMetadata {
+        matching_rules: MatchingRuleCategory::empty("metadata"),
+        generators: hashmap!{},
+        values: hashmap!{},
+        json_values: hashmap!{}
+      });
+      metadata.matching_rules.add_rule(DocPath::new(USER_AGENT_KEY)?, MatchingRule::Regex(regex.clone()), RuleLogic::And);
+      if !metadata.values.contains_key(USER_AGENT_KEY) {
+        let gen = rand_regex::Regex::with_hir(hir, 20)?;
+        metadata.values.insert(USER_AGENT_KEY.to_string(), rand::thread_rng().sample::<String, _>(gen));
+      }
+      Ok(Some(metadata))
+    }
+  }
+}
+
+/// Removes the start and end anchors from the regex, as examples can not be generated for them
+fn unanchored(regex: &str) -> &str {
+  let regex = regex.strip_prefix('^').unwrap_or(regex);
+  match regex.strip_suffix('$') {
+    Some(stripped) if !stripped.ends_with('\\') => stripped,
+    _ => regex
+  }
+}
+
+/// Processes a metadata value declared as JSON, adding any matching rules and generators for the
+/// string values that are matcher definitions. Returns the example JSON value.
+fn process_json_metadata(
+  path: &DocPath,
+  value: &serde_json::Value,
+  matching_rules: &mut MatchingRuleCategory,
+  generators: &mut HashMap<String, Generator>
+) -> anyhow::Result<serde_json::Value> {
+  match value {
+    serde_json::Value::Object(map) => {
+      let mut result = serde_json::Map::new();
+      for (key, value) in map {
+        result.insert(key.clone(), process_json_metadata(&path.join(key), value, matching_rules, generators)?);
+      }
+      Ok(serde_json::Value::Object(result))
+    }
+    serde_json::Value::Array(list) => {
+      let mut result = vec![];
+      for (index, value) in list.iter().enumerate() {
+        let mut item_path = path.clone();
+        item_path.push_index(index);
+        result.push(process_json_metadata(&item_path, value, matching_rules, generators)?);
+      }
+      Ok(serde_json::Value::Array(result))
+    }
+    serde_json::Value::String(s) if is_matcher_def(s.as_str()) => {
+      let mrd = parse_matcher_def(s.as_str())?;
+      for rule in &mrd.rules {
+        match rule {
+          Either::Left(rule) => matching_rules.add_rule(path.clone(), rule.clone(), RuleLogic::And),
+          Either::Right(mr) => return Err(anyhow!("Was expecting a value for metadata path '{}', but got a matching reference {:?}", path, mr))
+        }
+      }
+      if let Some(generator) = mrd.generator {
+        generators.insert(path.to_string(), generator);
+      }
+      Ok(match &mrd.value_type {
+        ValueType::Integer => mrd.value.parse::<i64>().map(|v| json!(v)).unwrap_or_else(|_| json!(mrd.value)),
+        ValueType::Decimal | ValueType::Number => mrd.value.parse::<f64>().map(|v| json!(v)).unwrap_or_else(|_| json!(mrd.value)),
+        ValueType::Boolean => mrd.value.parse::<bool>().map(|v| json!(v)).unwrap_or_else(|_| json!(mrd.value)),
+        _ => json!(mrd.value)
+      })
+    }
+    _ => Ok(value.clone())
+  }
+}
+
 #[derive(Clone, Debug)]
 pub struct MetadataMatchResult {
   /// Overall result of the comparison
   pub result: bool,
   /// List of mismatches that occurred
-  pub mismatches: Vec<Mismatch>
+  pub mismatches: Vec<Mismatch>,
+  /// The user-agent sent by the client, recorded for reporting
+  pub user_agent: Option<String>
 }
 
 impl MetadataMatchResult {
@@ -92,6 +225,7 @@ impl MetadataMatchResult {
     MetadataMatchResult {
       result: false,
       mismatches,
+      user_agent: None
     }
   }
 
@@ -99,24 +233,173 @@ impl MetadataMatchResult {
   pub(crate) fn ok() -> MetadataMatchResult {
     MetadataMatchResult {
       result: true,
-      mismatches: vec![]
+      mismatches: vec![],
+      user_agent: None
     }
   }
+
+  /// Records the user-agent sent by the client with the result
+  pub(crate) fn with_user_agent(mut self, user_agent: Option<String>) -> MetadataMatchResult {
+    self.user_agent = user_agent;
+    self
+  }
+}
+
+/// Prefix for expected metadata keys that must be received in the initial metadata (headers)
+pub const HEADER_PREFIX: &str = "header:";
+/// Prefix for expected metadata keys that must be received in the trailers
+pub const TRAILER_PREFIX: &str = "trailer:";
+
+/// Where an expected metadata entry must be received
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MetadataLocation {
+  /// Either the headers or the trailers
+  Any,
+  /// Initial metadata (headers)
+  Header,
+  /// Trailers
+  Trailer
+}
+
+/// Returns the location for the metadata key, and the key without any location prefix
+pub fn metadata_location(key: &str) -> (MetadataLocation, &str) {
+  if let Some(key) = key.strip_prefix(HEADER_PREFIX) {
+    (MetadataLocation::Header, key)
+  } else if let Some(key) = key.strip_prefix(TRAILER_PREFIX) {
+    (MetadataLocation::Trailer, key)
+  } else {
+    (MetadataLocation::Any, key)
+  }
 }
 
+/// If the response headers are for a trailers-only response (an immediate error without a body),
+/// which has the gRPC status in the headers
+pub fn is_trailers_only(headers: &MetadataMap) -> bool {
+  headers.get("grpc-status").is_some()
+}
+
+/// gRPC response metadata, with the initial metadata (headers) and trailers kept separate
+#[derive(Clone, Debug, Default)]
+pub struct ResponseMetadata {
+  /// Initial metadata
+  pub headers: MetadataMap,
+  /// Trailing metadata
+  pub trailers: MetadataMap
+}
+
+impl ResponseMetadata {
+  /// Create the response metadata from the received headers and the combined metadata. Any
+  /// entries not in the headers were received in the trailers. If the headers have a gRPC status,
+  /// it was a trailers-only response.
+  pub fn new(headers: &MetadataMap, combined: &MetadataMap) -> Self {
+    if is_trailers_only(headers) {
+      ResponseMetadata {
+        headers: MetadataMap::new(),
+        trailers: combined.clone()
+      }
+    } else {
+      let header_map = headers.clone().into_headers();
+      let mut trailers = http::HeaderMap::new();
+      for (key, value) in combined.clone().into_headers().iter() {
+        if !header_map.contains_key(key) {
+          trailers.append(key.clone(), value.clone());
+        }
+      }
+      ResponseMetadata {
+        headers: headers.clone(),
+        trailers: MetadataMap::from_headers(trailers)
+      }
+    }
+  }
+
+  /// Looks up the value for the key, using the location prefix of the key (if any)
+  pub fn get(&self, key: &str) -> Option<&MetadataValue<Ascii>> {
+    match metadata_location(key) {
+      (MetadataLocation::Any, key) => self.headers.get(key).or_else(|| self.trailers.get(key)),
+      (MetadataLocation::Header, key) => self.headers.get(key),
+      (MetadataLocation::Trailer, key) => self.trailers.get(key)
+    }
+  }
+
+  /// Looks up the binary value for the key (a key ending in `-bin`), using the location prefix of
+  /// the key (if any)
+  pub fn get_bin(&self, key: &str) -> Option<&MetadataValue<Binary>> {
+    match metadata_location(key) {
+      (MetadataLocation::Any, key) => self.headers.get_bin(key).or_else(|| self.trailers.get_bin(key)),
+      (MetadataLocation::Header, key) => self.headers.get_bin(key),
+      (MetadataLocation::Trailer, key) => self.trailers.get_bin(key)
+    }
+  }
+
+  /// If there are no headers or trailers
+  pub fn is_empty(&self) -> bool {
+    self.headers.is_empty() && self.trailers.is_empty()
+  }
+}
+
+/// Actual value of a metadata entry. Entries with a key ending in `-bin` have binary values.
+#[derive(Clone, Copy, Debug)]
+enum ActualMetadataValue<'a> {
+  Ascii(&'a MetadataValue<Ascii>),
+  Binary(&'a MetadataValue<Binary>)
+}
+
+/// If the expected metadata value requires the key to not be present (see [ABSENT_METADATA_VALUE])
+pub fn is_absent_value(value: &serde_json::Value) -> bool {
+  value.as_str() == Some(ABSENT_METADATA_VALUE)
+}
+
+/// If the metadata key is for a binary value
+fn is_binary_key(key: &str) -> bool {
+  metadata_location(key).1.to_lowercase().ends_with("-bin")
+}
+
+/// Compares the expected metadata to the actual metadata. Any location prefixes on the expected
+/// keys are ignored.
 #[instrument(ret)]
 pub fn compare_metadata(
   expected_metadata: &HashMap<String, serde_json::Value>,
   actual_metadata: &MetadataMap,
   context: &CoreMatchingContext
 ) -> anyhow::Result<(MetadataMatchResult, Vec<String>)> {
+  compare_metadata_values(expected_metadata, actual_metadata.is_empty(),
+    |key| if is_binary_key(key) {
+      actual_metadata.get_bin(metadata_location(key).1).map(ActualMetadataValue::Binary)
+    } else {
+      actual_metadata.get(metadata_location(key).1).map(ActualMetadataValue::Ascii)
+    }, context)
+}
+
+/// Compares the expected metadata to the actual response metadata. Keys with a `header:` or
+/// `trailer:` prefix must be received in the headers or trailers respectively.
+#[instrument(ret)]
+pub fn compare_response_metadata(
+  expected_metadata: &HashMap<String, serde_json::Value>,
+  actual_metadata: &ResponseMetadata,
+  context: &CoreMatchingContext
+) -> anyhow::Result<(MetadataMatchResult, Vec<String>)> {
+  compare_metadata_values(expected_metadata, actual_metadata.is_empty(),
+    |key| if is_binary_key(key) {
+      actual_metadata.get_bin(key).map(ActualMetadataValue::Binary)
+    } else {
+      actual_metadata.get(key).map(ActualMetadataValue::Ascii)
+    }, context)
+}
+
+fn compare_metadata_values<'a, F>(
+  expected_metadata: &HashMap<String, serde_json::Value>,
+  actual_is_empty: bool,
+  lookup: F,
+  context: &CoreMatchingContext
+) -> anyhow::Result<(MetadataMatchResult, Vec<String>)>
+  where F: Fn(&str) -> Option<ActualMetadataValue<'a>> {
   if expected_metadata.is_empty() {
     Ok((MetadataMatchResult::ok(), vec![]))
-  } else if actual_metadata.is_empty() {
+  } else if actual_is_empty {
     let mut output = vec![];
     let bold = Style::new().bold();
     let mismatches = expected_metadata.iter()
-      .filter(|(k, _)| !is_special_metadata_key(k))
+      .filter(|(k, v)| !is_special_metadata_key(k) && !is_absent_value(v))
       .map(|(k, v)| {
         output.push(format!("          key '{}' ({})", bold.paint(k), Red.paint("FAILED")));
         Mismatch::MetadataMismatch {
@@ -134,8 +417,28 @@ pub fn compare_metadata(
     let bold = Style::new().bold();
 
     for (key, expected_value) in expected_metadata {
-      if let Some(actual_value) = actual_metadata.get(key) {
-        let out = match_metadata_value(&mut mismatches, key, expected_value, actual_value, context);
+      if is_absent_value(expected_value) {
+        if let Some(actual_value) = lookup(key.as_str()) {
+          let actual_value = match actual_value {
+            ActualMetadataValue::Ascii(value) => value.to_str().unwrap_or_default().to_string(),
+            ActualMetadataValue::Binary(value) => value.to_bytes().map(|bytes| BASE64.encode(bytes)).unwrap_or_default()
+          };
+          output.push(format!("        key '{}' is absent [{}]", bold.paint(key), Red.paint("FAILED")));
+          mismatches.push(Mismatch::MetadataMismatch { key: key.clone(),
+            expected: "".to_string(),
+            actual: actual_value,
+            mismatch: format!("Expected metadata with key '{}' to be absent but it was received", key) }
+          );
+        } else {
+          output.push(format!("        key '{}' is absent [{}]", bold.paint(key), Green.paint("OK")));
+        }
+      } else if let Some(actual_value) = lookup(key.as_str()) {
+        let out = match actual_value {
+          ActualMetadataValue::Ascii(actual_value) =>
+            match_metadata_value(&mut mismatches, key, expected_value, actual_value, context),
+          ActualMetadataValue::Binary(actual_value) =>
+            match_binary_metadata_value(&mut mismatches, key, expected_value, actual_value, context)
+        };
         output.push(out);
       } else if !is_special_metadata_key(key) {
         output.push(format!("          key '{}' ({})", bold.paint(key), Red.paint("FAILED")));
@@ -156,7 +459,7 @@ pub fn compare_metadata(
 }
 
 fn is_special_metadata_key(key: &String) -> bool {
-  let key = key.to_lowercase();
+  let key = metadata_location(key).1.to_lowercase();
   key == "content-type" || key == "contenttype"
 }
 
@@ -168,6 +471,9 @@ fn match_metadata_value(
   context: &CoreMatchingContext
 ) -> String {
   let path = DocPath::root().join(key);
+  if expected.is_object() || expected.is_array() {
+    return match_json_metadata_value(mismatches, key, &path, expected, actual, context);
+  }
   let expected = json_to_string(expected);
   let bold = Style::new().bold();
   match actual.to_str() {
@@ -192,9 +498,25 @@ fn match_metadata_value(
             .map(|r| matching_rule_description(r))
             .join(", ")
           ), result)
-      } else if key == "grpc-status" {
+      } else if metadata_location(key).1 == "grpc-status" {
         let actual_status = string_to_code(actual, "").unwrap_or(Status::unknown(""));
-        if let Some(expected_status) = string_to_code(expected.as_str(), "") {
+        if let Some(expected_class) = StatusClass::parse(expected.as_str()) {
+          let actual_code = string_to_code(actual, "").map(|status| status.code()).unwrap_or(Code::Ok);
+          let actual_class = StatusClass::of(actual_code);
+          let result = if expected_class != actual_class {
+            mismatches.push(Mismatch::MetadataMismatch {
+              key: key.clone(),
+              expected,
+              actual: actual.to_string(),
+              mismatch: format!("Comparison of metadata key '{}' failed: expected a {} status but received {} ({})", key,
+                expected_class, code_desc(&actual_code), actual_class)
+            });
+            Red.paint("FAILED")
+          } else {
+            Green.paint("OK")
+          };
+          format!("        key '{}' with a {} status [{}]", bold.paint(key), bold.paint(expected_class.to_string()), result)
+        } else if let Some(expected_status) = string_to_code(expected.as_str(), "") {
           let result = if expected_status.code() != actual_status.code() {
             mismatches.push(Mismatch::MetadataMismatch {
               key: key.clone(),
@@ -237,6 +559,145 @@ fn match_metadata_value(
   }
 }
 
+/// Compares a binary metadata value (a key ending in `-bin`) as bytes. The expected value is
+/// Base64 encoded, as it is sent on the wire. Any matching rules are applied to the bytes, so a
+/// min or max type matcher will check the number of bytes.
+fn match_binary_metadata_value(
+  mismatches: &mut Vec<Mismatch>,
+  key: &String,
+  expected: &serde_json::Value,
+  actual: &MetadataValue<Binary>,
+  context: &CoreMatchingContext
+) -> String {
+  let path = DocPath::root().join(key);
+  let bold = Style::new().bold();
+  let expected = json_to_string(expected);
+  let expected_bytes = BASE64.decode(expected.as_str()).unwrap_or_else(|_| expected.as_bytes().to_vec());
+  let actual_bytes = match actual.to_bytes() {
+    Ok(bytes) => bytes,
+    Err(err) => {
+      mismatches.push(Mismatch::MetadataMismatch {
+        key: key.clone(),
+        expected,
+        actual: "".to_string(),
+        mismatch: format!("Could not decode the actual binary value with key '{}' - {}", key, err)
+      });
+      return format!("        key '{}' with a binary value [{}]", bold.paint(key), Red.paint("FAILED"));
+    }
+  };
+
+  let result = if context.matcher_is_defined(&path) {
+    let matchers = context.select_best_matcher(&path);
+    matchers::match_values(&path, &matchers, expected_bytes.as_slice(), &actual_bytes[..])
+  } else {
+    expected_bytes.as_slice().matches_with(&actual_bytes[..], &MatchingRule::Equality, false)
+      .map_err(|err| vec![ err.to_string() ])
+  };
+  let result = match result {
+    Ok(_) => Green.paint("OK"),
+    Err(errors) => {
+      for mismatch in errors {
+        mismatches.push(Mismatch::MetadataMismatch {
+          key: key.clone(),
+          expected: expected.clone(),
+          actual: BASE64.encode(&actual_bytes),
+          mismatch: format!("Comparison of metadata key '{}' failed: {}", key, mismatch)
+        });
+      }
+      Red.paint("FAILED")
+    }
+  };
+  format!("        key '{}' with a binary value [{}]", bold.paint(key), result)
+}
+
+/// Compares a metadata value declared as JSON, applying any matching rules to the JSON document
+fn match_json_metadata_value(
+  mismatches: &mut Vec<Mismatch>,
+  key: &String,
+  path: &DocPath,
+  expected: &serde_json::Value,
+  actual: &MetadataValue<Ascii>,
+  context: &CoreMatchingContext
+) -> String {
+  let bold = Style::new().bold();
+  let actual_json = actual.to_str()
+    .map_err(|err| anyhow!(err))
+    .and_then(|actual| serde_json::from_str::<serde_json::Value>(actual).map_err(|err| anyhow!(err)));
+  let result = match actual_json {
+    Ok(actual_json) => {
+      let errors = match compare_json(path, expected, &actual_json, context) {
+        Ok(_) => vec![],
+        Err(errors) => errors.iter().map(|mismatch| mismatch.description()).collect()
+      };
+      let errors = errors.into_iter()
+        .chain(json_type_mismatches(path, &actual_json, context))
+        .collect_vec();
+      if errors.is_empty() {
+        Green.paint("OK")
+      } else {
+        for mismatch in errors {
+          mismatches.push(Mismatch::MetadataMismatch {
+            key: key.clone(),
+            expected: expected.to_string(),
+            actual: actual_json.to_string(),
+            mismatch: format!("Comparison of metadata key '{}' failed: {}", key, mismatch)
+          });
+        }
+        Red.paint("FAILED")
+      }
+    }
+    Err(err) => {
+      mismatches.push(Mismatch::MetadataMismatch {
+        key: key.clone(),
+        expected: expected.to_string(),
+        actual: actual.to_str().unwrap_or_default().to_string(),
+        mismatch: format!("Expected metadata value with key '{}' to be JSON - {}", key, err)
+      });
+      Red.paint("FAILED")
+    }
+  };
+  format!("        key '{}' with a JSON value [{}]", bold.paint(key), result)
+}
+
+/// The integer, decimal, number and boolean matchers also accept strings that can be parsed as the
+/// type. The values of metadata declared as JSON have types, so for these matchers the actual JSON
+/// value must have the type. Returns a description of each value that does not.
+fn json_type_mismatches(
+  path: &DocPath,
+  actual: &serde_json::Value,
+  context: &CoreMatchingContext
+) -> Vec<String> {
+  let mut mismatches = vec![];
+  if context.matcher_is_defined(path) {
+    let rules = context.select_best_matcher(path);
+    if !rules.cascaded {
+      for rule in &rules.rules {
+        let expected_type = match rule {
+          MatchingRule::Integer if !(actual.is_i64() || actual.is_u64()) => Some("an integer"),
+          MatchingRule::Decimal | MatchingRule::Number if !actual.is_number() => Some("a number"),
+          MatchingRule::Boolean if !actual.is_boolean() => Some("a boolean"),
+          _ => None
+        };
+        if let Some(expected_type) = expected_type {
+          mismatches.push(format!("{} -> Expected {} to be {}", path, actual, expected_type));
+        }
+      }
+    }
+  }
+  match actual {
+    serde_json::Value::Object(map) => for (key, value) in map {
+      mismatches.extend(json_type_mismatches(&path.join(key), value, context));
+    }
+    serde_json::Value::Array(list) => for (index, value) in list.iter().enumerate() {
+      let mut item_path = path.clone();
+      item_path.push_index(index);
+      mismatches.extend(json_type_mismatches(&item_path, value, context));
+    }
+    _ => {}
+  }
+  mismatches
+}
+
 // TODO: This should move into the Pact-Rust repo
 fn matching_rule_description(rule: &MatchingRule) -> String {
   match rule {
@@ -275,12 +736,106 @@ pub fn grpc_status(response_contents: &MessageContents) -> Option<Status> {
       .map(json_to_string)
       .unwrap_or("No message set".to_string());
     string_to_code(status.as_str(), message.as_str())
+      .map(|status| match response_contents.metadata.get(GRPC_STATUS_DETAILS) {
+        Some(details) => match status_with_details(&status, json_to_string(details).as_str()) {
+          Ok(status) => status,
+          Err(err) => {
+            warn!("Could not add the error details to the gRPC status, ignoring them - {}", err);
+            status
+          }
+        }
+        None => status
+      })
   } else {
     None
   }
 }
 
+/// Adds the error detail (a Base64 encoded `google.protobuf.Any`) to the status
+fn status_with_details(status: &Status, details: &str) -> anyhow::Result<Status> {
+  let detail = prost_types::Any::decode(BASE64.decode(details)?.as_slice())?;
+  let rpc_status = RpcStatus {
+    code: status.code() as i32,
+    message: status.message().to_string(),
+    details: vec![ detail ]
+  };
+  Ok(Status::with_details(status.code(), status.message(), Bytes::from(rpc_status.encode_to_vec())))
+}
+
+/// Returns the error details from the status as Base64 encoded `google.protobuf.Any` values
+pub fn status_details(status: &Status) -> anyhow::Result<Vec<String>> {
+  Ok(rpc_status(status)?
+    .map(|rpc_status| rpc_status.details.iter().map(|detail| BASE64.encode(detail.encode_to_vec())).collect())
+    .unwrap_or_default())
+}
+
+/// Decodes the `google.rpc.Status` from the details of the status, if it has any
+pub fn rpc_status(status: &Status) -> anyhow::Result<Option<RpcStatus>> {
+  if status.details().is_empty() {
+    Ok(None)
+  } else {
+    Ok(Some(RpcStatus::decode(status.details())?))
+  }
+}
+
+/// Classes of gRPC status codes, so a response can be matched against any client or server error
+/// status instead of a specific one. The codes are grouped using their HTTP status mapping, so
+/// codes that map to a 4xx HTTP status are client errors, and the rest are server errors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StatusClass {
+  /// The OK status
+  Ok,
+  /// Errors caused by the request (i.e. INVALID_ARGUMENT or NOT_FOUND)
+  ClientError,
+  /// Errors caused by the server (i.e. INTERNAL or UNAVAILABLE)
+  ServerError
+}
+
+impl StatusClass {
+  /// Parses the status class from an expected `grpc-status` value (`CLIENT_ERROR` or `SERVER_ERROR`)
+  pub fn parse(value: &str) -> Option<StatusClass> {
+    match value.to_uppercase().replace('-', "_").as_str() {
+      "CLIENT_ERROR" => Some(StatusClass::ClientError),
+      "SERVER_ERROR" => Some(StatusClass::ServerError),
+      _ => None
+    }
+  }
+
+  /// Returns the class of the status code
+  pub fn of(code: Code) -> StatusClass {
+    match code {
+      Code::Ok => StatusClass::Ok,
+      Code::Cancelled | Code::InvalidArgument | Code::NotFound | Code::AlreadyExists |
+      Code::PermissionDenied | Code::ResourceExhausted | Code::FailedPrecondition | Code::Aborted |
+      Code::OutOfRange | Code::Unauthenticated => StatusClass::ClientError,
+      _ => StatusClass::ServerError
+    }
+  }
+
+  /// Status to return from the mock server for the class
+  fn example_status(&self, message: &str) -> Option<Status> {
+    match self {
+      StatusClass::Ok => None,
+      StatusClass::ClientError => Some(Status::invalid_argument(message)),
+      StatusClass::ServerError => Some(Status::internal(message))
+    }
+  }
+}
+
+impl Display for StatusClass {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      StatusClass::Ok => write!(f, "OK"),
+      StatusClass::ClientError => write!(f, "CLIENT_ERROR"),
+      StatusClass::ServerError => write!(f, "SERVER_ERROR")
+    }
+  }
+}
+
 pub fn string_to_code(status: &str, message: &str) -> Option<Status> {
+  if let Some(class) = StatusClass::parse(status) {
+    return class.example_status(message);
+  }
   match status {
     // Taken from https://grpc.github.io/grpc/core/md_doc_statuscodes.html
     "OK" => None,
@@ -335,6 +890,8 @@ fn code_desc(code: &Code) -> String {
 
 #[cfg(test)]
 mod tests {
+  use base64::Engine;
+  use base64::engine::general_purpose::STANDARD as BASE64;
   use expectest::prelude::*;
   use maplit::{btreemap, hashmap};
   use pact_matching::{CoreMatchingContext, DiffConfig, Mismatch};
@@ -342,12 +899,26 @@ mod tests {
   use pact_models::matchingrules::MatchingRule;
   use pact_models::path_exp::DocPath;
   use pact_models::v4::message_parts::MessageContents;
+  use prost::Message;
   use prost_types::{Struct, Value, value};
   use serde_json::json;
   use tonic::Code;
-  use tonic::metadata::MetadataMap;
+  use tonic::metadata::{MetadataMap, MetadataValue};
 
-  use crate::metadata::{compare_metadata, grpc_status, process_metadata};
+  use crate::metadata::{
+    ABSENT_METADATA_VALUE,
+    add_user_agent_matcher,
+    compare_metadata,
+    compare_response_metadata,
+    grpc_status,
+    GRPC_STATUS_DETAILS,
+    process_metadata,
+    ResponseMetadata,
+    RpcStatus,
+    status_details,
+    StatusClass,
+    string_to_code
+  };
   use crate::utils::prost_string;
 
   #[test]
@@ -408,6 +979,33 @@ mod tests {
     ]));
   }
 
+  #[test]
+  fn add_user_agent_matcher_adds_a_regex_rule_for_the_user_agent() {
+    let result = add_user_agent_matcher(None, None).unwrap();
+    expect!(result).to(be_none());
+
+    let result = add_user_agent_matcher(None, Some(&prost_string(r"^grpc-test/\d+\.\d+$"))).unwrap().unwrap();
+    let rules = result.matching_rules.rules.get(&DocPath::new("user-agent").unwrap()).unwrap();
+    expect!(&rules.rules).to(be_equal_to(&vec![ MatchingRule::Regex(r"^grpc-test/\d+\.\d+$".to_string()) ]));
+    let example = result.values.get("user-agent").cloned().unwrap_or_default();
+    expect!(regex::Regex::new(r"^grpc-test/\d+\.\d+$").unwrap().is_match(example.as_str())).to(be_true());
+
+    let config = Value {
+      kind: Some(value::Kind::StructValue(Struct {
+        fields: btreemap!{ "user-agent".to_string() => prost_string("grpc-test/1.2") }
+      }))
+    };
+    let metadata = process_metadata(Some(&config)).unwrap();
+    let result = add_user_agent_matcher(metadata, Some(&prost_string(r"^grpc-test/\d+\.\d+$"))).unwrap().unwrap();
+    expect!(result.values.get("user-agent").cloned()).to(be_some().value("grpc-test/1.2".to_string()));
+
+    let result = add_user_agent_matcher(None, Some(&prost_string(r"^grpc-java-netty/1\.\d+\.\d+"))).unwrap().unwrap();
+    let example = result.values.get("user-agent").cloned().unwrap_or_default();
+    expect!(regex::Regex::new(r"^grpc-java-netty/1\.\d+\.\d+").unwrap().is_match(example.as_str())).to(be_true());
+
+    expect!(add_user_agent_matcher(None, Some(&prost_string("grpc-test/(")))).to(be_err());
+  }
+
   #[test]
   fn compare_metadata_returns_ok_if_there_is_no_expected_metadata() {
     let expected = hashmap!{};
@@ -496,6 +1094,67 @@ mod tests {
     }).collect::<Vec<String>>()).to(be_equal_to(vec!["x-b".to_string()]));
   }
 
+  #[test]
+  fn compare_metadata_with_a_binary_value() {
+    let expected = hashmap!{
+      "x-token-bin".to_string() => json!(BASE64.encode([1u8, 2, 3, 4]))
+    };
+    let context = CoreMatchingContext::new(
+      DiffConfig::NoUnexpectedKeys,
+      &matchingrules! {
+        "metadata" => {
+          "x-token-bin" => [ MatchingRule::MinMaxType(4, 8) ]
+        }
+      }.rules_for_category("metadata").unwrap(),
+      &hashmap!{}
+    );
+
+    let mut actual = MetadataMap::new();
+    actual.insert_bin("x-token-bin", MetadataValue::from_bytes(&[9, 8, 7, 6, 5, 4]));
+    let (result, _) = compare_metadata(&expected, &actual, &context).unwrap();
+    expect!(result.result).to(be_true());
+
+    let mut actual = MetadataMap::new();
+    actual.insert_bin("x-token-bin", MetadataValue::from_bytes(&[9, 8]));
+    let (result, _) = compare_metadata(&expected, &actual, &context).unwrap();
+    expect!(result.result).to(be_false());
+    expect!(result.mismatches.len()).to(be_equal_to(1));
+
+    let mut actual = MetadataMap::new();
+    actual.insert_bin("x-token-bin", MetadataValue::from_bytes(&[1, 2, 3, 4]));
+    let (result, _) = compare_metadata(&expected, &actual, &CoreMatchingContext::default()).unwrap();
+    expect!(result.result).to(be_true());
+  }
+
+  #[test]
+  fn compare_metadata_with_a_key_that_must_be_absent() {
+    let expected = hashmap!{
+      "x-a".to_string() => json!("A"),
+      "x-secret".to_string() => json!(ABSENT_METADATA_VALUE)
+    };
+    let context = CoreMatchingContext::default();
+
+    let mut actual = MetadataMap::new();
+    actual.insert("x-a", "A".parse().expect("Expected a value"));
+    let (result, _) = compare_metadata(&expected, &actual, &context).unwrap();
+    expect!(result.result).to(be_true());
+
+    let (result, _) = compare_metadata(&expected, &MetadataMap::new(), &context).unwrap();
+    expect!(result.mismatches.len()).to(be_equal_to(1));
+
+    actual.insert("x-secret", "s3cr3t".parse().expect("Expected a value"));
+    let (result, _) = compare_metadata(&expected, &actual, &context).unwrap();
+    expect!(result.result).to(be_false());
+    expect!(result.mismatches).to(be_equal_to(vec![
+      Mismatch::MetadataMismatch {
+        key: "x-secret".to_string(),
+        expected: "".to_string(),
+        actual: "s3cr3t".to_string(),
+        mismatch: "Expected metadata with key 'x-secret' to be absent but it was received".to_string()
+      }
+    ]));
+  }
+
   #[test]
   fn compare_metadata_when_checking_missing_keys_ignores_pact_special_values() {
     let expected = hashmap!{
@@ -563,4 +1222,162 @@ mod tests {
     let message = setup_message("33", None);
     expect!(grpc_status(&message).unwrap().code()).to(be_equal_to(Code::Unknown));
   }
+
+  #[test]
+  fn grpc_status_test_with_error_details() {
+    let detail = prost_types::Any {
+      type_url: "type.googleapis.com/MessageOut".to_string(),
+      value: vec![8, 1]
+    };
+    let mut message = setup_message("NOT_FOUND", Some("not here"));
+    message.metadata.insert(GRPC_STATUS_DETAILS.to_string(), json!(BASE64.encode(detail.encode_to_vec())));
+
+    let status = grpc_status(&message).unwrap();
+    expect!(status.code()).to(be_equal_to(Code::NotFound));
+    expect!(status.message()).to(be_equal_to("not here"));
+    let rpc_status = RpcStatus::decode(status.details()).unwrap();
+    expect!(rpc_status.code).to(be_equal_to(5));
+    expect!(rpc_status.message).to(be_equal_to("not here"));
+    expect!(rpc_status.details).to(be_equal_to(vec![ detail.clone() ]));
+    expect!(status_details(&status).unwrap()).to(be_equal_to(vec![ BASE64.encode(detail.encode_to_vec()) ]));
+  }
+
+  #[test]
+  fn response_metadata_separates_headers_and_trailers() {
+    let mut headers = MetadataMap::new();
+    headers.insert("x-a", "A".parse().expect("Expected a value"));
+    let mut combined = headers.clone();
+    combined.insert("x-b", "B".parse().expect("Expected a value"));
+    combined.insert("grpc-status", "0".parse().expect("Expected a value"));
+
+    let metadata = ResponseMetadata::new(&headers, &combined);
+    expect!(metadata.get("x-a")).to(be_some());
+    expect!(metadata.get("header:x-a")).to(be_some());
+    expect!(metadata.get("trailer:x-a")).to(be_none());
+    expect!(metadata.get("x-b")).to(be_some());
+    expect!(metadata.get("header:x-b")).to(be_none());
+    expect!(metadata.get("trailer:x-b")).to(be_some());
+    expect!(metadata.get("trailer:grpc-status")).to(be_some());
+
+    // trailers-only response
+    let metadata = ResponseMetadata::new(&combined, &combined);
+    expect!(metadata.get("header:x-a")).to(be_none());
+    expect!(metadata.get("trailer:x-a")).to(be_some());
+  }
+
+  #[test]
+  fn compare_response_metadata_does_not_match_trailer_with_a_header() {
+    let expected = hashmap! {
+      "trailer:x-a".to_string() => json!("A")
+    };
+    let mut headers = MetadataMap::new();
+    headers.insert("x-a", "A".parse().expect("Expected a value"));
+    let actual = ResponseMetadata::new(&headers, &headers);
+    let context = CoreMatchingContext::default();
+
+    let (result, _) = compare_response_metadata(&expected, &actual, &context).unwrap();
+    expect!(result.result).to(be_false());
+    expect!(result.mismatches).to(be_equal_to(vec![
+      Mismatch::MetadataMismatch {
+        key: "trailer:x-a".to_string(),
+        expected: "\"A\"".to_string(),
+        actual: "".to_string(),
+        mismatch: "Expected metadata value with key 'trailer:x-a' but was missing".to_string()
+      }
+    ]));
+
+    let mut combined = MetadataMap::new();
+    combined.insert("x-a", "A".parse().expect("Expected a value"));
+    let actual = ResponseMetadata::new(&MetadataMap::new(), &combined);
+    let (result, _) = compare_response_metadata(&expected, &actual, &context).unwrap();
+    expect!(result.result).to(be_true());
+  }
+
+  #[test]
+  fn compare_response_metadata_with_a_status_class() {
+    let expected = hashmap! {
+      "grpc-status".to_string() => json!("CLIENT_ERROR")
+    };
+    let context = CoreMatchingContext::default();
+    let response = |code: &str| {
+      let mut metadata = MetadataMap::new();
+      metadata.insert("grpc-status", code.parse().expect("Expected a value"));
+      metadata
+    };
+
+    // INVALID_ARGUMENT
+    let metadata = response("3");
+    let (result, _) = compare_response_metadata(&expected, &ResponseMetadata::new(&metadata, &metadata), &context).unwrap();
+    expect!(result.result).to(be_true());
+
+    // INTERNAL
+    let metadata = response("13");
+    let (result, _) = compare_response_metadata(&expected, &ResponseMetadata::new(&metadata, &metadata), &context).unwrap();
+    expect!(result.mismatches).to(be_equal_to(vec![
+      Mismatch::MetadataMismatch {
+        key: "grpc-status".to_string(),
+        expected: "CLIENT_ERROR".to_string(),
+        actual: "13".to_string(),
+        mismatch: "Comparison of metadata key 'grpc-status' failed: expected a CLIENT_ERROR status but received INTERNAL (SERVER_ERROR)".to_string()
+      }
+    ]));
+  }
+
+  #[test]
+  fn status_class_test() {
+    expect!(StatusClass::parse("CLIENT_ERROR")).to(be_some().value(StatusClass::ClientError));
+    expect!(StatusClass::parse("server-error")).to(be_some().value(StatusClass::ServerError));
+    expect!(StatusClass::parse("INVALID_ARGUMENT")).to(be_none());
+    expect!(StatusClass::of(Code::Ok)).to(be_equal_to(StatusClass::Ok));
+    expect!(StatusClass::of(Code::NotFound)).to(be_equal_to(StatusClass::ClientError));
+    expect!(StatusClass::of(Code::Unavailable)).to(be_equal_to(StatusClass::ServerError));
+    expect!(string_to_code("CLIENT_ERROR", "").map(|status| status.code())).to(be_some().value(Code::InvalidArgument));
+  }
+
+  #[test]
+  fn compare_metadata_with_a_json_value() {
+    let config = Value {
+      kind: Some(value::Kind::StructValue(Struct {
+        fields: btreemap!{
+          "x-context".to_string() => Value {
+            kind: Some(value::Kind::StructValue(Struct {
+              fields: btreemap!{
+                "tenant".to_string() => prost_string("acme"),
+                "user".to_string() => Value {
+                  kind: Some(value::Kind::StructValue(Struct {
+                    fields: btreemap!{
+                      "id".to_string() => prost_string("matching(integer, 100)")
+                    }
+                  }))
+                }
+              }
+            }))
+          }
+        }
+      }))
+    };
+    let metadata = process_metadata(Some(&config)).unwrap().unwrap();
+    expect!(metadata.json_values.clone()).to(be_equal_to(hashmap!{
+      "x-context".to_string() => json!({ "tenant": "acme", "user": { "id": 100 } })
+    }));
+
+    let expected = metadata.json_values.clone();
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &metadata.matching_rules, &hashmap!{});
+
+    let mut actual = MetadataMap::new();
+    actual.insert("x-context", r#"{"user": {"id": 2045}, "tenant": "acme"}"#.parse().expect("Expected a value"));
+    let (result, _) = compare_metadata(&expected, &actual, &context).unwrap();
+    expect!(result.result).to(be_true());
+
+    let mut actual = MetadataMap::new();
+    actual.insert("x-context", r#"{"user": {"id": "2045"}, "tenant": "acme"}"#.parse().expect("Expected a value"));
+    let (result, _) = compare_metadata(&expected, &actual, &context).unwrap();
+    expect!(result.result).to(be_false());
+    expect!(result.mismatches.len()).to(be_equal_to(1));
+
+    let mut actual = MetadataMap::new();
+    actual.insert("x-context", "not json".parse().expect("Expected a value"));
+    let (result, _) = compare_metadata(&expected, &actual, &context).unwrap();
+    expect!(result.result).to(be_false());
+  }
 }
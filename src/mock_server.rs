@@ -2,50 +2,511 @@
 
 use std::collections::HashMap;
 use std::future::Future;
+use std::fs;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::pin::Pin;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicUsize;
 use std::task::{Context, Poll};
 use std::thread;
 
 use anyhow::anyhow;
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD as BASE64;
-use bytes::Bytes;
-use http::Method;
+use bytes::{Buf, Bytes, BytesMut};
+use http::{HeaderMap, HeaderValue, Method, Uri};
+use http::header::HeaderName;
+use futures::future::ready;
+use futures::TryStreamExt;
+use http_body::Body;
 use hyper::{http, Request, Response};
 use hyper::server::accept;
+use hyper::service::make_service_fn;
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use maplit::hashmap;
-use pact_matching::BodyMatchResult;
+use pact_matching::{BodyMatchResult, Mismatch};
 use pact_models::content_types::ContentType;
 use pact_models::json_utils::json_to_string;
 use pact_models::plugins::PluginData;
 use pact_models::prelude::v4::V4Pact;
 use pact_models::v4::sync_message::SynchronousMessage;
 use prost::Message;
-use prost_types::{FileDescriptorSet, MethodDescriptorProto};
+use prost_types::{DescriptorProto, FileDescriptorSet, MethodDescriptorProto};
 use serde_json::{json, Value};
 use tokio::net::TcpListener;
 use tokio::runtime::Handle;
 use tokio::sync::oneshot::{channel, Sender};
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::server::AllowAnyAnonymousOrAuthenticatedClient;
+use tokio_rustls::TlsAcceptor;
 use tonic::body::{BoxBody, empty_body};
 use tonic::metadata::MetadataMap;
-use tower::make::Shared;
-use tower::ServiceBuilder;
+use tonic::Status;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tower::{Layer, ServiceBuilder, ServiceExt};
+use tower::limit::{ConcurrencyLimit, GlobalConcurrencyLimitLayer};
+use tower::util::Either;
 use tower_http::ServiceBuilderExt;
 use tower_service::Service;
-use tracing::{debug, error, Instrument, instrument, trace, trace_span};
+use tracing::{debug, error, Instrument, instrument, trace, trace_span, warn};
 use uuid::Uuid;
 
+use crate::conversion::message_to_json_with_proto_names;
 use crate::dynamic_message::PactCodec;
-use crate::metadata::MetadataMatchResult;
-use crate::mock_service::MockService;
-use crate::tcp::TcpIncoming;
-use crate::utils::{find_message_type_by_name, last_name};
+use crate::metadata::{is_absent_value, metadata_location, MetadataLocation, MetadataMatchResult};
+use crate::mock_service::{ClientStreamingMockService, MockService, StreamingMockService};
+use crate::tcp::{ClosableTcpStream, ConnectionCloser, TcpIncoming};
+use crate::timings::timed;
+use crate::message_decoder::ProtobufField;
+use crate::utils::{
+  decompress_descriptors,
+  find_message_type_by_qualified_name,
+  last_name,
+  list_interaction_configs,
+  PROTO_DESCRIPTORS_COMPRESSION
+};
+
+/// Results recorded for each route of a mock server (route -> (request count, match results))
+pub type MockServerResults = HashMap<String, (usize, Vec<(BodyMatchResult, MetadataMatchResult)>)>;
+
+/// Interaction configuration key for the fault the mock server must inject when responding
+pub const FAULT_CONFIG: &str = "fault";
+
+/// Interaction configuration key to add the actual request message, rendered as a snippet of the
+/// interaction configuration, to the mock server results when the request does not match
+pub const MISMATCH_SNIPPET_CONFIG: &str = "mismatchSnippet";
+
+/// Pseudo-metadata key for the address of the client connected to the mock server. It is added to
+/// the request metadata, so it can be matched like any other metadata value.
+pub const PEER_ADDRESS_KEY: &str = "pact-peer-address";
+
+/// Pseudo-metadata key for the subject of the TLS certificate presented by the client connected to
+/// the mock server (i.e. `CN=client`). It is only added when the client sent a certificate.
+pub const PEER_TLS_SUBJECT_KEY: &str = "pact-peer-tls-subject";
+
+/// Test context key for the TLS settings of the mock server. If set, the mock server only accepts
+/// TLS connections.
+pub const TLS_CONFIG: &str = "tls";
+
+/// Test context key for the address of an upstream server. If set, the mock server runs in record
+/// mode, forwarding all requests to the upstream server and recording the responses.
+pub const UPSTREAM_CONFIG: &str = "upstream";
+
+/// Test context key for the Pact file the interactions recorded in record mode are written to when
+/// the mock server is shut down
+pub const RECORDING_FILE_CONFIG: &str = "recordingFile";
+
+/// TLS settings of the mock server, configured with `tls` in the test context
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MockServerTls {
+  /// Path to the PEM encoded server certificate (and any intermediate certificates)
+  pub certificate: PathBuf,
+  /// Path to the PEM encoded private key of the server certificate
+  pub key: PathBuf,
+  /// Path to the PEM encoded CA certificate used to verify the certificates presented by clients.
+  /// If not set, clients can not present a certificate.
+  pub ca_certificate: Option<PathBuf>
+}
+
+impl MockServerTls {
+  /// Parses the TLS settings from the test context value (a map with `certificate`, `key` and
+  /// optionally `caCertificate`)
+  pub fn from_config(config: &Value) -> anyhow::Result<Self> {
+    let tls = config.as_object()
+      .ok_or_else(|| anyhow!("{} must be a map of TLS settings, got {}", TLS_CONFIG, config))?;
+    let path = |key: &str| tls.get(key).map(|value| PathBuf::from(json_to_string(value)));
+    Ok(MockServerTls {
+      certificate: path("certificate")
+        .ok_or_else(|| anyhow!("{}.certificate must be set to the path of the server certificate", TLS_CONFIG))?,
+      key: path("key")
+        .ok_or_else(|| anyhow!("{}.key must be set to the path of the server private key", TLS_CONFIG))?,
+      ca_certificate: path("caCertificate")
+    })
+  }
+
+  fn acceptor(&self) -> anyhow::Result<TlsAcceptor> {
+    let read_certificates = |path: &PathBuf| -> anyhow::Result<Vec<rustls::Certificate>> {
+      let pem = fs::read(path)
+        .map_err(|err| anyhow!("Could not read the TLS certificate '{}' - {}", path.display(), err))?;
+      let certificates = rustls_pemfile::certs(&mut pem.as_slice())?;
+      if certificates.is_empty() {
+        return Err(anyhow!("Did not find any certificates in '{}'", path.display()));
+      }
+      Ok(certificates.into_iter().map(rustls::Certificate).collect())
+    };
+
+    let certificates = read_certificates(&self.certificate)?;
+    let pem = fs::read(&self.key)
+      .map_err(|err| anyhow!("Could not read the TLS private key '{}' - {}", self.key.display(), err))?;
+    let key = rustls_pemfile::read_all(&mut pem.as_slice())?.into_iter()
+      .find_map(|item| match item {
+        rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) | rustls_pemfile::Item::ECKey(key) => Some(key),
+        _ => None
+      })
+      .ok_or_else(|| anyhow!("Did not find a private key in '{}'", self.key.display()))?;
+
+    let builder = rustls::ServerConfig::builder().with_safe_defaults();
+    let mut config = match &self.ca_certificate {
+      Some(ca_certificate) => {
+        let mut roots = rustls::RootCertStore::empty();
+        for certificate in read_certificates(ca_certificate)? {
+          roots.add(&certificate)?;
+        }
+        builder.with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed())
+      }
+      None => builder.with_no_client_auth()
+    }.with_single_cert(certificates, rustls::PrivateKey(key))?;
+    config.alpn_protocols = vec![ b"h2".to_vec() ];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+  }
+}
+
+/// Exchange with the upstream server recorded by a mock server in record mode
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordedResponse {
+  /// Messages sent in the request (without the gRPC message framing)
+  pub request_messages: Vec<Bytes>,
+  /// Messages received in the response (without the gRPC message framing)
+  pub response_messages: Vec<Bytes>,
+  /// Metadata received in the response headers and trailers
+  pub response_metadata: HashMap<String, String>
+}
+
+/// Responses recorded from the upstream server for each route of a mock server
+pub type RecordedResponses = HashMap<String, Vec<RecordedResponse>>;
+
+/// Where the exchanges recorded in record mode are written to
+#[derive(Clone, Debug)]
+pub struct RecordingTarget {
+  /// Pact file the recorded interactions are written to
+  pact_file: PathBuf,
+  /// Pact the mock server was started with, for the consumer, provider and plugin configuration
+  pact: V4Pact,
+  /// Descriptors used to resolve the message types of each route, keyed by descriptor key
+  descriptors: HashMap<String, FileDescriptorSet>
+}
+
+/// Exchanges recorded by a mock server in record mode
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+  /// Where the recorded exchanges are written when the mock server is shut down
+  target: Option<RecordingTarget>,
+  /// Exchanges recorded for each route
+  responses: RecordedResponses
+}
+
+/// Faults that the mock server can inject instead of sending the response
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MockServerFault {
+  /// Abort the response stream with a RST_STREAM frame
+  ResetStream,
+  /// Abruptly close the connection to the client
+  CloseConnection
+}
+
+impl MockServerFault {
+  /// Parse the fault from the interaction configuration value
+  pub fn parse(value: &str) -> anyhow::Result<MockServerFault> {
+    match value.to_lowercase().as_str() {
+      "reset-stream" => Ok(MockServerFault::ResetStream),
+      "close-connection" => Ok(MockServerFault::CloseConnection),
+      _ => Err(anyhow!("'{}' is not a valid fault, it must be either 'reset-stream' or 'close-connection'", value))
+    }
+  }
+
+  /// Returns the fault configured for the interaction, if any
+  pub fn for_interaction(message: &SynchronousMessage) -> Option<MockServerFault> {
+    message.plugin_config.get("protobuf")
+      .and_then(|config| config.get(FAULT_CONFIG))
+      .and_then(|fault| match MockServerFault::parse(json_to_string(fault).as_str()) {
+        Ok(fault) => Some(fault),
+        Err(err) => {
+          error!("{}, ignoring it", err);
+          None
+        }
+      })
+  }
+}
 
 lazy_static! {
-  pub static ref MOCK_SERVER_STATE: Mutex<HashMap<String, (Sender<()>, HashMap<String, (usize, Vec<(BodyMatchResult, MetadataMatchResult)>)>)>> = Mutex::new(hashmap!{});
+  /// Running mock servers, keyed by server key. Each server has its own results store, so the
+  /// global lock only needs to be held while an entry is looked up, added or removed.
+  pub static ref MOCK_SERVER_STATE: Mutex<HashMap<String, (Sender<()>, Arc<Mutex<MockServerResults>>, Arc<Mutex<Recording>>)>> = Mutex::new(hashmap!{});
+}
+
+/// Registers the mock server with the given key and initial route state. If the mock server is in
+/// record mode, the recording target is where the recorded exchanges are written to.
+pub(crate) fn register_mock_server(
+  server_key: &str,
+  shutdown: Sender<()>,
+  routes: MockServerResults,
+  recording: Option<RecordingTarget>
+) {
+  let mut guard = MOCK_SERVER_STATE.lock().unwrap();
+  let recording = Recording { target: recording, .. Recording::default() };
+  guard.insert(server_key.to_string(), (shutdown, Arc::new(Mutex::new(routes)), Arc::new(Mutex::new(recording))));
+}
+
+/// Returns the results store for the mock server with the given key
+pub(crate) fn mock_server_results(server_key: &str) -> Option<Arc<Mutex<MockServerResults>>> {
+  let guard = MOCK_SERVER_STATE.lock().unwrap();
+  guard.get(server_key).map(|(_, results, _)| results.clone())
+}
+
+/// Returns the responses recorded from the upstream server by the mock server with the given key
+pub fn recorded_responses(server_key: &str) -> Option<RecordedResponses> {
+  let guard = MOCK_SERVER_STATE.lock().unwrap();
+  guard.get(server_key).map(|(_, _, recording)| recording.lock().unwrap().responses.clone())
+}
+
+/// Returns the user-agents sent by the clients of the mock server with the given key, keyed by route
+pub fn received_user_agents(server_key: &str) -> Option<HashMap<String, Vec<String>>> {
+  mock_server_results(server_key).map(|results| {
+    results.lock().unwrap().iter()
+      .map(|(route, (_, route_results))| {
+        let user_agents = route_results.iter()
+          .filter_map(|(_, md_result)| md_result.user_agent.clone())
+          .collect();
+        (route.clone(), user_agents)
+      })
+      .collect()
+  })
+}
+
+/// Records the response from the upstream server against the route for the mock server with the
+/// given key. Returns false if there is no mock server registered with the key.
+fn record_upstream_response(server_key: &str, route: &str, response: RecordedResponse) -> bool {
+  let recording = {
+    let guard = MOCK_SERVER_STATE.lock().unwrap();
+    guard.get(server_key).map(|(_, _, recording)| recording.clone())
+  };
+  if let Some(recording) = recording {
+    recording.lock().unwrap().responses.entry(route.to_string()).or_default().push(response);
+    true
+  } else {
+    false
+  }
+}
+
+/// Writes the exchanges recorded by the mock server to its recording target, if it has one
+fn finish_recording(server_key: &str, recording: &Mutex<Recording>) {
+  let recording = recording.lock().unwrap();
+  if let Some(target) = &recording.target {
+    if recording.responses.is_empty() {
+      debug!("Mock server {} did not record any exchanges with the upstream server", server_key);
+      return;
+    }
+    match write_recorded_pact(target, &recording.responses) {
+      Ok(()) => debug!("Wrote the interactions recorded by mock server {} to {}", server_key, target.pact_file.display()),
+      Err(err) => error!("Failed to write the interactions recorded by mock server {} to {} - {}", server_key,
+        target.pact_file.display(), err)
+    }
+  }
+}
+
+/// Removes the mock server with the given key, returning its results store. The server is sent
+/// the shutdown signal, and any exchanges recorded in record mode are written to the Pact file.
+pub(crate) fn remove_mock_server(server_key: &str) -> Option<Arc<Mutex<MockServerResults>>> {
+  let entry = {
+    let mut guard = MOCK_SERVER_STATE.lock().unwrap();
+    guard.remove(server_key)
+  };
+  entry.map(|(shutdown, results, recording)| {
+    // The server may have already stopped, in which case there is no receiver
+    let _ = shutdown.send(());
+    finish_recording(server_key, &recording);
+    results
+  })
+}
+
+/// Shuts down and removes all the registered mock servers. This is used to isolate tests (or
+/// an embedding application) from any mock servers that were not shut down.
+pub fn clear_mock_server_state() {
+  let entries = {
+    let mut guard = MOCK_SERVER_STATE.lock().unwrap();
+    guard.drain().collect::<Vec<_>>()
+  };
+  for (server_key, (shutdown, _, recording)) in entries {
+    debug!("Shutting down mock server {}", server_key);
+    let _ = shutdown.send(());
+    finish_recording(server_key.as_str(), &recording);
+  }
+}
+
+/// Records a match result against the route for the mock server with the given key. Returns
+/// false if there is no mock server registered with the key.
+pub(crate) fn record_mock_server_result(
+  server_key: &str,
+  route: &str,
+  result: BodyMatchResult,
+  md_result: MetadataMatchResult
+) -> bool {
+  if let Some(results) = mock_server_results(server_key) {
+    let mut guard = results.lock().unwrap();
+    let route_results = guard.entry(route.to_string()).or_insert((0, vec![]));
+    trace!(store_length = route_results.1.len(), "Adding result to mock server '{}' static store", server_key);
+    route_results.0 += 1;
+    route_results.1.push((result, md_result));
+    true
+  } else {
+    false
+  }
+}
+
+/// Renders the actual request message as a snippet of the interaction configuration, which can be
+/// pasted into the consumer test to update the expected request
+pub(crate) fn request_config_snippet(
+  fields: &[ProtobufField],
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<String> {
+  let request = message_to_json_with_proto_names(fields, descriptor, descriptors)?;
+  Ok(serde_json::to_string_pretty(&json!({ "request": request }))?)
+}
+
+/// Adds the connection level details of the client (the peer address and the subject of the
+/// client TLS certificate) to the request headers as pseudo-metadata. Any values sent by the client
+/// for the keys are replaced.
+pub(crate) fn add_peer_metadata(headers: &mut HeaderMap, peer_address: Option<SocketAddr>, peer_tls_subject: Option<&str>) {
+  headers.remove(PEER_ADDRESS_KEY);
+  headers.remove(PEER_TLS_SUBJECT_KEY);
+  if let Some(address) = peer_address {
+    match HeaderValue::from_str(address.to_string().as_str()) {
+      Ok(value) => {
+        headers.insert(PEER_ADDRESS_KEY, value);
+      }
+      Err(err) => error!("Could not add the peer address {} to the request metadata - {}", address, err)
+    }
+  }
+  if let Some(subject) = peer_tls_subject {
+    match HeaderValue::from_str(subject) {
+      Ok(value) => {
+        headers.insert(PEER_TLS_SUBJECT_KEY, value);
+      }
+      Err(err) => error!("Could not add the peer TLS subject '{}' to the request metadata - {}", subject, err)
+    }
+  }
+}
+
+/// Returns the subject of the DER encoded X.509 certificate, formatted as a comma separated list of
+/// the attributes in the order they appear in the certificate (i.e. `O=Pact, CN=client`)
+pub(crate) fn certificate_subject(certificate: &[u8]) -> Option<String> {
+  let (_, certificate, _) = der_element(certificate)?;
+  let (_, tbs_certificate, _) = der_element(certificate)?;
+  let mut fields = tbs_certificate;
+  // The version is an optional explicitly tagged field
+  if fields.first() == Some(&0xa0) {
+    fields = der_element(fields)?.2;
+  }
+  // Skip the serial number, signature algorithm, issuer and validity
+  for _ in 0..4 {
+    fields = der_element(fields)?.2;
+  }
+  let (_, mut name, _) = der_element(fields)?;
+
+  let mut attributes = vec![];
+  while !name.is_empty() {
+    let (_, mut rdn, rest) = der_element(name)?;
+    while !rdn.is_empty() {
+      let (_, attribute, rest) = der_element(rdn)?;
+      let (_, oid, value) = der_element(attribute)?;
+      let (value_tag, value, _) = der_element(value)?;
+      let value = if value_tag == 0x1e {
+        // BMPString is UTF-16
+        String::from_utf16_lossy(&value.chunks(2).map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)])).collect_vec())
+      } else {
+        String::from_utf8_lossy(value).to_string()
+      };
+      attributes.push(format!("{}={}", attribute_name(oid), value));
+      rdn = rest;
+    }
+    name = rest;
+  }
+  Some(attributes.join(", "))
+}
+
+/// Splits the DER encoded element at the start of the bytes into its tag, contents and the remaining bytes
+fn der_element(bytes: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+  let tag = *bytes.first()?;
+  let length_byte = *bytes.get(1)? as usize;
+  let (length, header) = if length_byte < 0x80 {
+    (length_byte, 2)
+  } else {
+    let count = length_byte & 0x7f;
+    let length = bytes.get(2..2 + count)?.iter().fold(0_usize, |acc, b| (acc << 8) | *b as usize);
+    (length, 2 + count)
+  };
+  let contents = bytes.get(header..header + length)?;
+  Some((tag, contents, &bytes[header + length..]))
+}
+
+/// Short name of an X.509 name attribute, otherwise the OID in dotted form
+fn attribute_name(oid: &[u8]) -> String {
+  match oid {
+    [0x55, 0x04, 0x03] => "CN".to_string(),
+    [0x55, 0x04, 0x05] => "serialNumber".to_string(),
+    [0x55, 0x04, 0x06] => "C".to_string(),
+    [0x55, 0x04, 0x07] => "L".to_string(),
+    [0x55, 0x04, 0x08] => "ST".to_string(),
+    [0x55, 0x04, 0x0a] => "O".to_string(),
+    [0x55, 0x04, 0x0b] => "OU".to_string(),
+    [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x01] => "emailAddress".to_string(),
+    _ => {
+      let mut parts = vec![];
+      if let Some(first) = oid.first() {
+        parts.push((first / 40) as u64);
+        parts.push((first % 40) as u64);
+      }
+      let mut value = 0_u64;
+      for b in oid.iter().skip(1) {
+        value = (value << 7) | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+          parts.push(value);
+          value = 0;
+        }
+      }
+      parts.iter().map(|part| part.to_string()).join(".")
+    }
+  }
+}
+
+/// Returns the mismatch for a call to a service method the mock server is not configured for. If
+/// the service is configured but not the method, this is a method mismatch, otherwise it is a path
+/// mismatch.
+fn unexpected_route_mismatch<'a>(
+  routes: impl Iterator<Item = &'a String>,
+  service_name: &str,
+  method: &str
+) -> Mismatch {
+  let routes = routes.sorted().collect_vec();
+  let methods = routes.iter()
+    .filter_map(|route| route.split_once('/'))
+    .filter(|(service, _)| *service == service_name)
+    .map(|(_, method)| method)
+    .collect_vec();
+  if methods.is_empty() {
+    Mismatch::PathMismatch {
+      expected: routes.iter().join(", "),
+      actual: format!("{}/{}", service_name, method),
+      mismatch: format!("Received a call to '{}/{}', but the mock server is not configured for the service '{}'",
+        service_name, method, service_name)
+    }
+  } else {
+    Mismatch::MethodMismatch {
+      expected: methods.join(", "),
+      actual: method.to_string()
+    }
+  }
+}
+
+/// Records the mismatch for a request that did not match any of the routes of the mock server
+fn record_unexpected_route(server_key: &str, route: &str, mismatch: Mismatch) {
+  let result = BodyMatchResult::BodyMismatches(hashmap!{ "$".to_string() => vec![ mismatch ] });
+  if !record_mock_server_result(server_key, route, result, MetadataMatchResult::ok()) {
+    error!("INTERNAL ERROR: Did not find an entry for '{}' in mock server static store", server_key);
+  }
 }
 
 /// Main mock server that will use the provided Pact to provide behaviour
@@ -55,10 +516,29 @@ pub struct GrpcMockServer {
   plugin_config: PluginData,
   descriptors: HashMap<String, FileDescriptorSet>,
   routes: HashMap<String, (FileDescriptorSet, MethodDescriptorProto, SynchronousMessage)>,
+  /// Number of calls that have been handled for each route
+  call_counts: HashMap<String, Arc<AtomicUsize>>,
   /// Server key for this mock server
   pub server_key: String,
   /// test context pass in from the test framework
   pub test_context: HashMap<String, Value>,
+  /// Handle to close the connection the server is handling requests for
+  connection: Option<ConnectionCloser>,
+  /// Maximum number of requests that will be handled at the same time
+  max_concurrent_requests: Option<usize>,
+  /// Address of the client connected to the connection the server is handling requests for
+  peer_address: Option<SocketAddr>,
+  /// Subject of the TLS certificate presented by the client connected to the connection the server
+  /// is handling requests for
+  peer_tls_subject: Option<String>,
+  /// TLS settings, if the server must accept TLS connections
+  tls: Option<MockServerTls>,
+  /// Upstream server to forward requests to when in record mode
+  upstream: Option<String>,
+  /// Pact file the interactions recorded in record mode are written to
+  recording_file: Option<PathBuf>,
+  /// Channel to the upstream server, created when the server is started in record mode
+  upstream_channel: Option<Channel>
 }
 
 impl GrpcMockServer
@@ -70,11 +550,45 @@ impl GrpcMockServer
       plugin_config: plugin_config.clone(),
       descriptors: Default::default(),
       routes: Default::default(),
+      call_counts: Default::default(),
       server_key: Uuid::new_v4().to_string(),
-      test_context
+      test_context,
+      connection: None,
+      max_concurrent_requests: None,
+      peer_address: None,
+      peer_tls_subject: None,
+      tls: None,
+      upstream: None,
+      recording_file: None,
+      upstream_channel: None
     }
   }
 
+  /// Runs the mock server in record mode, where all requests are forwarded to the upstream server
+  /// (i.e. `http://localhost:50051` or `https://localhost:50051`) and the exchanges are recorded
+  /// against the route. The recorded interactions are written to the Pact file when the mock server
+  /// is shut down.
+  pub fn with_upstream(mut self, upstream: Option<String>, recording_file: Option<PathBuf>) -> Self {
+    self.upstream = upstream;
+    self.recording_file = recording_file;
+    self
+  }
+
+  /// Only accepts TLS connections to the mock server, using the certificate and key from the
+  /// TLS settings. If a CA certificate is set, clients can present a certificate signed by it, and
+  /// the subject of the certificate is added to the request metadata.
+  pub fn with_tls(mut self, tls: Option<MockServerTls>) -> Self {
+    self.tls = tls;
+    self
+  }
+
+  /// Limits the number of requests that are handled at the same time. Any requests over the limit
+  /// will wait until one of the running requests has completed.
+  pub fn with_max_concurrent_requests(mut self, max_concurrent_requests: Option<usize>) -> Self {
+    self.max_concurrent_requests = max_concurrent_requests;
+    self
+  }
+
   /// Start the mock server, consuming this instance and returning the connection details
   #[instrument(skip(self))]
   pub async fn start_server(mut self, host_interface: &str, port: u32, tls: bool) -> anyhow::Result<SocketAddr> {
@@ -83,7 +597,8 @@ impl GrpcMockServer
       if let Value::Object(map) = value {
         if let Some(descriptor) = map.get("protoDescriptors") {
           let bytes = BASE64.decode(json_to_string(descriptor))?;
-          let buffer = Bytes::from(bytes);
+          let compression = map.get(PROTO_DESCRIPTORS_COMPRESSION).map(json_to_string);
+          let buffer = decompress_descriptors(Bytes::from(bytes), compression.as_deref())?;
           let fds = FileDescriptorSet::decode(buffer)?;
           self.descriptors.insert(key.clone(), fds);
         }
@@ -94,6 +609,23 @@ impl GrpcMockServer
       return Err(anyhow!("Pact file does not contain any Protobuf descriptors"));
     }
 
+    if tls && self.tls.is_none() {
+      warn!("TLS was requested for the mock server, but the certificate and key have not been configured with \
+        '{}' in the test context. The mock server will only accept plain connections.", TLS_CONFIG);
+    }
+    let acceptor = self.tls.as_ref().map(MockServerTls::acceptor).transpose()?;
+
+    let recording = match &self.upstream {
+      Some(upstream) => {
+        let pact_file = self.recording_file.clone()
+          .ok_or_else(|| anyhow!("Record mode requires the Pact file to write the recorded interactions to \
+            ('{}' in the test context)", RECORDING_FILE_CONFIG))?;
+        self.upstream_channel = Some(upstream_channel(upstream)?);
+        Some(RecordingTarget { pact_file, pact: self.pact.clone(), descriptors: self.descriptors.clone() })
+      }
+      None => None
+    };
+
     // Build a map of routes using the interactions in the Pact file
     self.routes = self.pact.interactions.iter()
       .filter_map(|i| i.as_v4_sync_message())
@@ -128,6 +660,20 @@ impl GrpcMockServer
           None
         }
       }).collect();
+    self.call_counts = self.routes.keys()
+      .map(|route| (route.clone(), Arc::default()))
+      .collect();
+
+    // Interactions for a service method that did not resolve to a route will never be matched
+    for summary in list_interaction_configs(&self.pact) {
+      if let Some(service) = summary.service_or_message.as_ref().filter(|service| service.contains('/')) {
+        if !self.routes.contains_key(service.as_str()) {
+          warn!("Interaction '{}' is configured for the service method '{}' with descriptor key {:?}, but the \
+            method was not found in the descriptors. The mock server will not respond to calls for it.",
+            summary.description, service, summary.descriptor_key);
+        }
+      }
+    }
 
     // Bind to a OS provided port and create a TCP listener
     let interface = if host_interface.is_empty() {
@@ -139,14 +685,11 @@ impl GrpcMockServer
     trace!("setting up mock server {addr}");
 
     let (snd, rcr) = channel::<()>();
-    {
-      let mut guard = MOCK_SERVER_STATE.lock().unwrap();
-      // Initialise all the routes with an initial state of not received
-      let initial_state = self.routes.keys()
-        .map(|k| (k.clone(), (0, vec![])))
-        .collect();
-      guard.insert(self.server_key.clone(), (snd, initial_state));
-    }
+    // Initialise all the routes with an initial state of not received
+    let initial_state = self.routes.keys()
+      .map(|k| (k.clone(), (0, vec![])))
+      .collect();
+    register_mock_server(self.server_key.as_str(), snd, initial_state, recording);
 
     let listener = TcpListener::bind(addr).await?;
     let address = listener.local_addr()?;
@@ -158,15 +701,41 @@ impl GrpcMockServer
     let key = self.server_key.clone();
     let key2 = self.server_key.clone();
     let result = thread::spawn(move || {
-      let incoming_stream = TcpIncoming { inner: listener };
+      let incoming_stream = TcpIncoming { inner: listener }
+        .try_filter_map(move |stream| {
+          let acceptor = acceptor.clone();
+          async move {
+            match acceptor {
+              // A failed handshake only affects that connection
+              Some(acceptor) => match ClosableTcpStream::new_tls(stream, acceptor).await {
+                Ok(stream) => Ok(Some(stream)),
+                Err(err) => {
+                  warn!("TLS handshake with the client failed - {}", err);
+                  Ok(None)
+                }
+              },
+              None => ClosableTcpStream::new(stream).map(Some)
+            }
+          }
+        });
       let incoming = accept::from_stream(incoming_stream);
 
       trace!("setting up middleware");
-      let service = ServiceBuilder::new()
-        // High level logging of requests and responses
-        .trace_for_grpc()
-        // Wrap a `Service` in our middleware stack
-        .service(self);
+      // The limit is shared by all the connections to the server
+      let concurrency_limit = self.max_concurrent_requests.map(GlobalConcurrencyLimitLayer::new);
+      // Each connection gets its own copy of the server, so faults can close the connection
+      let make_service = make_service_fn(move |conn: &ClosableTcpStream| {
+        let mut server = self.clone();
+        server.connection = Some(conn.closer());
+        server.peer_address = conn.peer_addr().ok();
+        server.peer_tls_subject = conn.peer_certificate().and_then(certificate_subject);
+        let service = ServiceBuilder::new()
+          // High level logging of requests and responses
+          .trace_for_grpc()
+          // Wrap a `Service` in our middleware stack
+          .service(with_concurrency_limit(server, concurrency_limit.as_ref()));
+        ready(Ok::<_, std::convert::Infallible>(service))
+      });
 
       trace!("setting up HTTP server");
       let server = hyper::Server::builder(incoming)
@@ -177,7 +746,7 @@ impl GrpcMockServer
         //   //   // .http2_keep_alive_interval(http2_keepalive_interval)
         //   //   // .http2_keep_alive_timeout(http2_keepalive_timeout)
         //   //   // .http2_max_frame_size(max_frame_size)
-        .serve(Shared::new(service))
+        .serve(make_service)
         .with_graceful_shutdown(async move {
           let _ = rcr.await;
           trace!("Received shutdown signal for server {}", key);
@@ -198,8 +767,9 @@ impl GrpcMockServer
   }
 
   fn update_mock_server_address(&mut self, address: &SocketAddr) {
+    let scheme = if self.tls.is_some() { "https" } else { "http" };
     self.test_context.insert("mockServer".to_string(), json!({
-      "href": format!("http://{}:{}", address.ip(), address.port()),
+      "href": format!("{}://{}:{}", scheme, address.ip(), address.port()),
       "port": address.port()
     }));
   }
@@ -215,14 +785,22 @@ impl Service<Request<hyper::Body>> for GrpcMockServer  {
   }
 
   #[instrument(skip(self), level = "trace")]
-  fn call(&mut self, req: Request<hyper::Body>) -> Self::Future {
+  fn call(&mut self, mut req: Request<hyper::Body>) -> Self::Future {
     let routes = self.routes.clone();
+    let call_counts = self.call_counts.clone();
     let server_key = self.server_key.clone();
     let pact = self.pact.clone();
+    let connection = self.connection.clone();
+    let upstream = self.upstream_channel.clone();
+    add_peer_metadata(req.headers_mut(), self.peer_address, self.peer_tls_subject.as_deref());
 
-    Box::pin(async move {
+    Box::pin(timed("mock_server", async move {
       trace!("Got request {req:?}");
 
+      if let Some(upstream) = upstream {
+        return Ok(proxy_to_upstream(upstream, server_key.as_str(), req).await);
+      }
+
       let headers = req.headers();
       let metadata = MetadataMap::from_headers(headers.clone());
 
@@ -236,59 +814,444 @@ impl Service<Request<hyper::Body>> for GrpcMockServer  {
       };
 
       match content_type {
-        Ok(content_type) => if content_type.base_type().to_string().starts_with("application/grpc") {
-          let method = req.method();
-          if method == Method::POST {
-            let request_path = req.uri().path();
-            debug!(?request_path, "gRPC request received");
-            if let Some((service, method)) = request_path[1..].split_once('/') {
-              let service_name = last_name(service);
-              let lookup = format!("{service_name}/{method}");
-              if let Some((file, method_descriptor, message)) = routes.get(lookup.as_str()) {
-                trace!(message = message.description.as_str(), "Found route for service call");
-
-                let input_message_name = method_descriptor.input_type.clone().unwrap_or_default();
-                let input_message = find_message_type_by_name(last_name(input_message_name.as_str()), file);
-                let output_message_name = method_descriptor.output_type.clone().unwrap_or_default();
-                let output_message = find_message_type_by_name(last_name(output_message_name.as_str()), file);
-
-                if let Ok((input_message, _)) = input_message {
-                  if let Ok((output_message, _)) = output_message {
-                    let codec = PactCodec::new(file, &input_message, &output_message, message);
-                    let mock_service = MockService::new(file, service_name,
-                      method_descriptor, &input_message, &output_message, message, server_key.as_str(),
-                      pact
-                    );
-                    let mut grpc = tonic::server::Grpc::new(codec);
-                    let response = grpc.unary(mock_service, req).await;
-                    trace!(?response, ">> sending response");
-                    Ok(response)
-                  } else {
-                    error!("Did not find the descriptor for the output message {}", output_message_name);
-                    Ok(failed_precondition())
+        Ok(content_type) => match grpc_content_subtype(&content_type) {
+          Some(subtype) => {
+            let method = req.method();
+            if method == Method::POST {
+              let request_path = req.uri().path();
+              debug!(?request_path, "gRPC request received");
+              if let Some((service, method)) = request_path[1..].split_once('/') {
+                let service_name = last_name(service);
+                let lookup = format!("{service_name}/{method}");
+                if let Some((file, method_descriptor, message)) = routes.get(lookup.as_str()) {
+                  trace!(message = message.description.as_str(), "Found route for service call");
+
+                  let message_types = method_message_type(lookup.as_str(), "input", method_descriptor.input_type.as_deref(), file)
+                    .and_then(|input| method_message_type(lookup.as_str(), "output", method_descriptor.output_type.as_deref(), file)
+                      .map(|output| (input, output)));
+
+                  match message_types {
+                    Ok((input_message, output_message)) => {
+                      let codec = PactCodec::new(file, &input_message, &output_message, message)
+                        .with_json_encoding(subtype == GrpcContentSubtype::Json);
+                      let mock_service = MockService::new(file, service_name,
+                        method_descriptor, &input_message, &output_message, message, server_key.as_str(),
+                        pact, call_counts.get(lookup.as_str()).cloned().unwrap_or_default()
+                      );
+                      let trailers = response_trailers(message);
+                      let mut grpc = tonic::server::Grpc::new(codec);
+                      let mut response = if method_descriptor.server_streaming.unwrap_or(false) {
+                        trace!("Method is server streaming, responses will be returned as a stream");
+                        grpc.server_streaming(StreamingMockService(mock_service), req).await
+                      } else if method_descriptor.client_streaming.unwrap_or(false) {
+                        trace!("Method is client streaming, request messages will be read from the stream");
+                        grpc.client_streaming(ClientStreamingMockService(mock_service), req).await
+                      } else {
+                        grpc.unary(mock_service, req).await
+                      };
+                      if subtype == GrpcContentSubtype::Json {
+                        response.headers_mut().insert(http::header::CONTENT_TYPE, HeaderValue::from_static("application/grpc+json"));
+                      }
+                      trace!(?response, ">> sending response");
+                      if let Some(fault) = MockServerFault::for_interaction(message) {
+                        Ok(inject_fault(fault, response, connection.as_ref()))
+                      } else if trailers.is_empty() {
+                        Ok(response)
+                      } else {
+                        Ok(response.map(|body| BoxBody::new(TrailersBody { inner: body, trailers })))
+                      }
+                    }
+                    Err(err) => {
+                      error!("{}", err);
+                      Ok(failed_precondition(err.to_string()))
+                    }
                   }
                 } else {
-                  error!("Did not find the descriptor for the input message {}", input_message_name);
-                  Ok(failed_precondition())
+                  let mismatch = unexpected_route_mismatch(routes.keys(), service_name, method);
+                  error!("{}", mismatch.description());
+                  record_unexpected_route(server_key.as_str(), lookup.as_str(), mismatch);
+                  Ok(invalid_path())
                 }
               } else {
+                let mismatch = Mismatch::PathMismatch {
+                  expected: routes.keys().sorted().join(", "),
+                  actual: request_path.to_string(),
+                  mismatch: format!("'{}' is not a valid gRPC request path", request_path)
+                };
+                error!("{}", mismatch.description());
+                record_unexpected_route(server_key.as_str(), request_path, mismatch);
                 Ok(invalid_path())
               }
             } else {
-              Ok(invalid_path())
+              Ok(invalid_method())
             }
-          } else {
-            Ok(invalid_method())
           }
-        } else {
-          Ok(invalid_media())
+          None => Ok(invalid_media())
         }
         Err(err) => {
           error!("Failed to parse the content type - {err}");
           Ok(invalid_media())
         }
       }
-    }.instrument(trace_span!("mock_server_handler", key = self.server_key.as_str())))
+    }.instrument(trace_span!("mock_server_handler", key = self.server_key.as_str()))))
+  }
+}
+
+/// Creates the channel to the upstream server for record mode. TLS is used for `https` addresses.
+fn upstream_channel(upstream: &str) -> anyhow::Result<Channel> {
+  let uri = upstream.parse::<Uri>()
+    .map_err(|err| anyhow!("'{}' is not a valid upstream server address - {}", upstream, err))?;
+  let mut endpoint = Endpoint::from(uri.clone());
+  if uri.scheme_str() == Some("https") {
+    endpoint = endpoint.tls_config(ClientTlsConfig::new())?;
+  }
+  Ok(endpoint.connect_lazy())
+}
+
+/// Forwards the request to the upstream server, recording the exchange against the route for the
+/// mock server once the response has been streamed back to the client. If the upstream server can
+/// not be called, an UNAVAILABLE status is returned.
+async fn proxy_to_upstream(upstream: Channel, server_key: &str, req: Request<hyper::Body>) -> Response<BoxBody> {
+  let path = req.uri().path().to_string();
+  let route = path.strip_prefix('/')
+    .and_then(|path| path.split_once('/'))
+    .map(|(service, method)| format!("{}/{}", last_name(service), method))
+    .unwrap_or_else(|| path.clone());
+
+  let (mut parts, body) = req.into_parts();
+  parts.headers.remove(PEER_ADDRESS_KEY);
+  parts.headers.remove(PEER_TLS_SUBJECT_KEY);
+  parts.headers.remove(http::header::HOST);
+  let request_body = RecordingBody::new(body);
+  let request_capture = request_body.captured.clone();
+  let request = Request::from_parts(parts, BoxBody::new(request_body.map_err(|err| Status::from_error(Box::new(err)))));
+
+  match upstream.oneshot(request).await {
+    Ok(response) => {
+      // The request reached the route, so it is counted as received
+      if !record_mock_server_result(server_key, route.as_str(), BodyMatchResult::Ok, MetadataMatchResult::ok()) {
+        error!("INTERNAL ERROR: Did not find an entry for '{}' in mock server static store", server_key);
+      }
+      let (parts, body) = response.into_parts();
+      let headers = parts.headers.clone();
+      let server_key = server_key.to_string();
+      let response_body = RecordingBody::new(body);
+      let response_capture = response_body.captured.clone();
+      let response_body = response_body.on_complete(move || {
+        debug!("Recording the exchange with the upstream server for '{}'", route);
+        let request = request_capture.lock().unwrap();
+        let response = response_capture.lock().unwrap();
+        let recorded = RecordedResponse {
+          request_messages: grpc_messages(request.data.clone().freeze()),
+          response_messages: grpc_messages(response.data.clone().freeze()),
+          response_metadata: headers.iter()
+            .chain(response.trailers.iter())
+            .filter_map(|(key, value)| value.to_str().ok().map(|value| (key.to_string(), value.to_string())))
+            .collect()
+        };
+        if !record_upstream_response(server_key.as_str(), route.as_str(), recorded) {
+          error!("INTERNAL ERROR: Did not find an entry for '{}' in mock server static store", server_key);
+        }
+      });
+      Response::from_parts(parts, BoxBody::new(response_body.map_err(|err| Status::from_error(Box::new(err)))))
+    }
+    Err(err) => {
+      error!("Failed to forward the request for '{}' to the upstream server - {}", path, err);
+      Status::unavailable(format!("Failed to forward the request to the upstream server - {}", err)).to_http()
+    }
+  }
+}
+
+/// Data and trailers copied from a body as they are streamed through a [RecordingBody]
+#[derive(Debug, Default)]
+struct CapturedBody {
+  data: BytesMut,
+  trailers: HeaderMap
+}
+
+/// Body that keeps a copy of the data and trailers as they are streamed through it, so an exchange
+/// can be recorded without holding the messages back from the client. The completion callback is
+/// called once the trailers have been received, or when the body is dropped before then.
+struct RecordingBody<B> {
+  inner: B,
+  captured: Arc<Mutex<CapturedBody>>,
+  on_complete: Option<Box<dyn FnOnce() + Send>>
+}
+
+impl<B> RecordingBody<B> {
+  fn new(inner: B) -> Self {
+    RecordingBody { inner, captured: Arc::default(), on_complete: None }
+  }
+
+  fn on_complete(mut self, callback: impl FnOnce() + Send + 'static) -> Self {
+    self.on_complete = Some(Box::new(callback));
+    self
+  }
+}
+
+impl<B: Body<Data = Bytes> + Unpin> Body for RecordingBody<B> {
+  type Data = Bytes;
+  type Error = B::Error;
+
+  fn poll_data(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+    let this = self.get_mut();
+    let poll = Pin::new(&mut this.inner).poll_data(cx);
+    if let Poll::Ready(Some(Ok(data))) = &poll {
+      this.captured.lock().unwrap().data.extend_from_slice(data);
+    }
+    poll
+  }
+
+  fn poll_trailers(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+    let this = self.get_mut();
+    let poll = Pin::new(&mut this.inner).poll_trailers(cx);
+    if let Poll::Ready(result) = &poll {
+      if let Ok(Some(trailers)) = result {
+        this.captured.lock().unwrap().trailers.extend(trailers.clone());
+      }
+      // The trailers are the end of the body, so the exchange is complete
+      if let Some(callback) = this.on_complete.take() {
+        callback();
+      }
+    }
+    poll
+  }
+
+  fn is_end_stream(&self) -> bool {
+    self.inner.is_end_stream()
+  }
+
+  fn size_hint(&self) -> http_body::SizeHint {
+    self.inner.size_hint()
+  }
+}
+
+impl<B> Drop for RecordingBody<B> {
+  fn drop(&mut self) {
+    if let Some(callback) = self.on_complete.take() {
+      callback();
+    }
+  }
+}
+
+/// Splits a gRPC message stream into the individual messages, dropping the message framing
+fn grpc_messages(mut body: Bytes) -> Vec<Bytes> {
+  let mut messages = vec![];
+  while body.remaining() >= 5 {
+    body.advance(1);
+    let length = body.get_u32() as usize;
+    if body.remaining() < length {
+      error!("gRPC message is truncated, expected {} bytes but only {} remain", length, body.remaining());
+      break;
+    }
+    messages.push(body.split_to(length));
+  }
+  messages
+}
+
+/// Writes the exchanges recorded from the upstream server to the Pact file, with an interaction
+/// for each exchange. Exchanges for methods that are not in the descriptors are skipped.
+fn write_recorded_pact(target: &RecordingTarget, responses: &RecordedResponses) -> anyhow::Result<()> {
+  let interactions = responses.iter()
+    .sorted_by(|(a, _), (b, _)| a.cmp(b))
+    .flat_map(|(route, exchanges)| exchanges.iter().enumerate().map(move |(index, exchange)| (route, index, exchange)))
+    .filter_map(|(route, index, exchange)| match recorded_interaction(route, index, exchange, &target.descriptors) {
+      Ok(interaction) => Some(interaction),
+      Err(err) => {
+        warn!("Not writing the exchange recorded for '{}' - {}", route, err);
+        None
+      }
+    })
+    .collect_vec();
+  let plugins = target.pact.plugin_data.iter()
+    .map(|plugin| json!({
+      "name": plugin.name,
+      "version": plugin.version,
+      "configuration": plugin.configuration
+    }))
+    .collect_vec();
+  let pact = json!({
+    "consumer": { "name": target.pact.consumer.name },
+    "provider": { "name": target.pact.provider.name },
+    "interactions": interactions,
+    "metadata": {
+      "pactSpecification": { "version": "4.0" },
+      "plugins": plugins
+    }
+  });
+
+  if let Some(dir) = target.pact_file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+    fs::create_dir_all(dir)?;
+  }
+  fs::write(&target.pact_file, serde_json::to_string_pretty(&pact)?)?;
+  Ok(())
+}
+
+/// Returns the interaction JSON for an exchange recorded from the upstream server. Only the first
+/// request message is kept, and there is a response for each response message.
+fn recorded_interaction(
+  route: &str,
+  index: usize,
+  exchange: &RecordedResponse,
+  descriptors: &HashMap<String, FileDescriptorSet>
+) -> anyhow::Result<Value> {
+  let (service_name, method_name) = route.split_once('/')
+    .ok_or_else(|| anyhow!("'{}' is not a gRPC service method", route))?;
+  let (descriptor_key, method) = descriptors.iter()
+    .sorted_by(|(a, _), (b, _)| a.cmp(b))
+    .find_map(|(key, fds)| fds.file.iter()
+      .flat_map(|file| file.service.iter())
+      .find(|service| service.name.as_deref() == Some(service_name))
+      .and_then(|service| service.method.iter().find(|method| method.name.as_deref() == Some(method_name)))
+      .map(|method| (key.clone(), method.clone())))
+    .ok_or_else(|| anyhow!("Did not find the service method '{}' in the Protobuf descriptors", route))?;
+
+  let contents = |message: Option<&Bytes>, message_type: Option<&str>| json!({
+    "content": BASE64.encode(message.cloned().unwrap_or_default()),
+    "contentType": format!("application/protobuf;message={}", last_name(message_type.unwrap_or_default())),
+    "contentTypeHint": "BINARY",
+    "encoded": "base64"
+  });
+  let metadata = exchange.response_metadata.iter()
+    .filter(|(key, _)| !["content-type", "date"].contains(&key.as_str()))
+    .map(|(key, value)| (key.clone(), json!(value)))
+    .collect::<serde_json::Map<_, _>>();
+  let output_type = method.output_type.as_deref();
+  let responses = if exchange.response_messages.is_empty() {
+    vec![ json!({ "contents": contents(None, output_type), "metadata": metadata }) ]
+  } else {
+    exchange.response_messages.iter()
+      .map(|message| json!({ "contents": contents(Some(message), output_type), "metadata": metadata }))
+      .collect()
+  };
+
+  Ok(json!({
+    "type": "Synchronous/Messages",
+    "description": format!("{} recorded from the upstream server ({})", route, index + 1),
+    "pluginConfiguration": { "protobuf": { "descriptorKey": descriptor_key, "service": route } },
+    "request": { "contents": contents(exchange.request_messages.first(), method.input_type.as_deref()) },
+    "response": responses
+  }))
+}
+
+/// Applies the concurrency limit to the service, if one is configured
+fn with_concurrency_limit<S>(service: S, limit: Option<&GlobalConcurrencyLimitLayer>) -> Either<ConcurrencyLimit<S>, S> {
+  match limit {
+    Some(limit) => Either::A(limit.layer(service)),
+    None => Either::B(service)
+  }
+}
+
+/// Replaces the response with the fault configured for the interaction
+fn inject_fault(
+  fault: MockServerFault,
+  response: Response<BoxBody>,
+  connection: Option<&ConnectionCloser>
+) -> Response<BoxBody> {
+  if fault == MockServerFault::CloseConnection {
+    if let Some(connection) = connection {
+      debug!("Injecting fault, closing the connection");
+      if let Err(err) = connection.close() {
+        error!("Failed to close the connection - {}", err);
+      }
+    } else {
+      error!("No connection is available to close, resetting the stream instead");
+    }
+  } else {
+    debug!("Injecting fault, resetting the stream");
+  }
+  response.map(|_| BoxBody::new(FaultBody))
+}
+
+/// Response body that fails, which causes the stream to be reset with an INTERNAL_ERROR code
+struct FaultBody;
+
+impl Body for FaultBody {
+  type Data = Bytes;
+  type Error = Status;
+
+  fn poll_data(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+    Poll::Ready(Some(Err(Status::internal("fault injected by the mock server"))))
+  }
+
+  fn poll_trailers(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+    Poll::Ready(Ok(None))
+  }
+}
+
+/// Returns the response metadata from the interaction that must be sent as trailers
+fn response_trailers(message: &SynchronousMessage) -> HeaderMap {
+  let mut trailers = HeaderMap::new();
+  if let Some(response) = message.response.first() {
+    for (key, value) in response.metadata.iter().filter(|(_, value)| !is_absent_value(value)) {
+      if let (MetadataLocation::Trailer, key) = metadata_location(key) {
+        match (HeaderName::from_bytes(key.to_lowercase().as_bytes()), HeaderValue::from_str(json_to_string(value).as_str())) {
+          (Ok(name), Ok(value)) => {
+            trailers.insert(name, value);
+          }
+          _ => error!("'{}' is not a valid gRPC trailer, ignoring it", key)
+        }
+      }
+    }
+  }
+  trailers
+}
+
+/// Response body that adds the trailers configured in the interaction to the gRPC trailers
+struct TrailersBody {
+  inner: BoxBody,
+  trailers: HeaderMap
+}
+
+impl Body for TrailersBody {
+  type Data = Bytes;
+  type Error = Status;
+
+  fn poll_data(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+    Pin::new(&mut self.get_mut().inner).poll_data(cx)
+  }
+
+  fn poll_trailers(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+    let this = self.get_mut();
+    match Pin::new(&mut this.inner).poll_trailers(cx) {
+      Poll::Ready(Ok(trailers)) => {
+        let mut trailers = trailers.unwrap_or_default();
+        for (key, value) in &this.trailers {
+          trailers.insert(key.clone(), value.clone());
+        }
+        Poll::Ready(Ok(Some(trailers)))
+      }
+      poll => poll
+    }
+  }
+
+  fn is_end_stream(&self) -> bool {
+    self.inner.is_end_stream()
+  }
+}
+
+/// Message encoding of a gRPC request, taken from the content subtype
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum GrpcContentSubtype {
+  /// Protobuf encoded messages (`application/grpc` or `application/grpc+proto`)
+  Proto,
+  /// JSON encoded messages (`application/grpc+json`)
+  Json
+}
+
+/// Returns the message encoding for a gRPC content type, or None if it is not a gRPC content type
+pub(crate) fn grpc_content_subtype(content_type: &ContentType) -> Option<GrpcContentSubtype> {
+  if content_type.main_type.to_lowercase() != "application" {
+    return None;
+  }
+  let sub_type = match &content_type.suffix {
+    Some(suffix) => format!("{}+{}", content_type.sub_type, suffix),
+    None => content_type.sub_type.clone()
+  };
+  match sub_type.to_lowercase().as_str() {
+    "grpc" | "grpc+proto" => Some(GrpcContentSubtype::Proto),
+    "grpc+json" => Some(GrpcContentSubtype::Json),
+    _ => None
   }
 }
 
@@ -315,11 +1278,799 @@ fn invalid_path() -> Response<BoxBody> {
     .unwrap()
 }
 
-fn failed_precondition() -> Response<BoxBody> {
-  http::Response::builder()
-    .status(200)
-    .header("grpc-status", "9")
-    .header("content-type", "application/grpc")
-    .body(empty_body())
-    .unwrap()
+fn failed_precondition(message: String) -> Response<BoxBody> {
+  Status::failed_precondition(message).to_http()
+}
+
+/// Resolves the input or output message type of the method from the descriptors for the
+/// interaction, using the fully qualified name of the type so that types from imported proto
+/// files (which may have the same name as types in other packages) are found
+fn method_message_type(
+  route: &str,
+  kind: &str,
+  type_name: Option<&str>,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<DescriptorProto> {
+  let type_name = type_name
+    .filter(|type_name| !type_name.is_empty())
+    .ok_or_else(|| anyhow!("The method descriptor for '{}' does not have an {} message type", route, kind))?;
+  find_message_type_by_qualified_name(type_name, descriptors)
+    .map(|(message, _)| message)
+    .map_err(|err| anyhow!("Could not resolve the {} message type for '{}' - {}", kind, route, err))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use std::thread;
+
+  use base64::Engine;
+  use base64::engine::general_purpose::STANDARD as BASE64;
+  use expectest::prelude::*;
+  use maplit::hashmap;
+  use pact_matching::{BodyMatchResult, Mismatch};
+  use pact_models::content_types::ContentType;
+  use tokio::sync::oneshot::channel;
+
+  use crate::metadata::MetadataMatchResult;
+  use crate::mock_server::{
+    add_peer_metadata,
+    certificate_subject,
+    clear_mock_server_state,
+    grpc_content_subtype,
+    GrpcContentSubtype,
+    GrpcMockServer,
+    method_message_type,
+    mock_server_results,
+    MockServerTls,
+    PEER_ADDRESS_KEY,
+    PEER_TLS_SUBJECT_KEY,
+    received_user_agents,
+    record_mock_server_result,
+    recorded_responses,
+    MOCK_SERVER_STATE,
+    MockServerFault,
+    RecordedResponse,
+    register_mock_server,
+    remove_mock_server,
+    request_config_snippet,
+    with_concurrency_limit
+  };
+  use crate::message_decoder::{ProtobufField, ProtobufFieldData};
+  use crate::utils::parse_pact_from_request_json;
+
+  lazy_static::lazy_static! {
+    // Tests that clear the global mock server state can not run at the same time as tests that
+    // expect their servers to remain registered
+    pub(crate) static ref STATE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+  }
+
+  #[test]
+  fn mock_server_state_is_isolated_between_servers() {
+    let _lock = STATE_LOCK.blocking_lock();
+    let servers = 10;
+    let requests = 50;
+    let keys = (0..servers).map(|i| format!("isolation-test-{}", i)).collect::<Vec<_>>();
+    for key in &keys {
+      let (snd, _) = channel::<()>();
+      register_mock_server(key, snd, hashmap!{ "Test/GetTest".to_string() => (0, vec![]) }, None);
+    }
+
+    let handles = keys.iter().enumerate().map(|(i, key)| {
+      let key = key.clone();
+      thread::spawn(move || {
+        for _ in 0..requests {
+          let result = BodyMatchResult::BodyMismatches(hashmap!{
+            "$".to_string() => vec![ Mismatch::BodyMismatch {
+              path: "$".to_string(),
+              expected: None,
+              actual: None,
+              mismatch: key.clone()
+            } ]
+          });
+          let recorded = record_mock_server_result(key.as_str(), "Test/GetTest", result, MetadataMatchResult::ok());
+          assert!(recorded);
+          if i % 2 == 0 {
+            record_mock_server_result(key.as_str(), "Test/GetValues", BodyMatchResult::Ok, MetadataMatchResult::ok());
+          }
+        }
+      })
+    }).collect::<Vec<_>>();
+    for handle in handles {
+      handle.join().unwrap();
+    }
+
+    for (i, key) in keys.iter().enumerate() {
+      let results = remove_mock_server(key).unwrap();
+      let results = results.lock().unwrap();
+      let (count, route_results) = results.get("Test/GetTest").unwrap();
+      expect!(*count).to(be_equal_to(requests));
+      expect!(route_results.iter().all(|(body, _)| {
+        body.mismatches().iter().all(|m| match m {
+          Mismatch::BodyMismatch { mismatch, .. } => mismatch == key,
+          _ => false
+        })
+      })).to(be_true());
+      if i % 2 == 0 {
+        expect!(results.get("Test/GetValues").map(|(count, _)| *count)).to(be_some().value(requests));
+      } else {
+        expect!(results.get("Test/GetValues")).to(be_none());
+      }
+    }
+  }
+
+  #[test]
+  fn remove_mock_server_removes_the_entry_and_shuts_the_server_down() {
+    let _lock = STATE_LOCK.blocking_lock();
+    let (snd, mut rcr) = channel::<()>();
+    register_mock_server("remove-test", snd, hashmap!{ "Test/GetTest".to_string() => (0, vec![]) }, None);
+
+    expect!(remove_mock_server("remove-test")).to(be_some());
+    expect!(MOCK_SERVER_STATE.lock().unwrap().contains_key("remove-test")).to(be_false());
+    expect!(rcr.try_recv()).to(be_ok());
+    expect!(remove_mock_server("remove-test")).to(be_none());
+  }
+
+  #[test]
+  fn clear_mock_server_state_removes_all_the_servers() {
+    let _lock = STATE_LOCK.blocking_lock();
+    let receivers = (0..3).map(|i| {
+      let (snd, rcr) = channel::<()>();
+      register_mock_server(format!("clear-test-{}", i).as_str(), snd, hashmap!{}, None);
+      rcr
+    }).collect::<Vec<_>>();
+
+    clear_mock_server_state();
+
+    expect!(MOCK_SERVER_STATE.lock().unwrap().is_empty()).to(be_true());
+    for mut rcr in receivers {
+      expect!(rcr.try_recv()).to(be_ok());
+    }
+  }
+
+  #[test]
+  fn method_message_type_resolves_types_from_imported_files() {
+    let message = |name: &str, field: &str| prost_types::DescriptorProto {
+      name: Some(name.to_string()),
+      field: vec![
+        prost_types::FieldDescriptorProto {
+          name: Some(field.to_string()),
+          number: Some(1),
+          .. prost_types::FieldDescriptorProto::default()
+        }
+      ],
+      .. prost_types::DescriptorProto::default()
+    };
+    let descriptors = prost_types::FileDescriptorSet {
+      file: vec![
+        prost_types::FileDescriptorProto {
+          name: Some("service.proto".to_string()),
+          package: Some("service".to_string()),
+          dependency: vec![ "common/types.proto".to_string() ],
+          message_type: vec![ message("Request", "local") ],
+          .. prost_types::FileDescriptorProto::default()
+        },
+        prost_types::FileDescriptorProto {
+          name: Some("common/types.proto".to_string()),
+          package: Some("common.types".to_string()),
+          message_type: vec![
+            prost_types::DescriptorProto {
+              nested_type: vec![ message("Page", "size") ],
+              .. message("Request", "imported")
+            }
+          ],
+          .. prost_types::FileDescriptorProto::default()
+        }
+      ]
+    };
+
+    expect!(method_message_type("Service/Get", "input", Some(".common.types.Request"), &descriptors))
+      .to(be_ok().value(descriptors.file[1].message_type[0].clone()));
+    expect!(method_message_type("Service/Get", "input", Some(".service.Request"), &descriptors))
+      .to(be_ok().value(descriptors.file[0].message_type[0].clone()));
+    expect!(method_message_type("Service/Get", "output", Some(".common.types.Request.Page"), &descriptors))
+      .to(be_ok().value(message("Page", "size")));
+    expect!(method_message_type("Service/Get", "input", Some(".common.Request"), &descriptors).unwrap_err().to_string())
+      .to(be_equal_to("Could not resolve the input message type for 'Service/Get' - Did not find a message type \
+        '.common.Request' in the descriptors (searched service.proto, common/types.proto) [service.proto: package \
+        'service' does not match; common/types.proto: package 'common.types' does not match]"));
+    expect!(method_message_type("Service/Get", "input", None, &descriptors).unwrap_err().to_string())
+      .to(be_equal_to("The method descriptor for 'Service/Get' does not have an input message type"));
+  }
+
+  #[test]
+  fn request_config_snippet_contains_the_actual_field_values() {
+    let descriptor = prost_types::DescriptorProto {
+      name: Some("Person".to_string()),
+      field: vec![
+        prost_types::FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          r#type: Some(prost_types::field_descriptor_proto::Type::String as i32),
+          .. prost_types::FieldDescriptorProto::default()
+        },
+        prost_types::FieldDescriptorProto {
+          name: Some("age".to_string()),
+          number: Some(2),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Int32 as i32),
+          .. prost_types::FieldDescriptorProto::default()
+        }
+      ],
+      .. prost_types::DescriptorProto::default()
+    };
+    let fields = vec![
+      ProtobufField {
+        field_num: 1,
+        field_name: "name".to_string(),
+        wire_type: prost::encoding::WireType::LengthDelimited,
+        data: ProtobufFieldData::String("Fred".to_string())
+      },
+      ProtobufField {
+        field_num: 2,
+        field_name: "age".to_string(),
+        wire_type: prost::encoding::WireType::Varint,
+        data: ProtobufFieldData::Integer32(42)
+      }
+    ];
+    let descriptors = prost_types::FileDescriptorSet { file: vec![] };
+
+    let snippet = request_config_snippet(&fields, &descriptor, &descriptors).unwrap();
+    let json: serde_json::Value = serde_json::from_str(snippet.as_str()).unwrap();
+    expect!(json).to(be_equal_to(serde_json::json!({
+      "request": { "name": "Fred", "age": 42 }
+    })));
+  }
+
+  #[test]
+  fn peer_address_can_be_matched_as_request_metadata() {
+    let mut headers = http::HeaderMap::new();
+    headers.insert(PEER_ADDRESS_KEY, http::HeaderValue::from_static("10.0.0.1:1234"));
+    add_peer_metadata(&mut headers, Some("127.0.0.1:50051".parse().unwrap()), None);
+    let metadata = tonic::metadata::MetadataMap::from_headers(headers);
+
+    let expected = hashmap!{
+      PEER_ADDRESS_KEY.to_string() => serde_json::json!("127.0.0.1:1234")
+    };
+    let context = pact_matching::CoreMatchingContext::new(
+      pact_matching::DiffConfig::NoUnexpectedKeys,
+      &pact_models::matchingrules! {
+        "metadata" => {
+          PEER_ADDRESS_KEY => [ pact_models::matchingrules::MatchingRule::Regex(r"^127\.0\.0\.1:\d+$".to_string()) ]
+        }
+      }.rules_for_category("metadata").unwrap(),
+      &hashmap!{}
+    );
+    let (result, _) = crate::metadata::compare_metadata(&expected, &metadata, &context).unwrap();
+    expect!(result.result).to(be_true());
+
+    let mut headers = http::HeaderMap::new();
+    headers.insert(PEER_ADDRESS_KEY, http::HeaderValue::from_static("127.0.0.1:1234"));
+    add_peer_metadata(&mut headers, None, None);
+    expect!(headers.get(PEER_ADDRESS_KEY)).to(be_none());
+  }
+
+  #[test]
+  fn certificate_subject_test() {
+    let der = |path: &str| {
+      let pem = std::fs::read(path).unwrap();
+      rustls_pemfile::certs(&mut pem.as_slice()).unwrap().remove(0)
+    };
+    expect!(certificate_subject(&der("tests/tls/client.pem"))).to(be_some().value("CN=client"));
+    expect!(certificate_subject(&der("tests/tls/ca.pem"))).to(be_some().value("CN=Pact Protobuf Plugin Test CA"));
+    expect!(certificate_subject(&[0x30, 0x82, 0x01])).to(be_none());
+  }
+
+  #[test_log::test(tokio::test(flavor = "multi_thread"))]
+  async fn mock_server_with_tls_matches_the_subject_of_the_client_certificate() {
+    use pact_models::matchingrules::{MatchingRule, RuleLogic};
+    use pact_models::path_exp::DocPath;
+    use pact_models::v4::interaction::V4Interaction;
+    use tonic::transport::{Certificate, ClientTlsConfig, Endpoint, Identity};
+    use tower::ServiceExt;
+
+    let _lock = STATE_LOCK.lock().await;
+    let mut pact = simple_pact(None);
+    let mut interaction = pact.interactions[0].as_v4_sync_message().unwrap();
+    interaction.request.metadata.insert(PEER_TLS_SUBJECT_KEY.to_string(), serde_json::json!("CN=client"));
+    interaction.request.matching_rules.add_category("metadata")
+      .add_rule(DocPath::new(PEER_TLS_SUBJECT_KEY).unwrap(), MatchingRule::Regex("^CN=client$".to_string()), RuleLogic::And);
+    pact.interactions[0] = interaction.boxed_v4();
+    let plugin_config = pact.plugin_data.iter().find(|p| p.name == "protobuf").cloned().unwrap();
+    let mock_server = GrpcMockServer::new(pact, &plugin_config, hashmap!{})
+      .with_tls(Some(MockServerTls {
+        certificate: "tests/tls/server.pem".into(),
+        key: "tests/tls/server.key".into(),
+        ca_certificate: Some("tests/tls/ca.pem".into())
+      }));
+    let server_key = mock_server.server_key.clone();
+    let address = mock_server.start_server("127.0.0.1", 0, true).await.unwrap();
+
+    let ca = Certificate::from_pem(std::fs::read("tests/tls/ca.pem").unwrap());
+    let client_identity = Identity::from_pem(std::fs::read("tests/tls/client.pem").unwrap(),
+      std::fs::read("tests/tls/client.key").unwrap());
+    let tls_configs = [
+      ClientTlsConfig::new().ca_certificate(ca.clone()).identity(client_identity).domain_name("localhost"),
+      ClientTlsConfig::new().ca_certificate(ca).domain_name("localhost")
+    ];
+    for tls in tls_configs {
+      let channel = Endpoint::new(format!("https://{}", address)).unwrap()
+        .tls_config(tls).unwrap()
+        .connect().await.unwrap();
+      let request = http::Request::post(format!("https://{}/Test/GetTest", address))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(tonic::body::BoxBody::new(http_body::Body::map_err(hyper::Body::from(vec![0_u8, 0, 0, 0, 2, 8, 1]),
+          |err| tonic::Status::from_error(Box::new(err)))))
+        .unwrap();
+      let response = channel.oneshot(request).await.unwrap();
+      let _ = hyper::body::to_bytes(response.into_body()).await;
+    }
+
+    let results = remove_mock_server(server_key.as_str()).unwrap();
+    let results = results.lock().unwrap();
+    let md_mismatches = results.get("Test/GetTest")
+      .map(|(_, route_results)| route_results.iter().map(|(_, md_result)| md_result.mismatches.clone()).collect::<Vec<_>>())
+      .unwrap_or_default();
+    expect!(md_mismatches.len()).to(be_equal_to(2));
+    expect!(md_mismatches[0].iter()).to(be_empty());
+    let keys = md_mismatches[1].iter()
+      .map(|mismatch| match mismatch {
+        Mismatch::MetadataMismatch { key, .. } => key.clone(),
+        _ => String::default()
+      })
+      .collect::<Vec<_>>();
+    expect!(keys).to(be_equal_to(vec![ PEER_TLS_SUBJECT_KEY.to_string() ]));
+  }
+
+  #[test]
+  fn grpc_content_subtype_test() {
+    let parse = |ct: &str| ContentType::parse(ct).unwrap();
+    expect!(grpc_content_subtype(&parse("application/grpc"))).to(be_some().value(GrpcContentSubtype::Proto));
+    expect!(grpc_content_subtype(&parse("application/grpc+proto"))).to(be_some().value(GrpcContentSubtype::Proto));
+    expect!(grpc_content_subtype(&parse("application/grpc+proto; charset=utf-8"))).to(be_some().value(GrpcContentSubtype::Proto));
+    expect!(grpc_content_subtype(&parse("application/grpc+json"))).to(be_some().value(GrpcContentSubtype::Json));
+    expect!(grpc_content_subtype(&parse("application/grpc-web"))).to(be_none());
+    expect!(grpc_content_subtype(&parse("application/json"))).to(be_none());
+  }
+
+  #[test]
+  fn record_mock_server_result_with_unknown_server() {
+    expect!(record_mock_server_result("unknown-server", "Test/GetTest", BodyMatchResult::Ok, MetadataMatchResult::ok()))
+      .to(be_false());
+    expect!(mock_server_results("unknown-server")).to(be_none());
+  }
+
+  #[test]
+  fn mock_server_fault_parse() {
+    expect!(MockServerFault::parse("reset-stream")).to(be_ok().value(MockServerFault::ResetStream));
+    expect!(MockServerFault::parse("Close-Connection")).to(be_ok().value(MockServerFault::CloseConnection));
+    expect!(MockServerFault::parse("explode")).to(be_err());
+  }
+
+  // Pact with a single interaction for the Test/GetTest method, with the given fault configured
+  pub(crate) fn simple_pact(fault: Option<&str>) -> pact_models::prelude::v4::V4Pact {
+    let descriptors = "CogCCgxzaW1wbGUucHJvdG8iGwoJTWVzc2FnZUluEg4KAmluGAEgASgIUgJpbiIeCgpNZXNzYWdlT3V0EhAKA291\
+      dBgBIAEoCFIDb3V0IicKD1ZhbHVlc01lc3NhZ2VJbhIUCgV2YWx1ZRgBIAMoCVIFdmFsdWUiKAoQVmFsdWVzTWVzc2FnZU\
+      91dBIUCgV2YWx1ZRgBIAMoCVIFdmFsdWUyYAoEVGVzdBIkCgdHZXRUZXN0EgouTWVzc2FnZUluGgsuTWVzc2FnZU91dCIA\
+      EjIKCUdldFZhbHVlcxIQLlZhbHVlc01lc3NhZ2VJbhoRLlZhbHVlc01lc3NhZ2VPdXQiAGIGcHJvdG8z";
+    // The descriptors are checked against the key when they are loaded, so it must be their MD5 hash
+    let descriptor_key = format!("{:x}", md5::compute(BASE64.decode(descriptors).unwrap()));
+    let mut interaction_config = serde_json::json!({
+      "descriptorKey": descriptor_key,
+      "service": "Test/GetTest"
+    });
+    if let Some(fault) = fault {
+      interaction_config["fault"] = serde_json::json!(fault);
+    }
+    let pact_json = serde_json::json!({
+      "consumer": { "name": "fault-consumer" },
+      "provider": { "name": "fault-provider" },
+      "interactions": [
+        {
+          "type": "Synchronous/Messages",
+          "description": "fault test",
+          "pluginConfiguration": { "protobuf": interaction_config },
+          "request": {
+            "contents": {
+              "content": "CAE=",
+              "contentType": "application/protobuf;message=MessageIn",
+              "contentTypeHint": "BINARY",
+              "encoded": "base64"
+            }
+          },
+          "response": [
+            {
+              "contents": {
+                "content": "CAE=",
+                "contentType": "application/protobuf;message=MessageOut",
+                "contentTypeHint": "BINARY",
+                "encoded": "base64"
+              }
+            }
+          ]
+        }
+      ],
+      "metadata": {
+        "pactSpecification": { "version": "4.0" },
+        "plugins": [
+          {
+            "name": "protobuf",
+            "version": "0.3.0",
+            "configuration": {
+              descriptor_key: { "protoDescriptors": descriptors, "protoFile": "" }
+            }
+          }
+        ]
+      }
+    });
+    parse_pact_from_request_json(pact_json.to_string().as_str(), "fault-test").unwrap()
+  }
+
+  // Sends a unary request to the Test/GetTest method of a mock server configured with the given fault
+  async fn call_mock_server_with_fault(fault: Option<&str>) -> Result<bytes::Bytes, hyper::Error> {
+    // The server must stay registered until the request has been made
+    let _lock = STATE_LOCK.lock().await;
+    let pact = simple_pact(fault);
+    let plugin_config = pact.plugin_data.iter().find(|p| p.name == "protobuf").cloned().unwrap();
+    let mock_server = GrpcMockServer::new(pact, &plugin_config, hashmap!{});
+    let server_key = mock_server.server_key.clone();
+    let address = mock_server.start_server("127.0.0.1", 0, false).await.unwrap();
+
+    let client = hyper::Client::builder()
+      .http2_only(true)
+      .build_http::<hyper::Body>();
+    let request = hyper::Request::post(format!("http://{}/Test/GetTest", address))
+      .header("content-type", "application/grpc")
+      .header("te", "trailers")
+      .body(hyper::Body::from(vec![0_u8, 0, 0, 0, 2, 8, 1]))
+      .unwrap();
+    let result = match client.request(request).await {
+      Ok(response) => hyper::body::to_bytes(response.into_body()).await,
+      Err(err) => Err(err)
+    };
+
+    let results = remove_mock_server(server_key.as_str()).unwrap();
+    let count = results.lock().unwrap().get("Test/GetTest").map(|(count, _)| *count);
+    expect!(count).to(be_some().value(1));
+
+    result
+  }
+
+  #[test_log::test(tokio::test(flavor = "multi_thread"))]
+  async fn mock_server_with_no_fault_configured() {
+    let result = call_mock_server_with_fault(None).await;
+    expect!(result).to(be_ok().value(bytes::Bytes::from_static(&[0, 0, 0, 0, 2, 8, 1])));
+  }
+
+  #[test_log::test(tokio::test(flavor = "multi_thread"))]
+  async fn mock_server_with_a_reset_stream_fault() {
+    let result = call_mock_server_with_fault(Some("reset-stream")).await;
+    let error = result.unwrap_err();
+    expect!(format!("{:?}", error).contains("INTERNAL_ERROR")).to(be_true());
+  }
+
+  #[test_log::test(tokio::test(flavor = "multi_thread"))]
+  async fn mock_server_with_a_close_connection_fault() {
+    let result = call_mock_server_with_fault(Some("close-connection")).await;
+    expect!(result).to(be_err());
+  }
+
+  // Starts a request that does not send its message, and returns if a second request completes
+  // while the first one is still being handled
+  async fn second_request_completes_while_first_is_running(max_concurrent_requests: Option<usize>) -> bool {
+    let _lock = STATE_LOCK.lock().await;
+    let pact = simple_pact(None);
+    let plugin_config = pact.plugin_data.iter().find(|p| p.name == "protobuf").cloned().unwrap();
+    let mock_server = GrpcMockServer::new(pact, &plugin_config, hashmap!{})
+      .with_max_concurrent_requests(max_concurrent_requests);
+    let server_key = mock_server.server_key.clone();
+    let address = mock_server.start_server("127.0.0.1", 0, false).await.unwrap();
+
+    let client = hyper::Client::builder()
+      .http2_only(true)
+      .build_http::<hyper::Body>();
+    let grpc_request = |body: hyper::Body| hyper::Request::post(format!("http://{}/Test/GetTest", address))
+      .header("content-type", "application/grpc")
+      .header("te", "trailers")
+      .body(body)
+      .unwrap();
+
+    let (mut sender, body) = hyper::Body::channel();
+    let first_request = tokio::spawn(client.request(grpc_request(body)));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let second_request = client.request(grpc_request(hyper::Body::from(vec![0_u8, 0, 0, 0, 2, 8, 1])));
+    let second_request_completed = tokio::time::timeout(std::time::Duration::from_millis(500), second_request)
+      .await.is_ok();
+
+    sender.send_data(bytes::Bytes::from_static(&[0, 0, 0, 0, 2, 8, 1])).await.unwrap();
+    drop(sender);
+    let first_response = first_request.await.unwrap().unwrap();
+    expect!(hyper::body::to_bytes(first_response.into_body()).await.unwrap())
+      .to(be_equal_to(bytes::Bytes::from_static(&[0, 0, 0, 0, 2, 8, 1])));
+
+    remove_mock_server(server_key.as_str());
+    second_request_completed
+  }
+
+  #[test_log::test(tokio::test(flavor = "multi_thread"))]
+  async fn mock_server_queues_requests_over_the_concurrency_limit() {
+    expect!(second_request_completes_while_first_is_running(Some(1)).await).to(be_false());
+    expect!(second_request_completes_while_first_is_running(None).await).to(be_true());
+  }
+
+  #[test_log::test(tokio::test(flavor = "multi_thread"))]
+  async fn mock_server_accepts_json_encoded_messages() {
+    let _lock = STATE_LOCK.lock().await;
+    let pact = simple_pact(None);
+    let plugin_config = pact.plugin_data.iter().find(|p| p.name == "protobuf").cloned().unwrap();
+    let mock_server = GrpcMockServer::new(pact, &plugin_config, hashmap!{});
+    let server_key = mock_server.server_key.clone();
+    let address = mock_server.start_server("127.0.0.1", 0, false).await.unwrap();
+
+    let client = hyper::Client::builder()
+      .http2_only(true)
+      .build_http::<hyper::Body>();
+    let message = br#"{"in":true}"#;
+    let mut body = vec![0_u8, 0, 0, 0, message.len() as u8];
+    body.extend_from_slice(message);
+    let request = hyper::Request::post(format!("http://{}/Test/GetTest", address))
+      .header("content-type", "application/grpc+json")
+      .header("te", "trailers")
+      .body(hyper::Body::from(body))
+      .unwrap();
+    let response = client.request(request).await.unwrap();
+    expect!(response.headers().get("content-type").map(|value| value.to_str().unwrap().to_string()))
+      .to(be_some().value("application/grpc+json"));
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let expected = br#"{"out":true}"#;
+    expect!(body.get(5..).map(|message| message.to_vec())).to(be_some().value(expected.to_vec()));
+
+    let results = remove_mock_server(server_key.as_str()).unwrap();
+    let results = results.lock().unwrap();
+    let (count, route_results) = results.get("Test/GetTest").cloned().unwrap();
+    expect!(count).to(be_equal_to(1));
+    expect!(route_results.iter().all(|(result, md_result)| result.all_matched() && md_result.mismatches.is_empty()))
+      .to(be_true());
+  }
+
+  #[test_log::test(tokio::test(flavor = "multi_thread"))]
+  async fn mock_server_records_a_mismatch_for_a_call_to_a_method_that_is_not_configured() {
+    let _lock = STATE_LOCK.lock().await;
+    let pact = simple_pact(None);
+    let plugin_config = pact.plugin_data.iter().find(|p| p.name == "protobuf").cloned().unwrap();
+    let mock_server = GrpcMockServer::new(pact, &plugin_config, hashmap!{});
+    let server_key = mock_server.server_key.clone();
+    let address = mock_server.start_server("127.0.0.1", 0, false).await.unwrap();
+
+    let client = hyper::Client::builder()
+      .http2_only(true)
+      .build_http::<hyper::Body>();
+    for path in [ "Test/GetValues", "Other/GetTest" ] {
+      let request = hyper::Request::post(format!("http://{}/{}", address, path))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(hyper::Body::from(vec![0_u8, 0, 0, 0, 2, 8, 1]))
+        .unwrap();
+      let response = client.request(request).await.unwrap();
+      expect!(response.headers().get("grpc-status").map(|status| status.to_str().unwrap().to_string()))
+        .to(be_some().value("12"));
+    }
+
+    let results = remove_mock_server(server_key.as_str()).unwrap();
+    let results = results.lock().unwrap();
+    expect!(results.get("Test/GetTest").map(|(count, _)| *count)).to(be_some().value(0));
+    let mismatches = |route: &str| results.get(route)
+      .map(|(_, route_results)| route_results.iter().flat_map(|(result, _)| result.mismatches()).collect::<Vec<_>>())
+      .unwrap_or_default();
+    expect!(mismatches("Test/GetValues")).to(be_equal_to(vec![
+      Mismatch::MethodMismatch { expected: "GetTest".to_string(), actual: "GetValues".to_string() }
+    ]));
+    expect!(mismatches("Other/GetTest")).to(be_equal_to(vec![
+      Mismatch::PathMismatch {
+        expected: "Test/GetTest".to_string(),
+        actual: "Other/GetTest".to_string(),
+        mismatch: "Received a call to 'Other/GetTest', but the mock server is not configured for the service 'Other'".to_string()
+      }
+    ]));
+  }
+
+  #[test_log::test(tokio::test(flavor = "multi_thread"))]
+  async fn mock_server_matches_the_user_agent_with_the_request_metadata_rules_and_records_it() {
+    use pact_models::matchingrules::{MatchingRule, RuleLogic};
+    use pact_models::path_exp::DocPath;
+    use pact_models::v4::interaction::V4Interaction;
+
+    let _lock = STATE_LOCK.lock().await;
+    let mut pact = simple_pact(None);
+    let mut interaction = pact.interactions[0].as_v4_sync_message().unwrap();
+    interaction.request.metadata.insert("user-agent".to_string(), serde_json::json!("grpc-test/1.0"));
+    interaction.request.matching_rules.add_category("metadata")
+      .add_rule(DocPath::new("user-agent").unwrap(), MatchingRule::Regex(r"^grpc-test/\d+\.\d+$".to_string()), RuleLogic::And);
+    pact.interactions[0] = interaction.boxed_v4();
+    let plugin_config = pact.plugin_data.iter().find(|p| p.name == "protobuf").cloned().unwrap();
+    let mock_server = GrpcMockServer::new(pact, &plugin_config, hashmap!{});
+    let server_key = mock_server.server_key.clone();
+    let address = mock_server.start_server("127.0.0.1", 0, false).await.unwrap();
+
+    let client = hyper::Client::builder()
+      .http2_only(true)
+      .build_http::<hyper::Body>();
+    for (user_agent, status) in [ ("grpc-test/1.2", None), ("curl/8.1.2", Some("9")) ] {
+      let request = hyper::Request::post(format!("http://{}/Test/GetTest", address))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .header("user-agent", user_agent)
+        .body(hyper::Body::from(vec![0_u8, 0, 0, 0, 2, 8, 1]))
+        .unwrap();
+      let response = client.request(request).await.unwrap();
+      let (parts, mut body) = response.into_parts();
+      while hyper::body::HttpBody::data(&mut body).await.is_some() {}
+      let trailers = hyper::body::HttpBody::trailers(&mut body).await.unwrap();
+      let grpc_status = parts.headers.get("grpc-status")
+        .or_else(|| trailers.as_ref().and_then(|trailers| trailers.get("grpc-status")))
+        .map(|status| status.to_str().unwrap().to_string());
+      expect!(grpc_status.filter(|status| status != "0")).to(be_equal_to(status.map(|s| s.to_string())));
+    }
+
+    let user_agents = received_user_agents(server_key.as_str()).unwrap();
+    expect!(user_agents.get("Test/GetTest").cloned()).to(be_some().value(vec![
+      "grpc-test/1.2".to_string(),
+      "curl/8.1.2".to_string()
+    ]));
+    let results = remove_mock_server(server_key.as_str()).unwrap();
+    let results = results.lock().unwrap();
+    let md_mismatches = results.get("Test/GetTest")
+      .map(|(_, route_results)| route_results.iter().flat_map(|(_, md_result)| md_result.mismatches.clone()).collect::<Vec<_>>())
+      .unwrap_or_default();
+    let keys = md_mismatches.iter()
+      .map(|mismatch| match mismatch {
+        Mismatch::MetadataMismatch { key, actual, .. } => (key.clone(), actual.clone()),
+        _ => (String::default(), String::default())
+      })
+      .collect::<Vec<_>>();
+    expect!(keys).to(be_equal_to(vec![ ("user-agent".to_string(), "curl/8.1.2".to_string()) ]));
+  }
+
+  #[test_log::test(tokio::test(flavor = "multi_thread"))]
+  async fn mock_server_in_record_mode_forwards_the_requests_and_records_the_responses() {
+    use hyper::body::HttpBody;
+
+    let _lock = STATE_LOCK.lock().await;
+    // The upstream server waits for the first response message to reach the client before sending
+    // the second one, so the test only completes if the response is streamed through
+    let first_message_received = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify = first_message_received.clone();
+    let make_service = hyper::service::make_service_fn(move |_| {
+      let notify = notify.clone();
+      async move {
+        Ok::<_, std::convert::Infallible>(hyper::service::service_fn(move |request: hyper::Request<hyper::Body>| {
+          let notify = notify.clone();
+          async move {
+            let path = request.uri().path().to_string();
+            let _ = hyper::body::to_bytes(request.into_body()).await;
+            let (mut sender, body) = hyper::Body::channel();
+            tokio::spawn(async move {
+              let _ = sender.send_data(bytes::Bytes::from_static(&[0, 0, 0, 0, 2, 8, 1])).await;
+              notify.notified().await;
+              let _ = sender.send_data(bytes::Bytes::from_static(&[0, 0, 0, 0, 2, 8, 2])).await;
+              let mut trailers = http::HeaderMap::new();
+              trailers.insert("grpc-status", http::HeaderValue::from_static("0"));
+              let _ = sender.send_trailers(trailers).await;
+            });
+            Ok::<_, std::convert::Infallible>(hyper::Response::builder()
+              .header("content-type", "application/grpc")
+              .header("x-upstream-path", path)
+              .body(body)
+              .unwrap())
+          }
+        }))
+      }
+    });
+    let upstream = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0)))
+      .http2_only(true)
+      .serve(make_service);
+    let upstream_address = upstream.local_addr();
+    tokio::spawn(upstream);
+
+    let dir = tempfile::tempdir().unwrap();
+    let pact_file = dir.path().join("recorded.json");
+    let pact = simple_pact(None);
+    let plugin_config = pact.plugin_data.iter().find(|p| p.name == "protobuf").cloned().unwrap();
+    let mock_server = GrpcMockServer::new(pact, &plugin_config, hashmap!{})
+      .with_upstream(Some(format!("http://{}", upstream_address)), Some(pact_file.clone()));
+    let server_key = mock_server.server_key.clone();
+    let address = mock_server.start_server("127.0.0.1", 0, false).await.unwrap();
+
+    let client = hyper::Client::builder()
+      .http2_only(true)
+      .build_http::<hyper::Body>();
+    let request = hyper::Request::post(format!("http://{}/Test/GetTest", address))
+      .header("content-type", "application/grpc")
+      .header("te", "trailers")
+      .body(hyper::Body::from(vec![0_u8, 0, 0, 0, 2, 8, 0]))
+      .unwrap();
+    let response = client.request(request).await.unwrap();
+    expect!(response.headers().get("x-upstream-path").map(|path| path.to_str().unwrap().to_string()))
+      .to(be_some().value("/Test/GetTest"));
+    let mut body = response.into_body();
+    let first = body.data().await.unwrap().unwrap();
+    expect!(first).to(be_equal_to(bytes::Bytes::from_static(&[0, 0, 0, 0, 2, 8, 1])));
+    first_message_received.notify_one();
+    let rest = hyper::body::to_bytes(body).await.unwrap();
+    expect!(rest).to(be_equal_to(bytes::Bytes::from_static(&[0, 0, 0, 0, 2, 8, 2])));
+
+    let recorded = recorded_responses(server_key.as_str()).unwrap();
+    let recorded = recorded.get("Test/GetTest").cloned().unwrap_or_default();
+    expect!(recorded.len()).to(be_equal_to(1));
+    let RecordedResponse { request_messages, response_messages, response_metadata } = recorded[0].clone();
+    expect!(request_messages).to(be_equal_to(vec![bytes::Bytes::from_static(&[8, 0])]));
+    expect!(response_messages).to(be_equal_to(vec![bytes::Bytes::from_static(&[8, 1]), bytes::Bytes::from_static(&[8, 2])]));
+    expect!(response_metadata.get("x-upstream-path").cloned()).to(be_some().value("/Test/GetTest"));
+    expect!(response_metadata.get("grpc-status").cloned()).to(be_some().value("0"));
+
+    // The forwarded request counts as received for the route
+    let results = remove_mock_server(server_key.as_str()).unwrap();
+    let count = results.lock().unwrap().get("Test/GetTest").map(|(count, _)| *count);
+    expect!(count).to(be_some().value(1));
+
+    let recorded_pact = parse_pact_from_request_json(std::fs::read_to_string(&pact_file).unwrap().as_str(), "recorded").unwrap();
+    expect!(recorded_pact.consumer.name.as_str()).to(be_equal_to("fault-consumer"));
+    expect!(recorded_pact.plugin_data.iter().any(|plugin| plugin.name == "protobuf")).to(be_true());
+    expect!(recorded_pact.interactions.len()).to(be_equal_to(1));
+    let interaction = recorded_pact.interactions[0].as_v4_sync_message().unwrap();
+    expect!(interaction.plugin_config.get("protobuf").and_then(|config| config.get("service")).cloned())
+      .to(be_some().value(serde_json::json!("Test/GetTest")));
+    expect!(interaction.request.contents.value()).to(be_some().value(bytes::Bytes::from_static(&[8, 0])));
+    let responses = interaction.response.iter().map(|response| response.contents.value().unwrap_or_default()).collect::<Vec<_>>();
+    expect!(responses).to(be_equal_to(vec![bytes::Bytes::from_static(&[8, 1]), bytes::Bytes::from_static(&[8, 2])]));
+    expect!(interaction.response[0].metadata.get("x-upstream-path").cloned())
+      .to(be_some().value(serde_json::json!("/Test/GetTest")));
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn mock_server_in_record_mode_requires_the_recording_file() {
+    let pact = simple_pact(None);
+    let plugin_config = pact.plugin_data.iter().find(|p| p.name == "protobuf").cloned().unwrap();
+    let mock_server = GrpcMockServer::new(pact, &plugin_config, hashmap!{})
+      .with_upstream(Some("http://127.0.0.1:50051".to_string()), None);
+    expect!(mock_server.start_server("127.0.0.1", 0, false).await).to(be_err());
+  }
+
+  // Runs the requests at the same time, returning the maximum number of requests that were being
+  // handled concurrently
+  async fn max_concurrent_requests(limit: Option<usize>, requests: usize) -> usize {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    let active = Arc::new(AtomicUsize::new(0));
+    let max = Arc::new(AtomicUsize::new(0));
+    let service = {
+      let active = active.clone();
+      let max = max.clone();
+      tower::service_fn(move |_: ()| {
+        let active = active.clone();
+        let max = max.clone();
+        async move {
+          let running = active.fetch_add(1, Ordering::SeqCst) + 1;
+          max.fetch_max(running, Ordering::SeqCst);
+          tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+          active.fetch_sub(1, Ordering::SeqCst);
+          Ok::<_, std::io::Error>(())
+        }
+      })
+    };
+    let limit = limit.map(tower::limit::GlobalConcurrencyLimitLayer::new);
+    let service = with_concurrency_limit(service, limit.as_ref());
+
+    let results = futures::future::join_all((0..requests).map(|_| service.clone().oneshot(()))).await;
+    expect!(results.iter().all(|result| result.is_ok())).to(be_true());
+    max.load(Ordering::SeqCst)
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn requests_over_the_concurrency_limit_wait_for_running_requests() {
+    expect!(max_concurrent_requests(Some(2), 6).await).to(be_equal_to(2));
+    expect!(max_concurrent_requests(None, 6).await).to(be_equal_to(6));
+  }
 }
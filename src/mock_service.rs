@@ -1,13 +1,24 @@
 //! Module provides the service implementation based on a Pact interaction
 
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::task::{Context, Poll};
 
+use anyhow::anyhow;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, stream, StreamExt};
+use itertools::Itertools;
 use maplit::hashmap;
-use pact_matching::{CoreMatchingContext, DiffConfig};
+use pact_matching::{BodyMatchResult, CoreMatchingContext, DiffConfig, Mismatch};
+use pact_matching::Mismatch::BodyMismatch;
 use pact_models::generators::{GenerateValue, GeneratorCategory, NoopVariantMatcher, VariantMatcher};
 use pact_models::json_utils::json_to_string;
+use pact_models::matchingrules::MatchingRuleCategory;
 use pact_models::pact::Pact;
 use pact_models::path_exp::DocPath;
 use pact_models::prelude::v4::V4Pact;
@@ -15,16 +26,58 @@ use pact_models::v4::message_parts::MessageContents;
 use pact_models::v4::sync_message::SynchronousMessage;
 use pact_plugin_driver::plugin_models::PluginInteractionConfig;
 use prost_types::{DescriptorProto, FileDescriptorSet, MethodDescriptorProto};
-use tonic::{Request, Response, Status};
+use serde_json::Value;
+use tonic::{Request, Response, Status, Streaming};
 use tonic::metadata::{Entry, MetadataMap};
 use tower_service::Service;
 use tracing::{debug, error, info, instrument, trace, warn};
 
 use crate::dynamic_message::DynamicMessage;
-use crate::matching::compare;
+use crate::matching::{
+  apply_field_mask,
+  compare,
+  FIELD_MASK_CONFIG,
+  field_mask_paths,
+  match_serialized_message,
+  with_field_presence,
+  with_message_part
+};
 use crate::message_decoder::decode_message;
-use crate::metadata::{compare_metadata, grpc_status};
-use crate::mock_server::MOCK_SERVER_STATE;
+use crate::metadata::{
+  compare_metadata,
+  grpc_status,
+  is_absent_value,
+  metadata_location,
+  MetadataLocation,
+  USER_AGENT_KEY
+};
+use crate::mock_server::{
+  MISMATCH_SNIPPET_CONFIG,
+  record_mock_server_result,
+  request_config_snippet
+};
+
+/// Interaction configuration key for the expected request messages of a client streaming call
+pub(crate) const REQUEST_STREAM_CONFIG: &str = "requestStream";
+
+/// Interaction configuration key for the matching rules of each of the expected request messages
+/// of a client streaming call
+pub(crate) const REQUEST_STREAM_RULES_CONFIG: &str = "requestStreamRules";
+
+/// Interaction configuration key for how the request messages of a client streaming call are
+/// matched (`ordered` or `unordered`)
+pub(crate) const STREAM_ORDERING_CONFIG: &str = "streamOrdering";
+
+/// Interaction configuration key for how the responses from the interaction are returned over
+/// successive calls to a unary method (`sequential` or `round-robin`)
+pub(crate) const RESPONSE_SEQUENCE_CONFIG: &str = "responseSequence";
+
+/// Interaction configuration key for the fields of the response message that are set from the
+/// request message (a map of response field path to request field path)
+pub(crate) const ECHO_FIELDS_CONFIG: &str = "echoFields";
+
+/// Stream of response messages returned for a server streaming call
+pub(crate) type MockResponseStream = Pin<Box<dyn Stream<Item = Result<DynamicMessage, Status>> + Send>>;
 
 #[derive(Debug, Clone)]
 pub(crate) struct MockService {
@@ -35,11 +88,14 @@ pub(crate) struct MockService {
   input_message: DescriptorProto,
   output_message: DescriptorProto,
   server_key: String,
-  pact: V4Pact
+  pact: V4Pact,
+  /// Number of calls that have been handled for the interaction, shared by all the connections to
+  /// the mock server
+  call_count: Arc<AtomicUsize>
 }
 
 impl MockService {
-  #[instrument(skip(self, message_descriptor, response_descriptor))]
+  #[instrument(skip(self, message_descriptor, response_descriptor), fields(route = %self.route_key()))]
   pub(crate) async fn handle_message(
     &self,
     request: DynamicMessage,
@@ -47,77 +103,367 @@ impl MockService {
     response_descriptor: DescriptorProto,
     request_metadata: MetadataMap
   ) -> Result<Response<DynamicMessage>, Status> {
+    self.match_request(&request, &message_descriptor, &request_metadata)?;
+    self.unary_response(&response_descriptor, Some(&request))
+  }
+
+  /// Handles a client streaming call. All the messages from the client are read, and then matched
+  /// against the expected request messages before the response is returned.
+  #[instrument(skip(self, requests, message_descriptor, response_descriptor), fields(route = %self.route_key()))]
+  pub(crate) async fn handle_client_streaming_message(
+    &self,
+    requests: Vec<DynamicMessage>,
+    message_descriptor: DescriptorProto,
+    response_descriptor: DescriptorProto,
+    request_metadata: MetadataMap
+  ) -> Result<Response<DynamicMessage>, Status> {
+    debug!("Received {} request messages", requests.len());
+    self.match_request_stream(&requests, &message_descriptor, &request_metadata)?;
+    self.unary_response(&response_descriptor, requests.last())
+  }
+
+  /// Returns the response message from the interaction, or the gRPC status if one is set on the
+  /// response metadata
+  fn unary_response(
+    &self,
+    response_descriptor: &DescriptorProto,
+    request: Option<&DynamicMessage>
+  ) -> Result<Response<DynamicMessage>, Status> {
+    let response_contents = self.response_for_call();
+    // check for a gRPC status on the response metadata
+    if let Some(status) = grpc_status(&response_contents) {
+      info!("a gRPC status {} is set for the response, returning that", status);
+      Err(status)
+    } else {
+      debug!("Returning response");
+      let message = self.response_message(&response_contents, response_descriptor, request)?;
+      trace!("Sending message {message:?}");
+      let mut response = Response::new(message);
+      if !response_contents.metadata.is_empty() {
+        Self::set_response_metadata(response_contents, &mut response);
+      }
+      Ok(response)
+    }
+  }
+
+  /// Returns the response from the interaction for this call. With a response sequence configured,
+  /// the number of calls to the interaction is used to select the response, otherwise the first
+  /// response is always returned.
+  fn response_for_call(&self) -> MessageContents {
+    let responses = &self.message.response;
+    let sequence = self.message.plugin_config.get("protobuf")
+      .and_then(|config| config.get(RESPONSE_SEQUENCE_CONFIG))
+      .map(json_to_string);
+    let index = match sequence {
+      Some(sequence) if responses.len() > 1 => {
+        let call = self.call_count.fetch_add(1, Ordering::SeqCst);
+        match sequence.to_lowercase().as_str() {
+          "sequential" => call.min(responses.len() - 1),
+          "round-robin" => call % responses.len(),
+          _ => {
+            warn!("'{}' is not a valid response sequence, it must be either 'sequential' or 'round-robin'", sequence);
+            0
+          }
+        }
+      }
+      _ => 0
+    };
+    trace!(index, "Returning response {} of {}", index + 1, responses.len());
+    responses.get(index).cloned().unwrap_or_default()
+  }
+
+  /// Key for the method in the mock server results
+  fn route_key(&self) -> String {
+    format!("{}/{}", self.service_name, self.method_descriptor.name.clone().unwrap_or_else(|| "unknown method".into()))
+  }
+
+  /// Handles a server streaming call. Each of the responses from the interaction is sent as a
+  /// message in the stream. The messages are only created when the stream is polled, so the
+  /// responses are not buffered and the stream will only advance as fast as the client reads it.
+  #[instrument(skip(self, message_descriptor, response_descriptor), fields(route = %self.route_key()))]
+  pub(crate) async fn handle_streaming_message(
+    &self,
+    request: DynamicMessage,
+    message_descriptor: DescriptorProto,
+    response_descriptor: DescriptorProto,
+    request_metadata: MetadataMap
+  ) -> Result<Response<MockResponseStream>, Status> {
+    self.match_request(&request, &message_descriptor, &request_metadata)?;
+
+    let response_contents = self.message.response.first().cloned().unwrap_or_default();
+    if let Some(status) = grpc_status(&response_contents) {
+      info!("a gRPC status {} is set for the response, returning that", status);
+      return Err(status);
+    }
+
+    debug!("Returning a stream of {} response messages", self.message.response.len());
+    let service = self.clone();
+    let stream = stream::iter(self.message.response.clone())
+      .map(move |contents| {
+        let message = service.response_message(&contents, &response_descriptor, Some(&request));
+        trace!("Sending message {message:?}");
+        message
+      });
+    let mut response = Response::new(Box::pin(stream) as MockResponseStream);
+    if !response_contents.metadata.is_empty() {
+      Self::set_response_metadata(response_contents, &mut response);
+    }
+    Ok(response)
+  }
+
+  /// Compares the incoming request to the request from the interaction, and records the result
+  /// with the mock server. Returns an error status if the request did not match.
+  fn match_request(
+    &self,
+    request: &DynamicMessage,
+    message_descriptor: &DescriptorProto,
+    request_metadata: &MetadataMap
+  ) -> Result<(), Status> {
     // 1. Compare the incoming message to the request message from the interaction
-    let mut expected_message_bytes = self.message.request.contents.value().unwrap_or_default();
-    let expected_message = decode_message(&mut expected_message_bytes, &message_descriptor, &self.file_descriptor_set)
+    let plugin_config = self.plugin_config();
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys,
+      &self.message.request.matching_rules.rules_for_category("body").unwrap_or_default(),
+      &plugin_config);
+    let expected_message_bytes = self.message.request.contents.value().unwrap_or_default();
+    let mismatches = self.compare_request_message(expected_message_bytes, request, message_descriptor, &context)
+      .map(|result| self.with_mismatch_snippet(result, request, message_descriptor));
+
+    // 2. Compare any metadata from the incoming message
+    self.record_request_result(mismatches, request_metadata, &plugin_config)
+  }
+
+  /// Compares the messages received from a client streaming call to the expected request
+  /// messages, and records the result with the mock server. In unordered mode
+  /// (`streamOrdering` set to `unordered`), each received message can match any of the expected
+  /// messages that has not already been matched, otherwise they are compared in order.
+  fn match_request_stream(
+    &self,
+    requests: &[DynamicMessage],
+    message_descriptor: &DescriptorProto,
+    request_metadata: &MetadataMap
+  ) -> Result<(), Status> {
+    let plugin_config = self.plugin_config();
+    let expected_messages = self.expected_request_stream()
       .map_err(|err| Status::invalid_argument(err.to_string()))?;
-    let plugin_config = self.pact.plugin_data().iter()
+    let contexts = self.expected_request_stream_rules(expected_messages.len())
+      .map_err(|err| Status::invalid_argument(err.to_string()))?
+      .iter()
+      .map(|rules| CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, rules, &plugin_config))
+      .collect_vec();
+
+    let mismatches = if self.unordered_stream() {
+      trace!("Matching {} request messages in any order", requests.len());
+      self.compare_unordered_messages(&expected_messages, requests, message_descriptor, &contexts)
+    } else {
+      self.compare_ordered_messages(&expected_messages, requests, message_descriptor, &contexts)
+    };
+
+    self.record_request_result(mismatches, request_metadata, &plugin_config)
+  }
+
+  fn compare_ordered_messages(
+    &self,
+    expected_messages: &[Bytes],
+    requests: &[DynamicMessage],
+    message_descriptor: &DescriptorProto,
+    contexts: &[CoreMatchingContext]
+  ) -> anyhow::Result<BodyMatchResult> {
+    let mut mismatches = vec![];
+    for ((expected, context), request) in expected_messages.iter().zip(contexts).zip(requests) {
+      let result = self.compare_request_message(expected.clone(), request, message_descriptor, context)?;
+      mismatches.extend(result.mismatches());
+    }
+    if expected_messages.len() != requests.len() {
+      mismatches.push(BodyMismatch {
+        path: "$".to_string(),
+        expected: None,
+        actual: None,
+        mismatch: format!("Expected {} request messages but received {}", expected_messages.len(), requests.len())
+      });
+    }
+    Ok(body_match_result(mismatches))
+  }
+
+  fn compare_unordered_messages(
+    &self,
+    expected_messages: &[Bytes],
+    requests: &[DynamicMessage],
+    message_descriptor: &DescriptorProto,
+    contexts: &[CoreMatchingContext]
+  ) -> anyhow::Result<BodyMatchResult> {
+    let mut matches = vec![];
+    for (expected, context) in expected_messages.iter().zip(contexts) {
+      let mut row = vec![];
+      for request in requests {
+        let result = self.compare_request_message(expected.clone(), request, message_descriptor, context)?;
+        row.push(result.all_matched());
+      }
+      matches.push(row);
+    }
+
+    let assignment = assign_unordered(&matches, requests.len());
+    let mut mismatches = vec![];
+    for (index, expected) in assignment.iter().enumerate() {
+      if expected.is_none() {
+        mismatches.push(BodyMismatch {
+          path: "$".to_string(),
+          expected: None,
+          actual: None,
+          mismatch: format!("Received request message {} did not match any of the expected messages", index + 1)
+        });
+      }
+    }
+    for index in 0..expected_messages.len() {
+      if !assignment.contains(&Some(index)) {
+        mismatches.push(BodyMismatch {
+          path: "$".to_string(),
+          expected: None,
+          actual: None,
+          mismatch: format!("Expected request message {} was not received", index + 1)
+        });
+      }
+    }
+    Ok(body_match_result(mismatches))
+  }
+
+  /// Compares a single request message to the expected message bytes, applying any field mask
+  fn compare_request_message(
+    &self,
+    mut expected_message_bytes: Bytes,
+    request: &DynamicMessage,
+    message_descriptor: &DescriptorProto,
+    context: &CoreMatchingContext
+  ) -> anyhow::Result<BodyMatchResult> {
+    let expected_message = decode_message(&mut expected_message_bytes, message_descriptor, &self.file_descriptor_set)?;
+    let field_mask = self.message.plugin_config.get("protobuf")
+      .and_then(|config| config.get(FIELD_MASK_CONFIG))
+      .map(field_mask_paths)
+      .unwrap_or_default();
+    let result = compare(message_descriptor, &expected_message, request.proto_fields(), context,
+      &expected_message_bytes, &self.file_descriptor_set)?;
+    let mut request_bytes = BytesMut::new();
+    request.write_to(&mut request_bytes)?;
+    match_serialized_message(result, &request_bytes, context)
+      .map(|result| apply_field_mask(result, &field_mask))
+  }
+
+  /// If enabled (`mismatchSnippet`), adds the actual request message, rendered as a snippet of the
+  /// interaction configuration, to the description of the first mismatch when the request did not match
+  fn with_mismatch_snippet(
+    &self,
+    result: BodyMatchResult,
+    request: &DynamicMessage,
+    message_descriptor: &DescriptorProto
+  ) -> BodyMatchResult {
+    let enabled = self.message.plugin_config.get("protobuf")
+      .and_then(|config| config.get(MISMATCH_SNIPPET_CONFIG))
+      .map(|value| value.as_bool().unwrap_or_else(|| json_to_string(value) == "true"))
+      .unwrap_or(false);
+    if !enabled || result.all_matched() {
+      return result;
+    }
+
+    match request_config_snippet(request.proto_fields(), message_descriptor, &self.file_descriptor_set) {
+      Ok(snippet) => attach_snippet(result, snippet.as_str()),
+      Err(err) => {
+        warn!("Could not render the actual request message as interaction configuration - {}", err);
+        result
+      }
+    }
+  }
+
+  /// The expected request messages for a client streaming call. These are stored Base64 encoded
+  /// in the `requestStream` interaction configuration, otherwise the request from the interaction
+  /// is the only expected message.
+  fn expected_request_stream(&self) -> anyhow::Result<Vec<Bytes>> {
+    match self.message.plugin_config.get("protobuf").and_then(|config| config.get(REQUEST_STREAM_CONFIG)) {
+      Some(Value::Array(messages)) => messages.iter()
+        .map(|message| {
+          let encoded = message.as_str()
+            .ok_or_else(|| anyhow!("Request stream messages must be Base64 encoded strings, got {}", message))?;
+          BASE64.decode(encoded)
+            .map(Bytes::from)
+            .map_err(|err| anyhow!("Request stream message is not Base64 encoded - {}", err))
+        })
+        .collect(),
+      Some(value) => Err(anyhow!("{} must be a list of Base64 encoded messages, got {}", REQUEST_STREAM_CONFIG, value)),
+      None => Ok(vec![ self.message.request.contents.value().unwrap_or_default() ])
+    }
+  }
+
+  /// The matching rules for each of the expected request messages of a client streaming call.
+  /// These are stored in the `requestStreamRules` interaction configuration, otherwise the request
+  /// matching rules from the interaction are used for all the messages.
+  fn expected_request_stream_rules(&self, count: usize) -> anyhow::Result<Vec<MatchingRuleCategory>> {
+    match self.message.plugin_config.get("protobuf").and_then(|config| config.get(REQUEST_STREAM_RULES_CONFIG)) {
+      Some(Value::Array(rules)) if rules.len() == count => rules.iter()
+        .map(|rules| {
+          let mut category = MatchingRuleCategory::empty("body");
+          category.add_rules_from_json(rules)?;
+          Ok(category)
+        })
+        .collect(),
+      Some(value) => Err(anyhow!("{} must be a list with the matching rules of each of the {} request messages, got {}",
+        REQUEST_STREAM_RULES_CONFIG, count, value)),
+      None => {
+        let rules = self.message.request.matching_rules.rules_for_category("body").unwrap_or_default();
+        Ok(vec![ rules; count ])
+      }
+    }
+  }
+
+  fn unordered_stream(&self) -> bool {
+    self.message.plugin_config.get("protobuf")
+      .and_then(|config| config.get(STREAM_ORDERING_CONFIG))
+      .map(|ordering| json_to_string(ordering).eq_ignore_ascii_case("unordered"))
+      .unwrap_or(false)
+  }
+
+  fn plugin_config(&self) -> HashMap<String, PluginInteractionConfig> {
+    let plugin_config: HashMap<_, _> = self.pact.plugin_data().iter()
       .map(|pd| {
         (pd.name.clone(), PluginInteractionConfig {
           pact_configuration: pd.configuration.clone(),
           interaction_configuration: self.message.plugin_config.get(pd.name.as_str()).cloned().unwrap_or_default()
         })
       }).collect();
+    // The mock server only compares requests
+    let plugin_config = with_message_part(&plugin_config, "request");
+    with_field_presence(&plugin_config, &self.file_descriptor_set).unwrap_or_else(|err| {
+      warn!("Could not resolve the field presence of the request message fields - {}", err);
+      plugin_config
+    })
+  }
 
-    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys,
-      &self.message.request.matching_rules.rules_for_category("body").unwrap_or_default(),
-      &plugin_config);
-    let mismatches = compare(&message_descriptor, &expected_message, request.proto_fields(), &context,
-                             &expected_message_bytes, &self.file_descriptor_set);
-
-    // 2. Compare any metadata from the incoming message
+  /// Compares the request metadata, and then records the result with the mock server. Returns an
+  /// error status if the request did not match.
+  fn record_request_result(
+    &self,
+    mismatches: anyhow::Result<BodyMatchResult>,
+    request_metadata: &MetadataMap,
+    plugin_config: &HashMap<String, PluginInteractionConfig>
+  ) -> Result<(), Status> {
     let md_context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys,
       &self.message.request.matching_rules.rules_for_category("metadata").unwrap_or_default(),
-      &plugin_config);
-    let md_mismatches = compare_metadata(&self.message.request.metadata, &request_metadata,
+      plugin_config);
+    let md_mismatches = compare_metadata(&self.message.request.metadata, request_metadata,
       &md_context);
 
     trace!("Comparison result = {:?}", mismatches);
     match (mismatches, md_mismatches) {
       (Ok(result), Ok((md_result, _))) => {
-        {
-          // record the result in the static store
-          let mut guard = MOCK_SERVER_STATE.lock().unwrap();
-          let key = format!("{}/{}", self.service_name, self.method_descriptor.name.clone().unwrap_or_else(|| "unknown method".into()));
-          if let Some((_, results)) = guard.get_mut(self.server_key.as_str()) {
-            let route_results = results.entry(key).or_insert((0, vec![]));
-            trace!(store_length = route_results.1.len(), "Adding result to mock server '{}' static store", self.server_key);
-            route_results.0 += 1;
-            route_results.1.push((result.clone(), md_result.clone()));
-          } else {
-            error!("INTERNAL ERROR: Did not find an entry for '{}' in mock server static store", self.server_key);
-          }
+        let user_agent = request_metadata.get(USER_AGENT_KEY)
+          .and_then(|value| value.to_str().ok())
+          .map(|value| value.to_string());
+        let md_result = md_result.with_user_agent(user_agent);
+        // record the result in the static store
+        if !record_mock_server_result(self.server_key.as_str(), self.route_key().as_str(), result.clone(), md_result.clone()) {
+          error!("INTERNAL ERROR: Did not find an entry for '{}' in mock server static store", self.server_key);
         }
 
         if result.all_matched() && md_result.all_matched() {
           debug!("Request matched OK");
-          let response_contents = self.message.response.first().cloned().unwrap_or_default();
-          // check for a gRPC status on the response metadata
-          if let Some(status) = grpc_status(&response_contents) {
-            info!("a gRPC status {} is set for the response, returning that", status);
-            Err(status)
-          } else {
-            debug!("Returning response");
-            let mut response_bytes = response_contents.contents.value()
-              .unwrap_or_default();
-            trace!("Response message has {} bytes", response_bytes.len());
-            let response_message = decode_message(&mut response_bytes, &response_descriptor, &self.file_descriptor_set)
-              .map_err(|err| {
-                error!("Failed to encode response message - {}", err);
-                Status::invalid_argument(err.to_string())
-              })?;
-            let mut message = DynamicMessage::new(&response_message, &self.file_descriptor_set);
-            self.apply_generators(&mut message, &response_contents).map_err(|err| {
-              error!("Failed to generate response message - {}", err);
-              Status::invalid_argument(err.to_string())
-            })?;
-            trace!("Sending message {message:?}");
-            let mut response = Response::new(message);
-            if !response_contents.metadata.is_empty() {
-              Self::set_response_metadata(response_contents, &mut response);
-            }
-            Ok(response)
-          }
+          Ok(())
         } else {
           error!("Failed to match the request message - {result:?}");
           Err(Status::failed_precondition(format!("Failed to match the request message - {result:?}")))
@@ -134,13 +480,44 @@ impl MockService {
     }
   }
 
-  fn set_response_metadata(response_contents: MessageContents, response: &mut Response<DynamicMessage>) {
+  /// Creates the response message from the response contents, applying any generators and
+  /// setting any fields echoed from the request message
+  fn response_message(
+    &self,
+    response_contents: &MessageContents,
+    response_descriptor: &DescriptorProto,
+    request: Option<&DynamicMessage>
+  ) -> Result<DynamicMessage, Status> {
+    let mut response_bytes = response_contents.contents.value()
+      .unwrap_or_default();
+    trace!("Response message has {} bytes", response_bytes.len());
+    let response_message = decode_message(&mut response_bytes, response_descriptor, &self.file_descriptor_set)
+      .map_err(|err| {
+        error!("Failed to encode response message - {}", err);
+        Status::invalid_argument(err.to_string())
+      })?;
+    let mut message = DynamicMessage::new(&response_message, &self.file_descriptor_set);
+    self.apply_generators(&mut message, response_contents).map_err(|err| {
+      error!("Failed to generate response message - {}", err);
+      Status::invalid_argument(err.to_string())
+    })?;
+    if let Some(request) = request {
+      self.apply_echo_fields(&mut message, request);
+    }
+    Ok(message)
+  }
+
+  fn set_response_metadata<T>(response_contents: MessageContents, response: &mut Response<T>) {
     let md = response.metadata_mut();
     for (key, value) in &response_contents.metadata {
+      let (location, key) = metadata_location(key);
       let key = key.to_lowercase();
       // exclude the content type, because that is a special value added by the Pact framework
       // also exclude the gRPC status, because that is handled separately
-      if key != "content-type" && key != "contenttype" && key != "grpc-status" {
+      // trailers are added by the mock server once the response has been sent
+      // and keys that must be absent
+      if location != MetadataLocation::Trailer && key != "content-type" && key != "contenttype" && key != "grpc-status" &&
+        !is_absent_value(value) {
         match json_to_string(value).parse() {
           Ok(parsed_val) => {
             match md.entry(key.as_str()) {
@@ -176,7 +553,8 @@ impl MockService {
     output_message: &DescriptorProto,
     message: &SynchronousMessage,
     server_key: &str,
-    pact: V4Pact
+    pact: V4Pact,
+    call_count: Arc<AtomicUsize>
   ) -> Self {
     MockService {
       file_descriptor_set: file_descriptor_set.clone(),
@@ -186,7 +564,8 @@ impl MockService {
       output_message: output_message.clone(),
       message: message.clone(),
       server_key: server_key.to_string(),
-      pact
+      pact,
+      call_count
     }
   }
 
@@ -208,6 +587,42 @@ impl MockService {
 
     Ok(())
   }
+
+  /// Sets the fields of the response message configured to be echoed from the request message.
+  /// Fields not set in either message are ignored.
+  fn apply_echo_fields(&self, message: &mut DynamicMessage, request: &DynamicMessage) {
+    let echo_fields = self.message.plugin_config.get("protobuf")
+      .and_then(|config| config.get(ECHO_FIELDS_CONFIG))
+      .and_then(|config| config.as_object().cloned())
+      .unwrap_or_default();
+    let mut request = request.clone();
+    for (response_path, request_path) in echo_fields.iter().sorted_by(|a, b| Ord::cmp(a.0, b.0)) {
+      let paths = DocPath::new(with_root(response_path))
+        .and_then(|response_path| DocPath::new(with_root(json_to_string(request_path).as_str()))
+          .map(|request_path| (response_path, request_path)));
+      match paths {
+        Ok((response_path, request_path)) => match request.fetch_value(&request_path) {
+          Some(value) => {
+            trace!(%response_path, %request_path, "Echoing request field {}", value);
+            if let Err(err) = message.set_value(&response_path, value.data) {
+              warn!("Could not echo request field '{}' into the response - {}", request_path, err);
+            }
+          }
+          None => warn!("Request field '{}' is not set, not echoing it into the response", request_path)
+        }
+        Err(err) => warn!("'{}' is not a valid echo field mapping, ignoring it - {}", response_path, err)
+      }
+    }
+  }
+}
+
+/// Adds the root marker to a path if it does not have one
+fn with_root(path: &str) -> String {
+  if path.starts_with('$') {
+    path.to_string()
+  } else {
+    format!("$.{}", path)
+  }
 }
 
 impl Service<Request<DynamicMessage>> for MockService {
@@ -230,21 +645,174 @@ impl Service<Request<DynamicMessage>> for MockService {
   }
 }
 
+/// Service for server streaming calls, where the responses from the interaction are returned as a
+/// stream of messages
+#[derive(Debug, Clone)]
+pub(crate) struct StreamingMockService(pub(crate) MockService);
+
+impl Service<Request<DynamicMessage>> for StreamingMockService {
+  type Response = Response<MockResponseStream>;
+  type Error = Status;
+  type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+  fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn call(&mut self, req: Request<DynamicMessage>) -> Self::Future {
+    let (request_metadata, _, request) = req.into_parts();
+    let message_descriptor = self.0.input_message.clone();
+    let response_descriptor = self.0.output_message.clone();
+    let service = self.0.clone();
+    Box::pin(async move {
+      service.handle_streaming_message(request, message_descriptor, response_descriptor, request_metadata).await
+    })
+  }
+}
+
+/// Service for client streaming calls, where all the request messages are read from the stream
+/// before being matched
+#[derive(Debug, Clone)]
+pub(crate) struct ClientStreamingMockService(pub(crate) MockService);
+
+impl Service<Request<Streaming<DynamicMessage>>> for ClientStreamingMockService {
+  type Response = Response<DynamicMessage>;
+  type Error = Status;
+  type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+  fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn call(&mut self, req: Request<Streaming<DynamicMessage>>) -> Self::Future {
+    let (request_metadata, _, mut stream) = req.into_parts();
+    let message_descriptor = self.0.input_message.clone();
+    let response_descriptor = self.0.output_message.clone();
+    let service = self.0.clone();
+    Box::pin(async move {
+      let mut requests = vec![];
+      while let Some(message) = stream.message().await? {
+        requests.push(message);
+      }
+      service.handle_client_streaming_message(requests, message_descriptor, response_descriptor, request_metadata).await
+    })
+  }
+}
+
+/// Matches each received message to one of the expected messages, where `matches[e][a]` is true
+/// if received message `a` matches expected message `e`. Each expected message can only be matched
+/// once. Returns the index of the expected message for each received message, or `None` if there
+/// was no expected message left that it matched.
+fn assign_unordered(matches: &[Vec<bool>], received: usize) -> Vec<Option<usize>> {
+  fn try_assign(
+    actual: usize,
+    matches: &[Vec<bool>],
+    visited: &mut [bool],
+    expected_for: &mut [Option<usize>],
+    actual_for: &mut [Option<usize>]
+  ) -> bool {
+    for (expected, row) in matches.iter().enumerate() {
+      if row[actual] && !visited[expected] {
+        visited[expected] = true;
+        let free = match actual_for[expected] {
+          None => true,
+          Some(other) => try_assign(other, matches, visited, expected_for, actual_for)
+        };
+        if free {
+          actual_for[expected] = Some(actual);
+          expected_for[actual] = Some(expected);
+          return true;
+        }
+      }
+    }
+    false
+  }
+
+  let mut expected_for = vec![None; received];
+  let mut actual_for = vec![None; matches.len()];
+  for actual in 0..received {
+    let mut visited = vec![false; matches.len()];
+    try_assign(actual, matches, &mut visited, &mut expected_for, &mut actual_for);
+  }
+  expected_for
+}
+
+/// Adds the snippet of the actual request message to the description of the first mismatch (by path)
+fn attach_snippet(result: BodyMatchResult, snippet: &str) -> BodyMatchResult {
+  let with_snippet = |description: &str| {
+    format!("{}\n\nThe actual request message as interaction configuration:\n{}", description, snippet)
+  };
+  match result {
+    BodyMatchResult::Ok => BodyMatchResult::Ok,
+    BodyMatchResult::BodyTypeMismatch { expected_type, actual_type, message, expected, actual } => {
+      BodyMatchResult::BodyTypeMismatch { expected_type, actual_type, message: with_snippet(&message), expected, actual }
+    }
+    BodyMatchResult::BodyMismatches(mut mismatches) => {
+      let first = mismatches.keys().min().cloned();
+      if let Some(mismatch) = first.and_then(|path| mismatches.get_mut(&path)).and_then(|m| m.first_mut()) {
+        match mismatch {
+          Mismatch::PathMismatch { mismatch, .. } |
+          Mismatch::StatusMismatch { mismatch, .. } |
+          Mismatch::QueryMismatch { mismatch, .. } |
+          Mismatch::HeaderMismatch { mismatch, .. } |
+          Mismatch::BodyTypeMismatch { mismatch, .. } |
+          Mismatch::BodyMismatch { mismatch, .. } |
+          Mismatch::MetadataMismatch { mismatch, .. } => *mismatch = with_snippet(mismatch),
+          Mismatch::MethodMismatch { .. } => {}
+        }
+      }
+      BodyMatchResult::BodyMismatches(mismatches)
+    }
+  }
+}
+
+fn body_match_result(mismatches: Vec<Mismatch>) -> BodyMatchResult {
+  if mismatches.is_empty() {
+    BodyMatchResult::Ok
+  } else {
+    BodyMatchResult::BodyMismatches(mismatches.into_iter()
+      .into_group_map_by(|mismatch| match mismatch {
+        BodyMismatch { path, .. } => path.clone(),
+        _ => "$".to_string()
+      }))
+  }
+}
+
 #[cfg(test)]
 mod tests {
+  use std::sync::{Arc, Mutex};
+
   use base64::Engine;
   use base64::engine::general_purpose::STANDARD as BASE64;
   use bytes::{Bytes, BytesMut};
   use expectest::prelude::*;
+  use futures::StreamExt;
+  use maplit::hashmap;
+  use pact_matching::{BodyMatchResult, Mismatch};
   use pact_models::v4::pact::V4Pact;
   use prost::Message;
-  use prost_types::FileDescriptorSet;
-  use serde_json::json;
+  use prost_types::{
+    DescriptorProto,
+    FieldDescriptorProto,
+    FileDescriptorProto,
+    FileDescriptorSet,
+    MethodDescriptorProto
+  };
+  use prost_types::field_descriptor_proto::{Label, Type};
+  use serde_json::{json, Value};
+  use tokio::sync::oneshot::channel;
   use tonic::metadata::MetadataMap;
+  use tracing::instrument::WithSubscriber;
+  use tracing::span::{Attributes, Id};
+  use tracing::Subscriber;
+  use tracing_subscriber::layer::{Context, SubscriberExt};
+  use tracing_subscriber::Layer;
 
   use crate::dynamic_message::DynamicMessage;
-  use crate::message_decoder::decode_message;
-  use crate::mock_service::MockService;
+  use crate::message_decoder::{decode_message, ProtobufFieldData};
+  use crate::mock_server::{register_mock_server, remove_mock_server};
+  use crate::mock_server::tests::STATE_LOCK;
+  use crate::mock_service::{attach_snippet, MockService};
   use crate::protobuf::tests::DESCRIPTOR_BYTES;
 
   #[test_log::test(tokio::test)]
@@ -364,7 +932,8 @@ mod tests {
       input_message: input_message.clone(),
       output_message: output_message.clone(),
       server_key: "1234".to_string(),
-      pact
+      pact,
+      call_count: Default::default()
     };
     let response = mock_service.handle_message(request,
       input_message.clone(), output_message.clone(),
@@ -375,4 +944,657 @@ mod tests {
     let area = &response_fields[0];
     expect!(area.data.to_string()).to_not(be_equal_to("12"));
   }
+
+  /// Records the name and fields of each span that is created
+  struct SpanRecorder(Arc<Mutex<Vec<String>>>);
+
+  impl<S: Subscriber> Layer<S> for SpanRecorder {
+    fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+      self.0.lock().unwrap().push(format!("{} {:?}", attrs.metadata().name(), attrs.values()));
+    }
+  }
+
+  #[tokio::test]
+  async fn handle_message_creates_a_span_for_the_request() {
+    let bytes = BASE64.decode(DESCRIPTOR_BYTES).unwrap();
+    let file_descriptor_set = FileDescriptorSet::decode(bytes.as_slice()).unwrap();
+    let ac_desc = file_descriptor_set.file.iter()
+      .find(|ds| ds.name.clone().unwrap_or_default() == "area_calculator.proto")
+      .unwrap();
+    let method = ac_desc.service.iter()
+      .find(|sd| sd.name.clone().unwrap_or_default() == "Calculator")
+      .and_then(|sd| sd.method.iter().find(|md| md.name.clone().unwrap_or_default() == "calculateOne"))
+      .unwrap();
+    let input_message = ac_desc.message_type.iter()
+      .find(|md| md.name.clone().unwrap_or_default() == "ShapeMessage")
+      .unwrap();
+    let output_message = ac_desc.message_type.iter()
+      .find(|md| md.name.clone().unwrap_or_default() == "AreaResponse")
+      .unwrap();
+
+    let pact_json = json!({
+      "interactions": [
+        {
+          "description": "calculate rectangle area request",
+          "key": "c7fbe3ee",
+          "pluginConfiguration": {
+            "protobuf": {
+              "descriptorKey": "d4147b5793ad1996e476382bd79499a5",
+              "service": "Calculator/calculateOne"
+            }
+          },
+          "request": {
+            "contents": {
+              "content": "EgoNAABAQBUAAIBA",
+              "contentType": "application/protobuf; message=ShapeMessage",
+              "contentTypeHint": "BINARY",
+              "encoded": "base64"
+            }
+          },
+          "response": [
+            {
+              "contents": {
+                "content": "CgQAAEBB",
+                "contentType": "application/protobuf; message=AreaResponse",
+                "contentTypeHint": "BINARY",
+                "encoded": "base64"
+              }
+            }
+          ],
+          "transport": "grpc",
+          "type": "Synchronous/Messages"
+        }
+      ],
+      "metadata": {
+        "pactSpecification": {
+          "version": "4.0"
+        }
+      }
+    });
+    let pact = V4Pact::pact_from_json(&pact_json, "<>").unwrap();
+    let message = pact.interactions.first().unwrap().as_v4_sync_message().unwrap();
+    let mock_service = MockService {
+      file_descriptor_set: file_descriptor_set.clone(),
+      service_name: "Calculator".to_string(),
+      message,
+      method_descriptor: method.clone(),
+      input_message: input_message.clone(),
+      output_message: output_message.clone(),
+      server_key: "span-test".to_string(),
+      pact,
+      call_count: Default::default()
+    };
+
+    let bytes = BASE64.decode("EgoNAABAQBUAAIBA").unwrap();
+    let mut bytes = BytesMut::from(bytes.as_slice());
+    let fields = decode_message(&mut bytes, input_message, &file_descriptor_set).unwrap();
+    let request = DynamicMessage::new(fields.as_slice(), &file_descriptor_set);
+
+    let spans = Arc::new(Mutex::new(vec![]));
+    let subscriber = tracing_subscriber::registry().with(SpanRecorder(spans.clone()));
+    let response = mock_service.handle_message(request, input_message.clone(), output_message.clone(),
+      MetadataMap::default())
+      .with_subscriber(subscriber)
+      .await;
+    expect!(response.is_ok()).to(be_true());
+
+    let spans = spans.lock().unwrap().clone();
+    expect!(spans.iter().any(|span| span.starts_with("handle_message") && span.contains("Calculator/calculateOne")))
+      .to(be_true());
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn handle_streaming_message_only_creates_response_messages_as_the_stream_is_read() {
+    let bytes = BASE64.decode(DESCRIPTOR_BYTES).unwrap();
+    let file_descriptor_set = FileDescriptorSet::decode(bytes.as_slice()).unwrap();
+    let ac_desc = file_descriptor_set.file.iter()
+      .find(|ds| ds.name.clone().unwrap_or_default() == "area_calculator.proto")
+      .unwrap();
+    let method = ac_desc.service.iter()
+      .find(|sd| sd.name.clone().unwrap_or_default() == "Calculator")
+      .and_then(|sd| sd.method.iter().find(|md| md.name.clone().unwrap_or_default() == "calculateOne"))
+      .unwrap();
+    let input_message = ac_desc.message_type.iter()
+      .find(|md| md.name.clone().unwrap_or_default() == "ShapeMessage")
+      .unwrap();
+    let output_message = ac_desc.message_type.iter()
+      .find(|md| md.name.clone().unwrap_or_default() == "AreaResponse")
+      .unwrap();
+
+    // A large number of responses, with the last one not being a valid message. If the responses were
+    // all created up front, the call would fail.
+    let response = |content: &str| json!({
+      "contents": {
+        "content": content,
+        "contentType": "application/protobuf; message=AreaResponse",
+        "contentTypeHint": "BINARY",
+        "encoded": "base64"
+      }
+    });
+    let mut responses = vec![response("CgQAAEBBEgoyMDAwLTAxLTAx"); 10000];
+    responses.push(response("/////w=="));
+    let pact_json = json!({
+      "interactions": [
+        {
+          "description": "calculate rectangle area request",
+          "key": "c7fbe3ee",
+          "pluginConfiguration": {
+            "protobuf": {
+              "descriptorKey": "d4147b5793ad1996e476382bd79499a5",
+              "service": "Calculator/calculateOne"
+            }
+          },
+          "request": {
+            "contents": {
+              "content": "EgoNAABAQBUAAIBA",
+              "contentType": "application/protobuf; message=ShapeMessage",
+              "contentTypeHint": "BINARY",
+              "encoded": "base64"
+            }
+          },
+          "response": Value::Array(responses),
+          "transport": "grpc",
+          "type": "Synchronous/Messages"
+        }
+      ],
+      "metadata": {
+        "pactSpecification": {
+          "version": "4.0"
+        }
+      }
+    });
+    let pact = V4Pact::pact_from_json(&pact_json, "<>").unwrap();
+    let message = pact.interactions.first().unwrap().as_v4_sync_message().unwrap();
+
+    let bytes = BASE64.decode("EgoNAABAQBUAAIBA").unwrap();
+    let mut bytes2 = BytesMut::from(bytes.as_slice());
+    let fields = decode_message(&mut bytes2, input_message, &file_descriptor_set).unwrap();
+    let request = DynamicMessage::new(fields.as_slice(), &file_descriptor_set);
+
+    let mock_service = MockService {
+      file_descriptor_set: file_descriptor_set.clone(),
+      service_name: "Calculator".to_string(),
+      message,
+      method_descriptor: method.clone(),
+      input_message: input_message.clone(),
+      output_message: output_message.clone(),
+      server_key: "1234".to_string(),
+      pact,
+      call_count: Default::default()
+    };
+    let response = mock_service.handle_streaming_message(request,
+      input_message.clone(), output_message.clone(),
+      MetadataMap::default()
+    ).await.unwrap();
+
+    // Read the stream slowly, like a client applying flow control
+    let mut stream = response.into_inner();
+    let mut count = 0;
+    while let Some(message) = stream.next().await {
+      if count < 10000 {
+        expect!(message.is_ok()).to(be_true());
+      } else {
+        expect!(message.is_err()).to(be_true());
+      }
+      count += 1;
+      if count % 1000 == 0 {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+      }
+    }
+    expect!(count).to(be_equal_to(10001));
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn handle_client_streaming_message_with_unordered_messages() {
+    let bytes = BASE64.decode(DESCRIPTOR_BYTES).unwrap();
+    let file_descriptor_set = FileDescriptorSet::decode(bytes.as_slice()).unwrap();
+    let ac_desc = file_descriptor_set.file.iter()
+      .find(|ds| ds.name.clone().unwrap_or_default() == "area_calculator.proto")
+      .unwrap();
+    let method = ac_desc.service.iter()
+      .find(|sd| sd.name.clone().unwrap_or_default() == "Calculator")
+      .and_then(|sd| sd.method.iter().find(|md| md.name.clone().unwrap_or_default() == "calculateOne"))
+      .unwrap();
+    let input_message = ac_desc.message_type.iter()
+      .find(|md| md.name.clone().unwrap_or_default() == "ShapeMessage")
+      .unwrap();
+    let output_message = ac_desc.message_type.iter()
+      .find(|md| md.name.clone().unwrap_or_default() == "AreaResponse")
+      .unwrap();
+
+    // rectangle 3 x 4 and a square with an edge length of 5
+    let rectangle = "EgoNAABAQBUAAIBA";
+    let square = "CgUNAACgQA==";
+    let mock_service = |ordering: &str| {
+      let pact_json = json!({
+        "interactions": [
+          {
+            "description": "calculate areas request",
+            "key": "c7fbe3ee",
+            "pluginConfiguration": {
+              "protobuf": {
+                "descriptorKey": "d4147b5793ad1996e476382bd79499a5",
+                "service": "Calculator/calculateOne",
+                "requestStream": [ rectangle, square ],
+                "streamOrdering": ordering
+              }
+            },
+            "request": {
+              "contents": {
+                "content": rectangle,
+                "contentType": "application/protobuf; message=ShapeMessage",
+                "contentTypeHint": "BINARY",
+                "encoded": "base64"
+              }
+            },
+            "response": [
+              {
+                "contents": {
+                  "content": "CgQAAEBBEgoyMDAwLTAxLTAx",
+                  "contentType": "application/protobuf; message=AreaResponse",
+                  "contentTypeHint": "BINARY",
+                  "encoded": "base64"
+                }
+              }
+            ],
+            "transport": "grpc",
+            "type": "Synchronous/Messages"
+          }
+        ],
+        "metadata": {
+          "pactSpecification": {
+            "version": "4.0"
+          }
+        }
+      });
+      let pact = V4Pact::pact_from_json(&pact_json, "<>").unwrap();
+      let message = pact.interactions.first().unwrap().as_v4_sync_message().unwrap();
+      MockService {
+        file_descriptor_set: file_descriptor_set.clone(),
+        service_name: "Calculator".to_string(),
+        message,
+        method_descriptor: method.clone(),
+        input_message: input_message.clone(),
+        output_message: output_message.clone(),
+        server_key: "1234".to_string(),
+        pact,
+        call_count: Default::default()
+      }
+    };
+    let request = |content: &str| {
+      let bytes = BASE64.decode(content).unwrap();
+      let mut bytes = BytesMut::from(bytes.as_slice());
+      let fields = decode_message(&mut bytes, input_message, &file_descriptor_set).unwrap();
+      DynamicMessage::new(fields.as_slice(), &file_descriptor_set)
+    };
+
+    // Messages received out of order
+    let requests = vec![ request(square), request(rectangle) ];
+
+    let response = mock_service("unordered").handle_client_streaming_message(requests.clone(),
+      input_message.clone(), output_message.clone(), MetadataMap::default()).await;
+    expect!(response.is_ok()).to(be_true());
+
+    let response = mock_service("ordered").handle_client_streaming_message(requests,
+      input_message.clone(), output_message.clone(), MetadataMap::default()).await;
+    expect!(response.is_err()).to(be_true());
+
+    let response = mock_service("unordered").handle_client_streaming_message(vec![ request(square), request(square) ],
+      input_message.clone(), output_message.clone(), MetadataMap::default()).await;
+    expect!(response.is_err()).to(be_true());
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn handle_client_streaming_message_applies_the_matching_rules_of_each_message() {
+    let bytes = BASE64.decode(DESCRIPTOR_BYTES).unwrap();
+    let file_descriptor_set = FileDescriptorSet::decode(bytes.as_slice()).unwrap();
+    let ac_desc = file_descriptor_set.file.iter()
+      .find(|ds| ds.name.clone().unwrap_or_default() == "area_calculator.proto")
+      .unwrap();
+    let method = ac_desc.service.iter()
+      .find(|sd| sd.name.clone().unwrap_or_default() == "Calculator")
+      .and_then(|sd| sd.method.iter().find(|md| md.name.clone().unwrap_or_default() == "calculateOne"))
+      .unwrap();
+    let input_message = ac_desc.message_type.iter()
+      .find(|md| md.name.clone().unwrap_or_default() == "ShapeMessage")
+      .unwrap();
+    let output_message = ac_desc.message_type.iter()
+      .find(|md| md.name.clone().unwrap_or_default() == "AreaResponse")
+      .unwrap();
+
+    // squares with an edge length of 5 and 7
+    let square_5 = "CgUNAACgQA==";
+    let square_7 = "CgUNAADgQA==";
+    // Only the first expected message has a type matcher on the edge length
+    let pact_json = json!({
+      "interactions": [
+        {
+          "description": "calculate areas request",
+          "key": "c7fbe3ee",
+          "pluginConfiguration": {
+            "protobuf": {
+              "descriptorKey": "d4147b5793ad1996e476382bd79499a5",
+              "service": "Calculator/calculateOne",
+              "requestStream": [ square_5, square_5 ],
+              "requestStreamRules": [
+                { "$.square.edge_length": { "combine": "AND", "matchers": [ { "match": "type" } ] } },
+                {}
+              ]
+            }
+          },
+          "request": {
+            "contents": {
+              "content": square_5,
+              "contentType": "application/protobuf; message=ShapeMessage",
+              "contentTypeHint": "BINARY",
+              "encoded": "base64"
+            },
+            "matchingRules": {
+              "body": {
+                "$.square.edge_length": { "combine": "AND", "matchers": [ { "match": "type" } ] }
+              }
+            }
+          },
+          "response": [
+            {
+              "contents": {
+                "content": "CgQAAEBBEgoyMDAwLTAxLTAx",
+                "contentType": "application/protobuf; message=AreaResponse",
+                "contentTypeHint": "BINARY",
+                "encoded": "base64"
+              }
+            }
+          ],
+          "transport": "grpc",
+          "type": "Synchronous/Messages"
+        }
+      ],
+      "metadata": {
+        "pactSpecification": {
+          "version": "4.0"
+        }
+      }
+    });
+    let pact = V4Pact::pact_from_json(&pact_json, "<>").unwrap();
+    let message = pact.interactions.first().unwrap().as_v4_sync_message().unwrap();
+    let mock_service = MockService {
+      file_descriptor_set: file_descriptor_set.clone(),
+      service_name: "Calculator".to_string(),
+      message,
+      method_descriptor: method.clone(),
+      input_message: input_message.clone(),
+      output_message: output_message.clone(),
+      server_key: "1234".to_string(),
+      pact,
+      call_count: Default::default()
+    };
+    let request = |content: &str| {
+      let bytes = BASE64.decode(content).unwrap();
+      let mut bytes = BytesMut::from(bytes.as_slice());
+      let fields = decode_message(&mut bytes, input_message, &file_descriptor_set).unwrap();
+      DynamicMessage::new(fields.as_slice(), &file_descriptor_set)
+    };
+
+    let response = mock_service.handle_client_streaming_message(vec![ request(square_7), request(square_5) ],
+      input_message.clone(), output_message.clone(), MetadataMap::default()).await;
+    expect!(response.is_ok()).to(be_true());
+
+    let response = mock_service.handle_client_streaming_message(vec![ request(square_5), request(square_7) ],
+      input_message.clone(), output_message.clone(), MetadataMap::default()).await;
+    expect!(response.is_err()).to(be_true());
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn handle_message_returns_the_responses_in_sequence() {
+    let _lock = STATE_LOCK.lock().await;
+    let bytes = BASE64.decode(DESCRIPTOR_BYTES).unwrap();
+    let file_descriptor_set = FileDescriptorSet::decode(bytes.as_slice()).unwrap();
+    let ac_desc = file_descriptor_set.file.iter()
+      .find(|ds| ds.name.clone().unwrap_or_default() == "area_calculator.proto")
+      .unwrap();
+    let method = ac_desc.service.iter()
+      .find(|sd| sd.name.clone().unwrap_or_default() == "Calculator")
+      .and_then(|sd| sd.method.iter().find(|md| md.name.clone().unwrap_or_default() == "calculateOne"))
+      .unwrap();
+    let input_message = ac_desc.message_type.iter()
+      .find(|md| md.name.clone().unwrap_or_default() == "ShapeMessage")
+      .unwrap();
+    let output_message = ac_desc.message_type.iter()
+      .find(|md| md.name.clone().unwrap_or_default() == "AreaResponse")
+      .unwrap();
+
+    let response = |content: &str| json!({
+      "contents": {
+        "content": content,
+        "contentType": "application/protobuf; message=AreaResponse",
+        "contentTypeHint": "BINARY",
+        "encoded": "base64"
+      }
+    });
+    let mock_service = |sequence: &str, server_key: &str| {
+      let pact_json = json!({
+        "interactions": [
+          {
+            "description": "calculate rectangle area request",
+            "key": "c7fbe3ee",
+            "pluginConfiguration": {
+              "protobuf": {
+                "descriptorKey": "d4147b5793ad1996e476382bd79499a5",
+                "service": "Calculator/calculateOne",
+                "responseSequence": sequence
+              }
+            },
+            "request": {
+              "contents": {
+                "content": "EgoNAABAQBUAAIBA",
+                "contentType": "application/protobuf; message=ShapeMessage",
+                "contentTypeHint": "BINARY",
+                "encoded": "base64"
+              }
+            },
+            // area values of 12 and 16
+            "response": [ response("CgQAAEBB"), response("CgQAAIBB") ],
+            "transport": "grpc",
+            "type": "Synchronous/Messages"
+          }
+        ],
+        "metadata": {
+          "pactSpecification": {
+            "version": "4.0"
+          }
+        }
+      });
+      let pact = V4Pact::pact_from_json(&pact_json, "<>").unwrap();
+      let message = pact.interactions.first().unwrap().as_v4_sync_message().unwrap();
+      let (snd, _) = channel::<()>();
+      register_mock_server(server_key, snd, hashmap!{ "Calculator/calculateOne".to_string() => (0, vec![]) }, None);
+      MockService {
+        file_descriptor_set: file_descriptor_set.clone(),
+        service_name: "Calculator".to_string(),
+        message,
+        method_descriptor: method.clone(),
+        input_message: input_message.clone(),
+        output_message: output_message.clone(),
+        server_key: server_key.to_string(),
+        pact,
+        call_count: Default::default()
+      }
+    };
+    let request = || {
+      let bytes = BASE64.decode("EgoNAABAQBUAAIBA").unwrap();
+      let mut bytes = BytesMut::from(bytes.as_slice());
+      let fields = decode_message(&mut bytes, input_message, &file_descriptor_set).unwrap();
+      DynamicMessage::new(fields.as_slice(), &file_descriptor_set)
+    };
+    let call = |service: MockService| {
+      let request = request();
+      async move {
+        let response = service.handle_message(request, service.input_message.clone(),
+          service.output_message.clone(), MetadataMap::default()).await.unwrap();
+        response.into_inner().proto_fields()[0].data.to_string()
+      }
+    };
+
+    let service = mock_service("sequential", "response-sequence-test");
+    expect!(call(service.clone()).await).to(be_equal_to("12"));
+    expect!(call(service.clone()).await).to(be_equal_to("16"));
+    expect!(call(service.clone()).await).to(be_equal_to("16"));
+    remove_mock_server("response-sequence-test");
+
+    let service = mock_service("round-robin", "response-round-robin-test");
+    expect!(call(service.clone()).await).to(be_equal_to("12"));
+    expect!(call(service.clone()).await).to(be_equal_to("16"));
+    expect!(call(service.clone()).await).to(be_equal_to("12"));
+    remove_mock_server("response-round-robin-test");
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn handle_message_echoes_the_configured_request_fields_into_the_response() {
+    let string_field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(Type::String as i32),
+      label: Some(Label::Optional as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let input_message = DescriptorProto {
+      name: Some("EchoRequest".to_string()),
+      field: vec![ string_field("id", 1) ],
+      .. DescriptorProto::default()
+    };
+    let output_message = DescriptorProto {
+      name: Some("EchoResponse".to_string()),
+      field: vec![ string_field("id", 1), string_field("message", 2) ],
+      .. DescriptorProto::default()
+    };
+    let method = MethodDescriptorProto {
+      name: Some("echo".to_string()),
+      input_type: Some(".echo.EchoRequest".to_string()),
+      output_type: Some(".echo.EchoResponse".to_string()),
+      .. MethodDescriptorProto::default()
+    };
+    let file_descriptor_set = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("echo.proto".to_string()),
+          package: Some("echo".to_string()),
+          syntax: Some("proto3".to_string()),
+          message_type: vec![ input_message.clone(), output_message.clone() ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+
+    let pact_json = json!({
+      "interactions": [
+        {
+          "description": "echo request",
+          "key": "5d5b3f0e",
+          "pluginConfiguration": {
+            "protobuf": {
+              "service": "Echo/echo",
+              "echoFields": {
+                "id": "$.id"
+              }
+            }
+          },
+          "request": {
+            "contents": {
+              // id = "abc"
+              "content": "CgNhYmM=",
+              "contentType": "application/protobuf; message=EchoRequest",
+              "contentTypeHint": "BINARY",
+              "encoded": "base64"
+            },
+            "matchingRules": {
+              "body": {
+                "$.id": {
+                  "combine": "AND",
+                  "matchers": [ { "match": "type" } ]
+                }
+              }
+            }
+          },
+          "response": [
+            {
+              "contents": {
+                // id = "none", message = "hello"
+                "content": "CgRub25lEgVoZWxsbw==",
+                "contentType": "application/protobuf; message=EchoResponse",
+                "contentTypeHint": "BINARY",
+                "encoded": "base64"
+              }
+            }
+          ],
+          "transport": "grpc",
+          "type": "Synchronous/Messages"
+        }
+      ],
+      "metadata": {
+        "pactSpecification": {
+          "version": "4.0"
+        }
+      }
+    });
+    let pact = V4Pact::pact_from_json(&pact_json, "<>").unwrap();
+    let message = pact.interactions.first().unwrap().as_v4_sync_message().unwrap();
+
+    // id = "ABC-123"
+    let bytes = BASE64.decode("CgdBQkMtMTIz").unwrap();
+    let mut bytes = BytesMut::from(bytes.as_slice());
+    let fields = decode_message(&mut bytes, &input_message, &file_descriptor_set).unwrap();
+    let request = DynamicMessage::new(fields.as_slice(), &file_descriptor_set);
+
+    let mock_service = MockService {
+      file_descriptor_set: file_descriptor_set.clone(),
+      service_name: "Echo".to_string(),
+      message,
+      method_descriptor: method,
+      input_message: input_message.clone(),
+      output_message: output_message.clone(),
+      server_key: "echo-fields-test".to_string(),
+      pact,
+      call_count: Default::default()
+    };
+    let response = mock_service.handle_message(request, input_message, output_message,
+      MetadataMap::default()).await.unwrap();
+    let response_fields = response.into_inner().proto_fields().to_vec();
+    expect!(response_fields[0].data.clone()).to(be_equal_to(ProtobufFieldData::String("ABC-123".to_string())));
+    expect!(response_fields[1].data.clone()).to(be_equal_to(ProtobufFieldData::String("hello".to_string())));
+  }
+
+  #[test]
+  fn attach_snippet_adds_the_snippet_to_the_first_mismatch() {
+    let result = BodyMatchResult::BodyMismatches(hashmap!{
+      "$.name".to_string() => vec![
+        Mismatch::BodyMismatch {
+          path: "$.name".to_string(),
+          expected: None,
+          actual: None,
+          mismatch: "Expected 'a' but got 'b'".to_string()
+        }
+      ],
+      "$.value".to_string() => vec![
+        Mismatch::BodyMismatch {
+          path: "$.value".to_string(),
+          expected: None,
+          actual: None,
+          mismatch: "Expected 1 but got 2".to_string()
+        }
+      ]
+    });
+
+    let result = attach_snippet(result, "{ \"request\": { \"name\": \"b\" } }");
+    let mismatches = result.mismatches();
+    expect!(mismatches.len()).to(be_equal_to(2));
+    let name_mismatch = mismatches.iter()
+      .find(|m| matches!(m, Mismatch::BodyMismatch { path, .. } if path == "$.name"))
+      .unwrap();
+    expect!(name_mismatch.description()).to(be_equal_to(
+      "$.name -> Expected 'a' but got 'b'\n\nThe actual request message as interaction configuration:\n{ \"request\": { \"name\": \"b\" } }".to_string()));
+    let value_mismatch = mismatches.iter()
+      .find(|m| matches!(m, Mismatch::BodyMismatch { path, .. } if path == "$.value"))
+      .unwrap();
+    expect!(value_mismatch.description()).to(be_equal_to("$.value -> Expected 1 but got 2".to_string()));
+  }
 }
@@ -24,8 +24,16 @@ use pact_plugin_driver::proto::{
 };
 use pact_plugin_driver::proto::body::ContentTypeHint;
 use pact_plugin_driver::proto::interaction_response::MarkupType;
-use pact_plugin_driver::utils::{proto_value_to_json, proto_value_to_string, to_proto_struct};
-use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto, ServiceDescriptorProto, Struct};
+use pact_plugin_driver::utils::{proto_struct_to_json, proto_struct_to_map, proto_value_to_json, proto_value_to_string, to_proto_struct, to_proto_value};
+use prost::Message;
+use prost_types::{
+  DescriptorProto,
+  FieldDescriptorProto,
+  FileDescriptorProto,
+  FileDescriptorSet,
+  ServiceDescriptorProto,
+  Struct
+};
 use prost_types::field_descriptor_proto::Type;
 use prost_types::value::Kind;
 use serde_json::{json, Value};
@@ -34,10 +42,62 @@ use tokio::io::AsyncReadExt;
 use tracing::{debug, error, instrument, trace, warn};
 use tracing_core::LevelFilter;
 
-use crate::message_builder::{MessageBuilder, MessageFieldValue, MessageFieldValueType, RType};
-use crate::metadata::{MessageMetadata, process_metadata};
+use crate::matching::{
+  ANY_TYPES_CONFIG,
+  BYTES_VALUE_TYPES_CONFIG,
+  CUSTOM_MATCHERS_CONFIG,
+  DEFAULT_MATCHER_CONFIG,
+  EACH_EQUALS_CONFIG,
+  ENUM_EXCLUDES_CONFIG,
+  ENUM_INCLUDES_CONFIG,
+  expand_encoding_matchers,
+  FIELD_MASK_CONFIG,
+  FIELD_RELATIONS_CONFIG,
+  FILTERED_MATCHERS_CONFIG,
+  IGNORE_CASE_CONFIG,
+  LENGTH_FIELDS_CONFIG,
+  JSON_REGEX_CONFIG,
+  NUMBER_LOCALES_CONFIG,
+  PRESENCE_GROUPS_CONFIG,
+  REDACTION_OPTION_CONFIG,
+  SCHEMA_ONLY_CONFIG,
+  SERIALIZED_REGEX_CONFIG,
+  SERIALIZED_SIZE_CONFIG,
+  STREAMING_THRESHOLD_CONFIG,
+  SUBSEQUENCE_CONFIG,
+  TREAT_DEFAULTS_AS_PRESENT_CONFIG,
+  UNKNOWN_ENUM_VALUES_CONFIG,
+  WARN_ON_UNEXPECTED_CONFIG
+};
+use crate::message_builder::{MessageBuilder, MessageFieldValue, MessageFieldValueType, NumberTypes, RType};
+use crate::message_decoder::{
+  EXAMPLE_OPTION_CONFIG,
+  EXAMPLE_VALUES_CONFIG,
+  FILL_DEFAULTS_CONFIG,
+  GENERATOR_SEED_CONFIG,
+  VALIDATION_OPTION_CONFIG,
+  WRAPPER_NULLS_CONFIG
+};
+use crate::metadata::{
+  GRPC_STATUS_DETAILS,
+  GRPC_STATUS_DETAILS_CONFIG,
+  add_user_agent_matcher,
+  MessageMetadata,
+  process_metadata
+};
+use crate::mock_server::{FAULT_CONFIG, MISMATCH_SNIPPET_CONFIG};
+use crate::mock_service::{
+  ECHO_FIELDS_CONFIG,
+  REQUEST_STREAM_CONFIG,
+  REQUEST_STREAM_RULES_CONFIG,
+  RESPONSE_SEQUENCE_CONFIG,
+  STREAM_ORDERING_CONFIG
+};
 use crate::protoc::Protoc;
+use crate::prototext::prototext_to_binary;
+use crate::verification::{DEADLINE_CONFIG, MESSAGE_FRAMING_CONFIG, METHOD_OPTIONS_CONFIG, TRAILERS_ONLY_CONFIG};
 use crate::utils::{
+  descriptors_checksum,
   find_enum_value_by_name,
   find_enum_value_by_name_in_message,
   find_message_type_in_file_descriptors,
@@ -45,9 +105,51 @@ use crate::utils::{
   is_map_field,
   is_repeated_field,
   last_name,
-  prost_string
+  prost_string,
+  proto_value_to_map
 };
 
+/// Interaction configuration key for the fully qualified request message type of a service method
+pub const REQUEST_TYPE_CONFIG: &str = "requestType";
+
+/// Interaction configuration key for the fully qualified response message type of a service method
+pub const RESPONSE_TYPE_CONFIG: &str = "responseType";
+
+/// Fully qualified name of the `google.protobuf.Empty` message type. Service methods that return
+/// it do not require the response to be configured.
+pub const EMPTY_MESSAGE_TYPE: &str = ".google.protobuf.Empty";
+
+/// Number of times a proto file that changes while it is being compiled will be compiled
+const MAX_COMPILE_ATTEMPTS: usize = 3;
+
+/// Compiles the proto file, returning the file descriptors along with the contents of the proto
+/// file they were compiled from. The proto file is always compiled again, so any changes to it
+/// between calls are picked up. If the file changes while protoc is running (i.e. it is being
+/// saved in watch mode), it is compiled again so the contents always match the descriptors.
+async fn compile_proto_file(
+  proto_file: &Path,
+  protoc: &Protoc
+) -> anyhow::Result<(FileDescriptorSet, md5::Digest, Vec<u8>, String)> {
+  let mut file_contents = read_proto_file(proto_file).await?;
+  for attempt in 1..=MAX_COMPILE_ATTEMPTS {
+    let (descriptors, digest, descriptor_bytes) = protoc.parse_proto_file(proto_file).await?;
+    let current_contents = read_proto_file(proto_file).await?;
+    if current_contents == file_contents {
+      return Ok((descriptors, digest, descriptor_bytes, file_contents));
+    }
+    debug!("Proto file changed while it was being compiled (attempt {}), compiling it again", attempt);
+    file_contents = current_contents;
+  }
+  Err(anyhow!("Proto file '{}' kept changing while it was being compiled", proto_file.to_string_lossy()))
+}
+
+async fn read_proto_file(proto_file: &Path) -> anyhow::Result<String> {
+  let mut f = File::open(proto_file).await?;
+  let mut file_contents = String::new();
+  f.read_to_string(&mut file_contents).await?;
+  Ok(file_contents)
+}
+
 /// Process the provided protobuf file and configure the interaction
 pub(crate) async fn process_proto(
   proto_file: String,
@@ -58,7 +160,7 @@ pub(crate) async fn process_proto(
   trace!(">> process_proto({proto_file}, {config:?})");
 
   let proto_file = Path::new(proto_file.as_str());
-  let (descriptors, digest, descriptor_bytes) = protoc.parse_proto_file(proto_file).await?;
+  let (descriptors, digest, descriptor_bytes, file_contents) = compile_proto_file(proto_file, protoc).await?;
   debug!("Parsed proto file OK, file descriptors = {:?}", descriptors.file.iter().map(|file| file.name.as_ref()).collect_vec());
   trace!("Descriptor bytes {:?}", descriptor_bytes.as_slice());
 
@@ -101,17 +203,14 @@ pub(crate) async fn process_proto(
     interactions.extend_from_slice(&response_part);
   }
 
-  let mut f = File::open(proto_file).await?;
-  let mut file_contents = String::new();
-  f.read_to_string(&mut file_contents).await?;
-
   let digest_str = format!("{:x}", digest);
   let plugin_config = PluginConfiguration {
     interaction_configuration: None,
     pact_configuration: Some(to_proto_struct(&hashmap!{
       digest_str => json!({
         "protoFile": file_contents,
-        "protoDescriptors": descriptor_encoded
+        "protoDescriptors": descriptor_encoded,
+        "protoDescriptorsChecksum": descriptors_checksum(&descriptor_bytes)
       })
     }))
   };
@@ -137,22 +236,42 @@ fn configure_protobuf_service(
     .ok_or_else(|| anyhow!("Did not find a descriptor for service '{}'", service_name))?;
   construct_protobuf_interaction_for_service(service_descriptor, config, service,
     proc_name, all_descriptors, descriptor)
-    .map(|(request, response)| {
+    .and_then(|(request, response)| {
+      let mut interaction_config = with_interaction_options(hashmap! {
+        "service".to_string() => Value::String(
+          service_name.split_once(':').map(|(s, _)| s).unwrap_or(service_name).to_string()
+        ),
+        "descriptorKey".to_string() => Value::String(descriptor_hash.to_string())
+      }, config)?;
+      // the message types the method resolved to, so tooling can tell what was configured
+      let method_name = proc_name.split_once(':').map(|(m, _)| m).unwrap_or(proc_name);
+      if let Some(method_descriptor) = service_descriptor.method.iter()
+        .find(|m| m.name.clone().unwrap_or_default() == method_name) {
+        if let Some(input_type) = &method_descriptor.input_type {
+          interaction_config.insert(REQUEST_TYPE_CONFIG.to_string(),
+            Value::String(input_type.trim_start_matches('.').to_string()));
+        }
+        if let Some(output_type) = &method_descriptor.output_type {
+          interaction_config.insert(RESPONSE_TYPE_CONFIG.to_string(),
+            Value::String(output_type.trim_start_matches('.').to_string()));
+        }
+      }
+      // any configuration from the request part (i.e. the request stream messages)
+      if let Some(request_config) = request.as_ref()
+        .and_then(|r| r.plugin_configuration.as_ref())
+        .and_then(|c| c.interaction_configuration.as_ref()) {
+        interaction_config.extend(proto_struct_to_map(request_config));
+      }
       let plugin_configuration = Some(PluginConfiguration {
-        interaction_configuration: Some(to_proto_struct(&hashmap! {
-            "service".to_string() => Value::String(
-              service_name.split_once(':').map(|(s, _)| s).unwrap_or(service_name).to_string()
-            ),
-            "descriptorKey".to_string() => Value::String(descriptor_hash.to_string())
-          })),
+        interaction_configuration: Some(to_proto_struct(&interaction_config)),
         pact_configuration: None
       });
       trace!("request = {request:?}");
       trace!("response = {response:?}");
-      (
+      Ok((
         request.map(|r| InteractionResponse { plugin_configuration: plugin_configuration.clone(), .. r }),
         response.iter().map(|r| InteractionResponse { plugin_configuration: plugin_configuration.clone(), .. r.clone() }).collect()
-      )
+      ))
     })
 }
 
@@ -188,13 +307,27 @@ fn construct_protobuf_interaction_for_service(
   let request_descriptor = find_message_descriptor(input_message_name, all_descriptors)?;
   let response_descriptor = find_message_descriptor(output_message_name, all_descriptors)?;
 
-  let request_part_config = request_part(config, service_part)?;
-  trace!(config = ?request_part_config, service_part, "Processing request part config");
-  let request_metadata = process_metadata(config.get("requestMetadata"))?;
-  let interaction = construct_protobuf_interaction_for_message(&request_descriptor,
-    &request_part_config, input_message_name, "", file_descriptor, all_descriptors,
-    request_metadata.as_ref()
-  )?;
+  let request_metadata = add_user_agent_matcher(process_metadata(config.get("requestMetadata"))?,
+    config.get("pact:user-agent"))?;
+  let request_stream = match config.get("request").and_then(|request| request.kind.as_ref()) {
+    Some(Kind::ListValue(list)) if service_part != "request" => Some(list),
+    _ => None
+  };
+  let interaction = if let Some(request_stream) = request_stream {
+    if !method_descriptor.client_streaming.unwrap_or(false) {
+      return Err(anyhow!("A list of request messages was provided, but method '{}' of service '{}' is not a client streaming method",
+        method_name, service_name));
+    }
+    construct_protobuf_interaction_for_request_stream(&request_descriptor, &request_stream.values,
+      input_message_name, file_descriptor, all_descriptors, request_metadata.as_ref())?
+  } else {
+    let request_part_config = with_message_options(request_part(config, service_part)?, config);
+    trace!(config = ?request_part_config, service_part, "Processing request part config");
+    construct_protobuf_interaction_for_message(&request_descriptor,
+      &request_part_config, input_message_name, "", file_descriptor, all_descriptors,
+      request_metadata.as_ref()
+    )?
+  };
   let request_part = Some(InteractionResponse {
     part_name: "request".into(),
     .. interaction
@@ -203,18 +336,142 @@ fn construct_protobuf_interaction_for_service(
   let response_part_config = response_part(config, service_part)?;
   trace!(config = ?response_part_config, service_part, "Processing response part config");
   let mut response_part = vec![];
-  for (config, md_config) in response_part_config {
-    let response_metadata = process_metadata(md_config)?;
+  for (message_config, md_config) in response_part_config {
+    let mut response_metadata = process_metadata(md_config)?;
+    if let Some(details) = build_status_details(md_config, all_descriptors)? {
+      if let Some(metadata) = response_metadata.as_mut() {
+        metadata.values.insert(GRPC_STATUS_DETAILS.to_string(), details);
+      }
+    }
     let interaction = construct_protobuf_interaction_for_message(
-      &response_descriptor, &config, output_message_name, "",
+      &response_descriptor, &with_message_options(message_config, config), output_message_name, "",
       file_descriptor, all_descriptors, response_metadata.as_ref()
     )?;
     response_part.push(InteractionResponse { part_name: "response".into(), .. interaction });
   }
+  if response_part.is_empty() && output_name == EMPTY_MESSAGE_TYPE {
+    debug!("Method returns {}, adding an empty response", EMPTY_MESSAGE_TYPE);
+    let interaction = construct_protobuf_interaction_for_message(
+      &response_descriptor, &btreemap!{}, output_message_name, "",
+      file_descriptor, all_descriptors, None
+    )?;
+    response_part.push(InteractionResponse { part_name: "response".into(), .. interaction });
+  }
 
   Ok((request_part, response_part))
 }
 
+/// Constructs the request part for a client streaming method where a list of request messages
+/// has been provided. The first message is used as the request contents, and all the messages are
+/// stored Base64 encoded in the `requestStream` interaction configuration. The matching rules of
+/// each message are stored with it in `requestStreamRules`, so they are only applied to that message.
+fn construct_protobuf_interaction_for_request_stream(
+  request_descriptor: &DescriptorProto,
+  messages: &[prost_types::Value],
+  message_name: &str,
+  file_descriptor: &FileDescriptorProto,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>,
+  metadata: Option<&MessageMetadata>
+) -> anyhow::Result<InteractionResponse> {
+  trace!(">> construct_protobuf_interaction_for_request_stream({}, {} messages)", message_name, messages.len());
+
+  let mut interactions = vec![];
+  for message in messages {
+    let message_config = match &message.kind {
+      Some(Kind::StructValue(s)) => s.fields.clone(),
+      Some(Kind::StringValue(_)) => btreemap!{ "value".to_string() => message.clone() },
+      kind => return Err(anyhow!("Request stream message is of an un-processable type: {:?}, it should be either a Struct or a StringValue", kind))
+    };
+    interactions.push(construct_protobuf_interaction_for_message(request_descriptor, &message_config,
+      message_name, "", file_descriptor, all_descriptors, metadata)?);
+  }
+
+  let encoded_messages = interactions.iter()
+    .map(|interaction| {
+      let contents = interaction.contents.as_ref().and_then(|body| body.content.clone()).unwrap_or_default();
+      Value::String(BASE64.encode(contents))
+    })
+    .collect();
+  let message_rules = interactions.iter()
+    .map(|interaction| matching_rules_to_json(&interaction.rules))
+    .collect::<anyhow::Result<Vec<_>>>()?;
+  let mut request = interactions.into_iter().next()
+    .ok_or_else(|| anyhow!("At least one request message is required for a client streaming method"))?;
+  request.plugin_configuration = Some(PluginConfiguration {
+    interaction_configuration: Some(to_proto_struct(&hashmap! {
+      REQUEST_STREAM_CONFIG.to_string() => Value::Array(encoded_messages),
+      REQUEST_STREAM_RULES_CONFIG.to_string() => Value::Array(message_rules)
+    })),
+    pact_configuration: None
+  });
+  Ok(request)
+}
+
+/// Converts the matching rules of a message to the Pact V3 JSON format
+fn matching_rules_to_json(rules: &HashMap<String, MatchingRules>) -> anyhow::Result<Value> {
+  let mut category = MatchingRuleCategory::empty("body");
+  for (path, rules) in rules {
+    let path = DocPath::new(path)?;
+    for rule in &rules.rule {
+      let values = rule.values.as_ref().map(proto_struct_to_json).unwrap_or_default();
+      category.add_rule(path.clone(), matchingrules::MatchingRule::create(&rule.r#type, &values)?, RuleLogic::And);
+    }
+  }
+  Ok(category.to_v3_json())
+}
+
+/// Builds the error detail message configured with the `grpc-status-details` response metadata
+/// key. The message type is set with `pact:message-type`, and the result is a Base64 encoded
+/// `google.protobuf.Any` to be sent in the `grpc-status-details-bin` trailer.
+fn build_status_details(
+  md_config: Option<&prost_types::Value>,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>
+) -> anyhow::Result<Option<String>> {
+  let details_config = md_config
+    .and_then(|config| proto_value_to_map(config).ok())
+    .and_then(|config| config.get(GRPC_STATUS_DETAILS_CONFIG).cloned());
+  match details_config {
+    Some(config) => {
+      let config = proto_value_to_map(&config)
+        .map_err(|err| anyhow!("{} must be configured with a Map - {}", GRPC_STATUS_DETAILS_CONFIG, err))?;
+      let message_type = config.get("pact:message-type")
+        .and_then(proto_value_to_string)
+        .ok_or_else(|| anyhow!("{} must have the message type set with pact:message-type", GRPC_STATUS_DETAILS_CONFIG))?;
+      let (file_descriptor, message_descriptor) = all_descriptors.values()
+        .find_map(|fd| {
+          fd.message_type.iter()
+            .find(|m| m.name.clone().unwrap_or_default() == message_type)
+            .map(|m| (*fd, m))
+        })
+        .ok_or_else(|| anyhow!("Did not find the descriptor for the error detail message {}", message_type))?;
+      let interaction = construct_protobuf_interaction_for_message(message_descriptor,
+        &config, message_type.as_str(), "", file_descriptor, all_descriptors, None)?;
+      let type_name = match &file_descriptor.package {
+        Some(package) if !package.is_empty() => format!("{}.{}", package, message_type),
+        _ => message_type.clone()
+      };
+      let detail = prost_types::Any {
+        type_url: format!("type.googleapis.com/{}", type_name),
+        value: interaction.contents.and_then(|body| body.content).unwrap_or_default()
+      };
+      Ok(Some(BASE64.encode(detail.encode_to_vec())))
+    }
+    None => Ok(None)
+  }
+}
+
+/// Copies the options that apply when constructing each message (`pact:number-types`) from the
+/// configuration for the service method to the configuration for a request or response message.
+fn with_message_options(
+  mut message_config: BTreeMap<String, prost_types::Value>,
+  config: &BTreeMap<String, prost_types::Value>
+) -> BTreeMap<String, prost_types::Value> {
+  if let Some(number_types) = config.get("pact:number-types") {
+    message_config.entry("pact:number-types".to_string()).or_insert_with(|| number_types.clone());
+  }
+  message_config
+}
+
 fn response_part<'a>(
   config: &'a BTreeMap<String, prost_types::Value>,
   service_part: &str
@@ -285,6 +542,175 @@ fn find_message_descriptor(message_name: &str, all_descriptors: &HashMap<String,
     .ok_or_else(|| anyhow!("Did not find the descriptor for message {}", message_name))
 }
 
+/// Adds any options set in the test configuration to the interaction configuration. These are:
+/// * `pact:field-mask` - only these fields will be compared
+/// * `pact:fill-defaults` - if unset fields are populated with default values when generating the contents
+/// * `pact:stream-ordering` - how client streaming request messages are matched
+/// * `pact:method-options` - the expected options of the service method
+/// * `pact:treat-defaults-as-present` - if expected fields set to their default values must be present
+/// * `pact:bytes-value-types` - the message types embedded in any `google.protobuf.BytesValue` fields
+/// * `grpc:fault` - the fault the mock server must inject
+/// * `pact:example-option` - the custom field option with example values for unset fields
+/// * `pact:example-values` - the example values for unset fields keyed by the field path
+/// * `pact:serialized-regex` - a regex the serialized message must match
+/// * `pact:serialized-size` - the minimum and maximum size of the serialized message
+/// * `pact:validation-option` - the field option with the validation rules generated values must satisfy
+/// * `pact:response-sequence` - how the responses are returned over successive calls to the mock server
+/// * `pact:custom-matchers` - the registered custom matchers to apply to fields
+/// * `pact:deadline` - the deadline the provider must respond within during verification
+/// * `pact:any-types` - the type URLs acceptable for any `google.protobuf.Any` fields
+/// * `pact:mismatch-snippet` - if the mock server results include the actual request as interaction configuration
+/// * `pact:field-relations` - the relations that must hold between fields of the actual message
+/// * `pact:filtered-matchers` - the matchers for the elements of repeated fields matching a predicate
+/// * `pact:each-equals` - the repeated fields where each value must equal the single expected value
+/// * `pact:default-matcher` - the matcher to apply to fields without their own matching rules
+/// * `pact:generator-seed` - the seed for the random values of the generators
+/// * `pact:unknown-enum-values` - how enum values not defined for the enum are treated
+/// * `pact:echo-fields` - the response fields the mock server sets from the request message
+/// * `pact:message-framing` - how the message bodies are framed when verifying
+/// * `pact:streaming-threshold` - the size above which the actual message is compared one field at a time
+/// * `pact:ignore-case` - the string fields that are compared ignoring case
+/// * `pact:length-fields` - the string and bytes fields where the min and max type matchers check the length
+/// * `pact:subsequence` - the repeated fields where the expected values must appear in order
+/// * `pact:enum-includes` and `pact:enum-excludes` - the values repeated enum fields must include or exclude
+/// * `pact:json-regex` - a regex that the JSON rendering of the actual message must match
+/// * `pact:redaction-option` - the custom field option that marks fields as redacted
+/// * `pact:number-locales` - the locales of string fields that contain formatted numbers
+/// * `pact:wrapper-nulls` - if wrapper type fields are generated as null or present
+/// * `pact:presence-groups` - the groups of fields where at least one field must be set
+/// * `pact:schema-only` - if only the schema of the actual message is verified
+/// * `pact:warn-on-unexpected` - if unexpected fields in the response are reported as warnings
+/// * `pact:trailers-only` - if an error response must be sent as a trailers-only response
+///
+/// Returns an error if the default matcher is not a valid matcher definition.
+fn with_interaction_options(
+  mut interaction_config: HashMap<String, Value>,
+  config: &BTreeMap<String, prost_types::Value>
+) -> anyhow::Result<HashMap<String, Value>> {
+  if let Some(field_mask) = config.get("pact:field-mask") {
+    interaction_config.insert(FIELD_MASK_CONFIG.to_string(), proto_value_to_json(field_mask));
+  }
+  if let Some(fill_defaults) = config.get("pact:fill-defaults") {
+    interaction_config.insert(FILL_DEFAULTS_CONFIG.to_string(), proto_value_to_json(fill_defaults));
+  }
+  if let Some(stream_ordering) = config.get("pact:stream-ordering") {
+    interaction_config.insert(STREAM_ORDERING_CONFIG.to_string(), proto_value_to_json(stream_ordering));
+  }
+  if let Some(method_options) = config.get("pact:method-options") {
+    interaction_config.insert(METHOD_OPTIONS_CONFIG.to_string(), proto_value_to_json(method_options));
+  }
+  if let Some(treat_defaults_as_present) = config.get("pact:treat-defaults-as-present") {
+    interaction_config.insert(TREAT_DEFAULTS_AS_PRESENT_CONFIG.to_string(), proto_value_to_json(treat_defaults_as_present));
+  }
+  if let Some(bytes_value_types) = config.get("pact:bytes-value-types") {
+    interaction_config.insert(BYTES_VALUE_TYPES_CONFIG.to_string(), proto_value_to_json(bytes_value_types));
+  }
+  if let Some(fault) = config.get("grpc:fault") {
+    interaction_config.insert(FAULT_CONFIG.to_string(), proto_value_to_json(fault));
+  }
+  if let Some(example_option) = config.get("pact:example-option") {
+    interaction_config.insert(EXAMPLE_OPTION_CONFIG.to_string(), proto_value_to_json(example_option));
+  }
+  if let Some(example_values) = config.get("pact:example-values") {
+    interaction_config.insert(EXAMPLE_VALUES_CONFIG.to_string(), proto_value_to_json(example_values));
+  }
+  if let Some(serialized_regex) = config.get("pact:serialized-regex") {
+    interaction_config.insert(SERIALIZED_REGEX_CONFIG.to_string(), proto_value_to_json(serialized_regex));
+  }
+  if let Some(serialized_size) = config.get("pact:serialized-size") {
+    interaction_config.insert(SERIALIZED_SIZE_CONFIG.to_string(), proto_value_to_json(serialized_size));
+  }
+  if let Some(validation_option) = config.get("pact:validation-option") {
+    interaction_config.insert(VALIDATION_OPTION_CONFIG.to_string(), proto_value_to_json(validation_option));
+  }
+  if let Some(response_sequence) = config.get("pact:response-sequence") {
+    interaction_config.insert(RESPONSE_SEQUENCE_CONFIG.to_string(), proto_value_to_json(response_sequence));
+  }
+  if let Some(custom_matchers) = config.get("pact:custom-matchers") {
+    interaction_config.insert(CUSTOM_MATCHERS_CONFIG.to_string(), proto_value_to_json(custom_matchers));
+  }
+  if let Some(deadline) = config.get("pact:deadline") {
+    interaction_config.insert(DEADLINE_CONFIG.to_string(), proto_value_to_json(deadline));
+  }
+  if let Some(any_types) = config.get("pact:any-types") {
+    interaction_config.insert(ANY_TYPES_CONFIG.to_string(), proto_value_to_json(any_types));
+  }
+  if let Some(mismatch_snippet) = config.get("pact:mismatch-snippet") {
+    interaction_config.insert(MISMATCH_SNIPPET_CONFIG.to_string(), proto_value_to_json(mismatch_snippet));
+  }
+  if let Some(field_relations) = config.get("pact:field-relations") {
+    interaction_config.insert(FIELD_RELATIONS_CONFIG.to_string(), proto_value_to_json(field_relations));
+  }
+  if let Some(filtered_matchers) = config.get("pact:filtered-matchers") {
+    interaction_config.insert(FILTERED_MATCHERS_CONFIG.to_string(), proto_value_to_json(filtered_matchers));
+  }
+  if let Some(each_equals) = config.get("pact:each-equals") {
+    interaction_config.insert(EACH_EQUALS_CONFIG.to_string(), proto_value_to_json(each_equals));
+  }
+  if let Some(default_matcher) = config.get("pact:default-matcher") {
+    let definition = proto_value_to_json(default_matcher);
+    parse_matcher_def(json_to_string(&definition).as_str())
+      .map_err(|err| anyhow!("'{}' is not a valid matcher definition for pact:default-matcher - {}",
+        json_to_string(&definition), err))?;
+    interaction_config.insert(DEFAULT_MATCHER_CONFIG.to_string(), definition);
+  }
+  if let Some(generator_seed) = config.get("pact:generator-seed") {
+    interaction_config.insert(GENERATOR_SEED_CONFIG.to_string(), proto_value_to_json(generator_seed));
+  }
+  if let Some(unknown_enum_values) = config.get("pact:unknown-enum-values") {
+    interaction_config.insert(UNKNOWN_ENUM_VALUES_CONFIG.to_string(), proto_value_to_json(unknown_enum_values));
+  }
+  if let Some(echo_fields) = config.get("pact:echo-fields") {
+    interaction_config.insert(ECHO_FIELDS_CONFIG.to_string(), proto_value_to_json(echo_fields));
+  }
+  if let Some(message_framing) = config.get("pact:message-framing") {
+    interaction_config.insert(MESSAGE_FRAMING_CONFIG.to_string(), proto_value_to_json(message_framing));
+  }
+  if let Some(streaming_threshold) = config.get("pact:streaming-threshold") {
+    interaction_config.insert(STREAMING_THRESHOLD_CONFIG.to_string(), proto_value_to_json(streaming_threshold));
+  }
+  if let Some(ignore_case) = config.get("pact:ignore-case") {
+    interaction_config.insert(IGNORE_CASE_CONFIG.to_string(), proto_value_to_json(ignore_case));
+  }
+  if let Some(length_fields) = config.get("pact:length-fields") {
+    interaction_config.insert(LENGTH_FIELDS_CONFIG.to_string(), proto_value_to_json(length_fields));
+  }
+  if let Some(subsequence) = config.get("pact:subsequence") {
+    interaction_config.insert(SUBSEQUENCE_CONFIG.to_string(), proto_value_to_json(subsequence));
+  }
+  if let Some(enum_includes) = config.get("pact:enum-includes") {
+    interaction_config.insert(ENUM_INCLUDES_CONFIG.to_string(), proto_value_to_json(enum_includes));
+  }
+  if let Some(enum_excludes) = config.get("pact:enum-excludes") {
+    interaction_config.insert(ENUM_EXCLUDES_CONFIG.to_string(), proto_value_to_json(enum_excludes));
+  }
+  if let Some(json_regex) = config.get("pact:json-regex") {
+    interaction_config.insert(JSON_REGEX_CONFIG.to_string(), proto_value_to_json(json_regex));
+  }
+  if let Some(redaction_option) = config.get("pact:redaction-option") {
+    interaction_config.insert(REDACTION_OPTION_CONFIG.to_string(), proto_value_to_json(redaction_option));
+  }
+  if let Some(number_locales) = config.get("pact:number-locales") {
+    interaction_config.insert(NUMBER_LOCALES_CONFIG.to_string(), proto_value_to_json(number_locales));
+  }
+  if let Some(wrapper_nulls) = config.get("pact:wrapper-nulls") {
+    interaction_config.insert(WRAPPER_NULLS_CONFIG.to_string(), proto_value_to_json(wrapper_nulls));
+  }
+  if let Some(presence_groups) = config.get("pact:presence-groups") {
+    interaction_config.insert(PRESENCE_GROUPS_CONFIG.to_string(), proto_value_to_json(presence_groups));
+  }
+  if let Some(schema_only) = config.get("pact:schema-only") {
+    interaction_config.insert(SCHEMA_ONLY_CONFIG.to_string(), proto_value_to_json(schema_only));
+  }
+  if let Some(warn_on_unexpected) = config.get("pact:warn-on-unexpected") {
+    interaction_config.insert(WARN_ON_UNEXPECTED_CONFIG.to_string(), proto_value_to_json(warn_on_unexpected));
+  }
+  if let Some(trailers_only) = config.get("pact:trailers-only") {
+    interaction_config.insert(TRAILERS_ONLY_CONFIG.to_string(), proto_value_to_json(trailers_only));
+  }
+  Ok(interaction_config)
+}
+
 /// Configure the interaction for a single Protobuf message
 fn configure_protobuf_message(
   message_name: &str,
@@ -299,17 +725,18 @@ fn configure_protobuf_message(
     .iter().find(|p| p.name.clone().unwrap_or_default() == message_name)
     .ok_or_else(|| anyhow!("Did not find a descriptor for message '{}'", message_name))?;
   construct_protobuf_interaction_for_message(message_descriptor, config, message_name, "", descriptor, all_descriptors, None)
-    .map(|interaction| {
-      InteractionResponse {
+    .and_then(|interaction| {
+      let interaction_config = with_interaction_options(hashmap!{
+        "message".to_string() => Value::String(message_name.to_string()),
+        "descriptorKey".to_string() => Value::String(descriptor_hash.to_string())
+      }, config)?;
+      Ok(InteractionResponse {
         plugin_configuration: Some(PluginConfiguration {
-          interaction_configuration: Some(to_proto_struct(&hashmap!{
-            "message".to_string() => Value::String(message_name.to_string()),
-            "descriptorKey".to_string() => Value::String(descriptor_hash.to_string())
-          })),
+          interaction_configuration: Some(to_proto_struct(&interaction_config)),
           pact_configuration: None
         }),
         .. interaction
-      }
+      })
     })
 }
 
@@ -328,6 +755,11 @@ fn construct_protobuf_interaction_for_message(
     message_part, file_descriptor.name, config.keys(), metadata);
 
   let mut message_builder = MessageBuilder::new(message_descriptor, message_name, file_descriptor);
+  if let Some(number_types) = config.get("pact:number-types") {
+    let number_types = proto_value_to_string(number_types)
+      .ok_or_else(|| anyhow!("Did not get a valid value for 'pact:number-types'. It should be a string"))?;
+    message_builder.number_types = NumberTypes::parse(number_types.as_str())?;
+  }
   let mut matching_rules = MatchingRuleCategory::empty("body");
   let mut generators = hashmap!{};
 
@@ -354,6 +786,22 @@ fn construct_protobuf_interaction_for_message(
   let rules = extract_rules(&matching_rules);
   let generators = extract_generators(&generators);
 
+  // If the example message has been provided in the Protobuf text format, it is used for the
+  // contents. Any other fields configured are only used for the matching rules and generators.
+  let (content, interaction_markup) = match config.get("pact:prototext") {
+    Some(text) => {
+      let text = proto_value_to_string(text)
+        .ok_or_else(|| anyhow!("Did not get a valid value for 'pact:prototext'. It should be a string"))?;
+      debug!("Building message {} from the text format example", message_name);
+      let descriptors = FileDescriptorSet {
+        file: all_descriptors.values().map(|fd| (*fd).clone()).collect()
+      };
+      let content = prototext_to_binary(text.as_str(), message_name, &descriptors)?;
+      (content, format!("```protobuf\n# {}\n{}\n```\n", message_name, text.trim()))
+    }
+    None => (message_builder.encode_message()?, message_builder.generate_markup("")?)
+  };
+
   let content_type = format!("application/protobuf;message={}", message_name);
   let mut metadata_fields = btreemap! {
     "contentType".to_string() => prost_string(&content_type)
@@ -362,12 +810,15 @@ fn construct_protobuf_interaction_for_message(
     for (k, v) in &metadata.values {
       metadata_fields.insert(k.clone(), prost_string(v));
     }
+    for (k, v) in &metadata.json_values {
+      metadata_fields.insert(k.clone(), to_proto_value(v));
+    }
   }
 
   Ok(InteractionResponse {
     contents: Some(Body {
       content_type: content_type.clone(),
-      content: Some(message_builder.encode_message()?.to_vec()),
+      content: Some(content.to_vec()),
       content_type_hint: ContentTypeHint::Binary as i32,
     }),
     message_metadata: Some(Struct {
@@ -375,7 +826,7 @@ fn construct_protobuf_interaction_for_message(
     }),
     rules,
     generators,
-    interaction_markup: message_builder.generate_markup("")?,
+    interaction_markup,
     interaction_markup_type: MarkupType::CommonMark as i32,
     part_name: message_part.to_string(),
     metadata_rules: metadata.map(|md| extract_rules(&md.matching_rules)).unwrap_or_default(),
@@ -418,6 +869,11 @@ fn extract_rules(matching_rules: &MatchingRuleCategory) -> HashMap<String, Match
   }).collect()
 }
 
+/// Parses a matching rule definition for a field, expanding any `base64` or `hex` matchers
+fn parse_field_matcher_def(definition: &str) -> anyhow::Result<MatchingRuleDefinition> {
+  parse_matcher_def(expand_encoding_matchers(definition).as_str())
+}
+
 /// Construct a single field for a message from the provided config
 #[tracing::instrument(ret,
   skip_all,
@@ -515,7 +971,7 @@ fn build_embedded_message_field_value(
           // expression. Normally it should be a matchValues or matchKeys (or both)
           let definition = json_to_string(definition);
           debug!("Configuring repeated field from a matcher definition expression '{}'", definition);
-          let mrd = parse_matcher_def( definition.as_str())?;
+          let mrd = parse_field_matcher_def(definition.as_str())?;
 
           let each_value = mrd.rules.iter()
               .filter_map(|rule| rule.clone().left())
@@ -537,7 +993,7 @@ fn build_embedded_message_field_value(
                     generators.insert(path.to_string(), generator.clone());
                   }
                   let constructed_value = value_for_type(field, each_value_def.value.as_str(),
-                    field_descriptor, &message_builder.descriptor, all_descriptors)?;
+                    field_descriptor, &message_builder.descriptor, all_descriptors, message_builder.number_types)?;
                   message_builder.set_field_value(field_descriptor, field, constructed_value);
                   Ok(())
                 }
@@ -568,7 +1024,7 @@ fn build_embedded_message_field_value(
             }
 
             let constructed = value_for_type(field, mrd.value.as_str(),
-              field_descriptor, &message_builder.descriptor, all_descriptors)?;
+              field_descriptor, &message_builder.descriptor, all_descriptors, message_builder.number_types)?;
             message_builder.add_repeated_field_value(field_descriptor, field, constructed);
 
             Ok(())
@@ -641,9 +1097,10 @@ fn build_single_embedded_field_value(
         .or_else(|| find_message_type_in_file_descriptors(message_name, &message_builder.file_descriptor, all_descriptors).ok())
         .ok_or_else(|| anyhow!("Did not find message '{}' in the current message or in the file descriptors", type_name))?;
       let mut embedded_builder = MessageBuilder::new(&embedded_type, message_name, &message_builder.file_descriptor);
+      embedded_builder.number_types = message_builder.number_types;
 
       if let Some(definition) = config.get("pact:match") {
-        let mrd = parse_matcher_def(json_to_string(definition).as_str())?;
+        let mrd = parse_field_matcher_def(json_to_string(definition).as_str())?;
         // when (val ruleDefinition = MatchingRuleDefinition.parseMatchingRuleDefinition(definition)) {
         //   is Ok -> for (rule in ruleDefinition.value.rules) {
         //     when (rule) {
@@ -745,7 +1202,7 @@ fn build_proto_value(
       Err(anyhow!("Got an invalid number (not f64, i64 or u64)"))
     },
     Value::String(s) => if is_matcher_def(s.as_str()) {
-      let mrd = parse_matcher_def(s.as_str())?;
+      let mrd = parse_field_matcher_def(s.as_str())?;
       if !mrd.rules.is_empty() {
         for rule in &mrd.rules {
           match rule {
@@ -822,7 +1279,7 @@ fn build_map_field(
     if let Some(definition) = config.get("pact:match") {
       debug!("Parsing matching rule definition {:?}", definition);
       let definition = json_to_string(definition);
-      let mrd = parse_matcher_def(definition.as_str())?;
+      let mrd = parse_field_matcher_def(definition.as_str())?;
       if !mrd.rules.is_empty() {
         trace!("Found matching rules: {:?}", mrd.rules);
         for rule in &mrd.rules {
@@ -851,6 +1308,7 @@ fn build_map_field(
       trace!("Map field value descriptor = {:?}", value_descriptor);
 
       let mut embedded_builder = MessageBuilder::new(&map_type, message_name.as_str(), &message_builder.file_descriptor);
+      embedded_builder.number_types = message_builder.number_types;
       for (inner_field, value) in config {
         if inner_field != "pact:match" {
           let entry_path = path.join(inner_field);
@@ -886,6 +1344,16 @@ fn build_map_field(
   }
 }
 
+/// Returns the configured value for a field as a string. Numbers in the test configuration are
+/// sent as floating point values, so a number without a fractional part (i.e. `5.0`) is returned
+/// as a whole number (`5`).
+fn field_value_string(value: &Value) -> String {
+  match value.as_f64() {
+    Some(number) if value.is_f64() && number.is_finite() && number.fract() == 0.0 => format!("{:.0}", number),
+    _ => json_to_string(value)
+  }
+}
+
 /// Constructs a simple message field (non-repeated or map) from the configuration value and
 /// updates the matching rules and generators for it.
 #[tracing::instrument(ret,
@@ -907,19 +1375,20 @@ fn build_field_value(
 
   match value {
     Value::Null => Ok(None),
-    Value::String(s) => {
+    Value::String(_) | Value::Number(_) => {
+      let s = field_value_string(value);
       let constructed_value = match field_type {
         MessageFieldValueType::Repeated => {
           let path = path.join("*");
           let constructed_value = construct_value_from_string(&path, message_builder,
-            descriptor, field_name, matching_rules, generators, s, all_descriptors)?;
+            descriptor, field_name, matching_rules, generators, &s, all_descriptors)?;
           debug!("Setting field {:?}:repeated to value {:?}", field_name, constructed_value);
           message_builder.add_repeated_field_value(descriptor, field_name, constructed_value.clone());
           constructed_value
         },
         _ => {
           let constructed_value = construct_value_from_string(path, message_builder,
-            descriptor, field_name, matching_rules, generators, s, all_descriptors)?;
+            descriptor, field_name, matching_rules, generators, &s, all_descriptors)?;
           debug!("Setting field {:?}:{:?} to value {:?}", field_name, field_type, constructed_value);
           message_builder.set_field_value(descriptor, field_name, constructed_value.clone());
           constructed_value
@@ -946,7 +1415,7 @@ fn build_field_value(
         Ok(None)
       }
     }
-    _ => Err(anyhow!("Field values must be configured with a string value, got {:?}", value))
+    _ => Err(anyhow!("Field values must be configured with a string or number value, got {:?}", value))
   }
 }
 
@@ -963,13 +1432,15 @@ fn construct_value_from_string(
   trace!(?field_name, string = ?s, "Building value from string");
   if is_matcher_def(s) {
     trace!("String value is a matcher definition");
-    let mrd = parse_matcher_def(s)?;
+    let mrd = parse_field_matcher_def(s)?;
     trace!("matcher definition = {:?}", mrd);
     if !mrd.rules.is_empty() {
       for rule in &mrd.rules {
         match rule {
           Either::Left(rule) => {
-            let path = if rule.is_values_matcher() && path.is_wildcard() {
+            // A not empty matcher for a repeated field applies to the field, not each of its values
+            let field_matcher = rule.is_values_matcher() || *rule == matchingrules::MatchingRule::NotEmpty;
+            let path = if field_matcher && path.is_wildcard() {
               // TODO: replace this with "path.parent().unwrap_or(DocPath::root())" when pact_models
               // 1.1.6 is released
               parent(path).unwrap_or(DocPath::root())
@@ -987,10 +1458,10 @@ fn construct_value_from_string(
       generators.insert(path.to_string(), generator.clone());
     }
     value_for_type(field_name, &*value_for_field(&mrd), descriptor, &message_builder.descriptor,
-                   all_descriptors)
+                   all_descriptors, message_builder.number_types)
   } else {
     value_for_type(field_name, s, descriptor, &message_builder.descriptor,
-      all_descriptors)
+      all_descriptors, message_builder.number_types)
   }
 }
 
@@ -1028,12 +1499,31 @@ fn value_for_field(mrd: &MatchingRuleDefinition) -> String {
   }
 }
 
+/// Returns the value configured for an integer field. Number values without a fractional part are
+/// always accepted (see [field_value_string]), and with lenient number types a string value without
+/// a fractional part (i.e. `"5.0"`) is also accepted as the integer value. A value with a
+/// fractional part is never a valid integer.
+fn integer_value<'a>(field_name: &str, field_value: &'a str, number_types: NumberTypes) -> anyhow::Result<&'a str> {
+  match field_value.split_once('.') {
+    Some((integer, fraction)) if fraction.chars().all(|ch| ch == '0') => match number_types {
+      NumberTypes::Strict => Err(anyhow!("'{}' is not a valid value for integer field '{}'. Set pact:number-types to 'lenient' to accept values without a fractional part",
+        field_value, field_name)),
+      NumberTypes::Lenient => {
+        trace!(field_name, field_value, "Accepting a number without a fractional part for an integer field");
+        Ok(integer)
+      }
+    },
+    _ => Ok(field_value)
+  }
+}
+
 fn value_for_type(
   field_name: &str,
   field_value: &str,
   descriptor: &FieldDescriptorProto,
   message_descriptor: &DescriptorProto,
-  all_descriptors: &HashMap<String, &FileDescriptorProto>
+  all_descriptors: &HashMap<String, &FileDescriptorProto>,
+  number_types: NumberTypes
 ) -> anyhow::Result<MessageFieldValue> {
   trace!("value_for_type({}, {}, _)", field_name, field_value);
 
@@ -1044,10 +1534,14 @@ fn value_for_type(
   match t {
     Type::Double => MessageFieldValue::double(field_name, field_value),
     Type::Float => MessageFieldValue::float(field_name, field_value),
-    Type::Int64 | Type::Sfixed64 | Type::Sint64 => MessageFieldValue::integer_64(field_name, field_value),
-    Type::Uint64 | Type::Fixed64 => MessageFieldValue::uinteger_64(field_name, field_value),
-    Type::Int32 | Type::Sfixed32 | Type::Sint32 => MessageFieldValue::integer_32(field_name, field_value),
-    Type::Uint32 | Type::Fixed32 => MessageFieldValue::uinteger_32(field_name, field_value),
+    Type::Int64 | Type::Sfixed64 | Type::Sint64 =>
+      MessageFieldValue::integer_64(field_name, integer_value(field_name, field_value, number_types)?),
+    Type::Uint64 | Type::Fixed64 =>
+      MessageFieldValue::uinteger_64(field_name, integer_value(field_name, field_value, number_types)?),
+    Type::Int32 | Type::Sfixed32 | Type::Sint32 =>
+      MessageFieldValue::integer_32(field_name, integer_value(field_name, field_value, number_types)?),
+    Type::Uint32 | Type::Fixed32 =>
+      MessageFieldValue::uinteger_32(field_name, integer_value(field_name, field_value, number_types)?),
     Type::Bool => MessageFieldValue::boolean(field_name, field_value),
     Type::String => Ok(MessageFieldValue::string(field_name, field_value)),
     Type::Message => {
@@ -1077,7 +1571,7 @@ fn value_for_type(
 
 #[cfg(test)]
 pub(crate) mod tests {
-  use std::collections::HashMap;
+  use std::collections::{BTreeMap, HashMap};
 
   use base64::Engine;
   use base64::engine::general_purpose::STANDARD as BASE64;
@@ -1091,6 +1585,7 @@ pub(crate) mod tests {
   use pact_models::prelude::MatchingRuleCategory;
   use pact_plugin_driver::proto::{MatchingRule, MatchingRules};
   use pact_plugin_driver::proto::interaction_response::MarkupType;
+  use pact_plugin_driver::utils::{proto_struct_to_json, proto_value_to_string};
   use prost::Message;
   use prost_types::{
     DescriptorProto,
@@ -1109,16 +1604,22 @@ pub(crate) mod tests {
   use serde_json::{json, Value};
   use trim_margin::MarginTrimmable;
 
-  use crate::message_builder::{MessageBuilder, MessageFieldValue, MessageFieldValueType, RType};
+  use crate::message_builder::{MessageBuilder, MessageFieldValue, MessageFieldValueType, NumberTypes, RType};
   use crate::protobuf::{
     build_embedded_message_field_value,
+    build_status_details,
     build_field_value,
     build_single_embedded_field_value,
+    configure_protobuf_service,
     construct_message_field,
     construct_protobuf_interaction_for_message,
+    construct_protobuf_interaction_for_request_stream,
     construct_protobuf_interaction_for_service,
+    process_proto,
     request_part,
+    REQUEST_TYPE_CONFIG,
     response_part,
+    RESPONSE_TYPE_CONFIG,
     value_for_type
   };
   use crate::utils::find_message_type_by_name;
@@ -1150,7 +1651,7 @@ pub(crate) mod tests {
       options: None,
       proto3_optional: None
     };
-    let result = value_for_type("test", "test", &descriptor, &message_descriptor, &hashmap!{}).unwrap();
+    let result = value_for_type("test", "test", &descriptor, &message_descriptor, &hashmap!{}, NumberTypes::Strict).unwrap();
     expect!(result.name).to(be_equal_to("test"));
     expect!(result.raw_value).to(be_some().value("test".to_string()));
     expect!(result.rtype).to(be_equal_to(RType::String("test".to_string())));
@@ -1168,7 +1669,7 @@ pub(crate) mod tests {
       options: None,
       proto3_optional: None
     };
-    let result = value_for_type("test", "100", &descriptor, &message_descriptor, &hashmap!{}).unwrap();
+    let result = value_for_type("test", "100", &descriptor, &message_descriptor, &hashmap!{}, NumberTypes::Strict).unwrap();
     expect!(result.name).to(be_equal_to("test"));
     expect!(result.raw_value).to(be_some().value("100".to_string()));
     expect!(result.rtype).to(be_equal_to(RType::UInteger64(100)));
@@ -1351,6 +1852,206 @@ pub(crate) mod tests {
     expect!(result.generators).to(be_equal_to(hashmap! {}));
   }
 
+  #[test_log::test]
+  fn construct_protobuf_interaction_for_message_with_a_prototext_example() {
+    let fds = FileDescriptorSet::decode(DESCRIPTORS_FOR_EACH_VALUE_TEST.as_slice()).unwrap();
+    let fs = fds.file.first().unwrap();
+    let all_descriptors = hashmap!{ "simple.proto".to_string() => fs };
+    let config = btreemap! {
+      "pact:prototext".to_string() => prost_types::Value { kind: Some(StringValue("value: [\"abc\", \"de\"]".to_string())) },
+      "value".to_string() => prost_types::Value { kind: Some(StringValue("eachValue(matching(type, 'xyz'))".to_string())) }
+    };
+    let (message_descriptor, _) = find_message_type_by_name("ValuesMessageIn", &fds).unwrap();
+
+    let result = construct_protobuf_interaction_for_message(
+      &message_descriptor,
+      &config,
+      "ValuesMessageIn",
+      "",
+      fs,
+      &all_descriptors,
+      None
+    ).unwrap();
+
+    let body = result.contents.as_ref().unwrap();
+    expect!(body.content_type.as_str()).to(be_equal_to("application/protobuf;message=ValuesMessageIn"));
+    expect!(body.content.as_ref()).to(be_some().value(&vec![
+      10, 3, 97, 98, 99, // value = "abc"
+      10, 2, 100, 101    // value = "de"
+    ]));
+    let value_matcher = result.rules.get("$.value").unwrap().rule.first().unwrap();
+    expect!(&value_matcher.r#type).to(be_equal_to("each-value"));
+    expect!(result.interaction_markup.as_str()).to(be_equal_to("```protobuf\n# ValuesMessageIn\nvalue: [\"abc\", \"de\"]\n```\n"));
+  }
+
+  #[test_log::test]
+  fn construct_protobuf_interaction_for_message_with_number_types() {
+    let file_descriptor = FileDescriptorProto {
+      name: Some("order.proto".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Order".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("quantity".to_string()),
+          number: Some(1),
+          r#type: Some(field_descriptor_proto::Type::Int32 as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let string_value = |s: &str| prost_types::Value { kind: Some(StringValue(s.to_string())) };
+    let construct = |config: BTreeMap<String, prost_types::Value>| {
+      construct_protobuf_interaction_for_message(&message_descriptor, &config, "Order", "",
+        &file_descriptor, &hashmap!{}, None)
+    };
+
+    let result = construct(btreemap! { "quantity".to_string() => string_value("5") }).unwrap();
+    expect!(result.contents.unwrap().content).to(be_some().value(vec![8, 5]));
+    let result = construct(btreemap! { "quantity".to_string() => string_value("5.0") });
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "'5.0' is not a valid value for integer field 'quantity'. Set pact:number-types to 'lenient' to accept values without a fractional part"));
+    let result = construct(btreemap! { "quantity".to_string() => prost_types::Value { kind: Some(NumberValue(5.0)) } }).unwrap();
+    expect!(result.contents.unwrap().content).to(be_some().value(vec![8, 5]));
+    let result = construct(btreemap! { "quantity".to_string() => prost_types::Value { kind: Some(NumberValue(5.5)) } });
+    expect!(result).to(be_err());
+
+    let result = construct(btreemap! {
+      "pact:number-types".to_string() => string_value("lenient"),
+      "quantity".to_string() => string_value("5.0")
+    }).unwrap();
+    expect!(result.contents.unwrap().content).to(be_some().value(vec![8, 5]));
+    let result = construct(btreemap! {
+      "pact:number-types".to_string() => string_value("lenient"),
+      "quantity".to_string() => prost_types::Value { kind: Some(NumberValue(5.0)) }
+    }).unwrap();
+    expect!(result.contents.unwrap().content).to(be_some().value(vec![8, 5]));
+    let result = construct(btreemap! {
+      "pact:number-types".to_string() => string_value("lenient"),
+      "quantity".to_string() => string_value("5.5")
+    });
+    expect!(result).to(be_err());
+
+    let result = construct(btreemap! {
+      "pact:number-types".to_string() => string_value("loose"),
+      "quantity".to_string() => string_value("5")
+    });
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "'loose' is not a valid value for pact:number-types, it must be either 'strict' or 'lenient'"));
+  }
+
+  #[test_log::test]
+  fn construct_protobuf_interaction_for_message_with_number_types_and_an_each_value_matcher() {
+    let file_descriptor = FileDescriptorProto {
+      name: Some("order.proto".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Order".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("quantities".to_string()),
+          number: Some(1),
+          label: Some(field_descriptor_proto::Label::Repeated as i32),
+          r#type: Some(field_descriptor_proto::Type::Int32 as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let string_value = |s: &str| prost_types::Value { kind: Some(StringValue(s.to_string())) };
+    let each_value = |example: &str| string_value(format!("eachValue(matching(type, '{}'))", example).as_str());
+    let construct = |config: BTreeMap<String, prost_types::Value>| {
+      construct_protobuf_interaction_for_message(&message_descriptor, &config, "Order", "",
+        &file_descriptor, &hashmap!{}, None)
+    };
+
+    let expected = construct(btreemap! { "quantities".to_string() => each_value("5") }).unwrap();
+    let result = construct(btreemap! { "quantities".to_string() => each_value("5.0") });
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "'5.0' is not a valid value for integer field 'quantities'. Set pact:number-types to 'lenient' to accept values without a fractional part"));
+
+    let result = construct(btreemap! {
+      "pact:number-types".to_string() => string_value("lenient"),
+      "quantities".to_string() => each_value("5.0")
+    }).unwrap();
+    expect!(result.contents.unwrap().content).to(be_equal_to(expected.contents.unwrap().content));
+    let result = construct(btreemap! {
+      "pact:number-types".to_string() => string_value("lenient"),
+      "quantities".to_string() => each_value("5.5")
+    });
+    expect!(result).to(be_err());
+  }
+
+  #[test_log::test]
+  fn construct_protobuf_interaction_for_request_stream_keeps_the_matching_rules_of_each_message() {
+    let file_descriptor = FileDescriptorProto {
+      name: Some("order.proto".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Order".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("quantity".to_string()),
+          number: Some(1),
+          r#type: Some(field_descriptor_proto::Type::Int32 as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let message = |quantity: &str| prost_types::Value {
+      kind: Some(StructValue(Struct {
+        fields: btreemap! { "quantity".to_string() => prost_types::Value { kind: Some(StringValue(quantity.to_string())) } }
+      }))
+    };
+
+    let result = construct_protobuf_interaction_for_request_stream(&message_descriptor,
+      &[ message("matching(integer, 5)"), message("6") ], "Order", &file_descriptor, &hashmap!{}, None).unwrap();
+    expect!(result.contents.unwrap().content).to(be_some().value(vec![8, 5]));
+    expect!(result.rules.keys().cloned().collect::<Vec<_>>()).to(be_equal_to(vec!["$.quantity".to_string()]));
+    let config = proto_struct_to_json(&result.plugin_configuration.unwrap().interaction_configuration.unwrap());
+    expect!(config).to(be_equal_to(json!({
+      "requestStream": [ "CAU=", "CAY=" ],
+      "requestStreamRules": [
+        { "$.quantity": { "combine": "AND", "matchers": [ { "match": "integer" } ] } },
+        {}
+      ]
+    })));
+  }
+
+  #[test_log::test]
+  fn build_status_details_test() {
+    let fds = FileDescriptorSet::decode(DESCRIPTORS_FOR_EACH_VALUE_TEST.as_slice()).unwrap();
+    let fs = fds.file.first().unwrap();
+    let all_descriptors = hashmap!{ "simple.proto".to_string() => fs };
+
+    expect!(build_status_details(None, &all_descriptors).unwrap()).to(be_none());
+
+    let metadata_config = prost_types::Value {
+      kind: Some(StructValue(Struct {
+        fields: btreemap!{
+          "grpc-status".to_string() => prost_types::Value { kind: Some(StringValue("NOT_FOUND".to_string())) },
+          "grpc-status-details".to_string() => prost_types::Value {
+            kind: Some(StructValue(Struct {
+              fields: btreemap!{
+                "pact:message-type".to_string() => prost_types::Value { kind: Some(StringValue("MessageOut".to_string())) },
+                "out".to_string() => prost_types::Value { kind: Some(StringValue("true".to_string())) }
+              }
+            }))
+          }
+        }
+      }))
+    };
+    let details = build_status_details(Some(&metadata_config), &all_descriptors).unwrap().unwrap();
+    let detail = prost_types::Any::decode(BASE64.decode(details).unwrap().as_slice()).unwrap();
+    expect!(detail.type_url).to(be_equal_to("type.googleapis.com/MessageOut"));
+    expect!(detail.value).to(be_equal_to(vec![8, 1]));
+  }
+
   #[test_log::test]
   fn construct_message_field_with_message_with_each_value_matcher() {
     let fds = FileDescriptorSet::decode(DESCRIPTORS_FOR_EACH_VALUE_TEST.as_slice()).unwrap();
@@ -1391,6 +2092,29 @@ pub(crate) mod tests {
     }));
   }
 
+  #[test_log::test]
+  fn construct_message_field_with_a_not_empty_matcher_for_a_repeated_field() {
+    let fds = FileDescriptorSet::decode(DESCRIPTORS_FOR_EACH_VALUE_TEST.as_slice()).unwrap();
+    let fs = fds.file.first().unwrap();
+    let (message_descriptor, _) = find_message_type_by_name("ValuesMessageIn", &fds).unwrap();
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "ValuesMessageIn", fs);
+    let path = DocPath::new("$.value").unwrap();
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+    let file_descriptors: HashMap<String, &FileDescriptorProto> = fds.file
+      .iter().map(|des| (des.name.clone().unwrap_or_default(), des))
+      .collect();
+
+    let result = construct_message_field(&mut message_builder, &mut matching_rules,
+      &mut generators, "value", &Value::String("notEmpty('abc')".to_string()), &path, &file_descriptors);
+    expect!(result).to(be_ok());
+
+    expect!(matching_rules).to(be_equal_to(matchingrules_list! {
+      "body";
+      "$.value" => [ pact_models::matchingrules::MatchingRule::NotEmpty ]
+    }));
+  }
+
   #[test_log::test]
   fn build_field_value_with_message_with_each_value_matcher() {
     let fds = FileDescriptorSet::decode(DESCRIPTORS_FOR_EACH_VALUE_TEST.as_slice()).unwrap();
@@ -1615,6 +2339,92 @@ pub(crate) mod tests {
     expect!(result).to(be_ok());
   }
 
+  #[test]
+  fn construct_protobuf_interaction_for_service_with_empty_messages() {
+    let empty_file_descriptor = FileDescriptorProto {
+      name: Some("google/protobuf/empty.proto".to_string()),
+      package: Some("google.protobuf".to_string()),
+      message_type: vec![
+        DescriptorProto {
+          name: Some("Empty".to_string()),
+          .. DescriptorProto::default()
+        }
+      ],
+      .. FileDescriptorProto::default()
+    };
+    let service_descriptor = ServiceDescriptorProto {
+      name: Some("Health".to_string()),
+      method: vec![
+        MethodDescriptorProto {
+          name: Some("ping".to_string()),
+          input_type: Some(".google.protobuf.Empty".to_string()),
+          output_type: Some(".google.protobuf.Empty".to_string()),
+          .. MethodDescriptorProto::default()
+        }
+      ],
+      options: None
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("health.proto".to_string()),
+      dependency: vec![ "google/protobuf/empty.proto".to_string() ],
+      service: vec![ service_descriptor.clone() ],
+      .. FileDescriptorProto::default()
+    };
+    let all_descriptors = hashmap!{
+      "google/protobuf/empty.proto".to_string() => &empty_file_descriptor,
+      "health.proto".to_string() => &file_descriptor
+    };
+
+    let (request, response) = construct_protobuf_interaction_for_service(&service_descriptor,
+      &btreemap!{}, "Health", "ping", &all_descriptors, &file_descriptor).unwrap();
+
+    let request = request.unwrap();
+    expect!(request.contents.unwrap().content).to(be_some().value(Vec::<u8>::new()));
+    expect!(response.len()).to(be_equal_to(1));
+    expect!(response[0].part_name.as_str()).to(be_equal_to("response"));
+    expect!(response[0].contents.clone().unwrap().content).to(be_some().value(Vec::<u8>::new()));
+  }
+
+  #[test]
+  fn configure_protobuf_service_includes_the_resolved_message_types() {
+    let all_descriptors = hashmap!{ "area_calculator.proto".to_string() => &*FILE_DESCRIPTOR };
+    let config = btreemap! {
+      "request".to_string() => prost_types::Value { kind: Some(StructValue(Struct::default())) },
+      "response".to_string() => prost_types::Value { kind: Some(StructValue(Struct::default())) }
+    };
+
+    let (request, response) = configure_protobuf_service("Calculator/calculateOne", &config,
+      &FILE_DESCRIPTOR, &all_descriptors, "1234").unwrap();
+
+    let request_config = request.unwrap().plugin_configuration.unwrap().interaction_configuration.unwrap();
+    expect!(request_config.fields.get(REQUEST_TYPE_CONFIG).and_then(proto_value_to_string))
+      .to(be_some().value("area_calculator.ShapeMessage".to_string()));
+    expect!(request_config.fields.get(RESPONSE_TYPE_CONFIG).and_then(proto_value_to_string))
+      .to(be_some().value("area_calculator.AreaResponse".to_string()));
+    let response_config = response[0].plugin_configuration.clone().unwrap().interaction_configuration.unwrap();
+    expect!(response_config.fields.get(RESPONSE_TYPE_CONFIG).and_then(proto_value_to_string))
+      .to(be_some().value("area_calculator.AreaResponse".to_string()));
+  }
+
+  #[test]
+  fn configure_protobuf_service_fails_if_the_default_matcher_is_not_valid() {
+    let all_descriptors = hashmap!{ "area_calculator.proto".to_string() => &*FILE_DESCRIPTOR };
+    let config = |default_matcher: &str| btreemap! {
+      "request".to_string() => prost_types::Value { kind: Some(StructValue(Struct::default())) },
+      "response".to_string() => prost_types::Value { kind: Some(StructValue(Struct::default())) },
+      "pact:default-matcher".to_string() => prost_types::Value { kind: Some(StringValue(default_matcher.to_string())) }
+    };
+
+    let result = configure_protobuf_service("Calculator/calculateOne", &config("matching(type, '')"),
+      &FILE_DESCRIPTOR, &all_descriptors, "1234");
+    expect!(result).to(be_ok());
+
+    let result = configure_protobuf_service("Calculator/calculateOne", &config("matching(typo, '')"),
+      &FILE_DESCRIPTOR, &all_descriptors, "1234");
+    expect!(result.unwrap_err().to_string()
+      .starts_with("'matching(typo, '')' is not a valid matcher definition for pact:default-matcher")).to(be_true());
+  }
+
   lazy_static! {
     static ref FILE_DESCRIPTOR: FileDescriptorProto = FileDescriptorProto {
       name: Some("area_calculator.proto".to_string()),
@@ -2552,4 +3362,87 @@ pub(crate) mod tests {
       ]
     ));
   }
+
+  #[cfg(not(windows))]
+  #[test_log::test(tokio::test)]
+  async fn process_proto_reflects_changes_to_the_proto_file_between_calls() {
+    let dir = tempfile::tempdir().unwrap();
+    let proto_file = dir.path().join("person.proto");
+    let descriptor_file = dir.path().join("person.pb");
+    let protoc = crate::protoc::tests::fake_protoc();
+    let proto_file_contents = |config: &pact_plugin_driver::proto::PluginConfiguration| {
+      let pact_configuration = config.pact_configuration.clone().unwrap();
+      let (key, value) = pact_configuration.fields.iter().next().unwrap();
+      (key.clone(), pact_models::json_utils::json_to_string(&pact_plugin_driver::utils::proto_value_to_json(value)["protoFile"]))
+    };
+
+    let v1 = "syntax = \"proto3\";\n\nmessage Person {\n  string name = 1;\n}\n";
+    std::fs::write(&proto_file, v1).unwrap();
+    std::fs::copy("tests/descriptors/person-v1.pb", &descriptor_file).unwrap();
+    let config = btreemap!{
+      "pact:message-type".to_string() => prost_types::Value { kind: Some(StringValue("Person".to_string())) },
+      "age".to_string() => prost_types::Value { kind: Some(StringValue("matching(integer, 10)".to_string())) }
+    };
+    let result = process_proto(proto_file.to_string_lossy().to_string(), &protoc, &config).await;
+    expect!(result).to(be_err());
+    let (_, plugin_config) = process_proto(proto_file.to_string_lossy().to_string(), &protoc, &btreemap!{
+      "pact:message-type".to_string() => prost_types::Value { kind: Some(StringValue("Person".to_string())) }
+    }).await.unwrap();
+    let (first_key, contents) = proto_file_contents(&plugin_config);
+    expect!(contents).to(be_equal_to(v1));
+
+    let v2 = "syntax = \"proto3\";\n\nmessage Person {\n  string name = 1;\n  int32 age = 2;\n}\n";
+    std::fs::write(&proto_file, v2).unwrap();
+    std::fs::copy("tests/descriptors/person-v2.pb", &descriptor_file).unwrap();
+    let (interactions, plugin_config) = process_proto(proto_file.to_string_lossy().to_string(), &protoc, &config)
+      .await.unwrap();
+    let (second_key, contents) = proto_file_contents(&plugin_config);
+    expect!(contents).to(be_equal_to(v2));
+    expect!(second_key).to_not(be_equal_to(first_key));
+    expect!(interactions.first().unwrap().contents.as_ref().unwrap().content.clone())
+      .to(be_some().value(vec![16, 10]));
+  }
+
+  #[cfg(not(windows))]
+  #[test_log::test(tokio::test)]
+  async fn process_proto_compiles_the_proto_file_again_if_it_changes_while_being_compiled() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let proto_file = dir.path().join("person.proto");
+    let v1 = "syntax = \"proto3\";\n\nmessage Person {\n  string name = 1;\n}\n";
+    let v2 = "syntax = \"proto3\";\n\nmessage Person {\n  string name = 1;\n  int32 age = 2;\n}\n";
+    std::fs::write(&proto_file, v1).unwrap();
+    std::fs::write(dir.path().join("person.v2"), v2).unwrap();
+    std::fs::copy("tests/descriptors/person-v1.pb", dir.path().join("person.pb")).unwrap();
+
+    // Protoc that updates the proto file to the second version the first time it is invoked
+    let fake_protoc = std::path::Path::new("tests/fake-protoc.sh").canonicalize().unwrap();
+    let v2_descriptors = std::path::Path::new("tests/descriptors/person-v2.pb").canonicalize().unwrap();
+    let script = dir.path().join("protoc.sh");
+    std::fs::write(&script, format!(r#"#!/bin/sh
+"{}" "$@" || exit 1
+dir="{}"
+if [ ! -f "$dir/person.changed" ]; then
+  touch "$dir/person.changed"
+  cp "$dir/person.v2" "$dir/person.proto"
+  cp "{}" "$dir/person.pb"
+fi
+"#, fake_protoc.to_string_lossy(), dir.path().to_string_lossy(), v2_descriptors.to_string_lossy())).unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let protoc = crate::protoc::tests::protoc_script(&script);
+
+    let config = btreemap!{
+      "pact:message-type".to_string() => prost_types::Value { kind: Some(StringValue("Person".to_string())) },
+      "age".to_string() => prost_types::Value { kind: Some(StringValue("matching(integer, 10)".to_string())) }
+    };
+    let (interactions, plugin_config) = process_proto(proto_file.to_string_lossy().to_string(), &protoc, &config)
+      .await.unwrap();
+    let pact_configuration = plugin_config.pact_configuration.unwrap();
+    let (_, value) = pact_configuration.fields.iter().next().unwrap();
+    expect!(pact_models::json_utils::json_to_string(&pact_plugin_driver::utils::proto_value_to_json(value)["protoFile"]))
+      .to(be_equal_to(v2));
+    expect!(interactions.first().unwrap().contents.as_ref().unwrap().content.clone())
+      .to(be_some().value(vec![16, 10]));
+  }
 }
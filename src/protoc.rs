@@ -26,16 +26,25 @@ use zip::ZipArchive;
 pub(crate) struct Protoc {
   protoc_path: String,
   local_install: bool,
-  additional_includes: Vec<String>
+  additional_includes: Vec<String>,
+  well_known_types_include: Option<PathBuf>
 }
 
 impl Protoc {
   /// Create a new Protoc
   fn new(path: String, local_install: bool, additional_includes: Vec<String>) -> Self {
+    // If it is a local install, the default Protobuf well-defined types will be available in the
+    // unpacked archive, otherwise we need to find where they have been installed
+    let well_known_types_include = if local_install {
+      Some(PathBuf::from("protoc").join("include"))
+    } else {
+      well_known_types_include(Path::new(&path), &default_include_dirs())
+    };
     Protoc {
       protoc_path: path,
       local_install,
-      additional_includes
+      additional_includes,
+      well_known_types_include
     }
   }
 
@@ -87,11 +96,11 @@ impl Protoc {
       cmd.arg(format!("-I{}", inc));
     }
 
-    // If it is a local install, the default Protobuf well-defined types will be available
-    if self.local_install {
-      let include_path = PathBuf::from("protoc").join("include");
-      let include2 = format!("-I{}", include_path.to_string_lossy());
-      cmd.arg(include2);
+    // Add the include directory for the default Protobuf well-defined types, so imports like
+    // google/protobuf/timestamp.proto will resolve
+    if let Some(include_path) = &self.well_known_types_include {
+      trace!(local_install = self.local_install, "Adding well-known types include {:?}", include_path);
+      cmd.arg(format!("-I{}", include_path.to_string_lossy()));
     }
 
     debug!("Invoking protoc: {:?}", cmd);
@@ -113,6 +122,38 @@ impl Protoc {
   }
 }
 
+/// Proto file used to detect if a directory contains the Protobuf well-known types
+const WELL_KNOWN_TYPES_PROTO: &str = "google/protobuf/timestamp.proto";
+
+/// Default directories where the Protobuf well-known types may be installed. The
+/// `PROTOC_INCLUDE` environment variable can be used to set a different location.
+fn default_include_dirs() -> Vec<PathBuf> {
+  let mut dirs = vec![];
+  if let Ok(include) = std::env::var("PROTOC_INCLUDE") {
+    dirs.push(PathBuf::from(include));
+  }
+  dirs.extend([ "/usr/local/include", "/usr/include", "/opt/homebrew/include" ].iter().map(PathBuf::from));
+  dirs
+}
+
+/// Locates the include directory with the Protobuf well-known types for the protoc binary. This
+/// will first check for an include directory next to the binary (which is how the protoc
+/// distributions are packaged), and then the provided default directories.
+fn well_known_types_include(protoc_path: &Path, default_dirs: &[PathBuf]) -> Option<PathBuf> {
+  let protoc_path = fs::canonicalize(protoc_path).unwrap_or_else(|_| protoc_path.to_path_buf());
+  let bundled = protoc_path.parent()
+    .and_then(|bin_dir| bin_dir.parent())
+    .map(|dir| dir.join("include"));
+  let include = bundled.iter()
+    .chain(default_dirs.iter())
+    .find(|dir| dir.join(WELL_KNOWN_TYPES_PROTO).exists())
+    .cloned();
+  if include.is_none() {
+    debug!("Could not find the Protobuf well-known types for protoc '{}'", protoc_path.to_string_lossy());
+  }
+  include
+}
+
 // This function first checks for an unpacked protoc binary, and tries to run that
 // otherwise it will try unpack the version for the current OS
 // otherwise it will try download and unpack the version for the current OS
@@ -303,11 +344,14 @@ fn os_type(os_info: Bitness, arch: &str, os: &str) -> String {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+  use std::fs;
+  use std::path::{Path, PathBuf};
+
   use expectest::prelude::*;
   use os_info::Bitness;
 
-  use super::os_type;
+  use super::{os_type, Protoc, well_known_types_include};
 
   #[test]
   fn os_type_test() {
@@ -318,4 +362,100 @@ mod tests {
     expect!(os_type(Bitness::X32, "", "windows").as_str()).to(be_equal_to("win32"));
     expect!(os_type(Bitness::X64, "", "windows").as_str()).to(be_equal_to("win64"));
   }
+
+  #[test]
+  fn well_known_types_include_test() {
+    let dir = tempfile::tempdir().unwrap();
+    let bin_dir = dir.path().join("protoc").join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let protoc_path = bin_dir.join("protoc");
+    fs::write(&protoc_path, "").unwrap();
+    let other_dir = dir.path().join("other");
+    fs::create_dir_all(other_dir.join("google/protobuf")).unwrap();
+    fs::write(other_dir.join("google/protobuf/timestamp.proto"), "").unwrap();
+
+    expect!(well_known_types_include(&protoc_path, &[])).to(be_none());
+    expect!(well_known_types_include(&protoc_path, &[ PathBuf::from("/does/not/exist"), other_dir.clone() ]))
+      .to(be_some().value(other_dir.clone()));
+
+    let include_dir = dir.path().join("protoc").join("include");
+    fs::create_dir_all(include_dir.join("google/protobuf")).unwrap();
+    fs::write(include_dir.join("google/protobuf/timestamp.proto"), "").unwrap();
+    expect!(well_known_types_include(&protoc_path, &[ other_dir ]).map(|p| p.ends_with("protoc/include")))
+      .to(be_some().value(true));
+  }
+
+  /// Protoc that uses the tests/fake-protoc.sh script with the pre-compiled descriptor set
+  /// fixtures, so the tests do not need to download protoc
+  pub(crate) fn fake_protoc() -> Protoc {
+    protoc_script(&PathBuf::from("tests/fake-protoc.sh"))
+  }
+
+  /// Protoc that invokes the given script
+  pub(crate) fn protoc_script(script: &Path) -> Protoc {
+    let path = script.canonicalize().unwrap();
+    Protoc {
+      protoc_path: path.to_string_lossy().to_string(),
+      local_install: false,
+      additional_includes: vec![],
+      well_known_types_include: None
+    }
+  }
+
+  #[cfg(not(windows))]
+  #[test_log::test(tokio::test)]
+  async fn parse_proto_file_importing_a_well_known_type() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Lay out protoc like the protoc distributions, with the well-known types in the include
+    // directory next to the bin directory
+    let dir = tempfile::tempdir().unwrap();
+    let bin_dir = dir.path().join("protoc").join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let protoc_path = bin_dir.join("protoc");
+    fs::copy("tests/fake-protoc.sh", &protoc_path).unwrap();
+    fs::set_permissions(&protoc_path, fs::Permissions::from_mode(0o755)).unwrap();
+    let include_dir = dir.path().join("protoc").join("include");
+    fs::create_dir_all(include_dir.join("google/protobuf")).unwrap();
+    fs::write(include_dir.join("google/protobuf/timestamp.proto"), r#"syntax = "proto3";
+
+package google.protobuf;
+
+message Timestamp {
+  int64 seconds = 1;
+  int32 nanos = 2;
+}
+"#).unwrap();
+
+    let proto_dir = dir.path().join("protos");
+    fs::create_dir_all(&proto_dir).unwrap();
+    let proto_file = proto_dir.join("event.proto");
+    fs::write(&proto_file, r#"syntax = "proto3";
+
+import "google/protobuf/timestamp.proto";
+
+message Event {
+  string name = 1;
+  google.protobuf.Timestamp created = 2;
+}
+"#).unwrap();
+    fs::copy("tests/descriptors/event.pb", proto_dir.join("event.pb")).unwrap();
+
+    let protoc = Protoc::new(protoc_path.to_string_lossy().to_string(), false, vec![]);
+    let (descriptors, _, _) = protoc.parse_proto_file(&proto_file).await.unwrap();
+    let files = descriptors.file.iter()
+      .map(|file| file.name.clone().unwrap_or_default())
+      .collect::<Vec<_>>();
+    expect!(files).to(be_equal_to(vec![
+      "google/protobuf/timestamp.proto".to_string(),
+      "event.proto".to_string()
+    ]));
+
+    // Without the well-known types include directory, the import can not be resolved
+    let protoc = Protoc {
+      well_known_types_include: None,
+      .. Protoc::new(protoc_path.to_string_lossy().to_string(), false, vec![])
+    };
+    expect!(protoc.parse_proto_file(&proto_file).await).to(be_err());
+  }
 }
@@ -0,0 +1,535 @@
+//! Support for Protobuf messages in the canonical text format (prototext). Text format messages
+//! are converted into the binary wire format using the descriptors, so they can be matched in
+//! the same way as binary Protobuf messages.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use anyhow::{anyhow, bail};
+use bytes::{BufMut, Bytes, BytesMut};
+use prost::encoding::{encode_key, encode_varint, WireType};
+use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+use prost_types::field_descriptor_proto::Type;
+use tracing::{debug, trace};
+
+use crate::utils::{
+  find_enum_by_name,
+  find_enum_by_name_in_message,
+  find_message_type_by_name,
+  is_repeated_field,
+  last_name
+};
+
+/// Content type for Protobuf messages in the text format
+pub const PROTOTEXT_CONTENT_TYPE: &str = "application/protobuf-text";
+
+/// If the content type is for a Protobuf message in the text format
+pub fn is_prototext_content_type(content_type: &str) -> bool {
+  content_type.split(';').next()
+    .map(|base_type| base_type.trim().eq_ignore_ascii_case(PROTOTEXT_CONTENT_TYPE))
+    .unwrap_or(false)
+}
+
+/// Parses the text format message and encodes it in the binary wire format
+pub fn prototext_to_binary(
+  text: &str,
+  message_name: &str,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<Bytes> {
+  debug!("Parsing text format message for '{}'", message_name);
+  let (descriptor, _) = find_message_type_by_name(message_name, descriptors)?;
+  let mut tokens = tokenise(text)?.into_iter().peekable();
+  let mut buffer = BytesMut::new();
+  parse_message(&mut tokens, None, &descriptor, descriptors, &mut buffer)?;
+  trace!("Encoded text format message to {} bytes", buffer.len());
+  Ok(buffer.freeze())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  Identifier(String),
+  String(Vec<u8>),
+  Number(String),
+  Symbol(char)
+}
+
+type Tokens = Peekable<std::vec::IntoIter<Token>>;
+
+fn tokenise(text: &str) -> anyhow::Result<Vec<Token>> {
+  let mut tokens = vec![];
+  let mut chars = text.chars().peekable();
+
+  while let Some(ch) = chars.peek().cloned() {
+    if ch.is_whitespace() {
+      chars.next();
+    } else if ch == '#' {
+      for ch in chars.by_ref() {
+        if ch == '\n' {
+          break;
+        }
+      }
+    } else if ch == '"' || ch == '\'' {
+      chars.next();
+      tokens.push(Token::String(read_string(&mut chars, ch)?));
+    } else if ch.is_ascii_digit() || ch == '.' {
+      let mut number = String::new();
+      while let Some(ch) = chars.peek().cloned() {
+        let exponent_sign = (ch == '-' || ch == '+') && number.ends_with(['e', 'E'])
+          && !number.starts_with("0x") && !number.starts_with("0X");
+        if ch.is_ascii_alphanumeric() || ch == '.' || exponent_sign {
+          number.push(ch);
+          chars.next();
+        } else {
+          break;
+        }
+      }
+      tokens.push(Token::Number(number));
+    } else if ch.is_alphabetic() || ch == '_' {
+      let mut identifier = String::new();
+      while let Some(ch) = chars.peek().cloned() {
+        if ch.is_alphanumeric() || ch == '_' {
+          identifier.push(ch);
+          chars.next();
+        } else {
+          break;
+        }
+      }
+      tokens.push(Token::Identifier(identifier));
+    } else if "{}<>[]:,;-".contains(ch) {
+      chars.next();
+      tokens.push(Token::Symbol(ch));
+    } else {
+      bail!("Invalid character '{}' in text format message", ch);
+    }
+  }
+
+  Ok(tokens)
+}
+
+fn read_string(chars: &mut Peekable<Chars>, quote: char) -> anyhow::Result<Vec<u8>> {
+  let mut buffer = vec![];
+  let mut utf8 = [0_u8; 4];
+
+  loop {
+    match chars.next() {
+      Some(ch) if ch == quote => return Ok(buffer),
+      Some('\\') => match chars.next() {
+        Some('n') => buffer.push(b'\n'),
+        Some('r') => buffer.push(b'\r'),
+        Some('t') => buffer.push(b'\t'),
+        Some('a') => buffer.push(0x07),
+        Some('b') => buffer.push(0x08),
+        Some('f') => buffer.push(0x0C),
+        Some('v') => buffer.push(0x0B),
+        Some('x') => {
+          let digits = read_digits(chars, 2, 16);
+          buffer.push(u8::from_str_radix(&digits, 16)
+            .map_err(|_| anyhow!("Invalid hex escape sequence in text format string"))?);
+        }
+        Some(ch) if ch.is_digit(8) => {
+          let digits = ch.to_string() + read_digits(chars, 2, 8).as_str();
+          buffer.push(u8::from_str_radix(&digits, 8)
+            .map_err(|_| anyhow!("Invalid octal escape sequence '\\{}' in text format string", digits))?);
+        }
+        Some(ch) if ch == 'u' || ch == 'U' => {
+          let digits = read_digits(chars, if ch == 'u' { 4 } else { 8 }, 16);
+          let ch = u32::from_str_radix(&digits, 16).ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| anyhow!("Invalid unicode escape sequence '\\{}' in text format string", digits))?;
+          buffer.extend_from_slice(ch.encode_utf8(&mut utf8).as_bytes());
+        }
+        Some(ch) => buffer.extend_from_slice(ch.encode_utf8(&mut utf8).as_bytes()),
+        None => bail!("Unterminated string in text format message")
+      },
+      Some(ch) => buffer.extend_from_slice(ch.encode_utf8(&mut utf8).as_bytes()),
+      None => bail!("Unterminated string in text format message")
+    }
+  }
+}
+
+fn read_digits(chars: &mut Peekable<Chars>, max: usize, radix: u32) -> String {
+  let mut digits = String::new();
+  while digits.len() < max {
+    match chars.peek() {
+      Some(ch) if ch.is_digit(radix) => {
+        digits.push(*ch);
+        chars.next();
+      }
+      _ => break
+    }
+  }
+  digits
+}
+
+fn parse_message(
+  tokens: &mut Tokens,
+  end: Option<char>,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet,
+  buffer: &mut BytesMut
+) -> anyhow::Result<()> {
+  loop {
+    let field_name = match tokens.next() {
+      None => return match end {
+        None => Ok(()),
+        Some(end) => Err(anyhow!("Expected '{}' but got to the end of the text format message", end))
+      },
+      Some(Token::Symbol(ch)) if Some(ch) == end => return Ok(()),
+      Some(Token::Identifier(name)) => name,
+      Some(token) => bail!("Expected a field name but got {:?}", token)
+    };
+
+    let field = descriptor.field.iter()
+      .find(|field| field.name.as_deref() == Some(field_name.as_str()))
+      .ok_or_else(|| anyhow!("Message '{}' does not have a field '{}'",
+        descriptor.name.clone().unwrap_or_default(), field_name))?;
+    trace!(%field_name, "Parsing text format field");
+
+    if tokens.peek() == Some(&Token::Symbol(':')) {
+      tokens.next();
+    }
+
+    if tokens.peek() == Some(&Token::Symbol('[')) && is_repeated_field(field) {
+      tokens.next();
+      if tokens.peek() == Some(&Token::Symbol(']')) {
+        tokens.next();
+      } else {
+        loop {
+          parse_field_value(tokens, field, descriptor, descriptors, buffer)?;
+          match tokens.next() {
+            Some(Token::Symbol(',')) => {}
+            Some(Token::Symbol(']')) => break,
+            token => bail!("Expected ',' or ']' in the list for field '{}' but got {:?}", field_name, token)
+          }
+        }
+      }
+    } else {
+      parse_field_value(tokens, field, descriptor, descriptors, buffer)?;
+    }
+
+    if matches!(tokens.peek(), Some(Token::Symbol(',')) | Some(Token::Symbol(';'))) {
+      tokens.next();
+    }
+  }
+}
+
+fn parse_field_value(
+  tokens: &mut Tokens,
+  field: &FieldDescriptorProto,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet,
+  buffer: &mut BytesMut
+) -> anyhow::Result<()> {
+  let tag = field.number.unwrap_or_default() as u32;
+  let field_name = field.name.clone().unwrap_or_default();
+
+  if field.r#type() == Type::Message {
+    let end = match tokens.next() {
+      Some(Token::Symbol('{')) => '}',
+      Some(Token::Symbol('<')) => '>',
+      token => bail!("Expected '{{' for message field '{}' but got {:?}", field_name, token)
+    };
+    let type_name = field.type_name.clone().unwrap_or_default();
+    let message_descriptor = descriptor.nested_type.iter()
+      .find(|nested| nested.name.as_deref() == Some(last_name(type_name.as_str())))
+      .cloned()
+      .or_else(|| find_message_type_by_name(last_name(type_name.as_str()), descriptors).map(|(m, _)| m).ok())
+      .ok_or_else(|| anyhow!("Did not find the message type {} for the field '{}'", type_name, field_name))?;
+    let mut message_buffer = BytesMut::new();
+    parse_message(tokens, Some(end), &message_descriptor, descriptors, &mut message_buffer)?;
+    encode_key(tag, WireType::LengthDelimited, buffer);
+    encode_varint(message_buffer.len() as u64, buffer);
+    buffer.put_slice(&message_buffer);
+    return Ok(());
+  }
+
+  let negative = if tokens.peek() == Some(&Token::Symbol('-')) {
+    tokens.next();
+    true
+  } else {
+    false
+  };
+
+  match tokens.next() {
+    Some(Token::String(mut data)) => {
+      if negative {
+        bail!("Invalid value '-' for field '{}'", field_name);
+      }
+      while let Some(Token::String(next)) = tokens.peek() {
+        data.extend_from_slice(next);
+        tokens.next();
+      }
+      match field.r#type() {
+        Type::String => {
+          let value = String::from_utf8(data)
+            .map_err(|_| anyhow!("Value for string field '{}' is not valid UTF-8", field_name))?;
+          prost::encoding::string::encode(tag, &value, buffer);
+        }
+        Type::Bytes => prost::encoding::bytes::encode(tag, &data, buffer),
+        t => bail!("Can not set a string value for field '{}' of type {:?}", field_name, t)
+      }
+    }
+    Some(Token::Number(number)) => encode_number(tag, field, negative, &number, buffer)?,
+    Some(Token::Identifier(identifier)) => match field.r#type() {
+      Type::Bool => match identifier.as_str() {
+        "true" | "True" | "t" => prost::encoding::bool::encode(tag, &true, buffer),
+        "false" | "False" | "f" => prost::encoding::bool::encode(tag, &false, buffer),
+        _ => bail!("Invalid boolean value '{}' for field '{}'", identifier, field_name)
+      },
+      Type::Enum => {
+        let enum_type_name = field.type_name.clone().unwrap_or_default();
+        let enum_descriptor = find_enum_by_name_in_message(&descriptor.enum_type, enum_type_name.as_str())
+          .or_else(|| find_enum_by_name(descriptors, enum_type_name.as_str()))
+          .ok_or_else(|| anyhow!("Did not find the enum {} for the field '{}'", enum_type_name, field_name))?;
+        let value = enum_descriptor.value.iter()
+          .find(|value| value.name.as_deref() == Some(identifier.as_str()))
+          .and_then(|value| value.number)
+          .ok_or_else(|| anyhow!("'{}' is not a valid value for the enum {}", identifier, enum_type_name))?;
+        prost::encoding::int32::encode(tag, &value, buffer);
+      }
+      Type::Float | Type::Double => {
+        let value = match identifier.to_lowercase().as_str() {
+          "inf" | "infinity" => f64::INFINITY,
+          "nan" => f64::NAN,
+          _ => bail!("Invalid floating point value '{}' for field '{}'", identifier, field_name)
+        };
+        let value = if negative { -value } else { value };
+        if field.r#type() == Type::Float {
+          prost::encoding::float::encode(tag, &(value as f32), buffer);
+        } else {
+          prost::encoding::double::encode(tag, &value, buffer);
+        }
+      }
+      t => bail!("Invalid value '{}' for field '{}' of type {:?}", identifier, field_name, t)
+    },
+    token => bail!("Expected a value for field '{}' but got {:?}", field_name, token)
+  }
+
+  Ok(())
+}
+
+fn encode_number(
+  tag: u32,
+  field: &FieldDescriptorProto,
+  negative: bool,
+  number: &str,
+  buffer: &mut BytesMut
+) -> anyhow::Result<()> {
+  let field_name = field.name.clone().unwrap_or_default();
+  let t = field.r#type();
+  match t {
+    Type::Float | Type::Double => {
+      let value: f64 = if number.starts_with("0x") || number.starts_with("0X") {
+        parse_integer(false, number)? as f64
+      } else {
+        number.trim_end_matches(['f', 'F']).parse()
+          .map_err(|_| anyhow!("Invalid floating point value '{}' for field '{}'", number, field_name))?
+      };
+      let value = if negative { -value } else { value };
+      if t == Type::Float {
+        prost::encoding::float::encode(tag, &(value as f32), buffer);
+      } else {
+        prost::encoding::double::encode(tag, &value, buffer);
+      }
+    }
+    _ => {
+      let value = parse_integer(negative, number)
+        .map_err(|err| anyhow!("Invalid value for field '{}' - {}", field_name, err))?;
+      let out_of_range = || anyhow!("Value {} is out of range for field '{}' of type {:?}", value, field_name, t);
+      match t {
+        Type::Int32 => prost::encoding::int32::encode(tag, &i32::try_from(value).map_err(|_| out_of_range())?, buffer),
+        Type::Sint32 => prost::encoding::sint32::encode(tag, &i32::try_from(value).map_err(|_| out_of_range())?, buffer),
+        Type::Sfixed32 => prost::encoding::sfixed32::encode(tag, &i32::try_from(value).map_err(|_| out_of_range())?, buffer),
+        Type::Enum => prost::encoding::int32::encode(tag, &i32::try_from(value).map_err(|_| out_of_range())?, buffer),
+        Type::Int64 => prost::encoding::int64::encode(tag, &i64::try_from(value).map_err(|_| out_of_range())?, buffer),
+        Type::Sint64 => prost::encoding::sint64::encode(tag, &i64::try_from(value).map_err(|_| out_of_range())?, buffer),
+        Type::Sfixed64 => prost::encoding::sfixed64::encode(tag, &i64::try_from(value).map_err(|_| out_of_range())?, buffer),
+        Type::Uint32 => prost::encoding::uint32::encode(tag, &u32::try_from(value).map_err(|_| out_of_range())?, buffer),
+        Type::Fixed32 => prost::encoding::fixed32::encode(tag, &u32::try_from(value).map_err(|_| out_of_range())?, buffer),
+        Type::Uint64 => prost::encoding::uint64::encode(tag, &u64::try_from(value).map_err(|_| out_of_range())?, buffer),
+        Type::Fixed64 => prost::encoding::fixed64::encode(tag, &u64::try_from(value).map_err(|_| out_of_range())?, buffer),
+        Type::Bool => match value {
+          0 => prost::encoding::bool::encode(tag, &false, buffer),
+          1 => prost::encoding::bool::encode(tag, &true, buffer),
+          _ => bail!("Invalid boolean value '{}' for field '{}'", number, field_name)
+        },
+        _ => bail!("Can not set a numeric value for field '{}' of type {:?}", field_name, t)
+      }
+    }
+  }
+  Ok(())
+}
+
+fn parse_integer(negative: bool, number: &str) -> anyhow::Result<i128> {
+  let value = if let Some(hex) = number.strip_prefix("0x").or_else(|| number.strip_prefix("0X")) {
+    i128::from_str_radix(hex, 16)
+  } else if number.len() > 1 && number.starts_with('0') {
+    i128::from_str_radix(&number[1..], 8)
+  } else {
+    number.parse()
+  }.map_err(|_| anyhow!("'{}' is not a valid integer", number))?;
+  Ok(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+  use bytes::Bytes;
+  use expectest::prelude::*;
+  use pact_matching::BodyMatchResult;
+  use pact_models::prelude::MatchingRuleCategory;
+  use prost::Message;
+  use prost_types::{
+    DescriptorProto,
+    EnumDescriptorProto,
+    EnumValueDescriptorProto,
+    FieldDescriptorProto,
+    FileDescriptorProto,
+    FileDescriptorSet
+  };
+  use prost_types::field_descriptor_proto::{Label, Type};
+
+  use crate::matching::match_message;
+  use crate::prototext::{is_prototext_content_type, prototext_to_binary};
+
+  fn field(name: &str, number: i32, field_type: Type, label: Label, type_name: Option<&str>) -> FieldDescriptorProto {
+    FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(label as i32),
+      r#type: Some(field_type as i32),
+      type_name: type_name.map(|name| name.to_string()),
+      .. FieldDescriptorProto::default()
+    }
+  }
+
+  fn descriptors() -> FileDescriptorSet {
+    FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          package: Some("test".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Shape".to_string()),
+              field: vec![
+                field("name", 1, Type::String, Label::Optional, None),
+                field("sides", 2, Type::Int32, Label::Optional, None),
+                field("lengths", 3, Type::Double, Label::Repeated, None),
+                field("colour", 4, Type::Enum, Label::Optional, Some(".test.Colour")),
+                field("origin", 5, Type::Message, Label::Optional, Some(".test.Point")),
+                field("closed", 6, Type::Bool, Label::Optional, None)
+              ],
+              .. DescriptorProto::default()
+            },
+            DescriptorProto {
+              name: Some("Point".to_string()),
+              field: vec![
+                field("x", 1, Type::Sint32, Label::Optional, None),
+                field("y", 2, Type::Sint32, Label::Optional, None)
+              ],
+              .. DescriptorProto::default()
+            }
+          ],
+          enum_type: vec![
+            EnumDescriptorProto {
+              name: Some("Colour".to_string()),
+              value: vec![
+                EnumValueDescriptorProto { name: Some("RED".to_string()), number: Some(0), .. EnumValueDescriptorProto::default() },
+                EnumValueDescriptorProto { name: Some("BLUE".to_string()), number: Some(1), .. EnumValueDescriptorProto::default() }
+              ],
+              .. EnumDescriptorProto::default()
+            }
+          ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    }
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  struct Point {
+    #[prost(sint32, tag = "1")]
+    x: i32,
+    #[prost(sint32, tag = "2")]
+    y: i32
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  struct Shape {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(int32, tag = "2")]
+    sides: i32,
+    #[prost(double, repeated, tag = "3")]
+    lengths: Vec<f64>,
+    #[prost(int32, tag = "4")]
+    colour: i32,
+    #[prost(message, optional, tag = "5")]
+    origin: Option<Point>,
+    #[prost(bool, tag = "6")]
+    closed: bool
+  }
+
+  #[test]
+  fn is_prototext_content_type_test() {
+    expect!(is_prototext_content_type("application/protobuf-text")).to(be_true());
+    expect!(is_prototext_content_type("application/protobuf-text; message=Shape")).to(be_true());
+    expect!(is_prototext_content_type("application/protobuf")).to(be_false());
+    expect!(is_prototext_content_type("")).to(be_false());
+  }
+
+  #[test_log::test]
+  fn prototext_to_binary_test() {
+    let text = r#"
+      # A triangle
+      name: "tri" 'angle'
+      sides: 3
+      lengths: [3.0, 4.0]
+      lengths: 5
+      colour: BLUE
+      origin { x: -1 y: 0x10 }
+      closed: true
+    "#;
+    let bytes = prototext_to_binary(text, "Shape", &descriptors()).unwrap();
+    let shape = Shape::decode(bytes).unwrap();
+    expect!(shape).to(be_equal_to(Shape {
+      name: "triangle".to_string(),
+      sides: 3,
+      lengths: vec![3.0, 4.0, 5.0],
+      colour: 1,
+      origin: Some(Point { x: -1, y: 16 }),
+      closed: true
+    }));
+
+    expect!(prototext_to_binary("sides: \"3\"", "Shape", &descriptors())).to(be_err());
+    expect!(prototext_to_binary("corners: 3", "Shape", &descriptors())).to(be_err());
+    expect!(prototext_to_binary("origin { x: 1", "Shape", &descriptors())).to(be_err());
+    expect!(prototext_to_binary("colour: GREEN", "Shape", &descriptors())).to(be_err());
+  }
+
+  #[test_log::test]
+  fn match_prototext_body_against_a_message() {
+    let descriptors = descriptors();
+    let expected = Shape {
+      name: "square".to_string(),
+      sides: 4,
+      lengths: vec![1.0, 1.0, 1.0, 1.0],
+      colour: 0,
+      origin: Some(Point { x: 1, y: 2 }),
+      closed: true
+    };
+    let mut expected_bytes = Bytes::from(expected.encode_to_vec());
+
+    let mut actual = prototext_to_binary(
+      "name: 'square' sides: 4 lengths: [1, 1, 1, 1] origin < x: 1, y: 2 > closed: true",
+      "Shape", &descriptors).unwrap();
+    let result = match_message("Shape", &descriptors, &mut expected_bytes.clone(), &mut actual,
+      &MatchingRuleCategory::empty("body"), false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let mut actual = prototext_to_binary("name: 'square' sides: 3 closed: true", "Shape", &descriptors).unwrap();
+    let result = match_message("Shape", &descriptors, &mut expected_bytes, &mut actual,
+      &MatchingRuleCategory::empty("body"), false).unwrap();
+    expect!(result.mismatches().is_empty()).to(be_false());
+  }
+}
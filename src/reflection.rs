@@ -0,0 +1,148 @@
+//! Client for the gRPC server reflection service, used to get the descriptors from the provider
+
+use anyhow::anyhow;
+use prost::Message;
+use prost_types::{FileDescriptorProto, MethodDescriptorProto};
+use tonic::codec::ProstCodec;
+use tonic::transport::Channel;
+use tracing::{debug, trace};
+
+/// Path of the server reflection method
+const SERVER_REFLECTION_PATH: &str = "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo";
+
+/// Request to the server reflection service
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerReflectionRequest {
+  #[prost(string, tag = "1")]
+  pub host: String,
+  /// Fully-qualified name of the symbol to get the file descriptor for
+  #[prost(string, optional, tag = "4")]
+  pub file_containing_symbol: Option<String>
+}
+
+/// Response from the server reflection service
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerReflectionResponse {
+  #[prost(message, optional, tag = "4")]
+  pub file_descriptor_response: Option<FileDescriptorResponse>,
+  #[prost(message, optional, tag = "7")]
+  pub error_response: Option<ErrorResponse>
+}
+
+/// Encoded file descriptors returned by the server reflection service
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FileDescriptorResponse {
+  #[prost(bytes = "vec", repeated, tag = "1")]
+  pub file_descriptor_proto: Vec<Vec<u8>>
+}
+
+/// Error returned by the server reflection service
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ErrorResponse {
+  #[prost(int32, tag = "1")]
+  pub error_code: i32,
+  #[prost(string, tag = "2")]
+  pub error_message: String
+}
+
+/// Looks up the descriptor of the service method from the provider with the server reflection
+/// service. The service name must be fully-qualified (i.e. include the package).
+pub(crate) async fn provider_method_descriptor(
+  channel: Channel,
+  service_name: &str,
+  method_name: &str
+) -> anyhow::Result<MethodDescriptorProto> {
+  debug!("Looking up the descriptor for {} with server reflection", service_name);
+  let request = ServerReflectionRequest {
+    host: String::default(),
+    file_containing_symbol: Some(service_name.to_string())
+  };
+  let mut grpc = tonic::client::Grpc::new(channel);
+  grpc.ready().await?;
+  let path = http::uri::PathAndQuery::from_static(SERVER_REFLECTION_PATH);
+  let codec: ProstCodec<ServerReflectionRequest, ServerReflectionResponse> = ProstCodec::default();
+  let response = grpc.streaming(tonic::Request::new(futures::stream::iter(vec![ request ])), path, codec).await
+    .map_err(|status| anyhow!("Server reflection request failed with status {} - '{}'", status.code(), status.message()))?;
+  let response = response.into_inner().message().await?
+    .ok_or_else(|| anyhow!("The server reflection service did not return a response"))?;
+  trace!(?response, "Server reflection response");
+  method_descriptor_from_response(&response, service_name, method_name)
+}
+
+/// Finds the method descriptor in the file descriptors returned by the server reflection service
+fn method_descriptor_from_response(
+  response: &ServerReflectionResponse,
+  service_name: &str,
+  method_name: &str
+) -> anyhow::Result<MethodDescriptorProto> {
+  if let Some(error) = &response.error_response {
+    return Err(anyhow!("Server reflection returned an error for {} - {}", service_name, error.error_message));
+  }
+  let files = response.file_descriptor_response.as_ref()
+    .map(|response| response.file_descriptor_proto.clone())
+    .unwrap_or_default();
+  for bytes in files {
+    let file = FileDescriptorProto::decode(bytes.as_slice())?;
+    let package = file.package.clone().unwrap_or_default();
+    let service = file.service.iter().find(|service| {
+      let name = service.name.clone().unwrap_or_default();
+      if package.is_empty() { name == service_name } else { format!("{}.{}", package, name) == service_name }
+    });
+    if let Some(service) = service {
+      return service.method.iter()
+        .find(|method| method.name.clone().unwrap_or_default() == method_name)
+        .cloned()
+        .ok_or_else(|| anyhow!("The provider's descriptor for {} does not have a method {}", service_name, method_name));
+    }
+  }
+  Err(anyhow!("Server reflection did not return the descriptor for {}", service_name))
+}
+
+#[cfg(test)]
+mod tests {
+  use expectest::prelude::*;
+  use prost::Message;
+  use prost_types::{FileDescriptorProto, MethodDescriptorProto, MethodOptions, ServiceDescriptorProto};
+  use prost_types::method_options::IdempotencyLevel;
+
+  use super::*;
+
+  #[test]
+  fn method_descriptor_from_response_test() {
+    let method = MethodDescriptorProto {
+      name: Some("calculateOne".to_string()),
+      options: Some(MethodOptions {
+        idempotency_level: Some(IdempotencyLevel::NoSideEffects as i32),
+        .. MethodOptions::default()
+      }),
+      .. MethodDescriptorProto::default()
+    };
+    let file = FileDescriptorProto {
+      package: Some("area_calculator".to_string()),
+      service: vec![
+        ServiceDescriptorProto {
+          name: Some("Calculator".to_string()),
+          method: vec![ method.clone() ],
+          .. ServiceDescriptorProto::default()
+        }
+      ],
+      .. FileDescriptorProto::default()
+    };
+    let response = ServerReflectionResponse {
+      file_descriptor_response: Some(FileDescriptorResponse { file_descriptor_proto: vec![ file.encode_to_vec() ] }),
+      error_response: None
+    };
+
+    expect!(method_descriptor_from_response(&response, "area_calculator.Calculator", "calculateOne").unwrap())
+      .to(be_equal_to(method));
+    expect!(method_descriptor_from_response(&response, "area_calculator.Calculator", "calculateMulti")).to(be_err());
+    expect!(method_descriptor_from_response(&response, "Calculator", "calculateOne")).to(be_err());
+
+    let response = ServerReflectionResponse {
+      file_descriptor_response: None,
+      error_response: Some(ErrorResponse { error_code: 5, error_message: "symbol not found".to_string() })
+    };
+    expect!(method_descriptor_from_response(&response, "area_calculator.Calculator", "calculateOne").unwrap_err().to_string())
+      .to(be_equal_to("Server reflection returned an error for area_calculator.Calculator - symbol not found"));
+  }
+}
@@ -4,17 +4,20 @@ use std::collections::{BTreeMap, HashMap};
 use std::collections::hash_map::Entry;
 use std::fs::File;
 use std::io::BufReader;
+use std::path::PathBuf;
 
 use anyhow::{anyhow, bail};
 use bytes::{Bytes, BytesMut};
+use itertools::Itertools;
 use maplit::hashmap;
 use pact_matching::{BodyMatchResult, Mismatch};
 use pact_models::generators::{GenerateValue, Generator, NoopVariantMatcher, VariantMatcher};
-use pact_models::json_utils::json_to_string;
+use pact_models::json_utils::{json_to_num, json_to_string};
 use pact_models::matchingrules::MatchingRule;
 use pact_models::path_exp::DocPath;
+use pact_models::plugins::PluginData;
 use pact_models::prelude::{ContentType, MatchingRuleCategory, OptionalBody, RuleLogic};
-use pact_plugin_driver::plugin_models::PactPluginManifest;
+use pact_plugin_driver::plugin_models::{PactPluginManifest, PluginInteractionConfig};
 use pact_plugin_driver::proto;
 use pact_plugin_driver::proto::{Body, body, CompareContentsRequest, CompareContentsResponse, GenerateContentRequest, GenerateContentResponse, MockServerResult, PluginConfiguration};
 use pact_plugin_driver::proto::body::ContentTypeHint;
@@ -36,21 +39,61 @@ use tonic::metadata::KeyAndValueRef;
 use tracing::{debug, error, info, instrument, trace};
 
 use crate::dynamic_message::DynamicMessage;
-use crate::matching::{match_message, match_service};
-use crate::message_decoder::{decode_message, ProtobufField};
+use crate::matching::{
+  apply_field_mask,
+  FIELD_MASK_CONFIG,
+  field_mask_paths,
+  lookup_service_message_type,
+  match_message_with_config,
+  match_service_with_config
+};
+use crate::message_decoder::{
+  apply_wrapper_nullability,
+  decode_message,
+  EXAMPLE_OPTION_CONFIG,
+  EXAMPLE_VALUES_CONFIG,
+  example_values_from_config,
+  FILL_DEFAULTS_CONFIG,
+  GENERATOR_SEED_CONFIG,
+  GENERATOR_SEED_ENV_VAR,
+  populate_example_values,
+  populate_unset_fields,
+  ProtobufField,
+  qualified_field_name,
+  VALIDATION_OPTION_CONFIG,
+  ValidationConstraints,
+  with_generator_seed,
+  WRAPPER_NULLS_CONFIG,
+  wrapper_nullability_from_config
+};
 use crate::metadata::MetadataMatchResult;
-use crate::mock_server::{GrpcMockServer, MOCK_SERVER_STATE};
+use crate::mock_server::{
+  GrpcMockServer,
+  mock_server_results,
+  MockServerTls,
+  RECORDING_FILE_CONFIG,
+  remove_mock_server,
+  TLS_CONFIG,
+  UPSTREAM_CONFIG
+};
 use crate::protobuf::process_proto;
-use crate::protoc::setup_protoc;
+use crate::prototext::{is_prototext_content_type, prototext_to_binary};
+use crate::protoc::{Protoc, setup_protoc};
+use crate::timings::timed;
 use crate::utils::{
+  field_option_raw_values,
+  field_option_values,
+  find_field_option_number,
   find_message_type_by_name,
+  get_descriptor_bytes_for_interaction,
   get_descriptors_for_interaction,
   last_name,
   lookup_interaction_by_id,
   lookup_service_descriptors_for_interaction,
-  parse_pact_from_request_json
+  parse_pact_from_request_json,
+  RawFieldValue
 };
-use crate::verification::verify_interaction;
+use crate::verification::{MESSAGE_FRAMING_CONFIG, MessageFraming, unframe_message, verify_interaction};
 
 /// Plugin gRPC server implementation
 #[derive(Debug, Default)]
@@ -89,6 +132,23 @@ impl ProtobufPactPlugin {
       .map(json_to_string)
   }
 
+  /// Returns the maximum number of requests the mock server will handle at the same time from the
+  /// configuration in the manifest.
+  pub fn max_concurrent_requests(&self) -> Option<usize> {
+    json_to_num(self.manifest.plugin_config.get("maxConcurrentRequests").cloned())
+      .filter(|max| *max > 0)
+  }
+
+  /// If the aggregated timings of the request handlers must be logged when the plugin shuts down,
+  /// from the configuration in the manifest.
+  pub fn log_handler_timings(&self) -> bool {
+    match self.manifest.plugin_config.get("logHandlerTimings") {
+      Some(Value::Bool(b)) => *b,
+      Some(Value::String(s)) => s.eq_ignore_ascii_case("true"),
+      _ => false
+    }
+  }
+
   /// Returns any additional include paths from the configuration in the manifest to add to the
   /// Protocol Buffers compiler call.
   pub fn additional_includes(&self, config: &HashMap<String, Value>) -> Vec<String> {
@@ -142,6 +202,16 @@ impl ProtobufPactPlugin {
                     ..proto::ContentMismatch::default()
                   });
                 }
+                Mismatch::PathMismatch { ref expected, ref actual, .. } | Mismatch::MethodMismatch { ref expected, ref actual } => {
+                  // the request was for a service method the mock server is not configured for
+                  proto_result.push(proto::ContentMismatch {
+                    expected: Some(expected.as_bytes().to_vec()),
+                    actual: Some(actual.as_bytes().to_vec()),
+                    mismatch: m.description(),
+                    mismatch_type: "path".to_string(),
+                    ..proto::ContentMismatch::default()
+                  });
+                }
                 _ => {
                   proto_result.push(proto::ContentMismatch {
                     mismatch: m.description(),
@@ -185,6 +255,23 @@ impl ProtobufPactPlugin {
     (ok, results)
   }
 
+  /// If the body is in the Protobuf text format, converts it to the binary wire format
+  fn prototext_body(
+    body: Option<&Body>,
+    contents: Bytes,
+    message_name: &str,
+    descriptors: &FileDescriptorSet
+  ) -> anyhow::Result<Bytes> {
+    match body {
+      Some(body) if is_prototext_content_type(body.content_type.as_str()) => {
+        let text = std::str::from_utf8(&contents)
+          .map_err(|err| anyhow!("Text format message is not valid UTF-8 - {}", err))?;
+        prototext_to_binary(text, message_name, descriptors)
+      }
+      _ => Ok(contents)
+    }
+  }
+
   fn compare_contents_impl(&self, request: &CompareContentsRequest) -> anyhow::Result<CompareContentsResponse> {
     // Check for the plugin specific configuration for the interaction
     let plugin_configuration = request.plugin_configuration.clone().unwrap_or_default();
@@ -197,9 +284,28 @@ impl ProtobufPactPlugin {
 
     // From the plugin configuration for the interaction, there should be either a message type name
     // or a service name. Check for either.
+    let field_mask = interaction_config.get(FIELD_MASK_CONFIG)
+      .map(|mask| field_mask_paths(&proto_value_to_json(mask)))
+      .unwrap_or_default();
+    let plugin_config = hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: plugin_configuration.pact_configuration.clone().unwrap_or_default()
+          .fields.iter()
+          .map(|(key, value)| (key.clone(), proto_value_to_json(value)))
+          .collect(),
+        interaction_configuration: interaction_config.iter()
+          .map(|(key, value)| (key.clone(), proto_value_to_json(value)))
+          .collect()
+      }
+    };
+    // The actual message from the provider may be framed (i.e. in a gRPC length-prefixed frame)
+    let framing = interaction_config.get(MESSAGE_FRAMING_CONFIG)
+      .and_then(proto_value_to_string)
+      .map(|framing| MessageFraming::parse(framing.as_str()))
+      .transpose()?;
     let (message, service) = Self::lookup_message_and_service(interaction_config)?;
 
-    let descriptors = Self::lookup_descriptors(plugin_configuration, message_key)?;
+    let descriptors = Self::lookup_descriptors(plugin_configuration.clone(), message_key.clone())?;
 
     let mut expected_body = request.expected.as_ref()
       .and_then(|body| body.content.clone().map(Bytes::from))
@@ -223,15 +329,26 @@ impl ProtobufPactPlugin {
       }
     }
 
+    let prototext = [request.expected.as_ref(), request.actual.as_ref()].iter()
+      .any(|body| body.map(|body| is_prototext_content_type(body.content_type.as_str())).unwrap_or(false));
+    if let Some(framing) = framing.filter(|_| !prototext) {
+      actual_body = unframe_message(actual_body, framing)?;
+    }
+
     let result = if let Some(message_name) = message {
       debug!("Received compare_contents request for message {}", message_name);
-      match_message(
+      if prototext {
+        expected_body = Self::prototext_body(request.expected.as_ref(), expected_body, &message_name, &descriptors)?;
+        actual_body = Self::prototext_body(request.actual.as_ref(), actual_body, &message_name, &descriptors)?;
+      }
+      match_message_with_config(
         message_name.as_str(),
         &descriptors,
         &mut expected_body,
         &mut actual_body,
         &matching_rules,
-        request.allow_unexpected_keys
+        request.allow_unexpected_keys,
+        &plugin_config
       )
     } else if let Some(service_name) = service {
       debug!("Received compareContents request for service {}", service_name);
@@ -245,7 +362,12 @@ impl ProtobufPactPlugin {
         Ok(ct) => ct,
         Err(err) => return Err(anyhow!("Expected content type is not set or not valid - {}", err))
       };
-      match_service(
+      if prototext {
+        let message_name = lookup_service_message_type(service, method, &descriptors, &expected_content_type)?;
+        expected_body = Self::prototext_body(request.expected.as_ref(), expected_body, &message_name, &descriptors)?;
+        actual_body = Self::prototext_body(request.actual.as_ref(), actual_body, &message_name, &descriptors)?;
+      }
+      match_service_with_config(
         service,
         method,
         &descriptors,
@@ -253,11 +375,13 @@ impl ProtobufPactPlugin {
         &mut actual_body,
         &matching_rules,
         request.allow_unexpected_keys,
-        &expected_content_type
+        &expected_content_type,
+        &plugin_config
       )
     } else {
       Err(anyhow!("Did not get a message or service to match"))
     };
+    let result = result.map(|result| apply_field_mask(result, &field_mask));
 
     match result {
       Ok(result) => match result {
@@ -297,6 +421,62 @@ impl ProtobufPactPlugin {
     get_descriptors_for_interaction(message_key.as_str(), &config_for_interaction)
   }
 
+  /// Looks up the example values set with the custom field option on the fields of the messages
+  fn lookup_example_values(
+    plugin_configuration: &PluginConfiguration,
+    message_key: &str,
+    option_name: &str,
+    descriptors: &FileDescriptorSet
+  ) -> anyhow::Result<HashMap<String, String>> {
+    let (descriptor_bytes, option_number) = Self::lookup_field_option(plugin_configuration,
+      message_key, option_name, descriptors)?;
+    field_option_values(&descriptor_bytes, option_number)
+  }
+
+  /// Looks up the validation constraints from the field option for the fields the generators are
+  /// for, keyed by the generator path
+  fn lookup_validation_constraints<'a>(
+    plugin_configuration: &PluginConfiguration,
+    message_key: &str,
+    option_name: &str,
+    descriptors: &FileDescriptorSet,
+    message_name: &str,
+    message_descriptor: &prost_types::DescriptorProto,
+    generator_paths: impl Iterator<Item = &'a String>
+  ) -> anyhow::Result<HashMap<String, ValidationConstraints>> {
+    let (descriptor_bytes, option_number) = Self::lookup_field_option(plugin_configuration,
+      message_key, option_name, descriptors)?;
+    let rules = field_option_raw_values(&descriptor_bytes, option_number)?;
+    let mut constraints = hashmap!{};
+    for path in generator_paths {
+      let rules_for_field = qualified_field_name(path, message_name, message_descriptor, descriptors)
+        .and_then(|field_name| rules.get(&field_name));
+      if let Some(RawFieldValue::LengthDelimited(field_rules)) = rules_for_field {
+        let field_constraints = ValidationConstraints::parse(field_rules.clone())?;
+        trace!(%path, ?field_constraints, "Found validation constraints for generator");
+        constraints.insert(path.clone(), field_constraints);
+      }
+    }
+    Ok(constraints)
+  }
+
+  /// Returns the encoded descriptors and the field number of the named custom field option
+  fn lookup_field_option(
+    plugin_configuration: &PluginConfiguration,
+    message_key: &str,
+    option_name: &str,
+    descriptors: &FileDescriptorSet
+  ) -> anyhow::Result<(Bytes, u32)> {
+    let option_number = find_field_option_number(descriptors, option_name)
+      .ok_or_else(|| anyhow!("Did not find a field option with name '{}' in the Protobuf descriptors", option_name))?;
+    let config_for_interaction = plugin_configuration.pact_configuration.clone().unwrap_or_default()
+      .fields.iter()
+      .map(|(key, config)| (key.clone(), proto_value_to_json(config)))
+      .collect();
+    let descriptor_bytes = get_descriptor_bytes_for_interaction(message_key, &config_for_interaction)?;
+    Ok((descriptor_bytes, option_number))
+  }
+
   fn lookup_message_and_service(
     interaction_config: BTreeMap<String, prost_types::Value>
   ) -> anyhow::Result<(Option<String>, Option<String>)> {
@@ -330,22 +510,74 @@ impl ProtobufPactPlugin {
     // to lookup the encoded Protobuf descriptors in the Pact level plugin configuration
     let message_key = Self::lookup_message_key(&interaction_config)?;
     debug!("generate_contents: message_key = {}", message_key);
+    let fill_defaults = interaction_config.get(FILL_DEFAULTS_CONFIG)
+      .map(|value| matches!(value.kind, Some(Kind::BoolValue(true))))
+      .unwrap_or(false);
 
-    let descriptors = Self::lookup_descriptors(plugin_configuration, message_key)?;
+    let example_option = interaction_config.get(EXAMPLE_OPTION_CONFIG).and_then(proto_value_to_string);
+    let example_values = interaction_config.get(EXAMPLE_VALUES_CONFIG).map(proto_value_to_json);
+    let validation_option = interaction_config.get(VALIDATION_OPTION_CONFIG).and_then(proto_value_to_string);
+    let wrapper_nulls = interaction_config.get(WRAPPER_NULLS_CONFIG)
+      .map(|value| wrapper_nullability_from_config(&proto_value_to_json(value)))
+      .transpose()?;
+    let seed = generator_seed(interaction_config.get(GENERATOR_SEED_CONFIG),
+      std::env::var(GENERATOR_SEED_ENV_VAR).ok())?;
+
+    let descriptors = Self::lookup_descriptors(plugin_configuration.clone(), message_key.clone())?;
 
     if let Some(contents) = &request.contents {
       let content_type = ContentType::parse(contents.content_type.as_str())?;
       match content_type.attributes.get("message") {
         Some(message_type) => {
           debug!("Generating contents for message {}", message_type);
-          let (message_descriptor, _file_descriptor) = find_message_type_by_name(message_type, &descriptors)?;
+          let (message_descriptor, file_descriptor) = find_message_type_by_name(message_type, &descriptors)?;
           let mut body = contents.content.clone().map(Bytes::from).unwrap_or_default();
           if body.is_empty() {
             Ok(GenerateContentResponse::default())
           } else {
-            let message = decode_message(&mut body, &message_descriptor, &descriptors)?;
+            let mut message = decode_message(&mut body, &message_descriptor, &descriptors)?;
+            let name = message_descriptor.name.clone().unwrap_or_default();
+            let message_name = match file_descriptor.package.as_deref() {
+              Some(package) if !package.is_empty() => format!("{}.{}", package, name),
+              _ => name
+            };
+            if example_option.is_some() || example_values.is_some() {
+              let mut examples = hashmap!{};
+              if let Some(option_name) = &example_option {
+                debug!("Populating any unset fields with the example values from the '{}' field option", option_name);
+                examples = Self::lookup_example_values(&plugin_configuration, message_key.as_str(),
+                  option_name, &descriptors)?;
+              }
+              if let Some(example_values) = &example_values {
+                debug!("Populating any unset fields with the configured example values");
+                examples.extend(example_values_from_config(example_values, message_name.as_str())?);
+              }
+              let generated_fields = request.generators.keys()
+                .filter_map(|key| key.strip_prefix("$."))
+                .map(|key| key.to_string())
+                .collect_vec();
+              populate_example_values(&mut message, message_name.as_str(), &message_descriptor, &descriptors,
+                &examples, &generated_fields);
+            }
+            if fill_defaults {
+              debug!("Populating any unset fields with default values");
+              populate_unset_fields(&mut message, &message_descriptor, &file_descriptor, &descriptors);
+            }
+            if let Some(wrapper_nulls) = &wrapper_nulls {
+              debug!("Applying the null settings to the wrapper fields");
+              apply_wrapper_nullability(&mut message, &message_descriptor, &descriptors, wrapper_nulls);
+            }
+            let constraints = match &validation_option {
+              Some(option_name) => {
+                debug!("Constraining the generated values with the validation rules from the '{}' field option", option_name);
+                Self::lookup_validation_constraints(&plugin_configuration, message_key.as_str(), option_name,
+                  &descriptors, message_name.as_str(), &message_descriptor, request.generators.keys())?
+              }
+              None => hashmap!{}
+            };
             debug!("message to generate = {:?}", message);
-            let generated_message = generate_protobuf_contents(&message, &content_type, &request.generators, &descriptors)?;
+            let generated_message = with_generator_seed(seed, || generate_protobuf_contents(&message,
+              &content_type, &request.generators, &constraints, &descriptors))?;
             Ok(GenerateContentResponse {
               contents: Some(generated_message),
             })
@@ -358,6 +590,116 @@ impl ProtobufPactPlugin {
     }
   }
 
+  async fn verify_interaction_impl(
+    &self,
+    request: &proto::VerifyInteractionRequest
+  ) -> Result<Response<proto::VerifyInteractionResponse>, Status> {
+    debug!("Received verify interaction request");
+
+    trace!("Got verify_interaction request {:?}", request);
+
+    let pact = match parse_pact_from_request_json(request.pact.as_str(), "grpc:verify_interaction") {
+      Ok(pact) => pact,
+      Err(err) => return Ok(Response::new(proto::VerifyInteractionResponse {
+        response: Some(proto::verify_interaction_response::Response::Error(format!("Failed to parse Pact JSON: {}", err))),
+        .. proto::VerifyInteractionResponse::default()
+      }))
+    };
+
+    let key = request.interaction_key.as_str();
+    let interaction_by_id = lookup_interaction_by_id(key, &pact);
+    let interaction = match interaction_by_id {
+      Some(interaction) => match interaction.as_v4_sync_message() {
+        Some(interaction) => interaction,
+        None => return Ok(Response::new(proto::VerifyInteractionResponse {
+          response: Some(proto::verify_interaction_response::Response::Error(format!("gRPC interactions must be of type V4 synchronous message, got {}", interaction.type_of()))),
+          .. proto::VerifyInteractionResponse::default()
+        }))
+      }
+      None => {
+        error!(?key, "Did not find an interaction that matches the given key");
+        return Ok(Response::new(proto::VerifyInteractionResponse {
+          response: Some(proto::verify_interaction_response::Response::Error(
+            format!("Did not find an interaction that matches the given key '{}'", key)
+          )),
+          ..proto::VerifyInteractionResponse::default()
+        }))
+      }
+    };
+
+    let body = match &request.interaction_data {
+      Some(data) => match &data.body {
+        Some(b) => match &b.content {
+          Some(data) => OptionalBody::Present(Bytes::from(data.clone()), Some(ContentType::from(b.content_type.clone())), None),
+          None => OptionalBody::Missing
+        }
+        None => OptionalBody::Missing
+      }
+      None => OptionalBody::Missing
+    };
+    let metadata = match &request.interaction_data {
+      Some(data) => data.metadata.clone(),
+      None => HashMap::default()
+    };
+
+    let config = request.config.as_ref().map(proto_struct_to_map).unwrap_or_default();
+    match verify_interaction(&pact, &interaction, &body, &metadata, &config).await {
+      Ok((result, output)) => {
+        let results = result.iter()
+          .flat_map(|result| match result {
+            VerificationMismatchResult::Mismatches { mismatches, .. } => {
+              mismatches.iter()
+                .map(|mismatch| {
+                  if let Mismatch::BodyMismatch { path, expected, actual, mismatch } = mismatch {
+                    proto::VerificationResultItem {
+                      result: Some(proto::verification_result_item::Result::Mismatch(proto::ContentMismatch {
+                        expected: expected.as_ref().map(|b| b.to_vec()),
+                        actual: actual.as_ref().map(|b| b.to_vec()),
+                        mismatch: mismatch.clone(),
+                        path: path.clone(),
+                        .. proto::ContentMismatch::default()
+                      })),
+                      .. proto::VerificationResultItem::default()
+                    }
+                  } else {
+                    proto::VerificationResultItem {
+                      result: Some(proto::verification_result_item::Result::Mismatch(proto::ContentMismatch {
+                        mismatch: mismatch.description(),
+                        .. proto::ContentMismatch::default()
+                      })),
+                      .. proto::VerificationResultItem::default()
+                    }
+                  }
+                })
+                .collect()
+            }
+            VerificationMismatchResult::Error { error, .. } => {
+              vec![proto::VerificationResultItem {
+                result: Some(proto::verification_result_item::Result::Error(error.clone())),
+                .. proto::VerificationResultItem::default()
+              }]
+            }
+          })
+          .collect();
+        Ok(Response::new(proto::VerifyInteractionResponse {
+          response: Some(proto::verify_interaction_response::Response::Result(proto::VerificationResult {
+            success: result.is_empty(),
+            mismatches: results,
+            output,
+            .. proto::VerificationResult::default()
+          })),
+          .. proto::VerifyInteractionResponse::default()
+        }))
+      }
+      Err(err) => {
+        Ok(Response::new(proto::VerifyInteractionResponse {
+          response: Some(proto::verify_interaction_response::Response::Error(err.to_string())),
+          .. proto::VerifyInteractionResponse::default()
+        }))
+      }
+    }
+  }
+
   fn setup_plugin_config(&self, fields: &BTreeMap<String, prost_types::Value>) -> anyhow::Result<HashMap<String, Value>> {
     match fields.get("pact:protobuf-config") {
       Some(config) => if let Some(kind) = &config.kind {
@@ -388,11 +730,80 @@ impl ProtobufPactPlugin {
   }
 }
 
+/// If the interaction configuration is only to be validated (`pact:dry-run`). The proto file is
+/// compiled and the configuration applied to the types, but no interactions are returned.
+fn is_dry_run(fields: &BTreeMap<String, prost_types::Value>) -> bool {
+  fields.get("pact:dry-run")
+    .map(|value| matches!(value.kind, Some(Kind::BoolValue(true))))
+    .unwrap_or(false)
+}
+
+/// Returns the seed for the random values of the generators, from the interaction configuration or
+/// else the environment variable
+fn generator_seed(config: Option<&prost_types::Value>, env_value: Option<String>) -> anyhow::Result<Option<u64>> {
+  let seed = match config.map(|value| &value.kind) {
+    Some(Some(Kind::NumberValue(n))) => if *n >= 0.0 && n.fract() == 0.0 && *n <= u64::MAX as f64 {
+      return Ok(Some(*n as u64))
+    } else {
+      bail!("'{}' is not a valid seed for the generators, it must be a positive whole number", n)
+    },
+    Some(Some(Kind::StringValue(s))) => Some(s.clone()),
+    Some(_) => bail!("{} must be a number", GENERATOR_SEED_CONFIG),
+    None => env_value
+  };
+  seed.map(|seed| seed.trim().parse::<u64>()
+      .map_err(|err| anyhow!("'{}' is not a valid seed for the generators - {}", seed, err)))
+    .transpose()
+}
+
+/// Process the proto file with the protoc compiler and configure the interaction
+async fn configure_interaction_with_protoc(
+  proto_file: String,
+  protoc: &Protoc,
+  fields: &BTreeMap<String, prost_types::Value>
+) -> proto::ConfigureInteractionResponse {
+  match process_proto(proto_file, protoc, fields).await {
+    Ok(_) if is_dry_run(fields) => {
+      debug!("Dry run, the proto file and configuration are valid");
+      proto::ConfigureInteractionResponse::default()
+    }
+    Ok((interactions, plugin_config)) => {
+      proto::ConfigureInteractionResponse {
+        interaction: interactions,
+        plugin_configuration: Some(plugin_config),
+        .. proto::ConfigureInteractionResponse::default()
+      }
+    }
+    Err(err) => {
+      error!("Failed to process protobuf: {}", err);
+      proto::ConfigureInteractionResponse {
+        error: format!("Failed to process protobuf: {}", err),
+        .. proto::ConfigureInteractionResponse::default()
+      }
+    }
+  }
+}
+
+/// Error message for when the Pact file does not have any Protobuf descriptors. This lists any
+/// plugin data that is in the Pact file, in case the descriptors were stored under another name.
+fn no_descriptors_error(plugin_data: &[PluginData]) -> String {
+  if plugin_data.is_empty() {
+    "Provided Pact file does not have any Protobuf descriptors".to_string()
+  } else {
+    let entries = plugin_data.iter()
+      .map(|data| format!("'{}' (version {})", data.name, data.version))
+      .join(", ");
+    format!("Provided Pact file does not have any Protobuf descriptors (no plugin data with name 'protobuf' was found). \
+      The Pact file has plugin data for {}", entries)
+  }
+}
+
 #[instrument(level = "trace")]
 fn generate_protobuf_contents(
   fields: &Vec<ProtobufField>,
   content_type: &ContentType,
   generators: &HashMap<String, proto::Generator>,
+  constraints: &HashMap<String, ValidationConstraints>,
   all_descriptors: &FileDescriptorSet
 ) -> anyhow::Result<Body> {
   let mut message = DynamicMessage::new(fields, all_descriptors, );
@@ -400,13 +811,18 @@ fn generate_protobuf_contents(
   let vm_boxed = variant_matcher.boxed();
   let context = hashmap!{};
 
-  for (key, generator) in generators {
+  // The generators are applied in a fixed order, so a seeded generator produces the same values
+  for (key, generator) in generators.iter().sorted_by(|a, b| a.0.cmp(b.0)) {
     let path = DocPath::new(key)?;
     let value = message.fetch_value(&path);
     if let Some(value) = value {
       let generator_values = generator.values.as_ref().map(proto_struct_to_json).unwrap_or_default();
       let generator = Generator::create(generator.r#type.as_str(),
                                         &generator_values)?;
+      let generator = match constraints.get(key) {
+        Some(constraints) => constraints.constrain(generator),
+        None => generator
+      };
       let generated_value = generator.generate_value(&value.data, &context, &vm_boxed)?;
       message.set_value(&path, generated_value)?;
     }
@@ -440,7 +856,7 @@ impl PactPlugin for ProtobufPactPlugin {
           r#type: EntryType::ContentMatcher as i32,
           key: "protobuf".to_string(),
           values: hashmap! {
-            "content-types".to_string() => "application/protobuf;application/grpc".to_string()
+            "content-types".to_string() => "application/protobuf;application/grpc;application/protobuf-text".to_string()
           }
         },
         proto::CatalogueEntry {
@@ -471,16 +887,19 @@ impl PactPlugin for ProtobufPactPlugin {
   }
 
   // Request to compare the contents and return the results of the comparison.
+  #[instrument(skip_all)]
   async fn compare_contents(
     &self,
     request: Request<CompareContentsRequest>,
   ) -> Result<Response<CompareContentsResponse>, Status> {
     trace!("Got compare_contents request {:?}", request.get_ref());
     let request = request.get_ref();
-    match self.compare_contents_impl(request) {
-      Ok(result) => Ok(Response::new(result)),
-      Err(err) => Self::error_response(err.to_string())
-    }
+    timed("compare_contents", async {
+      match self.compare_contents_impl(request) {
+        Ok(result) => Ok(Response::new(result)),
+        Err(err) => Self::error_response(err.to_string())
+      }
+    }).await
   }
 
   // Request to configure the expected interaction for a consumer tests.
@@ -533,23 +952,7 @@ impl PactPlugin for ProtobufPactPlugin {
       }
     };
 
-    // Process the proto file and configure the interaction
-    match process_proto(proto_file, &protoc, &fields).await {
-      Ok((interactions, plugin_config)) => {
-        Ok(Response::new(proto::ConfigureInteractionResponse {
-          interaction: interactions,
-          plugin_configuration: Some(plugin_config),
-          .. proto::ConfigureInteractionResponse::default()
-        }))
-      }
-      Err(err) => {
-        error!("Failed to process protobuf: {}", err);
-        Ok(Response::new(proto::ConfigureInteractionResponse {
-          error: format!("Failed to process protobuf: {}", err),
-          .. proto::ConfigureInteractionResponse::default()
-        }))
-      }
-    }
+    Ok(Response::new(configure_interaction_with_protoc(proto_file, &protoc, &fields).await))
   }
 
   // Request to generate the contents of the interaction.
@@ -583,9 +986,10 @@ impl PactPlugin for ProtobufPactPlugin {
     // Check for the plugin specific configuration for the Protobuf descriptors
     let plugin_config = match pact.plugin_data.iter().find(|pd| pd.name == "protobuf") {
       None => {
-        error!("Provided Pact file does not have any Protobuf descriptors");
+        let message = no_descriptors_error(&pact.plugin_data);
+        error!("{}", message);
         return Ok(tonic::Response::new(proto::StartMockServerResponse {
-          response: Some(proto::start_mock_server_response::Response::Error("Provided Pact file does not have any Protobuf descriptors".to_string())),
+          response: Some(proto::start_mock_server_response::Response::Error(message)),
           .. proto::StartMockServerResponse::default()
         }))
       }
@@ -601,7 +1005,23 @@ impl PactPlugin for ProtobufPactPlugin {
       _ => hashmap!{}
     };
 
-    let grpc_mock_server = GrpcMockServer::new(pact, &plugin_config, test_context);
+    let upstream = test_context.get(UPSTREAM_CONFIG).map(json_to_string);
+    let recording_file = test_context.get(RECORDING_FILE_CONFIG).map(|file| PathBuf::from(json_to_string(file)));
+    let tls = match test_context.get(TLS_CONFIG).map(MockServerTls::from_config).transpose() {
+      Ok(tls) => tls,
+      Err(err) => {
+        error!("Failed to start gRPC mock server: {}", err);
+        return Ok(tonic::Response::new(proto::StartMockServerResponse {
+          response: Some(proto::start_mock_server_response::Response::Error(format!("Failed to start gRPC mock server: {}", err))),
+          .. proto::StartMockServerResponse::default()
+        }));
+      }
+    };
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let grpc_mock_server = GrpcMockServer::new(pact, &plugin_config, test_context)
+      .with_max_concurrent_requests(self.max_concurrent_requests())
+      .with_upstream(upstream, recording_file)
+      .with_tls(tls);
     let server_key = grpc_mock_server.server_key.clone();
     match grpc_mock_server.start_server(request.host_interface.as_str(), request.port, request.tls).await {
       Ok(address) => {
@@ -610,7 +1030,7 @@ impl PactPlugin for ProtobufPactPlugin {
           response: Some(proto::start_mock_server_response::Response::Details(proto::MockServerDetails {
             key: server_key,
             port: address.port() as u32,
-            address: format!("http://{}", address)
+            address: format!("{}://{}", scheme, address)
           }))
         }))
       }
@@ -629,10 +1049,8 @@ impl PactPlugin for ProtobufPactPlugin {
     request: Request<proto::ShutdownMockServerRequest>,
   ) -> Result<Response<proto::ShutdownMockServerResponse>, Status> {
     let request = request.get_ref();
-    let mut guard = MOCK_SERVER_STATE.lock().unwrap();
-    if let Some((_, results)) = guard.get(&request.server_key) {
-      let (ok, results) = Self::get_mock_server_results(results);
-      guard.remove(&request.server_key);
+    if let Some(results) = remove_mock_server(&request.server_key) {
+      let (ok, results) = Self::get_mock_server_results(&results.lock().unwrap());
       Ok(Response::new(proto::ShutdownMockServerResponse {
         ok,
         results
@@ -655,9 +1073,8 @@ impl PactPlugin for ProtobufPactPlugin {
     request: Request<proto::MockServerRequest>,
   ) -> Result<Response<proto::MockServerResults>, Status> {
     let request = request.get_ref();
-    let guard = MOCK_SERVER_STATE.lock().unwrap();
-    if let Some((_, results)) = guard.get(&request.server_key) {
-      let (ok, results) = Self::get_mock_server_results(results);
+    if let Some(results) = mock_server_results(&request.server_key) {
+      let (ok, results) = Self::get_mock_server_results(&results.lock().unwrap());
       Ok(tonic::Response::new(proto::MockServerResults {
         ok,
         results
@@ -800,115 +1217,12 @@ impl PactPlugin for ProtobufPactPlugin {
     }))
   }
 
+  #[instrument(skip_all, fields(interaction_key = %request.get_ref().interaction_key))]
   async fn verify_interaction(
     &self,
     request: Request<proto::VerifyInteractionRequest>
   ) -> Result<Response<proto::VerifyInteractionResponse>, Status> {
-    debug!("Received verify interaction request");
-
-    let request = request.get_ref();
-    trace!("Got verify_interaction request {:?}", request);
-
-    let pact = match parse_pact_from_request_json(request.pact.as_str(), "grpc:verify_interaction") {
-      Ok(pact) => pact,
-      Err(err) => return Ok(Response::new(proto::VerifyInteractionResponse {
-        response: Some(proto::verify_interaction_response::Response::Error(format!("Failed to parse Pact JSON: {}", err))),
-        .. proto::VerifyInteractionResponse::default()
-      }))
-    };
-
-    let key = request.interaction_key.as_str();
-    let interaction_by_id = lookup_interaction_by_id(key, &pact);
-    let interaction = match interaction_by_id {
-      Some(interaction) => match interaction.as_v4_sync_message() {
-        Some(interaction) => interaction,
-        None => return Ok(Response::new(proto::VerifyInteractionResponse {
-          response: Some(proto::verify_interaction_response::Response::Error(format!("gRPC interactions must be of type V4 synchronous message, got {}", interaction.type_of()))),
-          .. proto::VerifyInteractionResponse::default()
-        }))
-      }
-      None => {
-        error!(?key, "Did not find an interaction that matches the given key");
-        return Ok(Response::new(proto::VerifyInteractionResponse {
-          response: Some(proto::verify_interaction_response::Response::Error(
-            format!("Did not find an interaction that matches the given key '{}'", key)
-          )),
-          ..proto::VerifyInteractionResponse::default()
-        }))
-      }
-    };
-
-    let body = match &request.interaction_data {
-      Some(data) => match &data.body {
-        Some(b) => match &b.content {
-          Some(data) => OptionalBody::Present(Bytes::from(data.clone()), Some(ContentType::from(b.content_type.clone())), None),
-          None => OptionalBody::Missing
-        }
-        None => OptionalBody::Missing
-      }
-      None => OptionalBody::Missing
-    };
-    let metadata = match &request.interaction_data {
-      Some(data) => data.metadata.clone(),
-      None => HashMap::default()
-    };
-
-    let config = request.config.as_ref().map(proto_struct_to_map).unwrap_or_default();
-    match verify_interaction(&pact, &interaction, &body, &metadata, &config).await {
-      Ok((result, output)) => {
-        let results = result.iter()
-          .flat_map(|result| match result {
-            VerificationMismatchResult::Mismatches { mismatches, .. } => {
-              mismatches.iter()
-                .map(|mismatch| {
-                  if let Mismatch::BodyMismatch { path, expected, actual, mismatch } = mismatch {
-                    proto::VerificationResultItem {
-                      result: Some(proto::verification_result_item::Result::Mismatch(proto::ContentMismatch {
-                        expected: expected.as_ref().map(|b| b.to_vec()),
-                        actual: actual.as_ref().map(|b| b.to_vec()),
-                        mismatch: mismatch.clone(),
-                        path: path.clone(),
-                        .. proto::ContentMismatch::default()
-                      })),
-                      .. proto::VerificationResultItem::default()
-                    }
-                  } else {
-                    proto::VerificationResultItem {
-                      result: Some(proto::verification_result_item::Result::Mismatch(proto::ContentMismatch {
-                        mismatch: mismatch.description(),
-                        .. proto::ContentMismatch::default()
-                      })),
-                      .. proto::VerificationResultItem::default()
-                    }
-                  }
-                })
-                .collect()
-            }
-            VerificationMismatchResult::Error { error, .. } => {
-              vec![proto::VerificationResultItem {
-                result: Some(proto::verification_result_item::Result::Error(error.clone())),
-                .. proto::VerificationResultItem::default()
-              }]
-            }
-          })
-          .collect();
-        Ok(Response::new(proto::VerifyInteractionResponse {
-          response: Some(proto::verify_interaction_response::Response::Result(proto::VerificationResult {
-            success: result.is_empty(),
-            mismatches: results,
-            output,
-            .. proto::VerificationResult::default()
-          })),
-          .. proto::VerifyInteractionResponse::default()
-        }))
-      }
-      Err(err) => {
-        Ok(Response::new(proto::VerifyInteractionResponse {
-          response: Some(proto::verify_interaction_response::Response::Error(err.to_string())),
-          .. proto::VerifyInteractionResponse::default()
-        }))
-      }
-    }
+    timed("verify_interaction", self.verify_interaction_impl(request.get_ref())).await
   }
 }
 
@@ -1045,11 +1359,12 @@ mod tests {
   use pact_plugin_driver::proto::catalogue_entry::EntryType;
   use pact_plugin_driver::proto::pact_plugin_server::PactPlugin;
   use pact_plugin_driver::proto::start_mock_server_response;
+  use prost_types::value::Kind;
   use serde_json::{json, Map, Value};
   use tonic::Request;
   use crate::metadata::MetadataMatchResult;
 
-  use crate::server::{merge_value, ProtobufPactPlugin};
+  use crate::server::{configure_interaction_with_protoc, generator_seed, merge_value, ProtobufPactPlugin};
 
   #[tokio::test]
   async fn init_plugin_test() {
@@ -1066,7 +1381,7 @@ mod tests {
     let first = &response_message.catalogue.get(0).unwrap();
     expect!(first.key.as_str()).to(be_equal_to("protobuf"));
     expect!(first.r#type).to(be_equal_to(EntryType::ContentMatcher as i32));
-    expect!(first.values.get("content-types")).to(be_some().value(&"application/protobuf;application/grpc".to_string()));
+    expect!(first.values.get("content-types")).to(be_some().value(&"application/protobuf;application/grpc;application/protobuf-text".to_string()));
 
     let second = &response_message.catalogue.get(1).unwrap();
     expect!(second.key.as_str()).to(be_equal_to("protobuf"));
@@ -1113,6 +1428,32 @@ mod tests {
       be_equal_to("Config item with key 'pact:message-type' and the protobuf message name or 'pact:proto-service' and the service name is required"));
   }
 
+  #[cfg(not(windows))]
+  #[test_log::test(tokio::test)]
+  async fn configure_interaction_test__dry_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let proto_file = dir.path().join("simple.proto");
+    std::fs::copy("tests/simple.proto", &proto_file).unwrap();
+    let protoc = crate::protoc::tests::fake_protoc();
+    let string_value = |s: &str| prost_types::Value { kind: Some(prost_types::value::Kind::StringValue(s.to_string())) };
+    let config = |message_type: &str| btreemap!{
+      "pact:message-type".to_string() => string_value(message_type),
+      "pact:dry-run".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::BoolValue(true)) },
+      "in".to_string() => string_value("matching(boolean, true)")
+    };
+
+    let response = configure_interaction_with_protoc(proto_file.to_string_lossy().to_string(), &protoc,
+      &config("MessageIn")).await;
+    expect!(&response.error).to(be_equal_to(""));
+    expect!(response.interaction.iter()).to(be_empty());
+    expect!(response.plugin_configuration.clone()).to(be_none());
+
+    let response = configure_interaction_with_protoc(proto_file.to_string_lossy().to_string(), &protoc,
+      &config("MessageOut")).await;
+    expect!(response.error.starts_with("Failed to process protobuf:")).to(be_true());
+    expect!(response.interaction.iter()).to(be_empty());
+  }
+
   #[test]
   fn ProtobufPactPlugin__host_to_bind_to__default() {
     let plugin = ProtobufPactPlugin { manifest: Default::default() };
@@ -1143,6 +1484,30 @@ mod tests {
     expect!(plugin.host_to_bind_to()).to(be_some().value("127".to_string()));
   }
 
+  #[test]
+  fn ProtobufPactPlugin__max_concurrent_requests() {
+    let plugin = ProtobufPactPlugin { manifest: Default::default() };
+    expect!(plugin.max_concurrent_requests()).to(be_none());
+
+    let manifest = PactPluginManifest {
+      plugin_config: hashmap! {
+        "maxConcurrentRequests".to_string() => json!(10)
+      },
+      .. PactPluginManifest::default()
+    };
+    let plugin = ProtobufPactPlugin { manifest };
+    expect!(plugin.max_concurrent_requests()).to(be_some().value(10));
+
+    let manifest = PactPluginManifest {
+      plugin_config: hashmap! {
+        "maxConcurrentRequests".to_string() => json!("0")
+      },
+      .. PactPluginManifest::default()
+    };
+    let plugin = ProtobufPactPlugin { manifest };
+    expect!(plugin.max_concurrent_requests()).to(be_none());
+  }
+
   #[test]
   fn ProtobufPactPlugin__additional_includes__default() {
     let plugin = ProtobufPactPlugin { manifest: Default::default() };
@@ -1325,6 +1690,37 @@ mod tests {
     }
   }
 
+  #[test_log::test(tokio::test)]
+  async fn start_mock_server_returns_the_plugin_data_entries_if_there_are_no_protobuf_descriptors() {
+    let plugin = ProtobufPactPlugin { manifest: Default::default() };
+    let pact = json!({
+      "consumer": { "name": "consumer" },
+      "provider": { "name": "provider" },
+      "interactions": [],
+      "metadata": {
+        "pactSpecification": { "version": "4.0" },
+        "plugins": [
+          { "name": "grpc", "version": "0.1.0", "configuration": {} }
+        ]
+      }
+    });
+    let request = proto::StartMockServerRequest {
+      host_interface: "".to_string(),
+      port: 0,
+      tls: false,
+      pact: pact.to_string(),
+      .. proto::StartMockServerRequest::default()
+    };
+    let result = plugin.start_mock_server(Request::new(request)).await;
+    let response = result.unwrap();
+    if let Some(start_mock_server_response::Response::Error(message)) = &response.get_ref().response {
+      expect!(message.starts_with("Provided Pact file does not have any Protobuf descriptors")).to(be_true());
+      expect!(message.contains("'grpc' (version 0.1.0)")).to(be_true());
+    } else {
+      panic!("Was expecting an error message");
+    }
+  }
+
   #[test_log::test(tokio::test)]
   async fn shutdown_mock_server_returns_an_error_if_the_server_key_was_not_found() {
     let plugin = ProtobufPactPlugin { manifest: Default::default() };
@@ -1415,4 +1811,454 @@ mod tests {
     expect!(merge_value(&json!({"additional": ["ok"]}), &json!({"additional": ["not ok"], "other": "value"})).unwrap())
       .to(be_equal_to(json!({"additional": ["ok", "not ok"], "other": "value"})));
   }
+
+  #[test_log::test]
+  fn compare_contents_removes_the_framing_from_the_actual_message() {
+    use base64::Engine;
+
+    let descriptor_bytes = std::fs::read("tests/descriptors/simple.pb").unwrap();
+    let descriptor_key = format!("{:x}", md5::compute(&descriptor_bytes));
+    let plugin = ProtobufPactPlugin { manifest: Default::default() };
+    let request = |framing: &str, actual: Vec<u8>| proto::CompareContentsRequest {
+      expected: Some(proto::Body {
+        content_type: "application/protobuf;message=MessageIn".to_string(),
+        content: Some(vec![8, 1]),
+        content_type_hint: 0
+      }),
+      actual: Some(proto::Body {
+        content_type: "application/protobuf;message=MessageIn".to_string(),
+        content: Some(actual),
+        content_type_hint: 0
+      }),
+      plugin_configuration: Some(proto::PluginConfiguration {
+        interaction_configuration: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{
+          "descriptorKey".to_string() => json!(descriptor_key),
+          "message".to_string() => json!("MessageIn"),
+          "messageFraming".to_string() => json!(framing)
+        })),
+        pact_configuration: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{
+          descriptor_key.clone() => json!({
+            "protoDescriptors": base64::engine::general_purpose::STANDARD.encode(&descriptor_bytes)
+          })
+        }))
+      }),
+      .. proto::CompareContentsRequest::default()
+    };
+
+    let response = plugin.compare_contents_impl(&request("raw", vec![8, 1])).unwrap();
+    expect!(response.results.is_empty()).to(be_true());
+
+    let response = plugin.compare_contents_impl(&request("length-prefixed", vec![0, 0, 0, 0, 2, 8, 1])).unwrap();
+    expect!(response.results.is_empty()).to(be_true());
+    expect!(response.type_mismatch).to(be_none());
+
+    expect!(plugin.compare_contents_impl(&request("length-prefixed", vec![8, 1]))).to(be_err());
+  }
+
+  #[test_log::test]
+  fn generate_contents_populates_unset_fields_with_the_example_option_values() {
+    use base64::Engine;
+    use prost::Message;
+    use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto};
+    use prost_types::field_descriptor_proto::{Label, Type};
+
+    let append = crate::utils::tests::append_length_delimited;
+    let field = |name: &str, number: i32, field_type: Type| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(Label::Optional as i32),
+      r#type: Some(field_type as i32),
+      .. FieldDescriptorProto::default()
+    };
+
+    // message Person { int32 id = 1; string name = 2 [(example) = "Fred"]; }
+    let mut name_field = field("name", 2, Type::String).encode_to_vec();
+    let mut options = vec![];
+    append(&mut options, 50000, b"Fred");
+    append(&mut name_field, 8, &options);
+    let mut message = DescriptorProto {
+      name: Some("Person".to_string()),
+      field: vec![ field("id", 1, Type::Int32) ],
+      .. DescriptorProto::default()
+    }.encode_to_vec();
+    append(&mut message, 2, &name_field);
+    let mut file = FileDescriptorProto {
+      name: Some("person.proto".to_string()),
+      package: Some("test".to_string()),
+      extension: vec![
+        FieldDescriptorProto {
+          extendee: Some(".google.protobuf.FieldOptions".to_string()),
+          .. field("example", 50000, Type::String)
+        }
+      ],
+      syntax: Some("proto3".to_string()),
+      .. FileDescriptorProto::default()
+    }.encode_to_vec();
+    append(&mut file, 4, &message);
+    let mut descriptor_bytes = vec![];
+    append(&mut descriptor_bytes, 1, &file);
+    let descriptor_key = format!("{:x}", md5::compute(&descriptor_bytes));
+
+    let plugin = ProtobufPactPlugin { manifest: Default::default() };
+    let request = proto::GenerateContentRequest {
+      contents: Some(proto::Body {
+        content_type: "application/protobuf;message=Person".to_string(),
+        content: Some(vec![8, 1]),
+        content_type_hint: 0
+      }),
+      plugin_configuration: Some(proto::PluginConfiguration {
+        interaction_configuration: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{
+          "descriptorKey".to_string() => json!(descriptor_key),
+          "message".to_string() => json!("Person"),
+          "exampleOption".to_string() => json!("test.example")
+        })),
+        pact_configuration: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{
+          descriptor_key.clone() => json!({
+            "protoDescriptors": base64::engine::general_purpose::STANDARD.encode(&descriptor_bytes)
+          })
+        }))
+      }),
+      .. proto::GenerateContentRequest::default()
+    };
+
+    let response = plugin.generate_contents_impl(&request).unwrap();
+    let mut expected = vec![8, 1];
+    prost::encoding::string::encode(2, &"Fred".to_string(), &mut expected);
+    expect!(response.contents.unwrap().content).to(be_some().value(expected));
+  }
+
+  #[test_log::test]
+  fn generate_contents_populates_unset_fields_with_the_configured_example_values() {
+    use base64::Engine;
+    use prost::Message;
+    use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet};
+    use prost_types::field_descriptor_proto::{Label, Type};
+
+    let field = |name: &str, number: i32, field_type: Type| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(Label::Optional as i32),
+      r#type: Some(field_type as i32),
+      .. FieldDescriptorProto::default()
+    };
+    // message Person { int32 id = 1; string name = 2; bool active = 3; }
+    let descriptor_bytes = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("person.proto".to_string()),
+          package: Some("test".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Person".to_string()),
+              field: vec![ field("id", 1, Type::Int32), field("name", 2, Type::String), field("active", 3, Type::Bool) ],
+              .. DescriptorProto::default()
+            }
+          ],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    }.encode_to_vec();
+    let descriptor_key = format!("{:x}", md5::compute(&descriptor_bytes));
+
+    let plugin = ProtobufPactPlugin { manifest: Default::default() };
+    let request = proto::GenerateContentRequest {
+      contents: Some(proto::Body {
+        content_type: "application/protobuf;message=Person".to_string(),
+        content: Some(vec![8, 1]),
+        content_type_hint: 0
+      }),
+      plugin_configuration: Some(proto::PluginConfiguration {
+        interaction_configuration: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{
+          "descriptorKey".to_string() => json!(descriptor_key),
+          "message".to_string() => json!("Person"),
+          "exampleValues".to_string() => json!({ "$.name": "Fred", "active": true })
+        })),
+        pact_configuration: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{
+          descriptor_key.clone() => json!({
+            "protoDescriptors": base64::engine::general_purpose::STANDARD.encode(&descriptor_bytes)
+          })
+        }))
+      }),
+      .. proto::GenerateContentRequest::default()
+    };
+
+    let response = plugin.generate_contents_impl(&request).unwrap();
+    let mut expected = vec![8, 1];
+    prost::encoding::string::encode(2, &"Fred".to_string(), &mut expected);
+    prost::encoding::bool::encode(3, &true, &mut expected);
+    expect!(response.contents.unwrap().content).to(be_some().value(expected));
+  }
+
+  #[test_log::test]
+  fn generate_contents_with_null_and_present_wrapper_fields() {
+    use base64::Engine;
+    use prost::Message;
+    use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet};
+    use prost_types::field_descriptor_proto::{Label, Type};
+
+    let field = |name: &str, number: i32, field_type: Type| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(Label::Optional as i32),
+      r#type: Some(field_type as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let wrapper_field = |name: &str, number: i32| FieldDescriptorProto {
+      type_name: Some(".google.protobuf.StringValue".to_string()),
+      .. field(name, number, Type::Message)
+    };
+    // message Person { int32 id = 1; google.protobuf.StringValue nickname = 2; google.protobuf.StringValue middle_name = 3; }
+    let descriptor_bytes = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("google/protobuf/wrappers.proto".to_string()),
+          package: Some("google.protobuf".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("StringValue".to_string()),
+              field: vec![ field("value", 1, Type::String) ],
+              .. DescriptorProto::default()
+            }
+          ],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        },
+        FileDescriptorProto {
+          name: Some("person.proto".to_string()),
+          package: Some("test".to_string()),
+          dependency: vec![ "google/protobuf/wrappers.proto".to_string() ],
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Person".to_string()),
+              field: vec![ field("id", 1, Type::Int32), wrapper_field("nickname", 2), wrapper_field("middle_name", 3) ],
+              .. DescriptorProto::default()
+            }
+          ],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    }.encode_to_vec();
+    let descriptor_key = format!("{:x}", md5::compute(&descriptor_bytes));
+
+    let plugin = ProtobufPactPlugin { manifest: Default::default() };
+    let request = |wrapper_nulls: Value| proto::GenerateContentRequest {
+      contents: Some(proto::Body {
+        content_type: "application/protobuf;message=Person".to_string(),
+        // id = 1, nickname = "Bob"
+        content: Some(vec![8, 1, 18, 5, 10, 3, b'B', b'o', b'b']),
+        content_type_hint: 0
+      }),
+      plugin_configuration: Some(proto::PluginConfiguration {
+        interaction_configuration: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{
+          "descriptorKey".to_string() => json!(descriptor_key),
+          "message".to_string() => json!("Person"),
+          "wrapperNulls".to_string() => wrapper_nulls
+        })),
+        pact_configuration: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{
+          descriptor_key.clone() => json!({
+            "protoDescriptors": base64::engine::general_purpose::STANDARD.encode(&descriptor_bytes)
+          })
+        }))
+      }),
+      .. proto::GenerateContentRequest::default()
+    };
+
+    let response = plugin.generate_contents_impl(&request(json!({ "$.nickname": null, "$.middle_name": "present" }))).unwrap();
+    expect!(response.contents.unwrap().content).to(be_some().value(vec![8, 1, 26, 0]));
+
+    let response = plugin.generate_contents_impl(&request(json!({ "$.nickname": "present" }))).unwrap();
+    expect!(response.contents.unwrap().content).to(be_some().value(vec![8, 1, 18, 5, 10, 3, b'B', b'o', b'b']));
+
+    expect!(plugin.generate_contents_impl(&request(json!({ "$.nickname": "maybe" })))).to(be_err());
+  }
+
+  #[test_log::test]
+  fn generate_contents_constrains_the_generated_values_with_the_validation_rules() {
+    use base64::Engine;
+    use prost::Message;
+    use prost::encoding::{encode_key, encode_varint, WireType};
+    use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto};
+    use prost_types::field_descriptor_proto::{Label, Type};
+
+    let append = crate::utils::tests::append_length_delimited;
+    let field = |name: &str, number: i32, field_type: Type| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(Label::Optional as i32),
+      r#type: Some(field_type as i32),
+      .. FieldDescriptorProto::default()
+    };
+
+    // message Person { string name = 1 [(validate.rules).string.min_len = 15]; }
+    let mut string_rules = vec![];
+    encode_key(2, WireType::Varint, &mut string_rules);
+    encode_varint(15, &mut string_rules);
+    let mut field_rules = vec![];
+    append(&mut field_rules, 14, &string_rules);
+    let mut options = vec![];
+    append(&mut options, 1071, &field_rules);
+    let mut name_field = field("name", 1, Type::String).encode_to_vec();
+    append(&mut name_field, 8, &options);
+    let mut message = DescriptorProto {
+      name: Some("Person".to_string()),
+      .. DescriptorProto::default()
+    }.encode_to_vec();
+    append(&mut message, 2, &name_field);
+    let mut file = FileDescriptorProto {
+      name: Some("person.proto".to_string()),
+      package: Some("test".to_string()),
+      syntax: Some("proto3".to_string()),
+      .. FileDescriptorProto::default()
+    }.encode_to_vec();
+    append(&mut file, 4, &message);
+    let validate_file = FileDescriptorProto {
+      name: Some("validate/validate.proto".to_string()),
+      package: Some("validate".to_string()),
+      extension: vec![
+        FieldDescriptorProto {
+          extendee: Some(".google.protobuf.FieldOptions".to_string()),
+          type_name: Some(".validate.FieldRules".to_string()),
+          .. field("rules", 1071, Type::Message)
+        }
+      ],
+      .. FileDescriptorProto::default()
+    }.encode_to_vec();
+    let mut descriptor_bytes = vec![];
+    append(&mut descriptor_bytes, 1, &validate_file);
+    append(&mut descriptor_bytes, 1, &file);
+    let descriptor_key = format!("{:x}", md5::compute(&descriptor_bytes));
+
+    let plugin = ProtobufPactPlugin { manifest: Default::default() };
+    let mut content = vec![];
+    prost::encoding::string::encode(1, &"Fred".to_string(), &mut content);
+    let request = proto::GenerateContentRequest {
+      contents: Some(proto::Body {
+        content_type: "application/protobuf;message=Person".to_string(),
+        content: Some(content),
+        content_type_hint: 0
+      }),
+      generators: hashmap!{
+        "$.name".to_string() => proto::Generator {
+          r#type: "RandomString".to_string(),
+          values: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{ "size".to_string() => json!(5) }))
+        }
+      },
+      plugin_configuration: Some(proto::PluginConfiguration {
+        interaction_configuration: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{
+          "descriptorKey".to_string() => json!(descriptor_key),
+          "message".to_string() => json!("Person"),
+          "validationOption".to_string() => json!("validate.rules")
+        })),
+        pact_configuration: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{
+          descriptor_key.clone() => json!({
+            "protoDescriptors": base64::engine::general_purpose::STANDARD.encode(&descriptor_bytes)
+          })
+        }))
+      }),
+      .. proto::GenerateContentRequest::default()
+    };
+
+    let response = plugin.generate_contents_impl(&request).unwrap();
+    let content = response.contents.unwrap().content.unwrap();
+    // field 1, wire type 2, then the length of the generated string
+    expect!(content[0]).to(be_equal_to(10));
+    expect!(content[1]).to(be_equal_to(15));
+    expect!(content.len()).to(be_equal_to(17));
+  }
+
+  #[test]
+  fn generator_seed_test() {
+    let number = |n: f64| prost_types::Value { kind: Some(Kind::NumberValue(n)) };
+    expect!(generator_seed(None, None).unwrap()).to(be_none());
+    expect!(generator_seed(None, Some("42".to_string())).unwrap()).to(be_some().value(42));
+    expect!(generator_seed(Some(&number(42.0)), Some("1".to_string())).unwrap()).to(be_some().value(42));
+    expect!(generator_seed(Some(&number(-1.0)), None)).to(be_err());
+    expect!(generator_seed(Some(&number(1.5)), None)).to(be_err());
+    expect!(generator_seed(None, Some("-1".to_string()))).to(be_err());
+  }
+
+  #[test_log::test]
+  fn generate_contents_with_a_generator_seed_produces_the_same_values() {
+    use base64::Engine;
+    use prost::Message;
+    use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet};
+    use prost_types::field_descriptor_proto::{Label, Type};
+
+    let field = |name: &str, number: i32, field_type: Type| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(Label::Optional as i32),
+      r#type: Some(field_type as i32),
+      .. FieldDescriptorProto::default()
+    };
+    // message Person { int32 id = 1; string name = 2; string ref = 3; }
+    let descriptor_bytes = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("person.proto".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Person".to_string()),
+              field: vec![ field("id", 1, Type::Int32), field("name", 2, Type::String), field("ref", 3, Type::String) ],
+              .. DescriptorProto::default()
+            }
+          ],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    }.encode_to_vec();
+    let descriptor_key = format!("{:x}", md5::compute(&descriptor_bytes));
+
+    let plugin = ProtobufPactPlugin { manifest: Default::default() };
+    let mut content = vec![8, 1];
+    prost::encoding::string::encode(2, &"Fred".to_string(), &mut content);
+    prost::encoding::string::encode(3, &"abc".to_string(), &mut content);
+    let request = |seed: u64| proto::GenerateContentRequest {
+      contents: Some(proto::Body {
+        content_type: "application/protobuf;message=Person".to_string(),
+        content: Some(content.clone()),
+        content_type_hint: 0
+      }),
+      generators: hashmap!{
+        "$.id".to_string() => proto::Generator {
+          r#type: "RandomInt".to_string(),
+          values: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{
+            "min".to_string() => json!(0),
+            "max".to_string() => json!(1000000)
+          }))
+        },
+        "$.name".to_string() => proto::Generator {
+          r#type: "RandomString".to_string(),
+          values: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{ "size".to_string() => json!(20) }))
+        },
+        "$.ref".to_string() => proto::Generator {
+          r#type: "Uuid".to_string(),
+          values: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{ "format".to_string() => json!("simple") }))
+        }
+      },
+      plugin_configuration: Some(proto::PluginConfiguration {
+        interaction_configuration: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{
+          "descriptorKey".to_string() => json!(descriptor_key),
+          "message".to_string() => json!("Person"),
+          "generatorSeed".to_string() => json!(seed)
+        })),
+        pact_configuration: Some(pact_plugin_driver::utils::to_proto_struct(&hashmap!{
+          descriptor_key.clone() => json!({
+            "protoDescriptors": base64::engine::general_purpose::STANDARD.encode(&descriptor_bytes)
+          })
+        }))
+      }),
+      .. proto::GenerateContentRequest::default()
+    };
+
+    let first = plugin.generate_contents_impl(&request(42)).unwrap().contents.unwrap().content.unwrap();
+    let second = plugin.generate_contents_impl(&request(42)).unwrap().contents.unwrap().content.unwrap();
+    let other = plugin.generate_contents_impl(&request(43)).unwrap().contents.unwrap().content.unwrap();
+    expect!(&first).to(be_equal_to(&second));
+    expect!(&first).to_not(be_equal_to(&content));
+    expect!(&first).to_not(be_equal_to(&other));
+  }
 }
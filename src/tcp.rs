@@ -1,10 +1,16 @@
 //! TCP support classes
 
+use std::io;
+use std::net::{Shutdown, SocketAddr};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use futures::Stream;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
 
 /// This struct is required, because we want to get the port of the running server to display
 /// to standard out. This maps a TcpListener (which we use to get the port) to a futures Stream
@@ -23,3 +29,107 @@ impl Stream for TcpIncoming {
       .map_ok(|(stream, _)| stream).map(Some)
   }
 }
+
+/// Handle that can be used to abruptly close a TCP connection from outside the task driving it
+#[derive(Debug, Clone)]
+pub struct ConnectionCloser {
+  socket: Arc<std::net::TcpStream>
+}
+
+impl ConnectionCloser {
+  /// Shuts down both halves of the connection
+  pub fn close(&self) -> io::Result<()> {
+    self.socket.shutdown(Shutdown::Both)
+  }
+}
+
+/// TCP stream that can be closed with a `ConnectionCloser`. The stream may be using TLS.
+#[derive(Debug)]
+pub struct ClosableTcpStream {
+  inner: MaybeTlsStream,
+  closer: ConnectionCloser,
+  peer_address: Option<SocketAddr>
+}
+
+#[derive(Debug)]
+enum MaybeTlsStream {
+  Plain(TcpStream),
+  Tls(Box<TlsStream<TcpStream>>)
+}
+
+impl ClosableTcpStream {
+  /// Wraps the TCP stream. Must be called from within a Tokio runtime.
+  pub fn new(stream: TcpStream) -> io::Result<Self> {
+    let (stream, closer) = Self::with_closer(stream)?;
+    let peer_address = stream.peer_addr().ok();
+    Ok(ClosableTcpStream { inner: MaybeTlsStream::Plain(stream), closer, peer_address })
+  }
+
+  /// Wraps the TCP stream after completing the TLS handshake with the client. Must be called from
+  /// within a Tokio runtime.
+  pub async fn new_tls(stream: TcpStream, acceptor: TlsAcceptor) -> io::Result<Self> {
+    let (stream, closer) = Self::with_closer(stream)?;
+    let peer_address = stream.peer_addr().ok();
+    let stream = acceptor.accept(stream).await?;
+    Ok(ClosableTcpStream { inner: MaybeTlsStream::Tls(Box::new(stream)), closer, peer_address })
+  }
+
+  fn with_closer(stream: TcpStream) -> io::Result<(TcpStream, ConnectionCloser)> {
+    let std_stream = stream.into_std()?;
+    let socket = std_stream.try_clone()?;
+    Ok((TcpStream::from_std(std_stream)?, ConnectionCloser { socket: Arc::new(socket) }))
+  }
+
+  /// Returns a handle that can be used to close this connection
+  pub fn closer(&self) -> ConnectionCloser {
+    self.closer.clone()
+  }
+
+  /// Returns the address of the remote end of this connection
+  pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+    self.peer_address.ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "peer address is not available"))
+  }
+
+  /// Returns the DER encoded certificate presented by the client, if the connection is using TLS
+  /// and the client sent a certificate
+  pub fn peer_certificate(&self) -> Option<&[u8]> {
+    match &self.inner {
+      MaybeTlsStream::Plain(_) => None,
+      MaybeTlsStream::Tls(stream) => stream.get_ref().1.peer_certificates()
+        .and_then(|certificates| certificates.first())
+        .map(|certificate| certificate.0.as_slice())
+    }
+  }
+}
+
+impl AsyncRead for ClosableTcpStream {
+  fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+    match &mut self.get_mut().inner {
+      MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+      MaybeTlsStream::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf)
+    }
+  }
+}
+
+impl AsyncWrite for ClosableTcpStream {
+  fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+    match &mut self.get_mut().inner {
+      MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+      MaybeTlsStream::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf)
+    }
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    match &mut self.get_mut().inner {
+      MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+      MaybeTlsStream::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx)
+    }
+  }
+
+  fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    match &mut self.get_mut().inner {
+      MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+      MaybeTlsStream::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx)
+    }
+  }
+}
@@ -0,0 +1,149 @@
+//! Timings of the plugin request handlers, for performance analysis
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use maplit::hashmap;
+use tracing::{debug, info};
+
+/// Aggregated timings for a request handler
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HandlerTimings {
+  /// Number of requests handled
+  pub count: u64,
+  /// Total time taken to handle all the requests
+  pub total: Duration,
+  /// Longest time taken to handle a request
+  pub max: Duration
+}
+
+impl HandlerTimings {
+  /// Average time taken to handle a request
+  pub fn average(&self) -> Duration {
+    if self.count == 0 {
+      Duration::ZERO
+    } else {
+      self.total / self.count as u32
+    }
+  }
+}
+
+lazy_static! {
+  static ref HANDLER_TIMINGS: Mutex<HashMap<String, HandlerTimings>> = Mutex::new(hashmap!{});
+}
+
+/// Records the time taken by the handler to handle a request, and logs it at debug level
+pub fn record_handler_timing(handler: &str, elapsed: Duration) {
+  debug!(handler, elapsed_ms = elapsed.as_millis() as u64, "{} request handled in {:?}", handler, elapsed);
+  let mut guard = HANDLER_TIMINGS.lock().unwrap();
+  let timings = guard.entry(handler.to_string()).or_default();
+  timings.count += 1;
+  timings.total += elapsed;
+  timings.max = timings.max.max(elapsed);
+}
+
+/// Runs the handler future, recording the time it takes to complete
+pub async fn timed<F: Future>(handler: &str, future: F) -> F::Output {
+  let started = Instant::now();
+  let result = future.await;
+  record_handler_timing(handler, started.elapsed());
+  result
+}
+
+/// Returns the aggregated timings for each handler that has handled a request
+pub fn handler_timings() -> HashMap<String, HandlerTimings> {
+  HANDLER_TIMINGS.lock().unwrap().clone()
+}
+
+/// Logs the aggregated timings for each handler at info level
+pub fn log_handler_timings() {
+  for (handler, timings) in handler_timings().iter().sorted_by_key(|(handler, _)| *handler) {
+    info!("{}: {} requests, average {:?}, max {:?}, total {:?}", handler, timings.count, timings.average(),
+      timings.max, timings.total);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+  use std::sync::{Arc, Mutex};
+  use std::time::Duration;
+
+  use expectest::prelude::*;
+  use pact_plugin_driver::proto::CompareContentsRequest;
+  use pact_plugin_driver::proto::pact_plugin_server::PactPlugin;
+  use tonic::Request;
+
+  use crate::server::ProtobufPactPlugin;
+  use crate::timings::{handler_timings, HandlerTimings, timed};
+
+  #[derive(Clone, Default)]
+  struct TestWriter(Arc<Mutex<Vec<u8>>>);
+
+  impl Write for TestWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[tokio::test]
+  async fn handled_requests_log_the_time_taken_by_the_handler() {
+    let writer = TestWriter::default();
+    let make_writer = writer.clone();
+    let subscriber = tracing_subscriber::fmt()
+      .with_max_level(tracing::Level::DEBUG)
+      .with_ansi(false)
+      .with_writer(move || make_writer.clone())
+      .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let plugin = ProtobufPactPlugin::default();
+    let _ = plugin.compare_contents(Request::new(CompareContentsRequest::default())).await;
+
+    let output = String::from_utf8(writer.0.lock().unwrap().clone()).unwrap();
+    expect!(output.lines().any(|line| line.contains("DEBUG") && line.contains("compare_contents request handled in")))
+      .to(be_true());
+    let count = handler_timings().get("compare_contents").map(|timings| timings.count).unwrap_or_default();
+    expect!(count >= 1).to(be_true());
+  }
+
+  #[tokio::test]
+  async fn timed_logs_and_records_the_time_taken_by_the_handler() {
+    let writer = TestWriter::default();
+    let make_writer = writer.clone();
+    let subscriber = tracing_subscriber::fmt()
+      .with_max_level(tracing::Level::DEBUG)
+      .with_ansi(false)
+      .with_writer(move || make_writer.clone())
+      .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let result = timed("timings_test", async {
+      tokio::time::sleep(Duration::from_millis(5)).await;
+      100
+    }).await;
+    expect!(result).to(be_equal_to(100));
+
+    let output = String::from_utf8(writer.0.lock().unwrap().clone()).unwrap();
+    expect!(output.lines().any(|line| line.contains("DEBUG") && line.contains("timings_test request handled in")))
+      .to(be_true());
+    let timings = handler_timings().get("timings_test").cloned().unwrap_or_default();
+    expect!(timings.count).to(be_equal_to(1));
+    expect!(timings.max >= Duration::from_millis(5)).to(be_true());
+  }
+
+  #[test]
+  fn handler_timings_average() {
+    expect!(HandlerTimings::default().average()).to(be_equal_to(Duration::ZERO));
+    let timings = HandlerTimings { count: 4, total: Duration::from_millis(100), max: Duration::from_millis(40) };
+    expect!(timings.average()).to(be_equal_to(Duration::from_millis(25)));
+  }
+}
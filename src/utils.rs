@@ -1,19 +1,22 @@
 //! Shared utilities
 
 use std::collections::{BTreeMap, HashMap};
-use std::fmt::Write;
+use std::fmt::{Display, Formatter, Write};
+use std::io::Read;
 use std::panic::RefUnwindSafe;
 
 use anyhow::anyhow;
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD as BASE64;
-use bytes::{Bytes, BytesMut};
+use bytes::{Buf, Bytes};
 use field_descriptor_proto::Type;
 use maplit::hashmap;
 use pact_models::json_utils::json_to_string;
 use pact_models::pact::load_pact_from_json;
 use pact_models::prelude::v4::V4Pact;
 use pact_models::v4::interaction::V4Interaction;
+use pact_plugin_driver::utils::proto_value_to_json;
+use prost::encoding::WireType;
 use prost::Message;
 use prost_types::{
   DescriptorProto,
@@ -28,28 +31,138 @@ use prost_types::{
 };
 use prost_types::field_descriptor_proto::Label;
 use prost_types::value::Kind;
-use serde_json::json;
-use tracing::{debug, error, trace, warn};
+use sha2::{Digest, Sha256};
+use tracing::{debug, error, trace};
 
-use crate::message_decoder::{decode_message, ProtobufField, ProtobufFieldData};
+use crate::message_decoder::ProtobufField;
 
 /// Return the last name in a dot separated string
 pub fn last_name(entry_type_name: &str) -> &str {
   entry_type_name.split('.').last().unwrap_or(entry_type_name)
 }
 
-/// Search for a message by type name in all the descriptors
+/// Search for a message by type name in all the descriptors. If the type is not found, the error
+/// is a [ResolutionError] with the name that was looked for in each file.
 pub fn find_message_type_by_name(message_name: &str, descriptors: &FileDescriptorSet) -> anyhow::Result<(DescriptorProto, FileDescriptorProto)> {
   descriptors.file.iter()
-    .map(|descriptor| {
-      find_message_type_in_file_descriptor(message_name, descriptor).map(|ds| (ds, descriptor)).ok()
+    .find_map(|descriptor| {
+      find_message_type_in_file_descriptor(message_name, descriptor).map(|ds| (ds, descriptor.clone())).ok()
     })
-    .find(|result| result.is_some())
-    .flatten()
-    .map(|(m, f)| (m, f.clone()))
-    .ok_or_else(|| anyhow!("Did not find a message type '{}' in the descriptors", message_name))
+    .ok_or_else(|| anyhow!(ResolutionError {
+      type_name: message_name.to_string(),
+      steps: descriptors.file.iter()
+        .map(|file| ResolutionStep {
+          file: file.name.clone().unwrap_or_default(),
+          attempted_name: Some(match file.package.as_deref() {
+            Some(package) if !package.is_empty() => format!(".{}.{}", package, message_name),
+            _ => format!(".{}", message_name)
+          }),
+          outcome: "no message with that name".to_string()
+        })
+        .collect()
+    }))
 }
 
+/// Search for a message by its fully qualified type name (i.e. `.package.Outer.Inner`) in all the
+/// descriptors. Names without a package are searched for by name, as with [find_message_type_by_name].
+/// If the type is not found, the error is a [ResolutionError] with the steps that were tried.
+pub fn find_message_type_by_qualified_name(
+  type_name: &str,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<(DescriptorProto, FileDescriptorProto)> {
+  let name = type_name.strip_prefix('.').unwrap_or(type_name);
+  if !name.contains('.') {
+    return find_message_type_by_name(name, descriptors);
+  }
+
+  let mut steps = vec![];
+  for file in &descriptors.file {
+    match resolve_message_type_in_file(name, file) {
+      Ok(message) => return Ok((message, file.clone())),
+      Err(step) => {
+        trace!("Did not resolve '{}' in {} - {}", type_name, step.file, step.outcome);
+        steps.push(step);
+      }
+    }
+  }
+  Err(anyhow!(ResolutionError { type_name: type_name.to_string(), steps }))
+}
+
+/// Resolves the fully qualified type name (without the leading dot) in the file descriptor,
+/// returning the step that failed if it is not found
+fn resolve_message_type_in_file(name: &str, file: &FileDescriptorProto) -> Result<DescriptorProto, ResolutionStep> {
+  let file_name = file.name.clone().unwrap_or_default();
+  let path = match file.package.as_deref() {
+    Some(package) if !package.is_empty() => match name.strip_prefix(package).and_then(|path| path.strip_prefix('.')) {
+      Some(path) => path,
+      None => return Err(ResolutionStep {
+        file: file_name,
+        attempted_name: None,
+        outcome: format!("package '{}' does not match", package)
+      })
+    },
+    _ => name
+  };
+  let failed = |outcome: String| ResolutionStep {
+    file: file_name.clone(),
+    attempted_name: Some(format!(".{}", name)),
+    outcome
+  };
+
+  let mut parts = path.split('.');
+  let first = parts.next().unwrap_or_default();
+  let mut message = file.message_type.iter()
+    .find(|message| message.name.as_deref() == Some(first))
+    .ok_or_else(|| failed(format!("no message '{}'", first)))?;
+  for part in parts {
+    message = message.nested_type.iter()
+      .find(|nested| nested.name.as_deref() == Some(part))
+      .ok_or_else(|| failed(format!("message '{}' has no nested message '{}'",
+        message.name.clone().unwrap_or_default(), part)))?;
+  }
+  Ok(message.clone())
+}
+
+/// A step tried when resolving a message type in the descriptors
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolutionStep {
+  /// Name of the file that was searched
+  pub file: String,
+  /// Fully qualified name looked for in the file. This is not set if the package of the file
+  /// does not match.
+  pub attempted_name: Option<String>,
+  /// Why the type was not found in the file
+  pub outcome: String
+}
+
+impl Display for ResolutionStep {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match &self.attempted_name {
+      Some(name) => write!(f, "{}: looked for '{}', {}", self.file, name, self.outcome),
+      None => write!(f, "{}: {}", self.file, self.outcome)
+    }
+  }
+}
+
+/// Error for a message type that could not be resolved, with the steps that were tried
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolutionError {
+  /// The type name that could not be resolved
+  pub type_name: String,
+  /// The steps tried for each file in the descriptors
+  pub steps: Vec<ResolutionStep>
+}
+
+impl Display for ResolutionError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Did not find a message type '{}' in the descriptors (searched {}) [{}]", self.type_name,
+      self.steps.iter().map(|step| step.file.as_str()).collect::<Vec<_>>().join(", "),
+      self.steps.iter().map(|step| step.to_string()).collect::<Vec<_>>().join("; "))
+  }
+}
+
+impl std::error::Error for ResolutionError {}
+
 /// Search for a message by type name in the file descriptor
 pub fn find_message_type_in_file_descriptor(message_name: &str, descriptor: &FileDescriptorProto) -> anyhow::Result<DescriptorProto> {
   descriptor.message_type.iter()
@@ -59,6 +172,11 @@ pub fn find_message_type_in_file_descriptor(message_name: &str, descriptor: &Fil
       message_name, descriptor.name.as_deref().unwrap_or("unknown")))
 }
 
+/// If the message is the `google.protobuf.Empty` well-known type
+pub fn is_empty_message(message: &DescriptorProto, file_descriptor: &FileDescriptorProto) -> bool {
+  file_descriptor.package.as_deref() == Some("google.protobuf") && message.name.as_deref() == Some("Empty")
+}
+
 /// Search for a message by type name in the file descriptor, and if not found, search in all the
 /// descriptors
 pub fn find_message_type_in_file_descriptors(
@@ -74,6 +192,57 @@ pub fn find_message_type_in_file_descriptors(
     })
 }
 
+/// If the message is defined in a file that uses Protobuf Editions (the syntax of the file is
+/// `editions`). Nested messages are included.
+pub fn is_editions_message(message: &DescriptorProto, descriptors: &FileDescriptorSet) -> bool {
+  descriptors.file.iter()
+    .find(|file| contains_message_type(&file.message_type, message))
+    .map(|file| file.syntax.as_deref() == Some("editions"))
+    .unwrap_or(false)
+}
+
+/// If the field tracks presence explicitly, so a field set to its default value is still sent on
+/// the wire. This is the case for message fields, and for the singular fields of proto2 files and
+/// proto3 fields declared `optional` (or in a oneOf). Repeated fields never have explicit presence.
+pub fn has_explicit_presence(field: &FieldDescriptorProto, file: &FileDescriptorProto) -> bool {
+  if is_repeated_field(field) {
+    false
+  } else if matches!(field.r#type(), Type::Message | Type::Group) {
+    true
+  } else if file.syntax.as_deref() == Some("proto3") {
+    field.proto3_optional.unwrap_or(false) || field.oneof_index.is_some()
+  } else {
+    true
+  }
+}
+
+/// Returns the fully qualified name of the message (without the leading dot), which includes the
+/// package and the names of any messages it is nested in
+pub fn message_full_name(message: &DescriptorProto, descriptors: &FileDescriptorSet) -> Option<String> {
+  descriptors.file.iter().find_map(|file| {
+    message_path(&file.message_type, message).map(|path| match &file.package {
+      Some(package) if !package.is_empty() => format!("{}.{}", package, path),
+      _ => path
+    })
+  })
+}
+
+fn message_path(message_types: &[DescriptorProto], message: &DescriptorProto) -> Option<String> {
+  message_types.iter().find_map(|message_type| {
+    let name = message_type.name.clone().unwrap_or_default();
+    if message_type == message {
+      Some(name)
+    } else {
+      message_path(&message_type.nested_type, message).map(|path| format!("{}.{}", name, path))
+    }
+  })
+}
+
+fn contains_message_type(message_types: &[DescriptorProto], message: &DescriptorProto) -> bool {
+  message_types.iter()
+    .any(|message_type| message_type == message || contains_message_type(&message_type.nested_type, message))
+}
+
 /// If the field is a map field. A field will be a map field if it is a repeated field, the field
 /// type is a message and the nested type has the map flag set on the message options.
 pub fn is_map_field(message_descriptor: &DescriptorProto, field: &FieldDescriptorProto) -> bool {
@@ -138,6 +307,17 @@ pub fn find_message_field_by_name(descriptor: &DescriptorProto, field_data: Vec<
   field_data.iter().find(|d| d.field_num == field_num as u32).cloned()
 }
 
+/// Returns the options set on the method descriptor that can be asserted on, keyed by the JSON
+/// name of the option (`idempotencyLevel` and `deprecated`). Options that are not set will have
+/// their default values.
+pub fn method_options(method: &MethodDescriptorProto) -> BTreeMap<String, String> {
+  let options = method.options.clone().unwrap_or_default();
+  BTreeMap::from([
+    ("idempotencyLevel".to_string(), options.idempotency_level().as_str_name().to_string()),
+    ("deprecated".to_string(), options.deprecated().to_string())
+  ])
+}
+
 /// If the field is a repeated field
 pub fn is_repeated_field(descriptor: &FieldDescriptorProto) -> bool {
   descriptor.label() == Label::Repeated
@@ -150,6 +330,15 @@ pub fn enum_name(enum_value: i32, descriptor: &EnumDescriptorProto) -> String {
     .unwrap_or_else(|| format!("Unknown enum {}", enum_value))
 }
 
+/// Returns all the names for the enum value. There will be more than one if the enum allows aliases
+/// (`option allow_alias = true`), with the first name being the one returned by [enum_name].
+pub fn enum_value_names(enum_value: i32, descriptor: &EnumDescriptorProto) -> Vec<String> {
+  descriptor.value.iter()
+    .filter(|v| v.number == Some(enum_value))
+    .filter_map(|v| v.name.clone())
+    .collect()
+}
+
 /// Find the integer value of the given enum type and name in the message descriptor.
 #[tracing::instrument(ret, skip_all, fields(%enum_name, %enum_value))]
 pub fn find_enum_value_by_name_in_message(
@@ -249,45 +438,46 @@ pub fn find_enum_by_name(
   }
 }
 
-/// Convert the message field data into a JSON value
-pub fn field_data_to_json(
-  field_data: Vec<ProtobufField>,
-  descriptor: &DescriptorProto,
-  descriptors: &FileDescriptorSet
-) -> anyhow::Result<serde_json::Value> {
-  let mut object = hashmap!{};
-
-  for field in field_data {
-    if let Some(value) = descriptor.field.iter().find(|f| f.number.unwrap_or(-1) as u32 == field.field_num) {
-      match &value.name {
-        Some(name) => {
-          object.insert(name.clone(), match &field.data {
-            ProtobufFieldData::String(s) => serde_json::Value::String(s.clone()),
-            ProtobufFieldData::Boolean(b) => serde_json::Value::Bool(*b),
-            ProtobufFieldData::UInteger32(n) => json!(n),
-            ProtobufFieldData::Integer32(n) => json!(n),
-            ProtobufFieldData::UInteger64(n) => json!(n),
-            ProtobufFieldData::Integer64(n) => json!(n),
-            ProtobufFieldData::Float(n) => json!(n),
-            ProtobufFieldData::Double(n) => json!(n),
-            ProtobufFieldData::Bytes(b) => serde_json::Value::Array(b.iter().map(|v| json!(v)).collect()),
-            ProtobufFieldData::Enum(n, descriptor) => serde_json::Value::String(enum_name(*n, descriptor)),
-            ProtobufFieldData::Message(b, descriptor) => {
-              let mut bytes = BytesMut::from(b.as_slice());
-              let message_data = decode_message(&mut bytes, descriptor, descriptors)?;
-              field_data_to_json(message_data, descriptor, descriptors)?
-            }
-            ProtobufFieldData::Unknown(b) => serde_json::Value::Array(b.iter().map(|v| json!(v)).collect())
-          });
-        }
-        None => warn!("Did not get the field name for field number {}", field.field_num)
-      }
-    } else {
-      warn!("Did not find the descriptor for field number {}", field.field_num);
-    }
-  }
+/// Converts an encoded `google.protobuf.Struct`, `google.protobuf.Value` or `google.protobuf.ListValue`
+/// message into the JSON it represents. Infinite and NaN numbers can not be represented in JSON, so
+/// they are converted to the string forms used by Protobuf JSON (`Infinity`, `-Infinity` and `NaN`).
+pub fn well_known_json_type_to_json(type_name: &str, data: &[u8]) -> anyhow::Result<serde_json::Value> {
+  let kind = match type_name {
+    ".google.protobuf.Struct" => prost_types::value::Kind::StructValue(prost_types::Struct::decode(data)?),
+    ".google.protobuf.ListValue" => prost_types::value::Kind::ListValue(prost_types::ListValue::decode(data)?),
+    ".google.protobuf.Value" => return Ok(proto_value_to_json(&non_finite_numbers_as_strings(prost_types::Value::decode(data)?))),
+    _ => return Err(anyhow!("{} is not a JSON well-known type", type_name))
+  };
+  Ok(proto_value_to_json(&non_finite_numbers_as_strings(prost_types::Value { kind: Some(kind) })))
+}
 
-  Ok(serde_json::Value::Object(object.iter().map(|(k, v)| (k.clone(), v.clone())).collect()))
+/// Replaces any infinite or NaN number values with their Protobuf JSON string form
+fn non_finite_numbers_as_strings(value: prost_types::Value) -> prost_types::Value {
+  let kind = value.kind.map(|kind| match kind {
+    prost_types::value::Kind::NumberValue(n) if !n.is_finite() =>
+      prost_types::value::Kind::StringValue(float_to_json_string(n)),
+    prost_types::value::Kind::StructValue(s) => prost_types::value::Kind::StructValue(prost_types::Struct {
+      fields: s.fields.into_iter().map(|(k, v)| (k, non_finite_numbers_as_strings(v))).collect()
+    }),
+    prost_types::value::Kind::ListValue(l) => prost_types::value::Kind::ListValue(prost_types::ListValue {
+      values: l.values.into_iter().map(non_finite_numbers_as_strings).collect()
+    }),
+    kind => kind
+  });
+  prost_types::Value { kind }
+}
+
+/// Returns the Protobuf JSON form of a floating point value
+pub(crate) fn float_to_json_string(n: f64) -> String {
+  if n.is_nan() {
+    "NaN".to_string()
+  } else if n == f64::INFINITY {
+    "Infinity".to_string()
+  } else if n == f64::NEG_INFINITY {
+    "-Infinity".to_string()
+  } else {
+    n.to_string()
+  }
 }
 
 /// Parse the JSON string into a V4 Pact model
@@ -337,6 +527,38 @@ pub fn lookup_interaction_config(interaction: &dyn V4Interaction) -> Option<Hash
     })
 }
 
+/// Summary of the Protobuf configuration for an interaction in a Pact file
+#[derive(Clone, Debug, PartialEq)]
+pub struct InteractionConfigSummary {
+  /// Interaction description
+  pub description: String,
+  /// gRPC service method (in the form <SERVICE>/<METHOD>) or message name the interaction uses
+  pub service_or_message: Option<String>,
+  /// Key of the Protobuf descriptors used by the interaction
+  pub descriptor_key: Option<String>
+}
+
+/// Lists all the interactions in the Pact that have Protobuf configuration, along with the
+/// service or message and the descriptor key they resolve to
+pub fn list_interaction_configs(pact: &V4Pact) -> Vec<InteractionConfigSummary> {
+  pact.interactions.iter()
+    .filter_map(|interaction| {
+      lookup_interaction_config(interaction.as_ref()).map(|config| {
+        let summary = InteractionConfigSummary {
+          description: interaction.description(),
+          service_or_message: config.get("service")
+            .or_else(|| config.get("message"))
+            .map(json_to_string),
+          descriptor_key: config.get("descriptorKey").map(json_to_string)
+        };
+        trace!("Interaction '{}' uses {:?} with descriptor key {:?}", summary.description,
+          summary.service_or_message, summary.descriptor_key);
+        summary
+      })
+    })
+    .collect()
+}
+
 /// Returns the service descriptors for the given interaction
 pub(crate) fn lookup_service_descriptors_for_interaction(
   interaction: &dyn V4Interaction,
@@ -371,11 +593,52 @@ pub(crate) fn lookup_service_descriptors_for_interaction(
   Ok((descriptors.clone(), service_descriptor.clone(), method_descriptor.clone(), package.unwrap_or_default()))
 }
 
+/// Calculates the checksum of the encoded Protobuf descriptors that is stored in the Pact file
+/// (`protoDescriptorsChecksum`)
+pub(crate) fn descriptors_checksum(descriptor_bytes: &[u8]) -> String {
+  format!("sha256:{:x}", Sha256::digest(descriptor_bytes))
+}
+
 /// Get the encoded Protobuf descriptors from the Pact level configuration for the message key
 pub fn get_descriptors_for_interaction(
   message_key: &str,
   plugin_config: &BTreeMap<String, serde_json::Value>
 ) -> anyhow::Result<FileDescriptorSet> {
+  let descriptor_bytes = get_descriptor_bytes_for_interaction(message_key, plugin_config)?;
+
+  // Decode the Protobuf descriptors
+  FileDescriptorSet::decode(descriptor_bytes)
+    .map_err(|err| anyhow!(err))
+}
+
+/// Key in the Pact level configuration for the compression (`gzip` or `zstd`) applied to the
+/// encoded Protobuf descriptors
+pub const PROTO_DESCRIPTORS_COMPRESSION: &str = "protoDescriptorsCompression";
+
+/// Decompresses the encoded Protobuf descriptors using the compression from the Pact level
+/// configuration. Without any compression, the bytes are returned as is.
+pub(crate) fn decompress_descriptors(bytes: Bytes, compression: Option<&str>) -> anyhow::Result<Bytes> {
+  match compression {
+    None => Ok(bytes),
+    Some(compression) => {
+      let mut buffer = vec![];
+      match compression.to_lowercase().as_str() {
+        "gzip" => flate2::read::GzDecoder::new(bytes.as_ref()).read_to_end(&mut buffer),
+        "zstd" => zstd::stream::read::Decoder::new(bytes.as_ref())?.read_to_end(&mut buffer),
+        _ => return Err(anyhow!("'{}' is not a supported compression for the Protobuf descriptors, it must be either 'gzip' or 'zstd'", compression))
+      }.map_err(|err| anyhow!("Failed to decompress the {} compressed Protobuf descriptors - {}", compression, err))?;
+      trace!("Decompressed {} bytes of Protobuf descriptors to {} bytes", bytes.len(), buffer.len());
+      Ok(Bytes::from(buffer))
+    }
+  }
+}
+
+/// Get the raw bytes of the encoded Protobuf descriptors from the Pact level configuration for
+/// the message key, checking that they have not been modified
+pub(crate) fn get_descriptor_bytes_for_interaction(
+  message_key: &str,
+  plugin_config: &BTreeMap<String, serde_json::Value>
+) -> anyhow::Result<Bytes> {
   let descriptor_config = plugin_config.get(message_key)
     .ok_or_else(|| anyhow!("Plugin configuration item with key '{}' is required. Received config {:?}", message_key, plugin_config.keys()))?
     .as_object()
@@ -387,15 +650,26 @@ pub fn get_descriptors_for_interaction(
     return Err(anyhow!("Plugin configuration item with key '{}' is required, but the descriptors were empty. Received config {:?}", message_key, plugin_config.keys()));
   }
 
-  // The descriptor bytes will be base 64 encoded.
+  // The descriptor bytes will be base 64 encoded, and may be compressed.
   let descriptor_bytes = match BASE64.decode(descriptor_bytes_encoded) {
     Ok(bytes) => Bytes::from(bytes),
     Err(err) => {
       return Err(anyhow!("Failed to decode the Protobuf descriptor - {}", err));
     }
   };
+  let compression = descriptor_config.get(PROTO_DESCRIPTORS_COMPRESSION).map(json_to_string);
+  let descriptor_bytes = decompress_descriptors(descriptor_bytes, compression.as_deref())?;
   debug!("Protobuf file descriptor set is {} bytes", descriptor_bytes.len());
 
+  // If the Pact file has a checksum for the descriptors, check that they have not been modified
+  if let Some(checksum) = descriptor_config.get("protoDescriptorsChecksum").map(json_to_string) {
+    let actual_checksum = descriptors_checksum(&descriptor_bytes);
+    if checksum != actual_checksum {
+      return Err(anyhow!("Protobuf descriptor checksum mismatch; pact may be corrupted. Expected {} but got {}",
+        checksum, actual_checksum));
+    }
+  }
+
   // Get an MD5 hash of the bytes to check that it matches the descriptor key
   let digest = md5::compute(&descriptor_bytes);
   let descriptor_hash = format!("{:x}", digest);
@@ -403,9 +677,239 @@ pub fn get_descriptors_for_interaction(
     return Err(anyhow!("Protobuf descriptors checksum failed. Expected {} but got {}", message_key, descriptor_hash));
   }
 
-  // Decode the Protobuf descriptors
-  FileDescriptorSet::decode(descriptor_bytes)
-    .map_err(|err| anyhow!(err))
+  Ok(descriptor_bytes)
+}
+
+/// Finds the field number of the custom field option (an extension of `google.protobuf.FieldOptions`)
+/// with the given name. The name can be qualified with the package name.
+pub(crate) fn find_field_option_number(descriptors: &FileDescriptorSet, option_name: &str) -> Option<u32> {
+  let option_name = option_name.strip_prefix('.').unwrap_or(option_name);
+  descriptors.file.iter().find_map(|file| {
+    let package = file.package.clone().unwrap_or_default();
+    file.extension.iter()
+      .chain(file.message_type.iter().flat_map(|message| message.extension.iter()))
+      .find(|extension| {
+        let name = extension.name.clone().unwrap_or_default();
+        extension.extendee.as_deref() == Some(".google.protobuf.FieldOptions") &&
+          (name == option_name || format!("{}.{}", package, name) == option_name)
+      })
+      .and_then(|extension| extension.number.map(|n| n as u32))
+  })
+}
+
+/// Raw field value read from an encoded message
+pub(crate) enum RawFieldValue {
+  Varint(u64),
+  LengthDelimited(Bytes),
+  Fixed64(u64),
+  Fixed32(u32)
+}
+
+impl RawFieldValue {
+  /// If the value is not the default (zero or empty) value
+  pub(crate) fn is_set(&self) -> bool {
+    match self {
+      RawFieldValue::Varint(v) => *v != 0,
+      RawFieldValue::LengthDelimited(b) => !b.is_empty(),
+      RawFieldValue::Fixed64(v) => *v != 0,
+      RawFieldValue::Fixed32(v) => *v != 0
+    }
+  }
+
+  /// String form of the value, with any length delimited value treated as UTF-8
+  fn to_value_string(&self) -> String {
+    match self {
+      RawFieldValue::Varint(v) => (*v as i64).to_string(),
+      RawFieldValue::LengthDelimited(b) => String::from_utf8_lossy(b).to_string(),
+      RawFieldValue::Fixed64(v) => f64::from_bits(*v).to_string(),
+      RawFieldValue::Fixed32(v) => f32::from_bits(*v).to_string()
+    }
+  }
+}
+
+/// Reads the fields of an encoded message without a descriptor
+pub(crate) fn read_raw_fields(mut buffer: Bytes) -> anyhow::Result<Vec<(u32, RawFieldValue)>> {
+  let mut fields = vec![];
+  while buffer.has_remaining() {
+    let (field_num, wire_type) = prost::encoding::decode_key(&mut buffer)?;
+    let value = match wire_type {
+      WireType::Varint => RawFieldValue::Varint(prost::encoding::decode_varint(&mut buffer)?),
+      WireType::LengthDelimited => {
+        let length = prost::encoding::decode_varint(&mut buffer)? as usize;
+        if buffer.remaining() < length {
+          return Err(anyhow!("Insufficient data remaining ({} bytes) to read {} bytes for field {}", buffer.remaining(), length, field_num));
+        }
+        RawFieldValue::LengthDelimited(buffer.copy_to_bytes(length))
+      }
+      WireType::SixtyFourBit if buffer.remaining() >= 8 => RawFieldValue::Fixed64(buffer.get_u64_le()),
+      WireType::ThirtyTwoBit if buffer.remaining() >= 4 => RawFieldValue::Fixed32(buffer.get_u32_le()),
+      _ => return Err(anyhow!("Failed to read field {} with {:?} wire type", field_num, wire_type))
+    };
+    fields.push((field_num, value));
+  }
+  Ok(fields)
+}
+
+/// Returns the values of the custom field option with the given field number set on all the
+/// message fields in the encoded descriptors, keyed by the fully qualified field name. The raw
+/// descriptor bytes are required, as the option values are dropped when the descriptors are decoded.
+pub(crate) fn field_option_values(descriptor_bytes: &Bytes, option_number: u32) -> anyhow::Result<HashMap<String, String>> {
+  Ok(field_option_raw_values(descriptor_bytes, option_number)?.iter()
+    .map(|(name, value)| (name.clone(), value.to_value_string()))
+    .collect())
+}
+
+/// Returns the raw values of the custom field option with the given field number set on all the
+/// message fields in the encoded descriptors, keyed by the fully qualified field name
+pub(crate) fn field_option_raw_values(descriptor_bytes: &Bytes, option_number: u32) -> anyhow::Result<HashMap<String, RawFieldValue>> {
+  let mut values = hashmap!{};
+  for (field_num, file) in read_raw_fields(descriptor_bytes.clone())? {
+    if let (1, RawFieldValue::LengthDelimited(file)) = (field_num, file) {
+      let file_fields = read_raw_fields(file)?;
+      let package = file_fields.iter().find_map(|(n, v)| match (n, v) {
+        (2, RawFieldValue::LengthDelimited(b)) => Some(String::from_utf8_lossy(b).to_string()),
+        _ => None
+      }).unwrap_or_default();
+      for (n, message) in file_fields {
+        if let (4, RawFieldValue::LengthDelimited(message)) = (n, message) {
+          message_field_option_values(message, package.as_str(), option_number, &mut values)?;
+        }
+      }
+    }
+  }
+  Ok(values)
+}
+
+fn message_field_option_values(
+  message: Bytes,
+  prefix: &str,
+  option_number: u32,
+  values: &mut HashMap<String, RawFieldValue>
+) -> anyhow::Result<()> {
+  let message_fields = read_raw_fields(message)?;
+  let name = message_fields.iter().find_map(|(n, v)| match (n, v) {
+    (1, RawFieldValue::LengthDelimited(b)) => Some(String::from_utf8_lossy(b).to_string()),
+    _ => None
+  }).unwrap_or_default();
+  let message_name = if prefix.is_empty() { name } else { format!("{}.{}", prefix, name) };
+
+  for (n, value) in message_fields {
+    match (n, value) {
+      (2, RawFieldValue::LengthDelimited(field)) => {
+        let field_fields = read_raw_fields(field)?;
+        let field_name = field_fields.iter().find_map(|(n, v)| match (n, v) {
+          (1, RawFieldValue::LengthDelimited(b)) => Some(String::from_utf8_lossy(b).to_string()),
+          _ => None
+        }).unwrap_or_default();
+        for (n, options) in field_fields {
+          if let (8, RawFieldValue::LengthDelimited(options)) = (n, options) {
+            let option_value = read_raw_fields(options)?.into_iter()
+              .find(|(n, _)| *n == option_number)
+              .map(|(_, value)| value);
+            if let Some(option_value) = option_value {
+              values.insert(format!("{}.{}", message_name, field_name), option_value);
+            }
+          }
+        }
+      }
+      (3, RawFieldValue::LengthDelimited(nested)) => {
+        message_field_option_values(nested, message_name.as_str(), option_number, values)?;
+      }
+      _ => {}
+    }
+  }
+  Ok(())
+}
+
+/// Value of the `field_presence` feature (`google.protobuf.FeatureSet.FieldPresence`) for fields
+/// with implicit presence
+const IMPLICIT_FIELD_PRESENCE: u64 = 2;
+
+/// Returns the fully qualified names of the fields in files that use Protobuf Editions that have
+/// implicit presence. The `field_presence` feature is resolved from the field, message and file
+/// options (in that order), with explicit presence being the edition default. The raw descriptor
+/// bytes are required, as the features are dropped when the descriptors are decoded.
+pub(crate) fn implicit_presence_fields(descriptor_bytes: &Bytes) -> anyhow::Result<Vec<String>> {
+  let mut fields = vec![];
+  for (field_num, file) in read_raw_fields(descriptor_bytes.clone())? {
+    if let (1, RawFieldValue::LengthDelimited(file)) = (field_num, file) {
+      let file_fields = read_raw_fields(file)?;
+      if raw_string_field(&file_fields, 12).as_deref() != Some("editions") {
+        continue;
+      }
+      let package = raw_string_field(&file_fields, 2).unwrap_or_default();
+      // FileOptions.features is field 50
+      let presence = raw_field_presence(&file_fields, 8, 50)?;
+      for (n, message) in file_fields {
+        if let (4, RawFieldValue::LengthDelimited(message)) = (n, message) {
+          message_implicit_presence_fields(message, package.as_str(), presence, &mut fields)?;
+        }
+      }
+    }
+  }
+  Ok(fields)
+}
+
+fn message_implicit_presence_fields(
+  message: Bytes,
+  prefix: &str,
+  presence: Option<u64>,
+  fields: &mut Vec<String>
+) -> anyhow::Result<()> {
+  let message_fields = read_raw_fields(message)?;
+  let name = raw_string_field(&message_fields, 1).unwrap_or_default();
+  let message_name = if prefix.is_empty() { name } else { format!("{}.{}", prefix, name) };
+  // MessageOptions.features is field 12
+  let presence = raw_field_presence(&message_fields, 7, 12)?.or(presence);
+
+  for (n, value) in message_fields {
+    match (n, value) {
+      (2, RawFieldValue::LengthDelimited(field)) => {
+        let field_fields = read_raw_fields(field)?;
+        // FieldOptions.features is field 21
+        if raw_field_presence(&field_fields, 8, 21)?.or(presence) == Some(IMPLICIT_FIELD_PRESENCE) {
+          let field_name = raw_string_field(&field_fields, 1).unwrap_or_default();
+          fields.push(format!("{}.{}", message_name, field_name));
+        }
+      }
+      (3, RawFieldValue::LengthDelimited(nested)) => {
+        message_implicit_presence_fields(nested, message_name.as_str(), presence, fields)?;
+      }
+      _ => {}
+    }
+  }
+  Ok(())
+}
+
+/// Reads the `field_presence` feature (field 1 of `google.protobuf.FeatureSet`) from the features
+/// field of the options field of a raw descriptor
+fn raw_field_presence(
+  descriptor_fields: &[(u32, RawFieldValue)],
+  options_number: u32,
+  features_number: u32
+) -> anyhow::Result<Option<u64>> {
+  let mut presence = None;
+  for (_, options) in descriptor_fields.iter().filter(|(n, _)| *n == options_number) {
+    if let RawFieldValue::LengthDelimited(options) = options {
+      for (n, features) in read_raw_fields(options.clone())? {
+        if let (true, RawFieldValue::LengthDelimited(features)) = (n == features_number, features) {
+          for (n, value) in read_raw_fields(features)? {
+            if let (1, RawFieldValue::Varint(value)) = (n, value) {
+              presence = Some(value);
+            }
+          }
+        }
+      }
+    }
+  }
+  Ok(presence)
+}
+
+fn raw_string_field(fields: &[(u32, RawFieldValue)], field_number: u32) -> Option<String> {
+  fields.iter().find_map(|(n, v)| match v {
+    RawFieldValue::LengthDelimited(b) if *n == field_number => Some(String::from_utf8_lossy(b).to_string()),
+    _ => None
+  })
 }
 
 pub(crate) fn find_service_descriptor<'a>(
@@ -461,9 +965,13 @@ pub(crate) fn prost_string<S: Into<String>>(s: S) -> Value {
 
 #[cfg(test)]
 pub(crate) mod tests {
+  use std::io::Write;
+
+  use base64::Engine;
+  use base64::engine::general_purpose::STANDARD as BASE64;
   use bytes::Bytes;
   use expectest::prelude::*;
-  use maplit::hashmap;
+  use maplit::{btreemap, hashmap};
   use prost::Message;
   use prost_types::{
     DescriptorProto,
@@ -472,11 +980,117 @@ pub(crate) mod tests {
     FieldDescriptorProto,
     FileDescriptorProto,
     FileDescriptorSet,
-    MessageOptions
+    MessageOptions,
+    MethodDescriptorProto,
+    MethodOptions
   };
+  use pact_models::prelude::v4::V4Pact;
+  use pact_models::v4::interaction::V4Interaction;
+  use pact_models::v4::sync_message::SynchronousMessage;
   use prost_types::field_descriptor_proto::{Label, Type};
+  use serde_json::json;
+
+  use crate::utils::{
+    as_hex,
+    descriptors_checksum,
+    find_enum_value_by_name,
+    find_message_type_by_name,
+    find_message_type_by_qualified_name,
+    find_nested_type,
+    get_descriptors_for_interaction,
+    InteractionConfigSummary,
+    is_map_field,
+    last_name,
+    list_interaction_configs,
+    method_options,
+    ResolutionError,
+    ResolutionStep
+  };
+
+  #[test]
+  fn method_options_test() {
+    let method = MethodDescriptorProto::default();
+    expect!(method_options(&method)).to(be_equal_to(btreemap!{
+      "idempotencyLevel".to_string() => "IDEMPOTENCY_UNKNOWN".to_string(),
+      "deprecated".to_string() => "false".to_string()
+    }));
+
+    let method = MethodDescriptorProto {
+      options: Some(MethodOptions {
+        deprecated: Some(true),
+        idempotency_level: Some(1),
+        .. MethodOptions::default()
+      }),
+      .. MethodDescriptorProto::default()
+    };
+    expect!(method_options(&method)).to(be_equal_to(btreemap!{
+      "idempotencyLevel".to_string() => "NO_SIDE_EFFECTS".to_string(),
+      "deprecated".to_string() => "true".to_string()
+    }));
+  }
+
+  #[test]
+  fn get_descriptors_for_interaction_checks_the_descriptors_checksum() {
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let descriptor_bytes = descriptors.encode_to_vec();
+    let key = format!("{:x}", md5::compute(&descriptor_bytes));
+    let config = |bytes: &[u8]| btreemap!{
+      key.clone() => json!({
+        "protoDescriptors": BASE64.encode(bytes),
+        "protoDescriptorsChecksum": descriptors_checksum(&descriptor_bytes)
+      })
+    };
 
-  use crate::utils::{as_hex, find_enum_value_by_name, find_message_type_by_name, find_nested_type, is_map_field, last_name};
+    expect!(get_descriptors_for_interaction(key.as_str(), &config(&descriptor_bytes)))
+      .to(be_ok().value(descriptors));
+
+    let tampered = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("tampered.proto".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    }.encode_to_vec();
+    let result = get_descriptors_for_interaction(key.as_str(), &config(&tampered));
+    expect!(result.unwrap_err().to_string().starts_with("Protobuf descriptor checksum mismatch; pact may be corrupted"))
+      .to(be_true());
+  }
+
+  #[test]
+  fn get_descriptors_for_interaction_with_gzip_compressed_descriptors() {
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let descriptor_bytes = descriptors.encode_to_vec();
+    let key = format!("{:x}", md5::compute(&descriptor_bytes));
+    let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    encoder.write_all(&descriptor_bytes).unwrap();
+    let compressed = encoder.finish().unwrap();
+    let config = |compression: &str| btreemap!{
+      key.clone() => json!({
+        "protoDescriptors": BASE64.encode(&compressed),
+        "protoDescriptorsCompression": compression
+      })
+    };
+
+    expect!(get_descriptors_for_interaction(key.as_str(), &config("gzip")))
+      .to(be_ok().value(descriptors));
+    expect!(get_descriptors_for_interaction(key.as_str(), &config("lz4")).unwrap_err().to_string())
+      .to(be_equal_to("'lz4' is not a supported compression for the Protobuf descriptors, it must be either 'gzip' or 'zstd'"));
+  }
 
   #[test]
   fn last_name_test() {
@@ -489,6 +1103,14 @@ pub(crate) mod tests {
     expect!(last_name("1.2.3.4")).to(be_equal_to("4"));
   }
 
+  /// Appends a length delimited field to the encoded message. Used to build descriptors with
+  /// custom options, which prost does not support.
+  pub(crate) fn append_length_delimited(buffer: &mut Vec<u8>, field_num: u32, bytes: &[u8]) {
+    prost::encoding::encode_key(field_num, prost::encoding::WireType::LengthDelimited, buffer);
+    prost::encoding::encode_varint(bytes.len() as u64, buffer);
+    buffer.extend_from_slice(bytes);
+  }
+
   pub(crate) const DESCRIPTOR_WITH_EXT_MESSAGE: [u8; 626] = [
     10, 168, 2, 10, 11, 86, 97, 108, 117, 101, 46, 112, 114, 111, 116, 111, 18, 21, 97, 114, 101,
     97, 95, 99, 97, 108, 99, 117, 108, 97, 116, 111, 114, 46, 86, 97, 108, 117, 101, 34, 162, 1,
@@ -529,7 +1151,11 @@ pub(crate) mod tests {
     let fds = FileDescriptorSet::decode(buffer).unwrap();
 
     expect!(find_message_type_by_name("", &fds)).to(be_err());
-    expect!(find_message_type_by_name("Does not exist", &fds)).to(be_err());
+    let err = find_message_type_by_name("Does not exist", &fds).unwrap_err();
+    let error = err.downcast_ref::<ResolutionError>().unwrap();
+    expect!(error.type_name.as_str()).to(be_equal_to("Does not exist"));
+    expect!(error.steps.len()).to(be_equal_to(fds.file.len()));
+    expect!(error.steps.iter().all(|step| step.outcome == "no message with that name")).to(be_true());
 
     let (result, _) = find_message_type_by_name("AdBreakRequest", &fds).unwrap();
     expect!(result.name).to(be_some().value("AdBreakRequest"));
@@ -538,6 +1164,54 @@ pub(crate) mod tests {
     expect!(result.name).to(be_some().value("AdBreakContext"));
   }
 
+  #[test]
+  fn find_message_type_by_qualified_name_returns_the_resolution_steps_if_not_found() {
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("orders.proto".to_string()),
+          package: Some("shop.orders".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Order".to_string()),
+              .. DescriptorProto::default()
+            }
+          ],
+          .. FileDescriptorProto::default()
+        },
+        FileDescriptorProto {
+          name: Some("users.proto".to_string()),
+          package: Some("shop.users".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+
+    let err = find_message_type_by_qualified_name(".shop.orders.Order.Line", &descriptors).unwrap_err();
+    let error = err.downcast_ref::<ResolutionError>().unwrap();
+    expect!(error.steps.clone()).to(be_equal_to(vec![
+      ResolutionStep {
+        file: "orders.proto".to_string(),
+        attempted_name: Some(".shop.orders.Order.Line".to_string()),
+        outcome: "message 'Order' has no nested message 'Line'".to_string()
+      },
+      ResolutionStep {
+        file: "users.proto".to_string(),
+        attempted_name: None,
+        outcome: "package 'shop.users' does not match".to_string()
+      }
+    ]));
+    expect!(err.to_string()).to(be_equal_to("Did not find a message type '.shop.orders.Order.Line' in the \
+      descriptors (searched orders.proto, users.proto) [orders.proto: looked for '.shop.orders.Order.Line', message \
+      'Order' has no nested message 'Line'; users.proto: package 'shop.users' does not match]"));
+
+    let err = find_message_type_by_qualified_name("Missing", &descriptors).unwrap_err();
+    let attempted_names = err.downcast_ref::<ResolutionError>().unwrap().steps.iter()
+      .map(|step| step.attempted_name.clone().unwrap_or_default())
+      .collect::<Vec<_>>();
+    expect!(attempted_names).to(be_equal_to(vec![".shop.orders.Missing".to_string(), ".shop.users.Missing".to_string()]));
+  }
+
   #[test]
   fn find_nested_type_test() {
     let non_message_field = FieldDescriptorProto {
@@ -759,4 +1433,50 @@ pub(crate) mod tests {
     let result3 = find_enum_value_by_name(&descriptors, ".TestEnum", "VALUE_TWO");
     expect!(result3).to(be_some().value((2, enum1.clone())));
   }
+
+  #[test]
+  fn list_interaction_configs_test() {
+    let pact = V4Pact {
+      interactions: vec![
+        SynchronousMessage {
+          description: "get feature".to_string(),
+          plugin_config: hashmap!{
+            "protobuf".to_string() => hashmap!{
+              "descriptorKey".to_string() => json!("d58838959e37498cddf51805bedf4537"),
+              "service".to_string() => json!("RouteGuide/GetFeature")
+            }
+          },
+          .. SynchronousMessage::default()
+        }.boxed_v4(),
+        SynchronousMessage {
+          description: "no protobuf config".to_string(),
+          .. SynchronousMessage::default()
+        }.boxed_v4(),
+        SynchronousMessage {
+          description: "init plugin".to_string(),
+          plugin_config: hashmap!{
+            "protobuf".to_string() => hashmap!{
+              "descriptorKey".to_string() => json!("a77ff8bbe7f2e8f5e7d3f1a8b9c1d2e3"),
+              "message".to_string() => json!("InitPluginRequest")
+            }
+          },
+          .. SynchronousMessage::default()
+        }.boxed_v4()
+      ],
+      .. V4Pact::default()
+    };
+
+    expect!(list_interaction_configs(&pact)).to(be_equal_to(vec![
+      InteractionConfigSummary {
+        description: "get feature".to_string(),
+        service_or_message: Some("RouteGuide/GetFeature".to_string()),
+        descriptor_key: Some("d58838959e37498cddf51805bedf4537".to_string())
+      },
+      InteractionConfigSummary {
+        description: "init plugin".to_string(),
+        service_or_message: Some("InitPluginRequest".to_string()),
+        descriptor_key: Some("a77ff8bbe7f2e8f5e7d3f1a8b9c1d2e3".to_string())
+      }
+    ]));
+  }
 }
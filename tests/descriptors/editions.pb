@@ -0,0 +1,7 @@
+
+
+editions.protoeditions"T
+Account
+name (	Rname
+balance (Rbalance
+limit (BRlimitBbeditionsp
\ No newline at end of file
@@ -0,0 +1,11 @@
+
+w
+google/protobuf/timestamp.protogoogle.protobuf";
+	Timestamp
+seconds (Rseconds
+nanos (Rnanosbproto3
+
+event.protogoogle/protobuf/timestamp.proto"Q
+Event
+name (	Rname4
+created (2.google.protobuf.TimestampRcreatedbproto3
\ No newline at end of file
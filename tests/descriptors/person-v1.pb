@@ -0,0 +1,5 @@
+
+4
+person.proto"
+Person
+name (	Rnamebproto3
\ No newline at end of file
@@ -0,0 +1,6 @@
+
+F
+person.proto".
+Person
+name (	Rname
+age (Ragebproto3
\ No newline at end of file
@@ -0,0 +1,49 @@
+#!/bin/sh
+# Stand-in for protoc used by the unit tests, so they do not need to download protoc. It writes
+# the arguments it was invoked with to a .args file next to the proto file, checks that the files
+# imported by the proto file can be found in the include directories (as protoc does), and copies
+# the pre-compiled descriptor set for the proto file (a .pb file next to the proto file, or in the
+# descriptors directory) to the output file.
+if [ "$1" = "--version" ]; then
+  echo "libprotoc 3.19.1"
+  exit 0
+fi
+
+includes=""
+for arg in "$@"; do
+  case "$arg" in
+    -o*) output="${arg#-o}" ;;
+    -I*) includes="$includes
+${arg#-I}" ;;
+    --*) ;;
+    *) proto_file="$arg" ;;
+  esac
+done
+
+name="$(basename "$proto_file" .proto)"
+printf '%s\n' "$@" > "$(dirname "$proto_file")/$name.args"
+
+imports=$(sed -n -e 's/^import  *"\(.*\)";.*$/\1/p' -e 's/^import  *[a-z]*  *"\(.*\)";.*$/\1/p' "$proto_file")
+for import in $imports; do
+  found=""
+  while IFS= read -r include; do
+    if [ -n "$include" ] && [ -f "$include/$import" ]; then
+      found="yes"
+    fi
+  done <<INCLUDES
+$includes
+INCLUDES
+  if [ -z "$found" ]; then
+    echo "$import: File not found." >&2
+    exit 1
+  fi
+done
+
+if [ -f "$(dirname "$proto_file")/$name.pb" ]; then
+  cp "$(dirname "$proto_file")/$name.pb" "$output"
+elif [ -f "$(dirname "$0")/descriptors/$name.pb" ]; then
+  cp "$(dirname "$0")/descriptors/$name.pb" "$output"
+else
+  echo "$proto_file: No descriptor set fixture found" >&2
+  exit 1
+fi
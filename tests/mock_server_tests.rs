@@ -165,3 +165,73 @@ async fn each_value_matcher() {
   grpc.unary(Request::new(message), path, codec).await.unwrap();
 }
 
+
+async fn concurrent_mock_server(index: usize) {
+  let mut pact_builder = PactBuilderAsync::new_v4(format!("concurrent-{}", index).as_str(), "protobuf-plugin");
+  pact_builder
+    .using_plugin("protobuf", None).await
+    .synchronous_message_interaction("get a test value", |mut i| async move {
+      let proto_file = Path::new("tests/simple.proto")
+        .canonicalize().unwrap().to_string_lossy().to_string();
+      i.contents_from(json!({
+        "pact:proto": proto_file,
+        "pact:content-type": "application/protobuf",
+        "pact:proto-service": "Test/GetTest",
+
+        "request": {
+          "in": "matching(boolean, true)"
+        },
+
+        "response": {
+          "out": "matching(boolean, true)"
+        }
+      })).await;
+      i
+    })
+    .await;
+  let mock_server = pact_builder
+    .start_mock_server_async(Some("protobuf/transport/grpc"))
+    .await;
+
+  let url = mock_server.url();
+  let descriptors = base64::engine::general_purpose::STANDARD.decode(
+    "CogCCgxzaW1wbGUucHJvdG8iGwoJTWVzc2FnZUluEg4KAmluGAEgASgIUgJpbiIeCgpNZXNzYWdlT3V0EhAKA291\
+    dBgBIAEoCFIDb3V0IicKD1ZhbHVlc01lc3NhZ2VJbhIUCgV2YWx1ZRgBIAMoCVIFdmFsdWUiKAoQVmFsdWVzTWVzc2FnZU\
+    91dBIUCgV2YWx1ZRgBIAMoCVIFdmFsdWUyYAoEVGVzdBIkCgdHZXRUZXN0EgouTWVzc2FnZUluGgsuTWVzc2FnZU91dCIA\
+    EjIKCUdldFZhbHVlcxIQLlZhbHVlc01lc3NhZ2VJbhoRLlZhbHVlc01lc3NhZ2VPdXQiAGIGcHJvdG8z").unwrap();
+  let fds = FileDescriptorSet::decode(descriptors.as_slice()).unwrap();
+  let field = ProtobufField {
+    field_num: 1,
+    field_name: "in".to_string(),
+    wire_type: WireType::Varint,
+    data: ProtobufFieldData::Boolean(true)
+  };
+  let message = DynamicMessage::new(&[ field ], &fds);
+
+  let mut conn = tonic::transport::Endpoint::from_shared(url.to_string())
+    .unwrap()
+    .connect()
+    .await
+    .unwrap();
+  conn.ready().await.unwrap();
+
+  let (input_message, _) = find_message_type_by_name("MessageIn", &fds).unwrap();
+  let (output_message, _) = find_message_type_by_name("MessageOut", &fds).unwrap();
+  let interaction = pact_builder.build()
+    .interactions().first().unwrap()
+    .as_v4_sync_message().unwrap();
+
+  let codec = PactCodec::new(&fds, &input_message, &output_message, &interaction);
+  let mut grpc = tonic::client::Grpc::new(conn);
+  let path = http::uri::PathAndQuery::try_from("/Test/GetTest").unwrap();
+  grpc.unary(Request::new(message), path, codec).await.unwrap();
+
+  // Each mock server must only have recorded its own request, otherwise it will fail verification
+  // when it is dropped at the end of this function
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn concurrent_mock_servers_have_isolated_state() {
+  // The mock server futures are not Send, so they are run concurrently on this task
+  futures::future::join_all((0..5).map(concurrent_mock_server)).await;
+}